@@ -3,7 +3,7 @@ set -euo pipefail
 
 export RUSTFLAGS="-D warnings"
 
-for dir in usbpd usbpd-traits examples/embassy-nucleo-h563zi examples/embassy-stm32-g431cb examples/embassy-stm32-g431cb-epr;
+for dir in usbpd usbpd-messages usbpd-traits examples/embassy-nucleo-h563zi examples/embassy-stm32-g431cb examples/embassy-stm32-g431cb-epr examples/panic-free-check;
 do
     pushd $dir
     cargo +nightly fmt --check
@@ -12,10 +12,11 @@ do
     popd
 done
 
-for dir in usbpd usbpd-traits
+for dir in usbpd usbpd-messages usbpd-traits
 do
     pushd $dir
     cargo clippy --features defmt
+    cargo clippy --all-targets --features defmt
     popd
 done
 
@@ -23,4 +24,6 @@ done
 pushd usbpd
 cargo build --features serde,log
 cargo build --features serde,defmt
+cargo build --features std,serde
+cargo clippy --features panic-free
 popd
@@ -1,7 +1,7 @@
 #!/bin/bash
 set -euo pipefail
 
-for dir in ./usbpd;
+for dir in ./usbpd ./usbpd-messages;
 do
     pushd $dir
     cargo test
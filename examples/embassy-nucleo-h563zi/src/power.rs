@@ -5,7 +5,7 @@ use embassy_futures::select::{Either, select};
 use embassy_stm32::gpio::Output;
 use embassy_stm32::ucpd::{self, CcPhy, CcPull, CcSel, CcVState, PdPhy, Ucpd};
 use embassy_stm32::{Peri, bind_interrupts, dma, peripherals};
-use embassy_time::{Duration, Ticker, Timer, with_timeout};
+use embassy_time::{Duration, Instant, Ticker, Timer, with_timeout};
 use uom::si::electric_potential;
 use usbpd::protocol_layer::message::data::request::{self, CurrentRequest, VoltageRequest};
 use usbpd::protocol_layer::message::data::source_capabilities::SourceCapabilities;
@@ -52,13 +52,13 @@ impl<'d> UcpdSinkDriver<'d> {
 }
 
 impl SinkDriver for UcpdSinkDriver<'_> {
-    async fn wait_for_vbus(&mut self) {
-        // The sink policy engine is only running when attached. Therefore VBus is present.
-    }
+    // The sink policy engine is only running when attached. Therefore VBus is present.
+    const HAS_VBUS_DETECTION: bool = false;
 
     async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usbpd_traits::DriverRxError> {
         self.pd_phy.receive(buffer).await.map_err(|err| match err {
-            ucpd::RxError::Crc | ucpd::RxError::Overrun => usbpd_traits::DriverRxError::Discarded,
+            ucpd::RxError::Crc => usbpd_traits::DriverRxError::Discarded,
+            ucpd::RxError::Overrun => usbpd_traits::DriverRxError::Fault(usbpd_traits::DriverFault::Overrun),
             ucpd::RxError::HardReset => usbpd_traits::DriverRxError::HardReset,
         })
     }
@@ -122,6 +122,14 @@ impl SinkTimer for EmbassySinkTimer {
     async fn after_millis(milliseconds: u64) {
         Timer::after_millis(milliseconds).await
     }
+
+    async fn after_micros(microseconds: u64) {
+        Timer::after_micros(microseconds).await
+    }
+
+    fn now_micros() -> Option<u64> {
+        Some(Instant::now().as_micros())
+    }
 }
 
 /// Capabilities that are tested (cycled through).
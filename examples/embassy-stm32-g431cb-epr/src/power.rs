@@ -3,7 +3,7 @@ use defmt::{Format, info, warn};
 use embassy_futures::select::{Either, select};
 use embassy_stm32::ucpd::{self, CcPhy, CcPull, CcSel, CcVState, PdPhy, Ucpd};
 use embassy_stm32::{Peri, bind_interrupts, dma, peripherals};
-use embassy_time::{Duration, Timer, with_timeout};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
 use uom::si::electric_current::{centiampere, milliampere};
 use uom::si::electric_potential::millivolt;
 use uom::si::power::{milliwatt, watt};
@@ -16,7 +16,10 @@ use usbpd::protocol_layer::message::data::request::{
 use usbpd::protocol_layer::message::data::source_capabilities::{Augmented, PowerDataObject, SourceCapabilities};
 use usbpd::sink::device_policy_manager::{DevicePolicyManager, Event};
 use usbpd::sink::policy_engine::Sink;
+use usbpd::sink::power_transition::CurrentRamp;
 use usbpd::timers::Timer as SinkTimer;
+#[cfg(feature = "avs")]
+use usbpd::units::ElectricPotential;
 use usbpd::units::Power;
 use usbpd_traits::Driver as SinkDriver;
 use {defmt_rtt as _, panic_probe as _};
@@ -165,13 +168,13 @@ impl<'d> UcpdSinkDriver<'d> {
 }
 
 impl SinkDriver for UcpdSinkDriver<'_> {
-    async fn wait_for_vbus(&mut self) {
-        // The sink policy engine is only running when attached. Therefore VBus is present.
-    }
+    // The sink policy engine is only running when attached. Therefore VBus is present.
+    const HAS_VBUS_DETECTION: bool = false;
 
     async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usbpd_traits::DriverRxError> {
         self.pd_phy.receive(buffer).await.map_err(|err| match err {
-            ucpd::RxError::Crc | ucpd::RxError::Overrun => usbpd_traits::DriverRxError::Discarded,
+            ucpd::RxError::Crc => usbpd_traits::DriverRxError::Discarded,
+            ucpd::RxError::Overrun => usbpd_traits::DriverRxError::Fault(usbpd_traits::DriverFault::Overrun),
             ucpd::RxError::HardReset => usbpd_traits::DriverRxError::HardReset,
         })
     }
@@ -235,6 +238,14 @@ impl SinkTimer for EmbassySinkTimer {
     async fn after_millis(milliseconds: u64) {
         Timer::after_millis(milliseconds).await
     }
+
+    async fn after_micros(microseconds: u64) {
+        Timer::after_micros(microseconds).await
+    }
+
+    fn now_micros() -> Option<u64> {
+        Some(Instant::now().as_micros())
+    }
 }
 
 #[derive(Default)]
@@ -249,7 +260,7 @@ impl DevicePolicyManager for Device {
         print_capabilities(source_capabilities);
     }
 
-    async fn get_event(&mut self, source_capabilities: &SourceCapabilities) -> Event {
+    fn poll_event(&mut self, source_capabilities: &SourceCapabilities) -> Option<Event> {
         // After initial SPR negotiation, enter EPR mode if source is EPR capable
         if !self.entered_epr_mode
             && let Some(PowerDataObject::FixedSupply(fixed)) = source_capabilities.pdos().first()
@@ -257,9 +268,9 @@ impl DevicePolicyManager for Device {
         {
             info!("Source is EPR capable, entering EPR mode");
             self.entered_epr_mode = true;
-            return Event::EnterEprMode(Power::new::<watt>(OPERATIONAL_PDP_WATTS));
+            return Some(Event::EnterEprMode(Power::new::<watt>(OPERATIONAL_PDP_WATTS)));
         }
-        core::future::pending().await
+        None
     }
 
     async fn request(&mut self, source_capabilities: &SourceCapabilities) -> PowerSource {
@@ -280,10 +291,6 @@ impl DevicePolicyManager for Device {
         if source_capabilities.is_epr_capabilities() {
             // Find 28V EPR PDO (EPR PDOs start at position 8)
             for (position, pdo) in source_capabilities.epr_pdos() {
-                if pdo.is_zero_padding() {
-                    continue;
-                }
-
                 // Fixed EPR mode (default)
                 #[cfg(not(feature = "avs"))]
                 if let PowerDataObject::FixedSupply(fixed) = pdo {
@@ -311,7 +318,7 @@ impl DevicePolicyManager for Device {
                         );
 
                         let rdo = FixedVariableSupply(0)
-                            .with_object_position(position)
+                            .with_object_position(position.get())
                             .with_usb_communications_capable(true)
                             .with_no_usb_suspend(true)
                             .with_epr_mode_capable(true)
@@ -348,10 +355,6 @@ impl DevicePolicyManager for Device {
                             TARGET_AVS_CURRENT_RAW
                         };
 
-                        // AVS voltage is in 25 mV units with LSB 2 bits = 0 (effective 100 mV steps)
-                        // Per USB PD 3.2 Table 6.26
-                        let voltage_raw = ((TARGET_AVS_VOLTAGE_V * 1000 / 25) & !0x3) as u16;
-
                         info!(
                             "Requesting {}V AVS at position {} with {}mA",
                             TARGET_AVS_VOLTAGE_V,
@@ -360,14 +363,18 @@ impl DevicePolicyManager for Device {
                         );
 
                         let rdo = Avs(0)
-                            .with_object_position(position)
+                            .with_object_position(position.get())
                             .with_usb_communications_capable(true)
                             .with_no_usb_suspend(true)
                             .with_epr_mode_capable(true)
-                            .with_raw_output_voltage(voltage_raw)
-                            .with_raw_operating_current(current);
+                            .with_raw_operating_current(current)
+                            .with_output_voltage(ElectricPotential::new::<millivolt>(target_mv))
+                            .and_then(|rdo| rdo.validate(avs).map(|_| rdo));
 
-                        return PowerSource::EprRequest(EprRequestDataObject { rdo: rdo.0, pdo: *pdo });
+                        match rdo {
+                            Ok(rdo) => return PowerSource::EprRequest(EprRequestDataObject { rdo: rdo.0, pdo: *pdo }),
+                            Err(error) => warn!("Invalid AVS request: {}", error),
+                        }
                     }
                 }
             }
@@ -404,7 +411,7 @@ impl DevicePolicyManager for Device {
         }
     }
 
-    async fn transition_power(&mut self, accepted: &PowerSource) {
+    async fn transition_power(&mut self, accepted: &PowerSource, _ramp: &CurrentRamp) {
         info!("Power transition accepted: PDO position {}", accepted.object_position());
     }
 }
@@ -0,0 +1,6 @@
+fn main() {
+    // Fail to link, if any symbol is left undefined. The panic handler references a symbol that does not exist, so
+    // that linking only succeeds, if no panic path remains.
+    println!("cargo:rustc-link-arg-cdylib=-Wl,-z,defs");
+    println!("cargo:rustc-link-lib=c");
+}
@@ -0,0 +1,91 @@
+//! Links message parsing, and the callback protocol layer without a way to panic.
+#![no_std]
+
+use usbpd::protocol_layer::callback::{CallbackProtocolLayer, Config, TimerId};
+use usbpd::protocol_layer::message::Message;
+use usbpd::protocol_layer::message::extended::ExtendedHeader;
+use usbpd::protocol_layer::message::header::{ControlMessageType, Header, SpecificationRevision};
+use usbpd::{DataRole, PowerRole};
+
+unsafe extern "C" {
+    /// Never defined, such that any remaining panic path fails the link.
+    fn usbpd_panic_path_linked() -> !;
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    unsafe { usbpd_panic_path_linked() }
+}
+
+/// Get the slice of `length` bytes at `data`.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `length` bytes.
+unsafe fn slice<'a>(data: *const u8, length: usize) -> &'a [u8] {
+    unsafe { core::slice::from_raw_parts(data, length) }
+}
+
+/// Parse a message from `length` bytes at `data`.
+///
+/// Returns the number of data objects, or -1, if parsing failed.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `length` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usbpd_parse(data: *const u8, length: usize) -> i32 {
+    let data = unsafe { slice(data, length) };
+
+    match Message::from_bytes(data) {
+        Ok(message) => message.header.num_objects() as i32,
+        Err(_) => -1,
+    }
+}
+
+/// Parse an extended message header from `length` bytes at `data`.
+///
+/// Returns the data size of the extended message.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `length` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usbpd_parse_extended_header(data: *const u8, length: usize) -> i32 {
+    let data = unsafe { slice(data, length) };
+
+    ExtendedHeader::from_bytes(data).data_size() as i32
+}
+
+/// Pass a frame of `length` bytes at `data` to a new callback protocol layer.
+///
+/// Then, transmit a control message of the type in the first byte, and pass the frame again, e.g. as its GoodCRC.
+/// Returns the number of resulting actions, or -1, if the transmission was refused.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `length` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usbpd_callback(data: *const u8, length: usize) -> i32 {
+    let data = unsafe { slice(data, length) };
+
+    let mut protocol_layer = CallbackProtocolLayer::new(
+        Config::default(),
+        Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X),
+    );
+    let mut actions = protocol_layer.on_rx_frame(data).len();
+
+    if let Some(&message_type) = data.first() {
+        let header = protocol_layer.new_control_header(ControlMessageType::from(message_type));
+        match protocol_layer.transmit(&Message::new(header)) {
+            Ok(transmitted) => actions += transmitted.len(),
+            Err(_) => return -1,
+        }
+
+        actions += protocol_layer.on_tx_complete().len();
+        actions += protocol_layer.on_rx_frame(data).len();
+        actions += protocol_layer.on_timer_expired(TimerId::CrcReceive).len();
+    }
+
+    actions as i32
+}
@@ -0,0 +1,58 @@
+//! Definitions of alert data message content.
+//!
+//! See [6.4.6].
+use proc_bitfield::bitfield;
+
+bitfield! {
+    /// The alert data object (ADO), the only data object of an Alert message.
+    ///
+    /// See [Table 6.45].
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct AlertDataObject(pub u32): Debug, FromStorage, IntoStorage {
+        /// An extended alert event occurred, see `extended_alert_event_type`.
+        pub extended_alert_event: bool @ 31,
+        /// An over-voltage protection event occurred.
+        pub ovp_event: bool @ 30,
+        /// The source input changed, e.g. from external power to battery.
+        pub source_input_change: bool @ 29,
+        /// The operating condition changed, e.g. the temperature.
+        pub operating_condition_change: bool @ 28,
+        /// An over-temperature protection event occurred.
+        pub otp_event: bool @ 27,
+        /// An over-current protection event occurred.
+        pub ocp_event: bool @ 26,
+        /// The status of a battery changed.
+        pub battery_status_change: bool @ 25,
+        /// Fixed batteries, whose status changed, as a bit field.
+        pub fixed_batteries: u8 @ 20..=23,
+        /// Hot-swappable batteries, whose status changed, as a bit field.
+        pub hot_swappable_batteries: u8 @ 16..=19,
+        /// The type of the extended alert event.
+        pub extended_alert_event_type: u8 @ 0..=3,
+    }
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for AlertDataObject {
+    fn default() -> Self {
+        Self(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AlertDataObject;
+
+    #[test]
+    fn test_alert() {
+        let alert = AlertDataObject::default().with_ocp_event(true);
+        assert_eq!(alert.0, 0x0400_0000);
+        assert!(!alert.otp_event());
+
+        let alert = AlertDataObject(0x0201_0000);
+        assert!(alert.battery_status_change());
+        assert_eq!(alert.hot_swappable_batteries(), 1);
+    }
+}
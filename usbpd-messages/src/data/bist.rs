@@ -0,0 +1,81 @@
+//! Definitions of built-in self-test (BIST) data message content.
+//!
+//! See [6.4.3].
+use proc_bitfield::bitfield;
+
+/// The test modes that a BIST message requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Transmit a continuous BMC-encoded carrier signal for tBISTContMode, see [6.4.3.1].
+    CarrierMode,
+    /// Receive test frames, which are not acknowledged, see [6.4.3.2].
+    TestData,
+    /// Enter the BIST shared capacity test mode, see [6.4.3.3].
+    SharedTestModeEntry,
+    /// Exit the BIST shared capacity test mode, see [6.4.3.4].
+    SharedTestModeExit,
+    /// A reserved, or unknown mode, with its raw value.
+    Unknown(u8),
+}
+
+impl From<Mode> for u8 {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::CarrierMode => 0b0101,
+            Mode::TestData => 0b1000,
+            Mode::SharedTestModeEntry => 0b1001,
+            Mode::SharedTestModeExit => 0b1010,
+            Mode::Unknown(value) => value,
+        }
+    }
+}
+
+impl From<u8> for Mode {
+    fn from(value: u8) -> Self {
+        match value {
+            0b0101 => Mode::CarrierMode,
+            0b1000 => Mode::TestData,
+            0b1001 => Mode::SharedTestModeEntry,
+            0b1010 => Mode::SharedTestModeExit,
+            _ => Mode::Unknown(value),
+        }
+    }
+}
+
+bitfield! {
+    /// The BIST data object, the first data object of a BIST message.
+    ///
+    /// See [Table 6.28].
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct BistDataObject(pub u32): Debug, FromStorage, IntoStorage {
+        /// The requested test mode.
+        pub mode: u8 [Mode] @ 28..=31,
+    }
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for BistDataObject {
+    fn default() -> Self {
+        Self(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BistDataObject, Mode};
+
+    #[test]
+    fn test_mode() {
+        let bist = BistDataObject(0x5000_0000);
+        assert_eq!(bist.mode(), Mode::CarrierMode);
+
+        assert_eq!(
+            BistDataObject::default().with_mode(Mode::SharedTestModeEntry).0,
+            0x9000_0000
+        );
+        assert_eq!(BistDataObject(0x3000_0000).mode(), Mode::Unknown(3));
+    }
+}
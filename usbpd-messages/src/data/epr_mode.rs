@@ -0,0 +1,244 @@
+//! Definitions of EPR mode data message content.
+//!
+//! See [6.4.10].
+use proc_bitfield::bitfield;
+
+/// Possible actions, encoded in the EPR mode data object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// Enter EPR mode.
+    Enter,
+    /// Entering EPR mode was acknowledged.
+    EnterAcknowledged,
+    /// Entering EPR mode succeeded.
+    EnterSucceeded,
+    /// Entering EPR mode failed.
+    EnterFailed,
+    /// Exit EPR mode.
+    Exit,
+    /// A reserved action, kept as its raw value.
+    Reserved(u8),
+}
+
+impl From<Action> for u8 {
+    fn from(value: Action) -> Self {
+        match value {
+            Action::Enter => 0x01,
+            Action::EnterAcknowledged => 0x02,
+            Action::EnterSucceeded => 0x03,
+            Action::EnterFailed => 0x04,
+            Action::Exit => 0x05,
+            Action::Reserved(value) => value,
+        }
+    }
+}
+
+impl From<u8> for Action {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => Action::Enter,
+            0x02 => Action::EnterAcknowledged,
+            0x03 => Action::EnterSucceeded,
+            0x04 => Action::EnterFailed,
+            0x05 => Action::Exit,
+            _ => Action::Reserved(value),
+        }
+    }
+}
+
+bitfield! {
+    /// The EPR mode data object that encodes an action, as well as corresponding payload data.
+    ///
+    /// See [Table 6.50].
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct EprModeDataObject(pub u32): Debug, FromStorage, IntoStorage {
+        /// Action to perform with regard to EPR mode (e.g. enter).
+        pub action: u8 [Action] @ 24..=31,
+        /// Payload data that is attached to an [`Self::action`]
+        pub data: u8 @ 16..=23,
+    }
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for EprModeDataObject {
+    fn default() -> Self {
+        Self(0)
+    }
+}
+
+impl EprModeDataObject {
+    /// The action, together with its typed data field.
+    pub fn mode(&self) -> EprMode {
+        match self.action() {
+            Action::Enter => EprMode::Enter {
+                operational_pdp: self.data(),
+            },
+            Action::EnterAcknowledged => EprMode::EnterAcknowledged,
+            Action::EnterSucceeded => EprMode::EnterSucceeded,
+            Action::EnterFailed => EprMode::EnterFailed(self.data().into()),
+            Action::Exit => EprMode::Exit,
+            Action::Reserved(action) => EprMode::Reserved {
+                action,
+                data: self.data(),
+            },
+        }
+    }
+}
+
+impl From<EprMode> for EprModeDataObject {
+    fn from(value: EprMode) -> Self {
+        Self::default().with_action(value.action()).with_data(value.data())
+    }
+}
+
+/// An action, together with the content of the data field that belongs to it.
+///
+/// The data field is reserved (zero) for actions that carry no data.
+///
+/// See [Table 6.50].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EprMode {
+    /// Enter EPR mode.
+    Enter {
+        /// The operational PDP of the sink in W.
+        operational_pdp: u8,
+    },
+    /// Entering EPR mode was acknowledged.
+    EnterAcknowledged,
+    /// Entering EPR mode succeeded.
+    EnterSucceeded,
+    /// Entering EPR mode failed, for the given cause.
+    EnterFailed(DataEnterFailed),
+    /// Exit EPR mode.
+    Exit,
+    /// A reserved action, with its raw data field.
+    Reserved {
+        /// The raw action.
+        action: u8,
+        /// The raw data field.
+        data: u8,
+    },
+}
+
+impl EprMode {
+    /// The action without its data.
+    pub fn action(&self) -> Action {
+        match self {
+            EprMode::Enter { .. } => Action::Enter,
+            EprMode::EnterAcknowledged => Action::EnterAcknowledged,
+            EprMode::EnterSucceeded => Action::EnterSucceeded,
+            EprMode::EnterFailed(_) => Action::EnterFailed,
+            EprMode::Exit => Action::Exit,
+            EprMode::Reserved { action, .. } => Action::Reserved(*action),
+        }
+    }
+
+    /// The raw data field.
+    pub fn data(&self) -> u8 {
+        match self {
+            EprMode::Enter { operational_pdp } => *operational_pdp,
+            EprMode::EnterFailed(cause) => (*cause).into(),
+            EprMode::Reserved { data, .. } => *data,
+            EprMode::EnterAcknowledged | EprMode::EnterSucceeded | EprMode::Exit => 0,
+        }
+    }
+}
+
+/// Causes for failing to enter EPR mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataEnterFailed {
+    /// Unknown cause.
+    UnknownCause,
+    /// The cable is not EPR capable.
+    CableNotEprCapable,
+    /// The source failed to become the Vconn source.
+    SourceFailedToBecomeVconnSource,
+    /// The "EPR capable" bit is not set in RDO.
+    EprCapableBitNotSetInRdo,
+    /// The source is unable to enter EPR mode.
+    ///
+    /// The sink may retry entering EPR mode after receiving this [`Action::EnterFailed`] response.
+    SourceUnableToEnterEprMode,
+    /// The "EPR capable" bit is not set in PDO.
+    EprCapableBitNotSetInPdo,
+    /// A reserved cause, kept as its raw value.
+    Reserved(u8),
+}
+
+impl From<DataEnterFailed> for u8 {
+    fn from(value: DataEnterFailed) -> Self {
+        match value {
+            DataEnterFailed::UnknownCause => 0x00,
+            DataEnterFailed::CableNotEprCapable => 0x01,
+            DataEnterFailed::SourceFailedToBecomeVconnSource => 0x02,
+            DataEnterFailed::EprCapableBitNotSetInRdo => 0x03,
+            DataEnterFailed::SourceUnableToEnterEprMode => 0x04,
+            DataEnterFailed::EprCapableBitNotSetInPdo => 0x05,
+            DataEnterFailed::Reserved(value) => value,
+        }
+    }
+}
+
+impl From<u8> for DataEnterFailed {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => DataEnterFailed::UnknownCause,
+            0x01 => DataEnterFailed::CableNotEprCapable,
+            0x02 => DataEnterFailed::SourceFailedToBecomeVconnSource,
+            0x03 => DataEnterFailed::EprCapableBitNotSetInRdo,
+            0x04 => DataEnterFailed::SourceUnableToEnterEprMode,
+            0x05 => DataEnterFailed::EprCapableBitNotSetInPdo,
+            _ => DataEnterFailed::Reserved(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_round_trip() {
+        let modes = [
+            EprMode::Enter { operational_pdp: 140 },
+            EprMode::EnterAcknowledged,
+            EprMode::EnterSucceeded,
+            EprMode::EnterFailed(DataEnterFailed::UnknownCause),
+            EprMode::EnterFailed(DataEnterFailed::CableNotEprCapable),
+            EprMode::EnterFailed(DataEnterFailed::EprCapableBitNotSetInPdo),
+            EprMode::EnterFailed(DataEnterFailed::Reserved(0x42)),
+            EprMode::Exit,
+            EprMode::Reserved {
+                action: 0x06,
+                data: 0x12,
+            },
+        ];
+
+        for mode in modes {
+            let mdo = EprModeDataObject::from(mode);
+            assert_eq!(mdo.action(), mode.action());
+            assert_eq!(EprModeDataObject(mdo.0).mode(), mode);
+        }
+    }
+
+    #[test]
+    fn test_mode_from_raw() {
+        // Enter, with 140 W operational PDP.
+        assert_eq!(
+            EprModeDataObject(0x018C_0000).mode(),
+            EprMode::Enter { operational_pdp: 140 }
+        );
+        // EnterFailed, because the cable is not EPR capable.
+        assert_eq!(
+            EprModeDataObject(0x0401_0000).mode(),
+            EprMode::EnterFailed(DataEnterFailed::CableNotEprCapable)
+        );
+        // Reserved data is ignored for actions without data.
+        assert_eq!(EprModeDataObject(0x05FF_0000).mode(), EprMode::Exit);
+    }
+}
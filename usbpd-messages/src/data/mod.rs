@@ -6,18 +6,36 @@ use core::mem::size_of;
 use byteorder::{ByteOrder, LittleEndian};
 use heapless::Vec;
 
-use crate::protocol_layer::message::Payload;
-use crate::protocol_layer::message::header::DataMessageType;
+use crate::Payload;
+use crate::header::DataMessageType;
 
 /// Size of a Power Data Object in bytes.
 const PDO_SIZE: usize = size_of::<u32>();
 
+/// Implement access to the on-wire 32-bit value of data objects.
+macro_rules! impl_raw {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $ty {
+                /// The raw 32-bit value, as transmitted on the wire.
+                pub const fn raw(&self) -> u32 {
+                    self.0
+                }
+            }
+        )+
+    };
+}
+
 // FIXME: add documentation
 #[allow(missing_docs)]
 pub mod source_capabilities;
 
 pub mod sink_capabilities;
 
+pub mod alert;
+
+pub mod bist;
+
 pub mod epr_mode;
 
 // FIXME: add documentation
@@ -28,14 +46,81 @@ pub mod vendor_defined;
 #[allow(missing_docs)]
 pub mod request;
 
+/// The position of a PDO in source capabilities, as referenced by requests.
+///
+/// Positions count from one, and are valid in the range 1..=14. SPR (A)PDOs are at positions 1..=7, EPR (A)PDOs
+/// start at position 8. See [6.4.2] and [6.5.15.1].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectPosition(u8);
+
+impl ObjectPosition {
+    /// The position of the vSafe5V fixed supply PDO, which always comes first.
+    pub const VSAFE_5V: Self = Self(1);
+
+    /// The first position of EPR (A)PDOs.
+    pub const FIRST_EPR: Self = Self(8);
+
+    /// The highest valid position.
+    pub const MAX: Self = Self(14);
+
+    /// Create an object position, if `position` is in the valid range 1..=14.
+    pub const fn new(position: u8) -> Option<Self> {
+        match position {
+            1..=14 => Some(Self(position)),
+            _ => None,
+        }
+    }
+
+    /// Create an object position from a zero-based index into a PDO table.
+    pub const fn from_index(index: usize) -> Option<Self> {
+        if index < Self::MAX.0 as usize {
+            Some(Self(index as u8 + 1))
+        } else {
+            None
+        }
+    }
+
+    /// The position, as encoded in the request data object.
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// The zero-based index into a PDO table.
+    pub const fn index(self) -> usize {
+        self.0 as usize - 1
+    }
+
+    /// Whether the position is reserved for EPR (A)PDOs.
+    pub const fn is_epr(self) -> bool {
+        self.0 >= Self::FIRST_EPR.0
+    }
+}
+
+impl TryFrom<u8> for ObjectPosition {
+    type Error = u8;
+
+    /// Returns the invalid position as error.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::new(value).ok_or(value)
+    }
+}
+
+impl From<ObjectPosition> for u8 {
+    fn from(value: ObjectPosition) -> Self {
+        value.0
+    }
+}
+
 /// Determine the kind of PDO.
 pub trait PdoKind {
     /// Determine the kind of PDO at a given object position.
-    fn at_object_position(&self, position: u8) -> Option<source_capabilities::Kind>;
+    fn at_object_position(&self, position: ObjectPosition) -> Option<source_capabilities::Kind>;
 }
 
 impl PdoKind for () {
-    fn at_object_position(&self, _position: u8) -> Option<source_capabilities::Kind> {
+    fn at_object_position(&self, _position: ObjectPosition) -> Option<source_capabilities::Kind> {
         None
     }
 }
@@ -55,12 +140,15 @@ pub enum Data {
     SinkCapabilities(sink_capabilities::SinkCapabilities),
     /// Request for a power level from the source.
     Request(request::PowerSource),
+    /// Request a built-in self-test mode.
+    Bist(bist::BistDataObject),
+    /// Inform the port partner about a status change, or a fault.
+    Alert(alert::AlertDataObject),
     /// Used to enter, acknowledge or exit EPR mode.
     EprMode(epr_mode::EprModeDataObject),
     /// Vendor defined messages (VDM).
     ///
-    /// Currently parsed from the wire but not forwarded to user applications.
-    /// TODO: Add DevicePolicyManager callback to allow applications to handle vendor-specific messages.
+    /// Forwarded to the device policy manager, see `usbpd::vdm` for alternate mode handling.
     VendorDefined((vendor_defined::VdmHeader, Vec<u32, 7>)),
     /// Unknown data type.
     Unknown,
@@ -68,6 +156,7 @@ pub enum Data {
 
 impl Data {
     /// Parse a data message.
+    #[allow(clippy::chunks_exact_to_as_chunks)]
     pub fn parse_message<P: PdoKind>(
         mut message: super::Message,
         message_type: DataMessageType,
@@ -88,20 +177,7 @@ impl Data {
                     Data::Unknown
                 } else {
                     let raw = request::RawDataObject(LittleEndian::read_u32(payload));
-                    if let Some(t) = state.at_object_position(raw.object_position()) {
-                        Data::Request(match t {
-                            source_capabilities::Kind::FixedSupply | source_capabilities::Kind::VariableSupply => {
-                                request::PowerSource::FixedVariableSupply(request::FixedVariableSupply(raw.0))
-                            }
-                            source_capabilities::Kind::Battery => {
-                                request::PowerSource::Battery(request::Battery(raw.0))
-                            }
-                            source_capabilities::Kind::Pps => request::PowerSource::Pps(request::Pps(raw.0)),
-                            source_capabilities::Kind::Avs => request::PowerSource::Avs(request::Avs(raw.0)),
-                        })
-                    } else {
-                        Data::Request(request::PowerSource::Unknown(raw))
-                    }
+                    Data::Request(request::PowerSource::from_raw(raw, state))
                 }
             }
             DataMessageType::EprRequest => {
@@ -125,6 +201,21 @@ impl Data {
                     Data::Unknown
                 }
             }
+            DataMessageType::Bist => {
+                // The BIST data object may be followed by test data, which is ignored.
+                if len < PDO_SIZE {
+                    Data::Unknown
+                } else {
+                    Data::Bist(bist::BistDataObject(LittleEndian::read_u32(payload)))
+                }
+            }
+            DataMessageType::Alert => {
+                if len != PDO_SIZE {
+                    Data::Unknown
+                } else {
+                    Data::Alert(alert::AlertDataObject(LittleEndian::read_u32(payload)))
+                }
+            }
             DataMessageType::EprMode => {
                 if len != PDO_SIZE {
                     Data::Unknown
@@ -186,7 +277,7 @@ impl Data {
     pub fn to_bytes(&self, payload: &mut [u8]) -> usize {
         match self {
             Self::Unknown => 0,
-            Self::SourceCapabilities(_) => unimplemented!(),
+            Self::SourceCapabilities(caps) => caps.to_bytes(payload),
             Self::SinkCapabilities(caps) => caps.to_bytes(payload),
             Self::Request(request::PowerSource::FixedVariableSupply(data_object)) => data_object.to_bytes(payload),
             Self::Request(request::PowerSource::Pps(data_object)) => data_object.to_bytes(payload),
@@ -195,26 +286,85 @@ impl Data {
                 // Write RDO (raw u32)
                 LittleEndian::write_u32(payload, epr.rdo);
                 // Write PDO copy as raw u32
-                let raw_pdo = match &epr.pdo {
-                    source_capabilities::PowerDataObject::FixedSupply(p) => p.0,
-                    source_capabilities::PowerDataObject::Battery(p) => p.0,
-                    source_capabilities::PowerDataObject::VariableSupply(p) => p.0,
-                    source_capabilities::PowerDataObject::Augmented(a) => match a {
-                        source_capabilities::Augmented::Spr(p) => p.0,
-                        source_capabilities::Augmented::Epr(p) => p.0,
-                        source_capabilities::Augmented::Unknown(p) => *p,
-                    },
-                    source_capabilities::PowerDataObject::Unknown(p) => p.0,
-                };
-                LittleEndian::write_u32(&mut payload[PDO_SIZE..], raw_pdo);
+                LittleEndian::write_u32(&mut payload[PDO_SIZE..], epr.pdo.raw());
                 2 * PDO_SIZE
             }
-            Self::Request(_) => unimplemented!(),
-            Self::EprMode(epr_mode::EprModeDataObject(data_object)) => {
+            Self::Request(request::PowerSource::Battery(data_object)) => data_object.to_bytes(payload),
+            Self::Request(request::PowerSource::Unknown(data_object)) => {
+                LittleEndian::write_u32(payload, data_object.0);
+                PDO_SIZE
+            }
+            Self::Bist(bist::BistDataObject(data_object))
+            | Self::Alert(alert::AlertDataObject(data_object))
+            | Self::EprMode(epr_mode::EprModeDataObject(data_object)) => {
                 LittleEndian::write_u32(payload, *data_object);
                 PDO_SIZE
             }
-            Self::VendorDefined(_) => unimplemented!(),
+            Self::VendorDefined((header, vdos)) => {
+                LittleEndian::write_u32(payload, (*header).into());
+                for (chunk, vdo) in payload.as_chunks_mut::<PDO_SIZE>().0.iter_mut().skip(1).zip(vdos) {
+                    *chunk = vdo.to_le_bytes();
+                }
+                (vdos.len() + 1) * PDO_SIZE
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::source_capabilities::{FixedSupply, PowerDataObject, SourceCapabilities};
+    use super::{ObjectPosition, PdoKind};
+
+    #[test]
+    fn test_object_position() {
+        assert_eq!(ObjectPosition::new(0), None);
+        assert_eq!(ObjectPosition::new(15), None);
+        assert_eq!(ObjectPosition::try_from(14), Ok(ObjectPosition::MAX));
+        assert_eq!(ObjectPosition::from_index(0), Some(ObjectPosition::VSAFE_5V));
+        assert_eq!(ObjectPosition::from_index(14), None);
+
+        let position = ObjectPosition::from_index(7).unwrap();
+        assert_eq!(position, ObjectPosition::FIRST_EPR);
+        assert_eq!(position.index(), 7);
+        assert!(position.is_epr());
+        assert!(!ObjectPosition::new(7).unwrap().is_epr());
+    }
+
+    #[test]
+    fn test_positioned_pdos() {
+        // Two SPR PDOs, zero-padding, and one EPR PDO at position 8.
+        let fixed = PowerDataObject::FixedSupply(FixedSupply::default().with_raw_voltage(100));
+        let padding = PowerDataObject::from(0);
+        let mut pdos = heapless::Vec::from_array([fixed, fixed, padding, padding, padding, padding, padding, fixed]);
+        // Positions beyond 14 are not addressable.
+        pdos.resize(16, fixed).unwrap();
+        let capabilities = SourceCapabilities(pdos);
+
+        assert_eq!(capabilities.positioned_pdos().count(), 14);
+        assert_eq!(
+            capabilities.spr_pdos().map(|(position, _)| position.get()).max(),
+            Some(2)
+        );
+        assert_eq!(
+            capabilities.epr_pdos().map(|(position, _)| position).next(),
+            Some(ObjectPosition::FIRST_EPR)
+        );
+        assert_eq!(capabilities.epr_pdos().count(), 7);
+        assert_eq!(capabilities.pdo_at(ObjectPosition::new(3).unwrap()), Some(&padding));
+
+        // Padding is not offered.
+        assert_eq!(capabilities.offered_pdos().count(), 9);
+        assert!(capabilities.offered_pdos().all(|(_, pdo)| !pdo.is_zero_padding()));
+        assert_eq!(capabilities.offered_pdo_at(ObjectPosition::new(3).unwrap()), None);
+        assert_eq!(
+            capabilities.offered_pdo_at(ObjectPosition::new(2).unwrap()),
+            Some(&fixed)
+        );
+        assert!(
+            capabilities
+                .at_object_position(ObjectPosition::new(3).unwrap())
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,1199 @@
+//! Definitions of request data message content.
+use byteorder::{ByteOrder, LittleEndian};
+use proc_bitfield::bitfield;
+use uom::si::electric_current::{self, centiampere, milliampere};
+use uom::si::electric_potential::millivolt;
+use uom::si::power::milliwatt;
+use uom::si::{self};
+
+use super::{ObjectPosition, PdoKind, source_capabilities};
+use crate::_20millivolts_mod::_20millivolts;
+use crate::_25millivolts_mod::_25millivolts;
+use crate::_50milliamperes_mod::_50milliamperes;
+use crate::_250milliwatts_mod::_250milliwatts;
+use crate::units::{ElectricCurrent, ElectricPotential};
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct RawDataObject(pub u32): Debug, FromStorage, IntoStorage {
+        /// Valid range 1..=14
+        pub object_position: u8 @ 28..=31,
+        /// Capability mismatch, common to all RDO types.
+        pub capability_mismatch: bool @ 26,
+        /// USB communications capable, common to all RDO types.
+        pub usb_communications_capable: bool @ 25,
+        /// No USB Suspend, common to all RDO types.
+        pub no_usb_suspend: bool @ 24,
+        /// Unchunked extended messages supported, common to all RDO types.
+        pub unchunked_extended_messages_supported: bool @ 23,
+        /// EPR mode capable, common to all RDO types.
+        pub epr_mode_capable: bool @ 22,
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct FixedVariableSupply(pub u32): Debug, FromStorage, IntoStorage {
+        /// Valid range 1..=14
+        pub object_position: u8 @ 28..=31,
+        pub giveback_flag: bool @ 27,
+        pub capability_mismatch: bool @ 26,
+        pub usb_communications_capable: bool @ 25,
+        pub no_usb_suspend: bool @ 24,
+        /// Unchunked extended messages supported.
+        /// WARNING: Do not set to true - the library always uses chunked mode
+        /// for compatibility with more PHYs.
+        pub unchunked_extended_messages_supported: bool @ 23,
+        pub epr_mode_capable: bool @ 22,
+        pub raw_operating_current: u16 @ 10..=19,
+        pub raw_max_operating_current: u16 @ 0..=9,
+    }
+}
+
+impl FixedVariableSupply {
+    /// Create a new request for the fixed or variable supply at `object_position`, with default attributes.
+    pub const fn new(
+        object_position: ObjectPosition,
+        operating_current_10ma: u16,
+        max_operating_current_10ma: u16,
+    ) -> Self {
+        Self(
+            rdo_common(object_position)
+                | ((operating_current_10ma as u32 & 0x3ff) << 10)
+                | (max_operating_current_10ma as u32 & 0x3ff),
+        )
+    }
+
+    pub fn to_bytes(self, buf: &mut [u8]) -> usize {
+        LittleEndian::write_u32(buf, self.0);
+        4
+    }
+
+    pub fn operating_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<centiampere>(self.raw_operating_current().into())
+    }
+
+    pub fn max_operating_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<centiampere>(self.raw_max_operating_current().into())
+    }
+}
+
+bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Battery(pub u32): Debug, FromStorage, IntoStorage {
+        /// Object position (0000b and 1110b…1111b are Reserved and Shall Not be used)
+        pub object_position: u8 @ 28..=31,
+        /// GiveBackFlag = 0
+        pub giveback_flag: bool @ 27,
+        /// Capability mismatch
+        pub capability_mismatch: bool @ 26,
+        /// USB communications capable
+        pub usb_communications_capable: bool @ 25,
+        /// No USB Suspend
+        pub no_usb_suspend: bool @ 24,
+        /// Unchunked extended messages supported.
+        /// WARNING: Do not set to true - the library always uses chunked mode
+        /// for compatibility with more PHYs.
+        pub unchunked_extended_messages_supported: bool @ 23,
+        /// EPR mode capable
+        pub epr_mode_capable: bool @ 22,
+        /// Operating power in 250 mW units
+        pub raw_operating_power: u16 @ 10..=19,
+        /// Maximum operating power in 250 mW units
+        pub raw_max_operating_power: u16 @ 0..=9,
+    }
+}
+
+impl Battery {
+    /// Create a new request for the battery supply at `object_position`, with default attributes.
+    pub const fn new(
+        object_position: ObjectPosition,
+        operating_power_250mw: u16,
+        max_operating_power_250mw: u16,
+    ) -> Self {
+        Self(
+            rdo_common(object_position)
+                | ((operating_power_250mw as u32 & 0x3ff) << 10)
+                | (max_operating_power_250mw as u32 & 0x3ff),
+        )
+    }
+
+    pub fn to_bytes(self, buf: &mut [u8]) -> usize {
+        LittleEndian::write_u32(buf, self.0);
+        4
+    }
+
+    pub fn operating_power(&self) -> si::u32::Power {
+        si::u32::Power::new::<_250milliwatts>(self.raw_operating_power().into())
+    }
+
+    pub fn max_operating_power(&self) -> si::u32::Power {
+        si::u32::Power::new::<_250milliwatts>(self.raw_max_operating_power().into())
+    }
+}
+
+bitfield!(
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Pps(pub u32): Debug, FromStorage, IntoStorage {
+        /// Object position (0000b and 1110b…1111b are Reserved and Shall Not be used)
+        pub object_position: u8 @ 28..=31,
+        /// Capability mismatch
+        pub capability_mismatch: bool @ 26,
+        /// USB communications capable
+        pub usb_communications_capable: bool @ 25,
+        /// No USB Suspend
+        pub no_usb_suspend: bool @ 24,
+        /// Unchunked extended messages supported.
+        /// WARNING: Do not set to true - the library always uses chunked mode
+        /// for compatibility with more PHYs.
+        pub unchunked_extended_messages_supported: bool @ 23,
+        /// EPR mode capable
+        pub epr_mode_capable: bool @ 22,
+        /// Output voltage in 20 mV units
+        pub raw_output_voltage: u16 @ 9..=20,
+        /// Operating current in 50 mA units
+        pub raw_operating_current: u16 @ 0..=6,
+    }
+);
+
+impl Pps {
+    /// Create a new request for the PPS APDO at `object_position`, with default attributes.
+    pub const fn new(object_position: ObjectPosition, output_voltage_20mv: u16, operating_current_50ma: u16) -> Self {
+        Self(
+            rdo_common(object_position)
+                | ((output_voltage_20mv as u32 & 0xfff) << 9)
+                | (operating_current_50ma as u32 & 0x7f),
+        )
+    }
+
+    pub fn to_bytes(self, buf: &mut [u8]) -> usize {
+        LittleEndian::write_u32(buf, self.0);
+        4
+    }
+
+    pub fn output_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_20millivolts>(self.raw_output_voltage().into())
+    }
+
+    pub fn operating_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<_50milliamperes>(self.raw_operating_current().into())
+    }
+}
+
+bitfield!(
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Avs(pub u32): Debug, FromStorage, IntoStorage {
+        /// Object position (0000b and 1110b…1111b are Reserved and Shall Not be used)
+        pub object_position: u8 @ 28..=31,
+        /// Capability mismatch
+        pub capability_mismatch: bool @ 26,
+        /// USB communications capable
+        pub usb_communications_capable: bool @ 25,
+        /// No USB Suspend
+        pub no_usb_suspend: bool @ 24,
+        /// Unchunked extended messages supported.
+        /// WARNING: Do not set to true - the library always uses chunked mode
+        /// for compatibility with more PHYs.
+        pub unchunked_extended_messages_supported: bool @ 23,
+        /// EPR mode capable
+        pub epr_mode_capable: bool @ 22,
+        /// Output voltage in 25 mV units (per USB PD 3.2 Table 6.26).
+        /// The least two significant bits Shall be set to zero, making
+        /// the effective voltage step size 100 mV.
+        pub raw_output_voltage: u16 @ 9..=20,
+        /// Operating current in 50 mA units
+        pub raw_operating_current: u16 @ 0..=6,
+    }
+);
+
+impl Avs {
+    /// Create a new request for the EPR AVS APDO at `object_position`, with default attributes.
+    ///
+    /// The two least significant bits of the output voltage are cleared, making for 100 mV steps.
+    pub const fn new(object_position: ObjectPosition, output_voltage_25mv: u16, operating_current_50ma: u16) -> Self {
+        Self(
+            rdo_common(object_position)
+                | ((output_voltage_25mv as u32 & 0xffc) << 9)
+                | (operating_current_50ma as u32 & 0x7f),
+        )
+    }
+
+    pub fn to_bytes(self, buf: &mut [u8]) -> usize {
+        LittleEndian::write_u32(buf, self.0);
+        4
+    }
+
+    pub fn output_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_25millivolts>(self.raw_output_voltage().into())
+    }
+
+    pub fn operating_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<_50milliamperes>(self.raw_operating_current().into())
+    }
+
+    /// Set the output voltage, which must be at least 15 V, in 100 mV steps.
+    pub fn with_output_voltage(self, voltage: ElectricPotential) -> Result<Self, AvsError> {
+        Ok(self.with_raw_output_voltage(avs_raw_output_voltage(voltage)?))
+    }
+
+    /// Check the request against the EPR AVS APDO that it refers to.
+    ///
+    /// The output voltage must be within the range of the APDO, and the operating current must not exceed the PDP
+    /// of the APDO at that voltage.
+    pub fn validate(&self, apdo: &source_capabilities::EprAdjustableVoltageSupply) -> Result<(), AvsError> {
+        let voltage = self.output_voltage();
+        avs_raw_output_voltage(voltage)?;
+
+        if !(apdo.min_voltage()..=apdo.max_voltage()).contains(&voltage) {
+            return Err(AvsError::OutOfRange);
+        }
+
+        let power = u64::from(self.operating_current().get::<milliampere>()) * u64::from(voltage.get::<millivolt>());
+        if power > u64::from(apdo.pd_power().get::<milliwatt>()) * 1000 {
+            return Err(AvsError::CurrentExceedsPdp);
+        }
+
+        Ok(())
+    }
+}
+
+/// A PPS operating point, clipped to the range of an APDO by [`clip_pps`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClippedPps {
+    /// The output voltage, within the voltage range of the APDO.
+    pub voltage: ElectricPotential,
+    /// The operating current, at most the maximum current of the APDO.
+    pub current: ElectricCurrent,
+    /// Whether the requested voltage was outside of the voltage range.
+    pub voltage_clipped: bool,
+    /// Whether the requested current exceeded the maximum current.
+    pub current_clipped: bool,
+}
+
+impl ClippedPps {
+    /// Whether the requested voltage or current was clipped, i.e. the supply's limits were reached.
+    pub fn is_clipped(&self) -> bool {
+        self.voltage_clipped || self.current_clipped
+    }
+}
+
+/// Clip a requested PPS output voltage and operating current to the range that an SPR PPS APDO advertises.
+pub fn clip_pps(
+    apdo: &source_capabilities::SprProgrammablePowerSupply,
+    voltage: ElectricPotential,
+    current: ElectricCurrent,
+) -> ClippedPps {
+    let clipped_voltage = voltage.clamp(apdo.min_voltage(), apdo.max_voltage());
+    let clipped_current = Ord::min(current, apdo.max_current());
+
+    ClippedPps {
+        voltage: clipped_voltage,
+        current: clipped_current,
+        voltage_clipped: clipped_voltage != voltage,
+        current_clipped: clipped_current != current,
+    }
+}
+
+/// The minimum output voltage of an EPR AVS request in mV.
+pub const AVS_MIN_VOLTAGE_MILLIVOLTS: u32 = 15_000;
+
+/// The programming step of the EPR AVS output voltage in mV.
+///
+/// The voltage field has a 25 mV unit, but its two least significant bits shall be zero, see [Table 6.26].
+pub const AVS_VOLTAGE_STEP_MILLIVOLTS: u32 = 100;
+
+/// Errors of invalid EPR AVS requests.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AvsError {
+    /// The output voltage is below 15 V.
+    #[error("AVS voltage below 15 V")]
+    BelowMinimum,
+    /// The output voltage is not a multiple of 100 mV.
+    #[error("AVS voltage not in 100 mV steps")]
+    InvalidStep,
+    /// The output voltage is outside of the range of the APDO.
+    #[error("AVS voltage outside of the APDO range")]
+    OutOfRange,
+    /// The operating current exceeds the PDP of the APDO at the output voltage.
+    #[error("AVS current exceeds the PDP")]
+    CurrentExceedsPdp,
+}
+
+/// The raw output voltage field of an EPR AVS request, in 25 mV units.
+pub fn avs_raw_output_voltage(voltage: ElectricPotential) -> Result<u16, AvsError> {
+    let millivolts = voltage.get::<millivolt>();
+
+    if millivolts < AVS_MIN_VOLTAGE_MILLIVOLTS {
+        Err(AvsError::BelowMinimum)
+    } else if millivolts % AVS_VOLTAGE_STEP_MILLIVOLTS != 0 {
+        Err(AvsError::InvalidStep)
+    } else {
+        // In range, the field holds 12 bits, up to 102.375 V.
+        u16::try_from(voltage.get::<_25millivolts>())
+            .ok()
+            .filter(|raw| *raw <= 0xfff)
+            .ok_or(AvsError::OutOfRange)
+    }
+}
+
+/// EPR Request containing RDO + copy of requested PDO for source verification.
+///
+/// Per USB PD 3.x Section 6.4.9, EPR_Request always has 2 data objects:
+/// - The Request Data Object (format depends on PDO type being requested)
+/// - Copy of the PDO being requested (for source verification)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EprRequestDataObject {
+    /// The raw Request Data Object (format depends on PDO type being requested).
+    /// This could be a FixedVariableSupply RDO, Avs RDO, or other EPR RDO type.
+    pub rdo: u32,
+    /// Copy of the PDO being requested (for source verification)
+    pub pdo: source_capabilities::PowerDataObject,
+}
+
+impl EprRequestDataObject {
+    /// Create a new EPR request for the PDO at `object_position`.
+    ///
+    /// The `rdo` holds the request parameters and must match the type of `pdo` (e.g. [`FixedVariableSupply`] for
+    /// fixed supplies, [`Avs`] for EPR AVS). Its object position is overwritten with `object_position`. The PDO is
+    /// copied exactly as received, so that the source can verify it.
+    pub fn new(
+        object_position: ObjectPosition,
+        pdo: &source_capabilities::PowerDataObject,
+        rdo: impl Into<u32>,
+    ) -> Self {
+        Self {
+            rdo: RawDataObject(rdo.into()).with_object_position(object_position.get()).0,
+            pdo: *pdo,
+        }
+    }
+
+    /// Get the object position from the RDO
+    pub fn object_position(&self) -> u8 {
+        RawDataObject(self.rdo).object_position()
+    }
+
+    /// The raw 32-bit values of RDO and PDO copy, as transmitted on the wire.
+    pub fn raw(&self) -> [u32; 2] {
+        [self.rdo, self.pdo.raw()]
+    }
+}
+
+impl_raw!(RawDataObject, FixedVariableSupply, Battery, Pps, Avs);
+
+/// Power requests towards the source.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(unused)]
+pub enum PowerSource {
+    FixedVariableSupply(FixedVariableSupply),
+    Battery(Battery),
+    Pps(Pps),
+    Avs(Avs),
+    /// EPR Request: RDO + copy of requested PDO for source verification.
+    EprRequest(EprRequestDataObject),
+    Unknown(RawDataObject),
+}
+
+/// USB related attributes of a request, that the device policy manager decides on.
+///
+/// USB data capable devices must report these truthfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestAttributes {
+    /// The sink must not be suspended, when the USB host suspends it.
+    pub no_usb_suspend: bool,
+    /// The sink has USB data lines and is capable of communication.
+    pub usb_communications_capable: bool,
+}
+
+impl RequestAttributes {
+    /// The attributes that requests carry, unless the device policy manager decides otherwise.
+    pub const DEFAULT: Self = Self {
+        no_usb_suspend: true,
+        usb_communications_capable: true,
+    };
+}
+
+impl Default for RequestAttributes {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The bits that all RDO types share: the object position, and the default [`RequestAttributes`].
+const fn rdo_common(object_position: ObjectPosition) -> u32 {
+    let attributes = RequestAttributes::DEFAULT;
+
+    ((object_position.get() as u32) << 28)
+        | ((attributes.usb_communications_capable as u32) << 25)
+        | ((attributes.no_usb_suspend as u32) << 24)
+}
+
+/// Errors that can occur during sink requests towards the source.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A requested (specific) voltage does not exist in the PDOs.
+    #[error("voltage mismatch")]
+    VoltageMismatch,
+    /// The requested operating current exceeds the requested maximum operating current.
+    #[error("current mismatch")]
+    CurrentMismatch,
+}
+
+/// Requestable voltage levels.
+#[derive(Debug)]
+pub enum VoltageRequest {
+    /// The safe 5 V supply.
+    Safe5V,
+    /// The highest voltage that the source can supply.
+    Highest,
+    /// A specific voltage.
+    Specific(ElectricPotential),
+}
+
+/// Requestable currents.
+#[derive(Debug, Clone, Copy)]
+pub enum CurrentRequest {
+    /// The highest current that the source can supply.
+    Highest,
+    /// A specific current.
+    Specific(ElectricCurrent),
+}
+
+impl CurrentRequest {
+    /// Resolve the request against the maximum current that a PDO offers.
+    fn resolve(self, pdo_max_current: ElectricCurrent) -> ElectricCurrent {
+        match self {
+            CurrentRequest::Highest => pdo_max_current,
+            CurrentRequest::Specific(x) => x,
+        }
+    }
+}
+
+/// Resolve operating and maximum operating current requests against the maximum current that a PDO offers.
+///
+/// Reports both currents, and whether the sink's needs exceed the PDO (capability mismatch).
+fn resolve_currents(
+    current_request: CurrentRequest,
+    max_current_request: CurrentRequest,
+    pdo_max_current: ElectricCurrent,
+) -> Result<(ElectricCurrent, ElectricCurrent, bool), Error> {
+    let current = current_request.resolve(pdo_max_current);
+    let max_current = max_current_request.resolve(pdo_max_current);
+
+    if current > max_current {
+        return Err(Error::CurrentMismatch);
+    }
+
+    Ok((current, max_current, max_current > pdo_max_current))
+}
+
+/// A fixed supply PDO, alongside its object position.
+pub struct IndexedFixedSupply<'d>(pub &'d source_capabilities::FixedSupply, ObjectPosition);
+
+/// An augmented PDO, alongside its object position.
+pub struct IndexedAugmented<'d>(pub &'d source_capabilities::Augmented, ObjectPosition);
+
+impl PowerSource {
+    /// Interpret a raw request, based on the kind of the requested PDO.
+    ///
+    /// Requests for unknown object positions remain [`PowerSource::Unknown`].
+    pub fn from_raw(raw: RawDataObject, pdo_kind: &impl PdoKind) -> Self {
+        match ObjectPosition::new(raw.object_position()).and_then(|position| pdo_kind.at_object_position(position)) {
+            Some(source_capabilities::Kind::FixedSupply | source_capabilities::Kind::VariableSupply) => {
+                PowerSource::FixedVariableSupply(FixedVariableSupply(raw.0))
+            }
+            Some(source_capabilities::Kind::Battery) => PowerSource::Battery(Battery(raw.0)),
+            Some(source_capabilities::Kind::Pps) => PowerSource::Pps(Pps(raw.0)),
+            Some(source_capabilities::Kind::Avs) => PowerSource::Avs(Avs(raw.0)),
+            None => PowerSource::Unknown(raw),
+        }
+    }
+
+    pub fn object_position(&self) -> u8 {
+        match self {
+            PowerSource::FixedVariableSupply(p) => p.object_position(),
+            PowerSource::Battery(p) => p.object_position(),
+            PowerSource::Pps(p) => p.object_position(),
+            PowerSource::Avs(p) => p.object_position(),
+            PowerSource::EprRequest(epr) => epr.object_position(),
+            PowerSource::Unknown(p) => p.object_position(),
+        }
+    }
+
+    /// The raw 32-bit value of the request data object, as transmitted on the wire.
+    ///
+    /// For EPR requests, this is the RDO without the PDO copy.
+    pub fn raw(&self) -> u32 {
+        match self {
+            PowerSource::FixedVariableSupply(p) => p.0,
+            PowerSource::Battery(p) => p.0,
+            PowerSource::Pps(p) => p.0,
+            PowerSource::Avs(p) => p.0,
+            PowerSource::EprRequest(epr) => epr.rdo,
+            PowerSource::Unknown(p) => p.0,
+        }
+    }
+
+    /// Modify the flags that all RDO types share (bits 22..=26).
+    ///
+    /// Requests of unknown type are left untouched.
+    fn map_common_flags(self, f: impl FnOnce(RawDataObject) -> RawDataObject) -> Self {
+        match self {
+            PowerSource::FixedVariableSupply(p) => {
+                PowerSource::FixedVariableSupply(FixedVariableSupply(f(RawDataObject(p.0)).0))
+            }
+            PowerSource::Battery(p) => PowerSource::Battery(Battery(f(RawDataObject(p.0)).0)),
+            PowerSource::Pps(p) => PowerSource::Pps(Pps(f(RawDataObject(p.0)).0)),
+            PowerSource::Avs(p) => PowerSource::Avs(Avs(f(RawDataObject(p.0)).0)),
+            PowerSource::EprRequest(epr) => PowerSource::EprRequest(EprRequestDataObject {
+                rdo: f(RawDataObject(epr.rdo)).0,
+                ..epr
+            }),
+            PowerSource::Unknown(_) => self,
+        }
+    }
+
+    /// The flags that all RDO types share, if the request type is known.
+    fn common_flags(&self) -> Option<RawDataObject> {
+        match self {
+            PowerSource::Unknown(_) => None,
+            _ => Some(RawDataObject(self.raw())),
+        }
+    }
+
+    /// Apply the USB related request attributes.
+    ///
+    /// Requests of unknown type are left untouched.
+    pub fn with_attributes(self, attributes: RequestAttributes) -> Self {
+        self.map_common_flags(|rdo| {
+            rdo.with_no_usb_suspend(attributes.no_usb_suspend)
+                .with_usb_communications_capable(attributes.usb_communications_capable)
+        })
+    }
+
+    /// The USB related request attributes, if the request type is known.
+    pub fn attributes(&self) -> Option<RequestAttributes> {
+        self.common_flags().map(|rdo| RequestAttributes {
+            no_usb_suspend: rdo.no_usb_suspend(),
+            usb_communications_capable: rdo.usb_communications_capable(),
+        })
+    }
+
+    /// Set the capability mismatch flag.
+    ///
+    /// Requests of unknown type are left untouched.
+    pub fn with_capability_mismatch(self, capability_mismatch: bool) -> Self {
+        self.map_common_flags(|rdo| rdo.with_capability_mismatch(capability_mismatch))
+    }
+
+    /// Whether the capability mismatch flag is set.
+    pub fn capability_mismatch(&self) -> bool {
+        self.common_flags().is_some_and(|rdo| rdo.capability_mismatch())
+    }
+
+    /// Set the unchunked extended messages supported flag.
+    ///
+    /// The protocol layer always uses chunked extended messages, so this should only be set for testing source
+    /// behavior. Requests of unknown type are left untouched.
+    pub fn with_unchunked_extended_messages_supported(self, supported: bool) -> Self {
+        self.map_common_flags(|rdo| rdo.with_unchunked_extended_messages_supported(supported))
+    }
+
+    /// Whether the unchunked extended messages supported flag is set.
+    pub fn unchunked_extended_messages_supported(&self) -> bool {
+        self.common_flags()
+            .is_some_and(|rdo| rdo.unchunked_extended_messages_supported())
+    }
+
+    /// Set the EPR mode capable flag.
+    ///
+    /// Requests of unknown type are left untouched.
+    pub fn with_epr_mode_capable(self, epr_mode_capable: bool) -> Self {
+        self.map_common_flags(|rdo| rdo.with_epr_mode_capable(epr_mode_capable))
+    }
+
+    /// Whether the EPR mode capable flag is set.
+    pub fn epr_mode_capable(&self) -> bool {
+        self.common_flags().is_some_and(|rdo| rdo.epr_mode_capable())
+    }
+
+    /// Determine the data message type to use for this request.
+    pub fn message_type(&self) -> crate::header::DataMessageType {
+        match self {
+            PowerSource::EprRequest { .. } => crate::header::DataMessageType::EprRequest,
+            _ => crate::header::DataMessageType::Request,
+        }
+    }
+
+    /// Number of data objects required to encode this request.
+    pub fn num_objects(&self) -> u8 {
+        match self {
+            PowerSource::EprRequest { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    /// Find the highest fixed voltage that can be found in the source capabilities.
+    ///
+    /// Reports the position of the found PDO, and the fixed supply instance, or `None` if there is no fixed supply
+    /// PDO.
+    pub fn find_highest_fixed_voltage(
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Option<IndexedFixedSupply<'_>> {
+        let mut selected_pdo = None;
+
+        for (position, cap) in source_capabilities.offered_pdos() {
+            if let source_capabilities::PowerDataObject::FixedSupply(fixed_supply) = cap {
+                selected_pdo = match selected_pdo {
+                    None => Some(IndexedFixedSupply(fixed_supply, position)),
+                    Some(ref x) => {
+                        if fixed_supply.voltage() > x.0.voltage() {
+                            Some(IndexedFixedSupply(fixed_supply, position))
+                        } else {
+                            selected_pdo
+                        }
+                    }
+                };
+            }
+        }
+
+        selected_pdo
+    }
+
+    /// Find a specific fixed voltage within the source capabilities.
+    ///
+    /// Reports the position of the found PDO, and the fixed supply instance, or `None` if there is no match to the
+    /// request.
+    pub fn find_specific_fixed_voltage(
+        source_capabilities: &source_capabilities::SourceCapabilities,
+        voltage: ElectricPotential,
+    ) -> Option<IndexedFixedSupply<'_>> {
+        for (position, cap) in source_capabilities.offered_pdos() {
+            if let source_capabilities::PowerDataObject::FixedSupply(fixed_supply) = cap
+                && (fixed_supply.voltage() == voltage)
+            {
+                return Some(IndexedFixedSupply(fixed_supply, position));
+            }
+        }
+
+        None
+    }
+
+    /// Find a suitable Augmented PDO (PPS or AVS) by evaluating the provided voltage
+    /// request against the source capabilities.
+    ///
+    /// This searches both SPR PPS and EPR AVS PDOs for a matching voltage range.
+    ///
+    /// Reports the position of the found PDO, and the augmented supply instance, or `None` if there is no match to the
+    /// request.
+    pub fn find_augmented_pdo(
+        source_capabilities: &source_capabilities::SourceCapabilities,
+        voltage: ElectricPotential,
+    ) -> Option<IndexedAugmented<'_>> {
+        for (position, cap) in source_capabilities.offered_pdos() {
+            let source_capabilities::PowerDataObject::Augmented(augmented) = cap else {
+                trace!("Skip non-augmented PDO {:?}", cap);
+                continue;
+            };
+
+            match augmented {
+                source_capabilities::Augmented::Spr(spr) => {
+                    if spr.min_voltage() <= voltage && spr.max_voltage() >= voltage {
+                        return Some(IndexedAugmented(augmented, position));
+                    } else {
+                        trace!("Skip PDO, voltage out of range. {:?}", augmented);
+                    }
+                }
+                source_capabilities::Augmented::Epr(avs) => {
+                    if avs.min_voltage() <= voltage && avs.max_voltage() >= voltage {
+                        return Some(IndexedAugmented(augmented, position));
+                    } else {
+                        trace!("Skip PDO, voltage out of range. {:?}", augmented);
+                    }
+                }
+                _ => trace!("Skip PDO, only SPR PPS and EPR AVS are supported. {:?}", augmented),
+            };
+        }
+
+        trace!("Could not find suitable augmented PDO for voltage");
+        None
+    }
+
+    /// Create a new, specific power source request for a fixed supply.
+    ///
+    /// # Arguments
+    ///
+    /// * `supply` - The combination of fixed supply PDO and its object position.
+    /// * `current_request` - The desired current level.
+    pub fn new_fixed_specific(supply: IndexedFixedSupply, current_request: CurrentRequest) -> Result<Self, Error> {
+        Self::new_fixed_specific_with_max_current(supply, current_request, current_request)
+    }
+
+    /// Create a new, specific power source request for a fixed supply, with separate operating and maximum
+    /// operating current.
+    ///
+    /// The capability mismatch flag is set, if the maximum operating current exceeds the PDO's maximum current.
+    ///
+    /// # Arguments
+    ///
+    /// * `supply` - The combination of fixed supply PDO and its object position.
+    /// * `current_request` - The desired operating current level.
+    /// * `max_current_request` - The desired maximum operating current level.
+    pub fn new_fixed_specific_with_max_current(
+        supply: IndexedFixedSupply,
+        current_request: CurrentRequest,
+        max_current_request: CurrentRequest,
+    ) -> Result<Self, Error> {
+        let IndexedFixedSupply(pdo, position) = supply;
+
+        let (current, max_current, mismatch) =
+            resolve_currents(current_request, max_current_request, pdo.max_current())?;
+
+        let mut raw_current = current.get::<electric_current::centiampere>() as u16;
+        let mut raw_max_current = max_current.get::<electric_current::centiampere>() as u16;
+
+        if raw_current > 0x3ff {
+            error!("Clamping invalid current: {} mA", 10 * raw_current);
+            raw_current = 0x3ff;
+        }
+
+        if raw_max_current > 0x3ff {
+            error!("Clamping invalid maximum current: {} mA", 10 * raw_max_current);
+            raw_max_current = 0x3ff;
+        }
+
+        Ok(Self::FixedVariableSupply(
+            FixedVariableSupply::new(position, raw_current, raw_max_current).with_capability_mismatch(mismatch),
+        ))
+    }
+
+    /// Create a new power source request for a fixed supply.
+    ///
+    /// Finds a suitable PDO by evaluating the provided current and voltage requests against the source capabilities.
+    pub fn new_fixed(
+        current_request: CurrentRequest,
+        voltage_request: VoltageRequest,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Result<Self, Error> {
+        Self::new_fixed_with_max_current(current_request, current_request, voltage_request, source_capabilities)
+    }
+
+    /// Create a new power source request for a fixed supply, with separate operating and maximum operating current.
+    ///
+    /// Finds a suitable PDO by evaluating the provided voltage request against the source capabilities.
+    pub fn new_fixed_with_max_current(
+        current_request: CurrentRequest,
+        max_current_request: CurrentRequest,
+        voltage_request: VoltageRequest,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Result<Self, Error> {
+        let selected = match voltage_request {
+            VoltageRequest::Safe5V => source_capabilities
+                .vsafe_5v()
+                .map(|supply| IndexedFixedSupply(supply, ObjectPosition::VSAFE_5V)),
+            VoltageRequest::Highest => Self::find_highest_fixed_voltage(source_capabilities),
+            VoltageRequest::Specific(x) => Self::find_specific_fixed_voltage(source_capabilities, x),
+        };
+
+        let Some(selected) = selected else {
+            return Err(Error::VoltageMismatch);
+        };
+
+        Self::new_fixed_specific_with_max_current(selected, current_request, max_current_request)
+    }
+
+    /// Create a new power source request for a programmable power supply (PPS).
+    ///
+    /// Finds a suitable PDO by evaluating the provided current and voltage requests against the source capabilities.
+    /// If no PDO is found that matches the request, an error is returned.
+    pub fn new_pps(
+        current_request: CurrentRequest,
+        voltage: ElectricPotential,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Result<Self, Error> {
+        Self::new_pps_with_max_current(current_request, current_request, voltage, source_capabilities)
+    }
+
+    /// Create a new power source request for a programmable power supply (PPS), with separate operating and maximum
+    /// current.
+    ///
+    /// The RDO only carries the operating current. The maximum current that the sink needs determines the
+    /// capability mismatch flag.
+    pub fn new_pps_with_max_current(
+        current_request: CurrentRequest,
+        max_current_request: CurrentRequest,
+        voltage: ElectricPotential,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Result<Self, Error> {
+        let Some(IndexedAugmented(pdo, position)) = Self::find_augmented_pdo(source_capabilities, voltage) else {
+            return Err(Error::VoltageMismatch);
+        };
+        let max_current = match pdo {
+            source_capabilities::Augmented::Spr(spr) => spr.max_current(),
+            _ => return Err(Error::VoltageMismatch),
+        };
+
+        let (current, _, mismatch) = resolve_currents(current_request, max_current_request, max_current)?;
+
+        let mut raw_current = current.get::<_50milliamperes>() as u16;
+
+        if raw_current > 0x3ff {
+            error!("Clamping invalid current: {} mA", 10 * raw_current);
+            raw_current = 0x3ff;
+        }
+
+        let raw_voltage = voltage.get::<_20millivolts>() as u16;
+
+        Ok(Self::Pps(
+            Pps::new(position, raw_voltage, raw_current).with_capability_mismatch(mismatch),
+        ))
+    }
+
+    /// Create a new EPR AVS request.
+    ///
+    /// Per USB PD 3.x Section 6.4.9, this creates an EPR_Request with an AVS RDO
+    /// and a copy of the requested PDO.
+    pub fn new_epr_avs(
+        current_request: CurrentRequest,
+        voltage: ElectricPotential,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Result<Self, Error> {
+        Self::new_epr_avs_with_max_current(current_request, current_request, voltage, source_capabilities)
+    }
+
+    /// Create a new EPR AVS request, with separate operating and maximum current.
+    ///
+    /// As for PPS, only the operating current is transmitted, and the maximum current determines the capability
+    /// mismatch flag.
+    pub fn new_epr_avs_with_max_current(
+        current_request: CurrentRequest,
+        max_current_request: CurrentRequest,
+        voltage: ElectricPotential,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Result<Self, Error> {
+        let Some(IndexedAugmented(pdo, position)) = Self::find_augmented_pdo(source_capabilities, voltage) else {
+            return Err(Error::VoltageMismatch);
+        };
+        let max_current = match pdo {
+            source_capabilities::Augmented::Epr(avs) => avs.pd_power() / voltage,
+            _ => return Err(Error::VoltageMismatch),
+        };
+
+        let (current, _, mismatch) = resolve_currents(current_request, max_current_request, max_current)?;
+
+        let mut raw_current = current.get::<_50milliamperes>() as u16;
+
+        if raw_current > 0x7f {
+            error!("Clamping invalid AVS current: {} mA", 50 * raw_current);
+            raw_current = 0x7f;
+        }
+
+        // AVS voltage is in 25 mV units with LSB 2 bits = 0 (effective 100 mV steps), which `Avs::new` ensures.
+        // Per USB PD 3.2 Table 6.26: "Output voltage in 25 mV units,
+        // the least two significant bits Shall be set to zero"
+        let raw_voltage = voltage.get::<_25millivolts>() as u16;
+
+        // Build AVS RDO (Table 6.26).
+        let rdo = Avs::new(position, raw_voltage, raw_current)
+            .with_capability_mismatch(mismatch)
+            .with_epr_mode_capable(true);
+
+        Ok(Self::EprRequest(EprRequestDataObject::new(
+            position,
+            &source_capabilities::PowerDataObject::Augmented(*pdo),
+            rdo,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+    use uom::si::electric_current::milliampere;
+    use uom::si::electric_potential::millivolt;
+
+    use super::{
+        Avs, AvsError, Battery, CurrentRequest, EprRequestDataObject, Error, FixedVariableSupply, PowerSource, Pps,
+        RawDataObject, RequestAttributes, VoltageRequest, avs_raw_output_voltage, clip_pps,
+    };
+    use crate::data::source_capabilities::{
+        EprAdjustableVoltageSupply, PowerDataObject, SourceCapabilities, SprProgrammablePowerSupply,
+    };
+    use crate::data::{Data, ObjectPosition};
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::units::{ElectricCurrent, ElectricPotential};
+
+    fn source_capabilities() -> SourceCapabilities {
+        SourceCapabilities(heapless::Vec::from_slice(&get_dummy_source_capabilities()).unwrap())
+    }
+
+    fn milliamperes(value: u32) -> CurrentRequest {
+        CurrentRequest::Specific(ElectricCurrent::new::<milliampere>(value))
+    }
+
+    #[test]
+    fn test_fixed_max_current() {
+        let caps = source_capabilities();
+
+        // 5 V at 3 A: operate at 1 A, with peaks up to 2 A.
+        let PowerSource::FixedVariableSupply(rdo) = PowerSource::new_fixed_with_max_current(
+            milliamperes(1000),
+            milliamperes(2000),
+            VoltageRequest::Safe5V,
+            &caps,
+        )
+        .unwrap() else {
+            panic!("Expected fixed supply request");
+        };
+        assert_eq!(rdo.raw_operating_current(), 100);
+        assert_eq!(rdo.raw_max_operating_current(), 200);
+        assert!(!rdo.capability_mismatch());
+
+        // A maximum current beyond the PDO's capability is a mismatch.
+        let PowerSource::FixedVariableSupply(rdo) = PowerSource::new_fixed_with_max_current(
+            milliamperes(1000),
+            milliamperes(4000),
+            VoltageRequest::Safe5V,
+            &caps,
+        )
+        .unwrap() else {
+            panic!("Expected fixed supply request");
+        };
+        assert_eq!(rdo.raw_max_operating_current(), 400);
+        assert!(rdo.capability_mismatch());
+
+        assert!(matches!(
+            PowerSource::new_fixed_with_max_current(
+                milliamperes(2000),
+                milliamperes(1000),
+                VoltageRequest::Safe5V,
+                &caps
+            ),
+            Err(Error::CurrentMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_pps_max_current() {
+        let caps = source_capabilities();
+        let voltage = ElectricPotential::new::<millivolt>(5000);
+
+        let PowerSource::Pps(rdo) =
+            PowerSource::new_pps_with_max_current(milliamperes(1000), CurrentRequest::Highest, voltage, &caps).unwrap()
+        else {
+            panic!("Expected PPS request");
+        };
+        assert_eq!(rdo.raw_operating_current(), 20);
+        assert!(!rdo.capability_mismatch());
+
+        let PowerSource::Pps(rdo) =
+            PowerSource::new_pps_with_max_current(milliamperes(1000), milliamperes(6000), voltage, &caps).unwrap()
+        else {
+            panic!("Expected PPS request");
+        };
+        assert_eq!(rdo.raw_operating_current(), 20);
+        assert!(rdo.capability_mismatch());
+
+        assert!(matches!(
+            PowerSource::new_pps_with_max_current(CurrentRequest::Highest, milliamperes(1000), voltage, &caps),
+            Err(Error::CurrentMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_padding_not_selectable() {
+        use crate::data::PdoKind;
+        use crate::data::source_capabilities::FixedSupply;
+
+        // vSafe5V, zero-padding up to position 7, and a 28 V EPR PDO at position 8.
+        let fixed = |raw_voltage| {
+            PowerDataObject::FixedSupply(
+                FixedSupply::default()
+                    .with_raw_voltage(raw_voltage)
+                    .with_raw_max_current(300),
+            )
+        };
+        let padding = PowerDataObject::from(0);
+        let caps = SourceCapabilities(heapless::Vec::from_array([
+            fixed(100),
+            padding,
+            padding,
+            padding,
+            padding,
+            padding,
+            padding,
+            fixed(560),
+        ]));
+
+        assert!(PowerSource::find_specific_fixed_voltage(&caps, ElectricPotential::new::<millivolt>(0)).is_none());
+        assert!(PowerSource::find_augmented_pdo(&caps, ElectricPotential::new::<millivolt>(0)).is_none());
+        let super::IndexedFixedSupply(_, position) = PowerSource::find_highest_fixed_voltage(&caps).unwrap();
+        assert_eq!(position, ObjectPosition::FIRST_EPR);
+
+        // A request for a padding position is not interpreted as a fixed supply request.
+        let raw = RawDataObject(0).with_object_position(2);
+        assert!(matches!(PowerSource::from_raw(raw, &caps), PowerSource::Unknown(_)));
+        assert!(caps.at_object_position(ObjectPosition::new(2).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let caps = source_capabilities();
+        let attributes = RequestAttributes {
+            no_usb_suspend: false,
+            usb_communications_capable: true,
+        };
+
+        let request = PowerSource::new_fixed(CurrentRequest::Highest, VoltageRequest::Safe5V, &caps).unwrap();
+        assert_eq!(request.attributes(), Some(RequestAttributes::default()));
+
+        let request = request.with_attributes(attributes);
+        assert_eq!(request.attributes(), Some(attributes));
+        assert_eq!(request.raw() & (0b11 << 24), 1 << 25);
+    }
+
+    #[test]
+    fn test_const_new() {
+        const FIXED: FixedVariableSupply = FixedVariableSupply::new(ObjectPosition::new(2).unwrap(), 150, 300);
+        const BATTERY: Battery = Battery::new(ObjectPosition::new(3).unwrap(), 60, 80);
+        const PPS: Pps = Pps::new(ObjectPosition::new(5).unwrap(), 450, 40);
+        const AVS: Avs = Avs::new(ObjectPosition::FIRST_EPR, 1123, 40);
+
+        let attributes = RequestAttributes::default();
+        let expected = FixedVariableSupply(0)
+            .with_object_position(2)
+            .with_raw_operating_current(150)
+            .with_raw_max_operating_current(300)
+            .with_no_usb_suspend(attributes.no_usb_suspend)
+            .with_usb_communications_capable(attributes.usb_communications_capable);
+        assert_eq!(FIXED, expected);
+        assert_eq!(
+            PowerSource::FixedVariableSupply(FIXED).attributes(),
+            Some(RequestAttributes::DEFAULT)
+        );
+
+        assert_eq!(BATTERY.object_position(), 3);
+        assert_eq!(BATTERY.raw_operating_power(), 60);
+        assert_eq!(BATTERY.raw_max_operating_power(), 80);
+
+        assert_eq!(PPS.object_position(), 5);
+        assert_eq!(PPS.output_voltage(), ElectricPotential::new::<millivolt>(9000));
+        assert_eq!(PPS.operating_current(), ElectricCurrent::new::<milliampere>(2000));
+
+        // The two least significant voltage bits are cleared.
+        assert_eq!(AVS.raw_output_voltage(), 1120);
+        assert!(!AVS.capability_mismatch());
+    }
+
+    #[test]
+    fn test_common_flags() {
+        let requests = [
+            PowerSource::FixedVariableSupply(FixedVariableSupply(0).with_object_position(1)),
+            PowerSource::Battery(Battery(0).with_object_position(2)),
+            PowerSource::Pps(Pps(0).with_object_position(3)),
+            PowerSource::Avs(Avs(0).with_object_position(4)),
+            PowerSource::EprRequest(EprRequestDataObject::new(
+                ObjectPosition::FIRST_EPR,
+                &PowerDataObject::from(0xD3C0_968C),
+                Avs(0),
+            )),
+        ];
+
+        for request in requests {
+            let request = request
+                .with_capability_mismatch(true)
+                .with_unchunked_extended_messages_supported(true)
+                .with_epr_mode_capable(true);
+
+            assert!(request.capability_mismatch());
+            assert!(request.unchunked_extended_messages_supported());
+            assert!(request.epr_mode_capable());
+
+            let mut buf = [0u8; 8];
+            let len = Data::Request(request).to_bytes(&mut buf);
+            assert_eq!(len, 4 * request.num_objects() as usize);
+
+            let raw = LittleEndian::read_u32(&buf);
+            assert_eq!(raw >> 28, request.object_position() as u32);
+            assert_eq!(raw & (0b111_1111 << 20), (1 << 26) | (1 << 23) | (1 << 22));
+
+            let request = request.with_epr_mode_capable(false);
+            assert!(!request.epr_mode_capable());
+            assert!(request.capability_mismatch());
+        }
+
+        let unknown = PowerSource::Unknown(RawDataObject(0)).with_epr_mode_capable(true);
+        assert!(!unknown.epr_mode_capable());
+    }
+
+    #[test]
+    fn test_clip_pps() {
+        let millivolts = ElectricPotential::new::<millivolt>;
+        let milliamperes = ElectricCurrent::new::<milliampere>;
+
+        // 3.3-11 V, 3 A
+        let apdo = SprProgrammablePowerSupply::default()
+            .with_raw_min_voltage(33)
+            .with_raw_max_voltage(110)
+            .with_raw_max_current(60);
+
+        let clipped = clip_pps(&apdo, millivolts(9_000), milliamperes(2_000));
+        assert!(!clipped.is_clipped());
+        assert_eq!(clipped.voltage, millivolts(9_000));
+
+        let clipped = clip_pps(&apdo, millivolts(12_000), milliamperes(2_000));
+        assert!(clipped.voltage_clipped && !clipped.current_clipped);
+        assert_eq!(clipped.voltage, millivolts(11_000));
+
+        let clipped = clip_pps(&apdo, millivolts(3_000), milliamperes(5_000));
+        assert!(clipped.voltage_clipped && clipped.current_clipped);
+        assert_eq!(clipped.voltage, millivolts(3_300));
+        assert_eq!(clipped.current, milliamperes(3_000));
+    }
+
+    #[test]
+    fn test_avs_validation() {
+        let millivolts = ElectricPotential::new::<millivolt>;
+
+        assert_eq!(avs_raw_output_voltage(millivolts(24_000)), Ok(960));
+        assert_eq!(avs_raw_output_voltage(millivolts(14_900)), Err(AvsError::BelowMinimum));
+        assert_eq!(avs_raw_output_voltage(millivolts(20_050)), Err(AvsError::InvalidStep));
+        assert_eq!(avs_raw_output_voltage(millivolts(20_025)), Err(AvsError::InvalidStep));
+
+        // 15-48 V, 140 W
+        let apdo = EprAdjustableVoltageSupply(0xD3C0_968C);
+
+        let rdo = Avs(0).with_output_voltage(millivolts(28_000)).unwrap();
+        assert_eq!(rdo.raw_output_voltage(), 1120);
+        assert_eq!(rdo.with_raw_operating_current(100).validate(&apdo), Ok(()));
+        assert_eq!(
+            rdo.with_raw_operating_current(101).validate(&apdo),
+            Err(AvsError::CurrentExceedsPdp)
+        );
+
+        let rdo = Avs(0).with_output_voltage(millivolts(49_000)).unwrap();
+        assert_eq!(rdo.validate(&apdo), Err(AvsError::OutOfRange));
+
+        // Raw fields that are not in 100 mV steps.
+        assert_eq!(
+            Avs(0).with_raw_output_voltage(1121).validate(&apdo),
+            Err(AvsError::InvalidStep)
+        );
+    }
+}
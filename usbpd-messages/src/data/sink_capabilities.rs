@@ -0,0 +1,819 @@
+//! Definitions of sink capabilities data message content.
+//!
+//! Sink capabilities are sent in response to Get_Sink_Cap messages.
+//! Per USB PD Spec R3.2 Section 6.4.1.6, the Sink_Capabilities message
+//! contains Power Data Objects describing what power levels the sink can operate at.
+use heapless::Vec;
+use proc_bitfield::bitfield;
+use uom::si::electric_current::{centiampere, milliampere};
+use uom::si::electric_potential::{decivolt, millivolt};
+
+use crate::_50milliamperes_mod::_50milliamperes;
+use crate::_50millivolts_mod::_50millivolts;
+use crate::_250milliwatts_mod::_250milliwatts;
+use crate::units::{ElectricCurrent, ElectricPotential, Power};
+
+/// Fast Role Swap required USB Type-C current.
+/// Per USB PD Spec R3.2 Table 6.17.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FastRoleSwapCurrent {
+    /// Fast Role Swap not supported (default)
+    #[default]
+    NotSupported = 0b00,
+    /// Default USB Power
+    DefaultUsbPower = 0b01,
+    /// 1.5A @ 5V
+    Current1_5A = 0b10,
+    /// 3.0A @ 5V
+    Current3_0A = 0b11,
+}
+
+impl From<u8> for FastRoleSwapCurrent {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0b00 => Self::NotSupported,
+            0b01 => Self::DefaultUsbPower,
+            0b10 => Self::Current1_5A,
+            _ => Self::Current3_0A,
+        }
+    }
+}
+
+bitfield! {
+    /// A Sink Fixed Supply PDO.
+    ///
+    /// Per USB PD Spec R3.2 Table 6.17 (Fixed Supply PDO - Sink).
+    /// Different from Source Fixed Supply PDO in bits 28-20.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct FixedSupply(pub u32): Debug, FromStorage, IntoStorage {
+        /// Fixed supply (00b)
+        pub kind: u8 @ 30..=31,
+        /// Dual-Role Power - set if Dual-Role Power supported
+        pub dual_role_power: bool @ 29,
+        /// Higher Capability - set if sink needs more than vSafe5V for full functionality
+        pub higher_capability: bool @ 28,
+        /// Unconstrained Power - set if external power source is available
+        pub unconstrained_power: bool @ 27,
+        /// USB Communications Capable
+        pub usb_communications_capable: bool @ 26,
+        /// Dual-Role Data
+        pub dual_role_data: bool @ 25,
+        /// Fast Role Swap required USB Type-C Current (bits 24:23)
+        pub raw_fast_role_swap: u8 @ 23..=24,
+        /// Reserved - shall be set to zero (bits 22:20)
+        pub reserved: u8 @ 20..=22,
+        /// Voltage in 50 mV units
+        pub raw_voltage: u16 @ 10..=19,
+        /// Operational Current in 10 mA units
+        pub raw_operational_current: u16 @ 0..=9,
+    }
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for FixedSupply {
+    fn default() -> Self {
+        Self(0)
+    }
+}
+
+impl FixedSupply {
+    /// Create a new FixedSupply PDO for the required vSafe5V entry.
+    ///
+    /// All sinks must include at least one PDO at 5V.
+    pub const fn new_vsafe5v(operational_current_10ma: u16) -> Self {
+        // 5V = 100 * 50 mV
+        Self::new(100, operational_current_10ma)
+    }
+
+    /// Create a new FixedSupply PDO at a specific voltage.
+    pub const fn new(voltage_50mv: u16, operational_current_10ma: u16) -> Self {
+        Self(((voltage_50mv as u32 & 0x3ff) << 10) | (operational_current_10ma as u32 & 0x3ff))
+    }
+
+    /// Get the voltage in standard units.
+    pub fn voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_50millivolts>(self.raw_voltage().into())
+    }
+
+    /// Get the operational current in standard units.
+    pub fn operational_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<centiampere>(self.raw_operational_current().into())
+    }
+
+    /// Get the Fast Role Swap required current.
+    pub fn fast_role_swap(&self) -> FastRoleSwapCurrent {
+        FastRoleSwapCurrent::from(self.raw_fast_role_swap())
+    }
+
+    /// Set the voltage in standard units.
+    pub fn with_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_voltage(voltage.get::<_50millivolts>() as u16)
+    }
+
+    /// Set the operational current in standard units.
+    pub fn with_operational_current(self, current: ElectricCurrent) -> Self {
+        self.with_raw_operational_current(current.get::<centiampere>() as u16)
+    }
+
+    /// Set the Fast Role Swap required current.
+    pub fn with_fast_role_swap(self, current: FastRoleSwapCurrent) -> Self {
+        self.with_raw_fast_role_swap(current as u8)
+    }
+}
+
+bitfield! {
+    /// A Sink Battery Supply PDO.
+    ///
+    /// Per USB PD Spec R3.2 Table 6.19 (Battery Supply PDO - Sink).
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Battery(pub u32): Debug, FromStorage, IntoStorage {
+        /// Battery (01b)
+        pub kind: u8 @ 30..=31,
+        /// Maximum Voltage in 50 mV units
+        pub raw_max_voltage: u16 @ 20..=29,
+        /// Minimum Voltage in 50 mV units
+        pub raw_min_voltage: u16 @ 10..=19,
+        /// Operational Power in 250 mW units
+        pub raw_operational_power: u16 @ 0..=9,
+    }
+}
+
+impl Battery {
+    /// Create a new Battery PDO.
+    pub const fn new(min_voltage_50mv: u16, max_voltage_50mv: u16, operational_power_250mw: u16) -> Self {
+        Self(
+            (0b01 << 30)
+                | ((max_voltage_50mv as u32 & 0x3ff) << 20)
+                | ((min_voltage_50mv as u32 & 0x3ff) << 10)
+                | (operational_power_250mw as u32 & 0x3ff),
+        )
+    }
+
+    /// Get the maximum voltage in standard units.
+    pub fn max_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_50millivolts>(self.raw_max_voltage().into())
+    }
+
+    /// Get the minimum voltage in standard units.
+    pub fn min_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_50millivolts>(self.raw_min_voltage().into())
+    }
+
+    /// Get the operational power in standard units.
+    pub fn operational_power(&self) -> Power {
+        Power::new::<_250milliwatts>(self.raw_operational_power().into())
+    }
+
+    /// Set the maximum voltage in standard units.
+    pub fn with_max_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_max_voltage(voltage.get::<_50millivolts>() as u16)
+    }
+
+    /// Set the minimum voltage in standard units.
+    pub fn with_min_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_min_voltage(voltage.get::<_50millivolts>() as u16)
+    }
+
+    /// Set the operational power in standard units.
+    pub fn with_operational_power(self, power: Power) -> Self {
+        self.with_raw_operational_power(power.get::<_250milliwatts>() as u16)
+    }
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Self(0).with_kind(0b01)
+    }
+}
+
+bitfield! {
+    /// A Sink Variable Supply PDO.
+    ///
+    /// Per USB PD Spec R3.2 Table 6.18 (Variable Supply PDO - Sink).
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct VariableSupply(pub u32): Debug, FromStorage, IntoStorage {
+        /// Variable supply (10b)
+        pub kind: u8 @ 30..=31,
+        /// Maximum Voltage in 50 mV units
+        pub raw_max_voltage: u16 @ 20..=29,
+        /// Minimum Voltage in 50 mV units
+        pub raw_min_voltage: u16 @ 10..=19,
+        /// Operational current in 10 mA units
+        pub raw_operational_current: u16 @ 0..=9,
+    }
+}
+
+impl VariableSupply {
+    /// Create a new VariableSupply PDO.
+    pub const fn new(min_voltage_50mv: u16, max_voltage_50mv: u16, operational_current_10ma: u16) -> Self {
+        Self(
+            (0b10 << 30)
+                | ((max_voltage_50mv as u32 & 0x3ff) << 20)
+                | ((min_voltage_50mv as u32 & 0x3ff) << 10)
+                | (operational_current_10ma as u32 & 0x3ff),
+        )
+    }
+
+    /// Get the maximum voltage in standard units.
+    pub fn max_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_50millivolts>(self.raw_max_voltage().into())
+    }
+
+    /// Get the minimum voltage in standard units.
+    pub fn min_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<_50millivolts>(self.raw_min_voltage().into())
+    }
+
+    /// Get the operational current in standard units.
+    pub fn operational_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<centiampere>(self.raw_operational_current().into())
+    }
+
+    /// Set the maximum voltage in standard units.
+    pub fn with_max_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_max_voltage(voltage.get::<_50millivolts>() as u16)
+    }
+
+    /// Set the minimum voltage in standard units.
+    pub fn with_min_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_min_voltage(voltage.get::<_50millivolts>() as u16)
+    }
+
+    /// Set the operational current in standard units.
+    pub fn with_operational_current(self, current: ElectricCurrent) -> Self {
+        self.with_raw_operational_current(current.get::<centiampere>() as u16)
+    }
+}
+
+impl Default for VariableSupply {
+    fn default() -> Self {
+        Self(0).with_kind(0b10)
+    }
+}
+
+bitfield! {
+    /// A Sink SPR Programmable Power Supply APDO.
+    ///
+    /// Per USB PD Spec R3.2 Table 6.20 (SPR Programmable Power Supply APDO - Sink).
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Pps(pub u32): Debug, FromStorage, IntoStorage {
+        /// Augmented power data object (11b)
+        pub kind: u8 @ 30..=31,
+        /// SPR programmable power supply (00b)
+        pub supply: u8 @ 28..=29,
+        /// Maximum Voltage in 100 mV units
+        pub raw_max_voltage: u8 @ 17..=24,
+        /// Minimum Voltage in 100 mV units
+        pub raw_min_voltage: u8 @ 8..=15,
+        /// Maximum Current in 50 mA units
+        pub raw_max_current: u8 @ 0..=6,
+    }
+}
+
+impl Pps {
+    /// Create a new PPS APDO.
+    pub fn new(min_voltage: ElectricPotential, max_voltage: ElectricPotential, max_current: ElectricCurrent) -> Self {
+        Self::default()
+            .with_min_voltage(min_voltage)
+            .with_max_voltage(max_voltage)
+            .with_max_current(max_current)
+    }
+
+    /// Create a new PPS APDO from raw values, usable in constant contexts.
+    pub const fn new_raw(min_voltage_100mv: u8, max_voltage_100mv: u8, max_current_50ma: u8) -> Self {
+        Self(
+            (0b11 << 30)
+                | ((max_voltage_100mv as u32) << 17)
+                | ((min_voltage_100mv as u32) << 8)
+                | (max_current_50ma as u32 & 0x7f),
+        )
+    }
+
+    /// Get the maximum voltage in standard units.
+    pub fn max_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<decivolt>(self.raw_max_voltage().into())
+    }
+
+    /// Get the minimum voltage in standard units.
+    pub fn min_voltage(&self) -> ElectricPotential {
+        ElectricPotential::new::<decivolt>(self.raw_min_voltage().into())
+    }
+
+    /// Get the maximum current in standard units.
+    pub fn max_current(&self) -> ElectricCurrent {
+        ElectricCurrent::new::<_50milliamperes>(self.raw_max_current().into())
+    }
+
+    /// Set the maximum voltage in standard units.
+    pub fn with_max_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_max_voltage(voltage.get::<decivolt>() as u8)
+    }
+
+    /// Set the minimum voltage in standard units.
+    pub fn with_min_voltage(self, voltage: ElectricPotential) -> Self {
+        self.with_raw_min_voltage(voltage.get::<decivolt>() as u8)
+    }
+
+    /// Set the maximum current in standard units.
+    pub fn with_max_current(self, current: ElectricCurrent) -> Self {
+        self.with_raw_max_current(current.get::<_50milliamperes>() as u8)
+    }
+}
+
+impl Default for Pps {
+    fn default() -> Self {
+        Self(0).with_kind(0b11).with_supply(0b00)
+    }
+}
+
+/// A Sink Power Data Object.
+///
+/// Per USB PD Spec R3.2 Section 6.4.1.6, sinks report power levels they can
+/// operate at using Fixed, Variable, or Battery PDOs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SinkPowerDataObject {
+    /// Fixed voltage supply requirement.
+    FixedSupply(FixedSupply),
+    /// Battery supply requirement.
+    Battery(Battery),
+    /// Variable voltage supply requirement.
+    VariableSupply(VariableSupply),
+    /// Programmable power supply requirement.
+    Pps(Pps),
+}
+
+impl SinkPowerDataObject {
+    /// Convert the PDO to its raw u32 representation.
+    pub const fn to_raw(&self) -> u32 {
+        match self {
+            SinkPowerDataObject::FixedSupply(f) => f.0,
+            SinkPowerDataObject::Battery(b) => b.0,
+            SinkPowerDataObject::VariableSupply(v) => v.0,
+            SinkPowerDataObject::Pps(p) => p.0,
+        }
+    }
+
+    /// Parse a sink PDO from its raw representation.
+    ///
+    /// Returns `None` for augmented PDOs other than SPR PPS.
+    pub fn parse_raw(raw: u32) -> Option<Self> {
+        match raw >> 30 {
+            0b00 => Some(Self::FixedSupply(FixedSupply(raw))),
+            0b01 => Some(Self::Battery(Battery(raw))),
+            0b10 => Some(Self::VariableSupply(VariableSupply(raw))),
+            _ => {
+                let pps = Pps(raw);
+                (pps.supply() == 0b00).then_some(Self::Pps(pps))
+            }
+        }
+    }
+
+    /// The raw 32-bit value, as transmitted on the wire.
+    pub const fn raw(&self) -> u32 {
+        self.to_raw()
+    }
+}
+
+impl From<SinkPowerDataObject> for u32 {
+    fn from(pdo: SinkPowerDataObject) -> Self {
+        pdo.raw()
+    }
+}
+
+impl_raw!(FixedSupply, Battery, VariableSupply, Pps);
+
+/// Sink capabilities message content.
+///
+/// Contains a list of Power Data Objects describing what power levels the sink
+/// can operate at. Per USB PD Spec R3.2 Section 6.4.1.6:
+/// - All sinks shall minimally offer one PDO at vSafe5V
+/// - Maximum 7 PDOs for SPR mode
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SinkCapabilities(pub Vec<SinkPowerDataObject, 7>);
+
+impl SinkCapabilities {
+    /// Create new sink capabilities with a single vSafe5V PDO.
+    ///
+    /// This is the minimum required per spec - all sinks must support 5V.
+    pub const fn new_vsafe5v_only(operational_current_10ma: u16) -> Self {
+        Self::from_array([SinkPowerDataObject::FixedSupply(FixedSupply::new_vsafe5v(
+            operational_current_10ma,
+        ))])
+    }
+
+    /// Create sink capabilities from a list of PDOs.
+    pub const fn new(pdos: Vec<SinkPowerDataObject, 7>) -> Self {
+        Self(pdos)
+    }
+
+    /// Create sink capabilities from an array of PDOs, usable in constant contexts.
+    ///
+    /// More than seven PDOs fail at compile time. The first PDO should be the vSafe5V fixed supply.
+    ///
+    /// ```
+    /// use usbpd_messages::data::sink_capabilities::{FixedSupply, SinkCapabilities, SinkPowerDataObject};
+    ///
+    /// const SINK_CAPABILITIES: SinkCapabilities = SinkCapabilities::from_array([
+    ///     SinkPowerDataObject::FixedSupply(FixedSupply::new_vsafe5v(300)),
+    ///     SinkPowerDataObject::FixedSupply(FixedSupply::new(180, 200)),
+    /// ]);
+    ///
+    /// assert_eq!(SINK_CAPABILITIES.num_objects(), 2);
+    /// ```
+    pub const fn from_array<const M: usize>(pdos: [SinkPowerDataObject; M]) -> Self {
+        Self(Vec::from_array(pdos))
+    }
+
+    /// Get the PDOs.
+    pub fn pdos(&self) -> &[SinkPowerDataObject] {
+        &self.0
+    }
+
+    /// Get the number of PDOs.
+    pub fn num_objects(&self) -> u8 {
+        self.0.len() as u8
+    }
+
+    /// Derive the sink capabilities from a high-level description of the sink, see [`SinkPowerSpec`].
+    ///
+    /// Use [`SinkCapabilitiesBuilder::from_spec`] for setting the flags of the vSafe5V PDO.
+    ///
+    /// ```
+    /// use uom::si::electric_current::ampere;
+    /// use uom::si::electric_potential::volt;
+    /// use usbpd_messages::data::sink_capabilities::{SinkCapabilities, SinkPowerSpec};
+    /// use usbpd_messages::units::{ElectricCurrent, ElectricPotential};
+    ///
+    /// let capabilities = SinkCapabilities::from_spec(SinkPowerSpec {
+    ///     min_voltage: ElectricPotential::new::<volt>(5),
+    ///     max_voltage: ElectricPotential::new::<volt>(12),
+    ///     max_current: ElectricCurrent::new::<ampere>(2),
+    ///     pps: true,
+    /// });
+    ///
+    /// // vSafe5V, 9 V fixed, 5 V to 12 V variable, and 5 V to 12 V PPS.
+    /// assert_eq!(capabilities.num_objects(), 4);
+    /// ```
+    pub fn from_spec(spec: SinkPowerSpec) -> Self {
+        SinkCapabilitiesBuilder::from_spec(spec).build()
+    }
+
+    /// Create a builder for sink capabilities, with the mandatory vSafe5V PDO at the given operational current.
+    pub fn builder(vsafe5v_operational_current: ElectricCurrent) -> SinkCapabilitiesBuilder {
+        SinkCapabilitiesBuilder::new(vsafe5v_operational_current)
+    }
+
+    /// Convert to bytes for transmission.
+    ///
+    /// Each PDO is 4 bytes, little-endian.
+    pub fn to_bytes(&self, buffer: &mut [u8]) -> usize {
+        for (pdo, chunk) in self.0.iter().zip(buffer.as_chunks_mut::<4>().0) {
+            *chunk = pdo.to_raw().to_le_bytes();
+        }
+
+        self.0.len() * 4
+    }
+}
+
+/// A high-level description of the power that a sink can operate with, for deriving its PDOs.
+///
+/// See [`SinkCapabilities::from_spec`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SinkPowerSpec {
+    /// The minimum voltage that the sink can operate at.
+    pub min_voltage: ElectricPotential,
+    /// The maximum voltage that the sink can operate at.
+    pub max_voltage: ElectricPotential,
+    /// The maximum operational current.
+    pub max_current: ElectricCurrent,
+    /// Whether the sink can operate from a programmable power supply (PPS).
+    pub pps: bool,
+}
+
+impl SinkPowerSpec {
+    /// The fixed voltages of the SPR power rules in mV, beyond vSafe5V.
+    ///
+    /// See USB PD Spec R3.2 Section 10.2.
+    const FIXED_VOLTAGES_MILLIVOLTS: [u32; 3] = [9_000, 15_000, 20_000];
+
+    /// The highest current of a fixed or variable supply in mA, which requires a 5 A cable.
+    const MAX_CURRENT_MILLIAMPERES: u32 = 5_000;
+
+    /// The highest current of the vSafe5V supply in mA.
+    const MAX_VSAFE5V_CURRENT_MILLIAMPERES: u32 = 3_000;
+
+    /// The voltage range of an SPR PPS in mV.
+    const PPS_VOLTAGE_MILLIVOLTS: (u32, u32) = (3_300, 21_000);
+
+    /// The PDOs that the spec results in.
+    ///
+    /// - The mandatory vSafe5V fixed supply.
+    /// - A fixed supply for each SPR voltage (9 V, 15 V, 20 V) within the voltage range.
+    /// - A variable supply that covers the voltage range, if it is not a single voltage.
+    /// - A PPS APDO that covers the voltage range within the SPR PPS limits, if PPS is supported.
+    ///
+    /// Currents are limited to what a supply of each kind can deliver.
+    fn pdos(&self) -> Vec<SinkPowerDataObject, 6> {
+        let mut pdos = Vec::new();
+        let current = Ord::min(
+            self.max_current,
+            ElectricCurrent::new::<milliampere>(Self::MAX_CURRENT_MILLIAMPERES),
+        );
+
+        for voltage in Self::FIXED_VOLTAGES_MILLIVOLTS.map(ElectricPotential::new::<millivolt>) {
+            if (self.min_voltage..=self.max_voltage).contains(&voltage) {
+                let pdo = FixedSupply::default()
+                    .with_voltage(voltage)
+                    .with_operational_current(current);
+                // At most three fixed supplies, which always fit.
+                let _ = pdos.push(SinkPowerDataObject::FixedSupply(pdo));
+            }
+        }
+
+        if self.min_voltage < self.max_voltage {
+            let pdo = VariableSupply::default()
+                .with_min_voltage(self.min_voltage)
+                .with_max_voltage(self.max_voltage)
+                .with_operational_current(current);
+            let _ = pdos.push(SinkPowerDataObject::VariableSupply(pdo));
+        }
+
+        let (pps_min, pps_max) = Self::PPS_VOLTAGE_MILLIVOLTS;
+        let min_voltage = Ord::max(self.min_voltage, ElectricPotential::new::<millivolt>(pps_min));
+        let max_voltage = Ord::min(self.max_voltage, ElectricPotential::new::<millivolt>(pps_max));
+        if self.pps && min_voltage <= max_voltage {
+            let pdo = Pps::new(min_voltage, max_voltage, current);
+            let _ = pdos.push(SinkPowerDataObject::Pps(pdo));
+        }
+
+        pdos
+    }
+
+    /// The operational current of the vSafe5V supply.
+    fn vsafe5v_current(&self) -> ElectricCurrent {
+        Ord::min(
+            self.max_current,
+            ElectricCurrent::new::<milliampere>(Self::MAX_VSAFE5V_CURRENT_MILLIAMPERES),
+        )
+    }
+}
+
+/// Builder for [`SinkCapabilities`].
+///
+/// The first PDO is always the vSafe5V fixed supply. It carries the flags that describe the whole sink (see
+/// USB PD Spec R3.2 Table 6.17), which influence the source's behavior, e.g. its willingness to swap roles.
+#[derive(Clone, Debug)]
+pub struct SinkCapabilitiesBuilder {
+    vsafe5v: FixedSupply,
+    pdos: Vec<SinkPowerDataObject, 6>,
+}
+
+impl SinkCapabilitiesBuilder {
+    /// Create a new builder, with the mandatory vSafe5V PDO at the given operational current.
+    pub fn new(vsafe5v_operational_current: ElectricCurrent) -> Self {
+        Self {
+            vsafe5v: FixedSupply::new_vsafe5v(0).with_operational_current(vsafe5v_operational_current),
+            pdos: Vec::new(),
+        }
+    }
+
+    /// Create a new builder, with the PDOs that are derived from a high-level description of the sink.
+    ///
+    /// See [`SinkCapabilities::from_spec`].
+    pub fn from_spec(spec: SinkPowerSpec) -> Self {
+        Self {
+            vsafe5v: FixedSupply::new_vsafe5v(0).with_operational_current(spec.vsafe5v_current()),
+            pdos: spec.pdos(),
+        }
+    }
+
+    /// Declare support for dual-role power.
+    pub fn with_dual_role_power(mut self, dual_role_power: bool) -> Self {
+        self.vsafe5v = self.vsafe5v.with_dual_role_power(dual_role_power);
+        self
+    }
+
+    /// Declare support for dual-role data.
+    pub fn with_dual_role_data(mut self, dual_role_data: bool) -> Self {
+        self.vsafe5v = self.vsafe5v.with_dual_role_data(dual_role_data);
+        self
+    }
+
+    /// Declare the availability of an external power source.
+    pub fn with_unconstrained_power(mut self, unconstrained_power: bool) -> Self {
+        self.vsafe5v = self.vsafe5v.with_unconstrained_power(unconstrained_power);
+        self
+    }
+
+    /// Declare that the sink needs more than vSafe5V for full functionality.
+    pub fn with_higher_capability(mut self, higher_capability: bool) -> Self {
+        self.vsafe5v = self.vsafe5v.with_higher_capability(higher_capability);
+        self
+    }
+
+    /// Declare USB communications capability.
+    pub fn with_usb_communications_capable(mut self, usb_communications_capable: bool) -> Self {
+        self.vsafe5v = self.vsafe5v.with_usb_communications_capable(usb_communications_capable);
+        self
+    }
+
+    /// Declare the USB Type-C current that is required after a fast role swap.
+    pub fn with_fast_role_swap(mut self, current: FastRoleSwapCurrent) -> Self {
+        self.vsafe5v = self.vsafe5v.with_fast_role_swap(current);
+        self
+    }
+
+    /// Add a PDO after the vSafe5V PDO.
+    ///
+    /// PDOs should be added in order of increasing voltage. A PDO beyond the maximum of seven is dropped.
+    pub fn with_pdo(mut self, pdo: SinkPowerDataObject) -> Self {
+        if self.pdos.push(pdo).is_err() {
+            error!("Sink capabilities are full, dropping PDO");
+        }
+        self
+    }
+
+    /// Build the sink capabilities.
+    pub fn build(self) -> SinkCapabilities {
+        // The builder holds at most six PDOs, so that all fit after the vSafe5V PDO.
+        let mut pdos = Vec::new();
+        for pdo in core::iter::once(SinkPowerDataObject::FixedSupply(self.vsafe5v)).chain(self.pdos) {
+            _ = pdos.push(pdo);
+        }
+
+        SinkCapabilities(pdos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::electric_current::milliampere;
+    use uom::si::electric_potential::millivolt;
+    use uom::si::power::milliwatt;
+
+    use super::{
+        Battery, FastRoleSwapCurrent, FixedSupply, Pps, SinkCapabilities, SinkPowerDataObject, SinkPowerSpec,
+        VariableSupply,
+    };
+    use crate::units::{ElectricCurrent, ElectricPotential, Power};
+
+    fn mv(value: u32) -> ElectricPotential {
+        ElectricPotential::new::<millivolt>(value)
+    }
+
+    fn ma(value: u32) -> ElectricCurrent {
+        ElectricCurrent::new::<milliampere>(value)
+    }
+
+    #[test]
+    fn test_units() {
+        let fixed = FixedSupply::default()
+            .with_voltage(mv(9000))
+            .with_operational_current(ma(1500))
+            .with_fast_role_swap(FastRoleSwapCurrent::Current1_5A);
+        assert_eq!(fixed, FixedSupply::new(180, 150).with_raw_fast_role_swap(0b10));
+        assert_eq!(fixed.voltage(), mv(9000));
+        assert_eq!(fixed.operational_current(), ma(1500));
+        assert_eq!(fixed.fast_role_swap(), FastRoleSwapCurrent::Current1_5A);
+
+        let battery = Battery::default()
+            .with_min_voltage(mv(5000))
+            .with_max_voltage(mv(20000))
+            .with_operational_power(Power::new::<milliwatt>(15000));
+        assert_eq!(battery, Battery::new(100, 400, 60));
+
+        let variable = VariableSupply::default()
+            .with_min_voltage(mv(5000))
+            .with_max_voltage(mv(12000))
+            .with_operational_current(ma(2000));
+        assert_eq!(variable, VariableSupply::new(100, 240, 200));
+
+        let pps = Pps::new(mv(3300), mv(11000), ma(3000));
+        assert_eq!(pps.raw(), 0xC0DC_213C);
+        assert_eq!(pps.min_voltage(), mv(3300));
+        assert_eq!(pps.max_voltage(), mv(11000));
+        assert_eq!(pps.max_current(), ma(3000));
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        let pdos = [
+            SinkPowerDataObject::FixedSupply(FixedSupply::new_vsafe5v(300)),
+            SinkPowerDataObject::Battery(Battery::new(100, 400, 60)),
+            SinkPowerDataObject::VariableSupply(VariableSupply::new(100, 240, 200)),
+            SinkPowerDataObject::Pps(Pps::new(mv(3300), mv(11000), ma(3000))),
+        ];
+
+        for pdo in pdos {
+            assert_eq!(SinkPowerDataObject::parse_raw(pdo.raw()), Some(pdo));
+        }
+
+        // EPR AVS APDOs are not supported.
+        assert_eq!(SinkPowerDataObject::parse_raw(0xD3C0_968C), None);
+    }
+
+    #[test]
+    fn test_const() {
+        const CAPABILITIES: SinkCapabilities = SinkCapabilities::from_array([
+            SinkPowerDataObject::FixedSupply(FixedSupply::new_vsafe5v(300)),
+            SinkPowerDataObject::VariableSupply(VariableSupply::new(100, 240, 200)),
+            SinkPowerDataObject::Battery(Battery::new(100, 400, 60)),
+            SinkPowerDataObject::Pps(Pps::new_raw(33, 110, 60)),
+        ]);
+
+        let built = SinkCapabilities::builder(ma(3000))
+            .with_pdo(SinkPowerDataObject::VariableSupply(
+                VariableSupply::default()
+                    .with_min_voltage(mv(5000))
+                    .with_max_voltage(mv(12000))
+                    .with_operational_current(ma(2000)),
+            ))
+            .with_pdo(SinkPowerDataObject::Battery(
+                Battery::default()
+                    .with_min_voltage(mv(5000))
+                    .with_max_voltage(mv(20000))
+                    .with_operational_power(Power::new::<milliwatt>(15000)),
+            ))
+            .with_pdo(SinkPowerDataObject::Pps(Pps::new(mv(3300), mv(11000), ma(3000))))
+            .build();
+
+        assert_eq!(CAPABILITIES.pdos(), built.pdos());
+        assert_eq!(
+            SinkCapabilities::new_vsafe5v_only(300).pdos(),
+            &CAPABILITIES.pdos()[..1]
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let caps = SinkCapabilities::builder(ma(500))
+            .with_dual_role_power(true)
+            .with_dual_role_data(true)
+            .with_unconstrained_power(true)
+            .with_higher_capability(true)
+            .with_usb_communications_capable(true)
+            .with_pdo(SinkPowerDataObject::FixedSupply(FixedSupply::new(180, 200)))
+            .build();
+
+        assert_eq!(caps.num_objects(), 2);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(caps.to_bytes(&mut buf), 8);
+        // Flags in bits 29..=25, 5 V at 500 mA.
+        assert_eq!(&buf[..4], &0x3E01_9032u32.to_le_bytes());
+        assert_eq!(&buf[4..], &0x0002_D0C8u32.to_le_bytes());
+
+        let full = (0..7).fold(SinkCapabilities::builder(ma(100)), |builder, _| {
+            builder.with_pdo(SinkPowerDataObject::FixedSupply(FixedSupply::new(180, 100)))
+        });
+        assert_eq!(full.build().num_objects(), 7);
+    }
+
+    #[test]
+    fn test_from_spec() {
+        let spec = SinkPowerSpec {
+            min_voltage: mv(5_000),
+            max_voltage: mv(20_000),
+            max_current: ma(5_000),
+            pps: true,
+        };
+        let caps = SinkCapabilities::from_spec(spec);
+        let pdos = caps.pdos();
+
+        assert_eq!(pdos.len(), 6);
+        assert_eq!(pdos[0], SinkPowerDataObject::FixedSupply(FixedSupply::new_vsafe5v(300)));
+        assert_eq!(pdos[1], SinkPowerDataObject::FixedSupply(FixedSupply::new(180, 500)));
+        assert_eq!(pdos[2], SinkPowerDataObject::FixedSupply(FixedSupply::new(300, 500)));
+        assert_eq!(pdos[3], SinkPowerDataObject::FixedSupply(FixedSupply::new(400, 500)));
+        assert_eq!(
+            pdos[4],
+            SinkPowerDataObject::VariableSupply(VariableSupply::new(100, 400, 500))
+        );
+        assert_eq!(pdos[5], SinkPowerDataObject::Pps(Pps::new_raw(50, 200, 100)));
+
+        // A single voltage, without PPS support, only needs vSafe5V.
+        let caps = SinkCapabilities::from_spec(SinkPowerSpec {
+            min_voltage: mv(5_000),
+            max_voltage: mv(5_000),
+            max_current: ma(500),
+            pps: false,
+        });
+        assert_eq!(
+            caps.pdos(),
+            &[SinkPowerDataObject::FixedSupply(FixedSupply::new_vsafe5v(50))]
+        );
+    }
+}
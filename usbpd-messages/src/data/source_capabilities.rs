@@ -1,11 +1,11 @@
 //! Definitions of source capabilities data message content.
 use heapless::Vec;
 use proc_bitfield::bitfield;
-use uom::si::electric_current::centiampere;
-use uom::si::electric_potential::{decivolt, volt};
-use uom::si::power::watt;
+use uom::si::electric_current::{centiampere, milliampere};
+use uom::si::electric_potential::{decivolt, millivolt, volt};
+use uom::si::power::{milliwatt, watt};
 
-use super::PdoKind;
+use super::{ObjectPosition, PdoKind};
 use crate::_50milliamperes_mod::_50milliamperes;
 use crate::_50millivolts_mod::_50millivolts;
 use crate::_250milliwatts_mod::_250milliwatts;
@@ -45,25 +45,59 @@ pub enum PowerDataObject {
 }
 
 impl PowerDataObject {
+    /// Check if this PDO is of an unknown kind, e.g. one that a newer revision of the specification defines.
+    pub fn is_unknown(&self) -> bool {
+        matches!(
+            self,
+            PowerDataObject::Unknown(_) | PowerDataObject::Augmented(Augmented::Unknown(_))
+        )
+    }
+
     /// Check if this PDO is zero-padding (used in EPR capabilities messages).
     ///
     /// Per USB PD Spec R3.2 Section 6.5.15.1, if the SPR Capabilities Message
     /// contains fewer than 7 PDOs, the unused Data Objects are zero-filled.
+    ///
+    /// Padding separates SPR from EPR PDOs, and is not a capability. It parses as a fixed supply of 0 V, so it is
+    /// skipped by [`SourceCapabilities::offered_pdos`], and is never a request target.
     pub fn is_zero_padding(&self) -> bool {
-        (match self {
+        self.raw() == 0
+    }
+
+    /// The raw 32-bit value, as transmitted on the wire.
+    pub fn raw(&self) -> u32 {
+        match self {
             PowerDataObject::FixedSupply(f) => f.0,
             PowerDataObject::Battery(b) => b.0,
             PowerDataObject::VariableSupply(v) => v.0,
-            PowerDataObject::Augmented(a) => match a {
-                Augmented::Spr(s) => s.0,
-                Augmented::Epr(e) => e.0,
-                Augmented::Unknown(u) => *u,
-            },
+            PowerDataObject::Augmented(a) => a.raw(),
             PowerDataObject::Unknown(u) => u.0,
-        }) == 0
+        }
+    }
+}
+
+impl From<u32> for PowerDataObject {
+    fn from(raw: u32) -> Self {
+        parse_raw_pdo(raw)
     }
 }
 
+impl From<PowerDataObject> for u32 {
+    fn from(pdo: PowerDataObject) -> Self {
+        pdo.raw()
+    }
+}
+
+impl_raw!(
+    RawPowerDataObject,
+    FixedSupply,
+    Battery,
+    VariableSupply,
+    AugmentedRaw,
+    SprProgrammablePowerSupply,
+    EprAdjustableVoltageSupply,
+);
+
 bitfield! {
     /// A raw power data object.
     ///
@@ -194,6 +228,26 @@ pub enum Augmented {
     Unknown(u32),
 }
 
+impl Augmented {
+    /// The raw 32-bit value, as transmitted on the wire.
+    pub fn raw(&self) -> u32 {
+        match self {
+            Augmented::Spr(p) => p.0,
+            Augmented::Epr(p) => p.0,
+            Augmented::Unknown(p) => *p,
+        }
+    }
+}
+
+impl From<u32> for Augmented {
+    fn from(raw: u32) -> Self {
+        match parse_raw_pdo(raw) {
+            PowerDataObject::Augmented(augmented) => augmented,
+            _ => Augmented::Unknown(raw),
+        }
+    }
+}
+
 bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq)]
     #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -281,9 +335,28 @@ impl EprAdjustableVoltageSupply {
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct SourceCapabilities(pub(crate) Vec<PowerDataObject, 16>);
+pub struct SourceCapabilities(pub Vec<PowerDataObject, 16>);
 
 impl SourceCapabilities {
+    /// Create source capabilities from power data objects, starting with the vSafe5V fixed supply.
+    pub fn new(pdos: Vec<PowerDataObject, 16>) -> Self {
+        Self(pdos)
+    }
+
+    /// The number of data objects.
+    pub fn num_objects(&self) -> u8 {
+        self.0.len() as u8
+    }
+
+    /// Serialize the power data objects to a slice, returning the number of written bytes.
+    pub fn to_bytes(&self, buffer: &mut [u8]) -> usize {
+        for (pdo, chunk) in self.0.iter().zip(buffer.as_chunks_mut::<4>().0) {
+            *chunk = pdo.raw().to_le_bytes();
+        }
+
+        self.0.len() * 4
+    }
+
     pub fn vsafe_5v(&self) -> Option<&FixedSupply> {
         self.0.first().and_then(|supply| {
             if let PowerDataObject::FixedSupply(supply) = supply {
@@ -340,31 +413,56 @@ impl SourceCapabilities {
         self.0.len() > 7
     }
 
+    /// Get all PDOs at valid object positions (1-14), including zero-padding entries.
+    pub fn positioned_pdos(&self) -> impl Iterator<Item = (ObjectPosition, &PowerDataObject)> {
+        self.0
+            .iter()
+            .enumerate()
+            .map_while(|(index, pdo)| Some((ObjectPosition::from_index(index)?, pdo)))
+    }
+
+    /// Get the PDO at an object position, including zero-padding.
+    pub fn pdo_at(&self, position: ObjectPosition) -> Option<&PowerDataObject> {
+        self.0.get(position.index())
+    }
+
+    /// Get all PDOs at valid object positions (1-14), that the source offers, excluding zero-padding entries.
+    ///
+    /// These are the PDOs that a request may target.
+    pub fn offered_pdos(&self) -> impl Iterator<Item = (ObjectPosition, &PowerDataObject)> {
+        self.positioned_pdos().filter(|(_, pdo)| !pdo.is_zero_padding())
+    }
+
+    /// Get the PDO at an object position, if the source offers it, i.e. `None` for zero-padding.
+    ///
+    /// Use this for validating the object position of a request.
+    pub fn offered_pdo_at(&self, position: ObjectPosition) -> Option<&PowerDataObject> {
+        self.pdo_at(position).filter(|pdo| !pdo.is_zero_padding())
+    }
+
     /// Get SPR PDOs (positions 1-7), excluding zero-padding entries.
     ///
     /// Per USB PD Spec R3.2 Section 6.5.15.1:
     /// - Positions 1-7 contain SPR (A)PDOs
     /// - If fewer than 7 SPR PDOs exist, unused positions are zero-filled
-    ///
-    /// Returns iterator of (position, PDO) tuples where position is 1-indexed.
-    pub fn spr_pdos(&self) -> impl Iterator<Item = (u8, &PowerDataObject)> {
-        self.0
-            .iter()
-            .take(7)
-            .enumerate()
-            .filter(|(_, pdo)| !pdo.is_zero_padding())
-            .map(|(i, pdo)| ((i + 1) as u8, pdo))
+    pub fn spr_pdos(&self) -> impl Iterator<Item = (ObjectPosition, &PowerDataObject)> {
+        self.offered_pdos().filter(|(position, _)| !position.is_epr())
     }
 
-    /// Get EPR PDOs (positions 8+).
+    /// Get EPR PDOs (positions 8+), excluding zero-padding entries.
     ///
     /// Per USB PD Spec R3.2 Section 6.5.15.1:
     /// - EPR (A)PDOs start at Data Object position 8
     /// - Only valid in EPR Capabilities Messages
-    ///
-    /// Returns iterator of (position, PDO) tuples where position is 1-indexed (8, 9, 10, 11).
-    pub fn epr_pdos(&self) -> impl Iterator<Item = (u8, &PowerDataObject)> {
-        self.0.iter().skip(7).enumerate().map(|(i, pdo)| ((i + 8) as u8, pdo))
+    pub fn epr_pdos(&self) -> impl Iterator<Item = (ObjectPosition, &PowerDataObject)> {
+        self.offered_pdos().filter(|(position, _)| position.is_epr())
+    }
+
+    /// Get PDOs of unknown kind, e.g. from a newer revision of the specification, with their raw values.
+    pub fn unknown_pdos(&self) -> impl Iterator<Item = (ObjectPosition, u32)> {
+        self.positioned_pdos()
+            .filter(|(_, pdo)| pdo.is_unknown())
+            .map(|(position, pdo)| (position, pdo.raw()))
     }
 
     /// Check if any EPR PDO is in invalid position (1-7).
@@ -386,34 +484,120 @@ impl SourceCapabilities {
             _ => false,
         })
     }
+
+    /// Write a multi-line, human-readable description, e.g. for UART or CLI logs.
+    ///
+    /// The first line lists the flags of the source, followed by one line per PDO with its object position.
+    /// Zero-padding is skipped.
+    ///
+    /// ```
+    /// use usbpd_messages::data::source_capabilities::{FixedSupply, PowerDataObject, SourceCapabilities};
+    ///
+    /// let vsafe_5v = FixedSupply::default()
+    ///     .with_raw_voltage(100)
+    ///     .with_raw_max_current(300);
+    /// let capabilities = SourceCapabilities::new(heapless::Vec::from_iter([PowerDataObject::FixedSupply(vsafe_5v)]));
+    ///
+    /// let mut text: heapless::String<64> = heapless::String::new();
+    /// capabilities.describe(&mut text).unwrap();
+    /// assert_eq!(text, "Source capabilities:\n  1: Fixed 5.00 V, 3.00 A\n");
+    /// ```
+    pub fn describe(&self, out: &mut impl core::fmt::Write) -> core::fmt::Result {
+        let flags = [
+            (self.unconstrained_power(), "unconstrained power"),
+            (self.usb_suspend_supported(), "USB suspend"),
+            (self.dual_role_power(), "dual-role power"),
+            (self.dual_role_data(), "dual-role data"),
+            (self.epr_mode_capable(), "EPR mode capable"),
+        ];
+
+        out.write_str("Source capabilities:")?;
+        let mut separator = " ";
+        for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+            write!(out, "{separator}{flag}")?;
+            separator = ", ";
+        }
+        out.write_char('\n')?;
+
+        for (position, pdo) in self.offered_pdos() {
+            write!(out, "  {}: ", position.get())?;
+            match pdo {
+                PowerDataObject::FixedSupply(supply) => {
+                    out.write_str("Fixed ")?;
+                    write_millis(out, supply.voltage().get::<millivolt>(), "V")?;
+                    out.write_str(", ")?;
+                    write_millis(out, supply.max_current().get::<milliampere>(), "A")?;
+                }
+                PowerDataObject::Battery(battery) => {
+                    out.write_str("Battery ")?;
+                    write_range(out, battery.min_voltage(), battery.max_voltage())?;
+                    out.write_str(", ")?;
+                    write_millis(out, battery.max_power().get::<milliwatt>(), "W")?;
+                }
+                PowerDataObject::VariableSupply(supply) => {
+                    out.write_str("Variable ")?;
+                    write_range(out, supply.min_voltage(), supply.max_voltage())?;
+                    out.write_str(", ")?;
+                    write_millis(out, supply.max_current().get::<milliampere>(), "A")?;
+                }
+                PowerDataObject::Augmented(Augmented::Spr(pps)) => {
+                    out.write_str("PPS ")?;
+                    write_range(out, pps.min_voltage(), pps.max_voltage())?;
+                    out.write_str(", ")?;
+                    write_millis(out, pps.max_current().get::<milliampere>(), "A")?;
+                }
+                PowerDataObject::Augmented(Augmented::Epr(avs)) => {
+                    out.write_str("AVS ")?;
+                    write_range(out, avs.min_voltage(), avs.max_voltage())?;
+                    out.write_str(", ")?;
+                    write_millis(out, avs.pd_power().get::<milliwatt>(), "W")?;
+                }
+                PowerDataObject::Augmented(Augmented::Unknown(raw)) => write!(out, "Unknown APDO {raw:#010x}")?,
+                PowerDataObject::Unknown(raw) => write!(out, "Unknown PDO {:#010x}", raw.0)?,
+            }
+            out.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a value in thousandths of a unit, with two decimals.
+fn write_millis(out: &mut impl core::fmt::Write, millis: u32, unit: &str) -> core::fmt::Result {
+    write!(out, "{}.{:02} {unit}", millis / 1000, millis % 1000 / 10)
+}
+
+/// Write a voltage range.
+fn write_range(out: &mut impl core::fmt::Write, min: ElectricPotential, max: ElectricPotential) -> core::fmt::Result {
+    write_millis(out, min.get::<millivolt>(), "V")?;
+    out.write_str(" - ")?;
+    write_millis(out, max.get::<millivolt>(), "V")
 }
 
 impl PdoKind for SourceCapabilities {
-    fn at_object_position(&self, position: u8) -> Option<Kind> {
-        self.pdos()
-            .get(position.saturating_sub(1) as usize)
-            .and_then(|pdo| match pdo {
-                PowerDataObject::FixedSupply(_) => Some(Kind::FixedSupply),
-                PowerDataObject::Battery(_) => Some(Kind::Battery),
-                PowerDataObject::VariableSupply(_) => Some(Kind::VariableSupply),
-                PowerDataObject::Augmented(augmented) => match augmented {
-                    Augmented::Spr(_) => Some(Kind::Pps),
-                    Augmented::Epr(_) => Some(Kind::Avs),
-                    Augmented::Unknown(_) => None,
-                },
-                PowerDataObject::Unknown(_) => None,
-            })
+    fn at_object_position(&self, position: ObjectPosition) -> Option<Kind> {
+        self.offered_pdo_at(position).and_then(|pdo| match pdo {
+            PowerDataObject::FixedSupply(_) => Some(Kind::FixedSupply),
+            PowerDataObject::Battery(_) => Some(Kind::Battery),
+            PowerDataObject::VariableSupply(_) => Some(Kind::VariableSupply),
+            PowerDataObject::Augmented(augmented) => match augmented {
+                Augmented::Spr(_) => Some(Kind::Pps),
+                Augmented::Epr(_) => Some(Kind::Avs),
+                Augmented::Unknown(_) => None,
+            },
+            PowerDataObject::Unknown(_) => None,
+        })
     }
 }
 
 impl PdoKind for Option<SourceCapabilities> {
-    fn at_object_position(&self, position: u8) -> Option<Kind> {
+    fn at_object_position(&self, position: ObjectPosition) -> Option<Kind> {
         self.as_ref().at_object_position(position)
     }
 }
 
 impl PdoKind for Option<&SourceCapabilities> {
-    fn at_object_position(&self, position: u8) -> Option<Kind> {
+    fn at_object_position(&self, position: ObjectPosition) -> Option<Kind> {
         self.and_then(|s| s.at_object_position(position))
     }
 }
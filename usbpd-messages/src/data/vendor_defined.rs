@@ -57,14 +57,13 @@ impl From<VdmCommandType> for u8 {
 }
 
 impl From<u8> for VdmCommandType {
+    /// Only the two least significant bits are evaluated.
     fn from(value: u8) -> Self {
-        match value {
+        match value & 0b11 {
             0 => VdmCommandType::InitiatorREQ,
             1 => VdmCommandType::ResponderACK,
             2 => VdmCommandType::ResponderNAK,
-            3 => VdmCommandType::ResponderBSY,
-            _ => panic!("Cannot convert {} to VdmCommandType", value), /* Illegal values shall
-                                                                        * panic. */
+            _ => VdmCommandType::ResponderBSY,
         }
     }
 }
@@ -80,6 +79,8 @@ pub enum VdmCommand {
     Attention,
     DisplayPortStatus,
     DisplayPortConfig,
+    /// A reserved, or SVID specific command, kept as its raw value.
+    Other(u8),
 }
 
 impl From<VdmCommand> for u8 {
@@ -93,6 +94,7 @@ impl From<VdmCommand> for u8 {
             VdmCommand::Attention => 0x6,
             VdmCommand::DisplayPortStatus => 0x10,
             VdmCommand::DisplayPortConfig => 0x11,
+            VdmCommand::Other(value) => value,
         }
     }
 }
@@ -109,7 +111,7 @@ impl From<u8> for VdmCommand {
             0x10 => VdmCommand::DisplayPortStatus,
             0x11 => VdmCommand::DisplayPortConfig,
             // TODO: Find document that explains what 0x12-0x1f are (DP_SID??)
-            _ => panic!("Cannot convert {} to VdmCommand", value), // Illegal values shall panic.
+            _ => VdmCommand::Other(value),
         }
     }
 }
@@ -218,6 +220,50 @@ impl VdmHeaderStructured {
     pub fn to_bytes(self, buf: &mut [u8]) {
         LittleEndian::write_u32(buf, self.0);
     }
+
+    /// The structured VDM version, or `None` for a reserved major version.
+    ///
+    /// Minor versions beyond 2.1 are reported as 2.1, the latest known version.
+    pub fn version(&self) -> Option<VdmVersion> {
+        match (self.vdm_version_major().into(), self.vdm_version_minor().into()) {
+            (VdmVersionMajor::Version10, _) => Some(VdmVersion::V1_0),
+            (VdmVersionMajor::Version2x, VdmVersionMinor::Version20) => Some(VdmVersion::V2_0),
+            (VdmVersionMajor::Version2x, _) => Some(VdmVersion::V2_1),
+            (VdmVersionMajor::Reserved(_), _) => None,
+        }
+    }
+
+    /// Set the structured VDM version.
+    pub fn with_version(self, version: VdmVersion) -> Self {
+        let (major, minor) = match version {
+            VdmVersion::V1_0 => (VdmVersionMajor::Version10, VdmVersionMinor::Version20),
+            VdmVersion::V2_0 => (VdmVersionMajor::Version2x, VdmVersionMinor::Version20),
+            VdmVersion::V2_1 => (VdmVersionMajor::Version2x, VdmVersionMinor::Version21),
+        };
+        self.with_vdm_version_major(major.into())
+            .with_vdm_version_minor(minor.into())
+    }
+}
+
+/// The version of a structured VDM, combined from its major and minor version fields.
+///
+/// Port partners use the lower of their supported versions, see [6.4.4.2.3]. Versions are ordered, such that the
+/// common version is the minimum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VdmVersion {
+    /// Version 1.0, as used by USB PD revision 2.0.
+    V1_0,
+    /// Version 2.0.
+    V2_0,
+    /// Version 2.1.
+    V2_1,
+}
+
+impl VdmVersion {
+    /// The latest version, which is used until the version of the port partner is known.
+    pub const LATEST: Self = Self::V2_1;
 }
 
 impl Default for VdmHeaderStructured {
@@ -231,6 +277,8 @@ impl Default for VdmHeaderStructured {
 pub enum VdmVersionMajor {
     Version10,
     Version2x,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<VdmVersionMajor> for u8 {
@@ -238,6 +286,7 @@ impl From<VdmVersionMajor> for u8 {
         match value {
             VdmVersionMajor::Version10 => 0b00,
             VdmVersionMajor::Version2x => 0b01,
+            VdmVersionMajor::Reserved(value) => value,
         }
     }
 }
@@ -247,7 +296,7 @@ impl From<u8> for VdmVersionMajor {
         match value {
             0b00 => VdmVersionMajor::Version10,
             0b01 => VdmVersionMajor::Version2x,
-            _ => panic!("Cannot convert {} to VdmVersionMajor", value), // Illegal values shall panic.
+            _ => VdmVersionMajor::Reserved(value),
         }
     }
 }
@@ -257,6 +306,8 @@ impl From<u8> for VdmVersionMajor {
 pub enum VdmVersionMinor {
     Version20,
     Version21,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<VdmVersionMinor> for u8 {
@@ -264,6 +315,7 @@ impl From<VdmVersionMinor> for u8 {
         match value {
             VdmVersionMinor::Version20 => 0b00,
             VdmVersionMinor::Version21 => 0b01,
+            VdmVersionMinor::Reserved(value) => value,
         }
     }
 }
@@ -273,8 +325,7 @@ impl From<u8> for VdmVersionMinor {
         match value {
             0b00 => VdmVersionMinor::Version20,
             0b01 => VdmVersionMinor::Version21,
-            _ => panic!("Cannot convert {} to VdmVersionMinor", value), /* Illegal values shall
-                                                                         * panic. */
+            _ => VdmVersionMinor::Reserved(value),
         }
     }
 }
@@ -333,6 +384,8 @@ pub enum SopProductTypeUfp {
     PdUsbHub,
     PdUsbPeripheral,
     Psd,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<SopProductTypeUfp> for u8 {
@@ -342,6 +395,7 @@ impl From<SopProductTypeUfp> for u8 {
             SopProductTypeUfp::PdUsbHub => 0b001,
             SopProductTypeUfp::PdUsbPeripheral => 0b010,
             SopProductTypeUfp::Psd => 0b011,
+            SopProductTypeUfp::Reserved(value) => value,
         }
     }
 }
@@ -353,9 +407,7 @@ impl From<u8> for SopProductTypeUfp {
             0b001 => SopProductTypeUfp::PdUsbHub,
             0b010 => SopProductTypeUfp::PdUsbPeripheral,
             0b011 => SopProductTypeUfp::Psd,
-
-            _ => panic!("Cannot convert {} to SopProductTypeUfp", value), /* Illegal values
-                                                                           * shall panic. */
+            _ => SopProductTypeUfp::Reserved(value),
         }
     }
 }
@@ -367,6 +419,8 @@ pub enum SopProductTypeDfp {
     PDUSBHub,
     PDUSBHost,
     PowerBrick,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<SopProductTypeDfp> for u8 {
@@ -376,6 +430,7 @@ impl From<SopProductTypeDfp> for u8 {
             SopProductTypeDfp::PDUSBHub => 0b001,
             SopProductTypeDfp::PDUSBHost => 0b010,
             SopProductTypeDfp::PowerBrick => 0b011,
+            SopProductTypeDfp::Reserved(value) => value,
         }
     }
 }
@@ -387,9 +442,7 @@ impl From<u8> for SopProductTypeDfp {
             0b001 => SopProductTypeDfp::PDUSBHub,
             0b010 => SopProductTypeDfp::PDUSBHost,
             0b011 => SopProductTypeDfp::PowerBrick,
-
-            _ => panic!("Cannot convert {} to SopProductTypeDfp", value), /* Illegal values
-                                                                           * shall panic. */
+            _ => SopProductTypeDfp::Reserved(value),
         }
     }
 }
@@ -397,6 +450,8 @@ impl From<u8> for SopProductTypeDfp {
 pub enum ConnectorType {
     USBTypeCReceptacle,
     USBTypeCPlug,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<ConnectorType> for u8 {
@@ -404,6 +459,7 @@ impl From<ConnectorType> for u8 {
         match value {
             ConnectorType::USBTypeCReceptacle => 0b10,
             ConnectorType::USBTypeCPlug => 0b11,
+            ConnectorType::Reserved(value) => value,
         }
     }
 }
@@ -413,7 +469,7 @@ impl From<u8> for ConnectorType {
         match value {
             0b10 => ConnectorType::USBTypeCReceptacle,
             0b11 => ConnectorType::USBTypeCPlug,
-            _ => panic!("Cannot convert {} to ConnectorType", value), // Illegal values shall panic.
+            _ => ConnectorType::Reserved(value),
         }
     }
 }
@@ -477,6 +533,8 @@ pub enum USBHighestSpeed {
     USB32Gen2,
     USB40Gen3,
     USB40Gen4,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<USBHighestSpeed> for u8 {
@@ -487,6 +545,7 @@ impl From<USBHighestSpeed> for u8 {
             USBHighestSpeed::USB32Gen2 => 0b010,
             USBHighestSpeed::USB40Gen3 => 0b011,
             USBHighestSpeed::USB40Gen4 => 0b100,
+            USBHighestSpeed::Reserved(value) => value,
         }
     }
 }
@@ -499,7 +558,7 @@ impl From<u8> for USBHighestSpeed {
             0b010 => USBHighestSpeed::USB32Gen2,
             0b011 => USBHighestSpeed::USB40Gen3,
             0b100 => USBHighestSpeed::USB40Gen4,
-            _ => panic!("Cannot convert {} to USBHighestSpeed", value), // Illegal values shall panic.
+            _ => USBHighestSpeed::Reserved(value),
         }
     }
 }
@@ -514,6 +573,8 @@ pub enum VconnPower {
     P4W,
     P5W,
     P6W,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<VconnPower> for u8 {
@@ -526,6 +587,7 @@ impl From<VconnPower> for u8 {
             VconnPower::P4W => 0b100,
             VconnPower::P5W => 0b101,
             VconnPower::P6W => 0b110,
+            VconnPower::Reserved(value) => value,
         }
     }
 }
@@ -540,7 +602,7 @@ impl From<u8> for VconnPower {
             0b100 => VconnPower::P4W,
             0b101 => VconnPower::P5W,
             0b110 => VconnPower::P6W,
-            _ => panic!("Cannot convert {} to VconnPower", value), // Illegal values shall panic.
+            _ => VconnPower::Reserved(value),
         }
     }
 }
@@ -549,12 +611,15 @@ impl From<u8> for VconnPower {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum UFPVDOVersion {
     Version1_3,
+    /// A reserved value, kept as its raw value.
+    Reserved(u8),
 }
 
 impl From<UFPVDOVersion> for u8 {
     fn from(value: UFPVDOVersion) -> Self {
         match value {
             UFPVDOVersion::Version1_3 => 0b011,
+            UFPVDOVersion::Reserved(value) => value,
         }
     }
 }
@@ -563,7 +628,7 @@ impl From<u8> for UFPVDOVersion {
     fn from(value: u8) -> Self {
         match value {
             0b011 => UFPVDOVersion::Version1_3,
-            _ => panic!("Cannot convert {} to UFPVDOVersion", value), // Illegal values shall panic.
+            _ => UFPVDOVersion::Reserved(value),
         }
     }
 }
@@ -0,0 +1,55 @@
+//! Test data for the message parsers.
+use std::vec::Vec;
+
+use crate::data::source_capabilities::{Augmented, FixedSupply, PowerDataObject, SprProgrammablePowerSupply};
+
+/// EPR Source Capabilities - Chunk 0 (first 26 bytes of 40-byte message)
+/// Contains: 6 SPR PDOs + separator + start of EPR PDO #8 (28V)
+pub const DUMMY_EPR_SOURCE_CAPS_CHUNK_0: [u8; 30] = [
+    0xB1, 0xFD, 0x28, 0x80, 0x2C, 0x91, 0x91, 0x0A, 0x2C, 0xD1, 0x12, 0x00, 0x2C, 0xC1, 0x13, 0x00, 0x2C, 0xB1, 0x14,
+    0x00, 0xF4, 0x41, 0x16, 0x00, 0x64, 0x32, 0xA4, 0xC9, 0x00, 0x00,
+];
+
+/// EPR Source Capabilities - Chunk 1 (remaining 14 bytes)
+/// Contains: 3 EPR PDOs (28V, 36V, 48V @ 5A = 140W, 180W, 240W)
+pub const DUMMY_EPR_SOURCE_CAPS_CHUNK_1: [u8; 18] = [
+    0xB1, 0xCF, 0x28, 0x88, 0x00, 0x00, 0xF4, 0xC1, 0x18, 0x00, 0xF4, 0x41, 0x1B, 0x00, 0xF4, 0x01, 0x1F, 0x00,
+];
+
+/// Get dummy source capabilities for testing.
+///
+/// Corresponds to the `DUMMY_CAPABILITIES` of the `usbpd` crate.
+pub fn get_dummy_source_capabilities() -> Vec<PowerDataObject> {
+    vec![
+        PowerDataObject::FixedSupply(
+            FixedSupply::default()
+                .with_raw_voltage(100)
+                .with_raw_max_current(300)
+                .with_unconstrained_power(true),
+        ),
+        PowerDataObject::FixedSupply(FixedSupply::default().with_raw_voltage(180).with_raw_max_current(300)),
+        PowerDataObject::FixedSupply(FixedSupply::default().with_raw_voltage(300).with_raw_max_current(300)),
+        PowerDataObject::FixedSupply(FixedSupply::default().with_raw_voltage(400).with_raw_max_current(225)),
+        PowerDataObject::Augmented(Augmented::Spr(
+            SprProgrammablePowerSupply::default()
+                .with_raw_max_current(100)
+                .with_raw_min_voltage(33)
+                .with_raw_max_voltage(110)
+                .with_pps_power_limited(true),
+        )),
+        PowerDataObject::Augmented(Augmented::Spr(
+            SprProgrammablePowerSupply::default()
+                .with_raw_max_current(60)
+                .with_raw_min_voltage(33)
+                .with_raw_max_voltage(160)
+                .with_pps_power_limited(true),
+        )),
+        PowerDataObject::Augmented(Augmented::Spr(
+            SprProgrammablePowerSupply::default()
+                .with_raw_max_current(45)
+                .with_raw_min_voltage(33)
+                .with_raw_max_voltage(210)
+                .with_pps_power_limited(true),
+        )),
+    ]
+}
@@ -3,14 +3,14 @@
 //! Test fixtures captured from actual EPR hardware negotiation (KM003C sniffer).
 //! Covers: EPR mode entry, chunked source capabilities, EPR requests, keep-alive.
 
+use crate::data::Data;
+use crate::data::epr_mode::Action;
+use crate::data::request::PowerSource;
 use crate::dummy::{DUMMY_EPR_SOURCE_CAPS_CHUNK_0, DUMMY_EPR_SOURCE_CAPS_CHUNK_1};
-use crate::protocol_layer::message::data::Data;
-use crate::protocol_layer::message::data::epr_mode::Action;
-use crate::protocol_layer::message::data::request::PowerSource;
-use crate::protocol_layer::message::extended::Extended;
-use crate::protocol_layer::message::extended::chunked::{ChunkResult, ChunkedMessageAssembler};
-use crate::protocol_layer::message::header::{DataMessageType, ExtendedMessageType, MessageType};
-use crate::protocol_layer::message::{Message, Payload};
+use crate::extended::Extended;
+use crate::extended::chunked::{ChunkResult, ChunkedMessageAssembler};
+use crate::header::{DataMessageType, ExtendedMessageType, MessageType};
+use crate::{Message, Payload};
 
 // ============================================================================
 // Test Fixtures - Real EPR Messages
@@ -97,9 +97,7 @@ fn test_chunked_epr_source_caps_assembly() {
                 assert_eq!(pdos.len(), 10, "Expected 10 PDOs (6 SPR + 1 separator + 3 EPR)");
 
                 // Verify separator at PDO[6]
-                if let crate::protocol_layer::message::data::source_capabilities::PowerDataObject::FixedSupply(pdo) =
-                    &pdos[6]
-                {
+                if let crate::data::source_capabilities::PowerDataObject::FixedSupply(pdo) = &pdos[6] {
                     assert_eq!(pdo.0, 0, "PDO[6] should be separator (0x00000000)");
                 } else {
                     panic!("PDO[6] should be separator");
@@ -107,9 +105,7 @@ fn test_chunked_epr_source_caps_assembly() {
 
                 // Verify EPR PDO exists at position 7 (28V)
                 use uom::si::electric_potential::volt;
-                if let crate::protocol_layer::message::data::source_capabilities::PowerDataObject::FixedSupply(pdo) =
-                    &pdos[7]
-                {
+                if let crate::data::source_capabilities::PowerDataObject::FixedSupply(pdo) = &pdos[7] {
                     assert_eq!(pdo.voltage().get::<volt>() as f64, 28.0);
                 } else {
                     panic!("PDO[7] should be 28V EPR FixedSupply");
@@ -143,7 +139,7 @@ fn test_epr_request_parsing() {
         // Verify PDO is 28V
         use uom::si::electric_potential::volt;
 
-        use crate::protocol_layer::message::data::source_capabilities::PowerDataObject;
+        use crate::data::source_capabilities::PowerDataObject;
         if let PowerDataObject::FixedSupply(fixed) = epr.pdo {
             assert_eq!(fixed.voltage().get::<volt>() as f64, 28.0);
         } else {
@@ -164,9 +160,74 @@ fn test_epr_keep_alive() {
     );
 
     if let Some(Payload::Extended(Extended::ExtendedControl(ctrl))) = msg.payload {
-        use crate::protocol_layer::message::extended::extended_control::ExtendedControlMessageType;
+        use crate::extended::extended_control::ExtendedControlMessageType;
         assert_eq!(ctrl.message_type(), ExtendedControlMessageType::EprKeepAlive);
     } else {
         panic!("Expected ExtendedControl EprKeepAlive payload");
     }
 }
+
+#[test]
+fn test_epr_request_raw_words() {
+    let msg = Message::from_bytes(EPR_REQUEST_28V).expect("Failed to parse EPR_REQUEST_28V");
+
+    // RDO and PDO copy, as on the wire.
+    assert_eq!(msg.raw_words().as_slice(), &[0x80C7_D1F4, 0x0018_C1F4]);
+
+    if let Some(Payload::Data(Data::Request(PowerSource::EprRequest(epr)))) = msg.payload {
+        use crate::data::source_capabilities::PowerDataObject;
+
+        assert_eq!(PowerDataObject::from(epr.pdo.raw()), epr.pdo);
+        assert_eq!(u32::from(epr.pdo), 0x0018_C1F4);
+    } else {
+        panic!("Expected EprRequest payload");
+    }
+}
+
+#[test]
+fn test_epr_request_round_trip() {
+    let msg = Message::from_bytes(EPR_REQUEST_28V).expect("Failed to parse EPR_REQUEST_28V");
+
+    let mut buf = [0u8; 32];
+    let len = msg.to_bytes(&mut buf);
+    assert_eq!(&buf[..len], EPR_REQUEST_28V);
+}
+
+#[test]
+fn test_epr_request_new() {
+    use crate::data::ObjectPosition;
+    use crate::data::request::{Avs, EprRequestDataObject, FixedVariableSupply};
+    use crate::data::source_capabilities::{Augmented, PowerDataObject};
+    use crate::header::Header;
+
+    // Rebuild the captured request from its PDO copy and request parameters.
+    let msg = Message::from_bytes(EPR_REQUEST_28V).expect("Failed to parse EPR_REQUEST_28V");
+    let Some(Payload::Data(Data::Request(PowerSource::EprRequest(captured)))) = msg.payload else {
+        panic!("Expected EprRequest payload");
+    };
+
+    let rdo = FixedVariableSupply(captured.rdo).with_object_position(0);
+    let epr = EprRequestDataObject::new(ObjectPosition::FIRST_EPR, &captured.pdo, rdo);
+    assert_eq!(epr, captured);
+
+    // An AVS APDO (15-48 V, 140 W) is copied without modification, including its reserved bits.
+    let raw_apdo = 0xD3C0_968C | (1 << 16);
+    let pdo = PowerDataObject::from(raw_apdo);
+    assert!(matches!(pdo, PowerDataObject::Augmented(Augmented::Epr(_))));
+
+    let epr = EprRequestDataObject::new(
+        ObjectPosition::new(9).unwrap(),
+        &pdo,
+        Avs(0).with_raw_output_voltage(1120),
+    );
+    assert_eq!(epr.object_position(), 9);
+    assert_eq!(epr.raw()[1], raw_apdo);
+
+    let header = Header(msg.header.0);
+    let msg = Message::new_with_data(header, Data::Request(PowerSource::EprRequest(epr)));
+    let mut buf = [0u8; 32];
+    let len = msg.to_bytes(&mut buf);
+
+    let parsed = Message::from_bytes(&buf[..len]).expect("Failed to parse serialized EPR request");
+    assert_eq!(parsed.raw_words().as_slice(), &epr.raw());
+}
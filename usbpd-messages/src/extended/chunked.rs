@@ -10,8 +10,8 @@ use heapless::Vec;
 use super::ExtendedHeader;
 // Re-export for convenience
 pub use super::ExtendedHeader as ChunkExtendedHeader;
-use crate::protocol_layer::message::ParseError;
-use crate::protocol_layer::message::header::{ExtendedMessageType, Header};
+use crate::ParseError;
+use crate::header::{ExtendedMessageType, Header, MessageId};
 
 /// Maximum data bytes in a single extended message chunk.
 pub const MAX_EXTENDED_MSG_CHUNK_LEN: usize = 26;
@@ -22,6 +22,46 @@ pub const MAX_EXTENDED_MSG_LEN: usize = 260;
 /// Maximum number of chunks (260 / 26 = 10).
 pub const MAX_CHUNKS: usize = MAX_EXTENDED_MSG_LEN / MAX_EXTENDED_MSG_CHUNK_LEN;
 
+/// The length of a chunk request: the message header, the extended header, and padding to a whole data object.
+pub const CHUNK_REQUEST_LEN: usize = 6;
+
+/// Serialize a request for chunk `chunk_number` of an extended message of `message_type`, returning the number of
+/// written bytes, see spec [6.12.2.1.2.4].
+///
+/// The roles and revision of the message header are taken from `template`. The message type is the one of the
+/// chunked message that is requested, for any extended message type, including vendor and security messages.
+///
+/// ```
+/// use usbpd_messages::Message;
+/// use usbpd_messages::extended::chunked::{CHUNK_REQUEST_LEN, chunk_request_to_bytes};
+/// use usbpd_messages::header::{ExtendedMessageType, Header, MessageId};
+///
+/// let mut buffer = [0u8; CHUNK_REQUEST_LEN];
+/// let template = Header(0x0040);
+/// let len = chunk_request_to_bytes(template, MessageId::new(3), ExtendedMessageType::FirmwareUpdateResponse, 1, &mut buffer);
+///
+/// let (header, extended_header, _) = Message::parse_extended_chunk(&buffer[..len]).unwrap();
+/// assert_eq!(header.message_id(), 3);
+/// assert!(extended_header.request_chunk());
+/// assert_eq!(extended_header.chunk_number(), 1);
+/// ```
+pub fn chunk_request_to_bytes(
+    template: Header,
+    message_id: MessageId,
+    message_type: ExtendedMessageType,
+    chunk_number: u8,
+    buffer: &mut [u8],
+) -> usize {
+    let header = Header::new_extended_for_payload(template, message_id, message_type, 0);
+    let extended_header = ChunkedMessageAssembler::build_chunk_request_header(chunk_number);
+
+    let mut offset = header.to_bytes(buffer);
+    offset += extended_header.to_bytes(&mut buffer[offset..]);
+    buffer[offset..CHUNK_REQUEST_LEN].fill(0);
+
+    CHUNK_REQUEST_LEN
+}
+
 /// Information about a received chunk.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -56,11 +96,11 @@ pub enum ChunkResult<T> {
 ///
 /// # Example
 /// ```
-/// use usbpd::protocol_layer::message::extended::chunked::{
+/// use usbpd_messages::extended::chunked::{
 ///     ChunkedMessageAssembler, ChunkResult, MAX_EXTENDED_MSG_CHUNK_LEN,
 /// };
-/// use usbpd::protocol_layer::message::extended::ExtendedHeader;
-/// use usbpd::protocol_layer::message::header::Header;
+/// use usbpd_messages::extended::ExtendedHeader;
+/// use usbpd_messages::header::Header;
 ///
 /// let mut assembler = ChunkedMessageAssembler::new();
 ///
@@ -411,6 +451,38 @@ mod tests {
         assert!(sender.is_complete());
     }
 
+    #[test]
+    fn test_chunk_request() {
+        use crate::header::SpecificationRevision;
+        use crate::{DataRole, Message, ParseError, PowerRole};
+
+        let template = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+        let mut buffer = [0xffu8; 8];
+        let len = chunk_request_to_bytes(
+            template,
+            MessageId::new(5),
+            ExtendedMessageType::SecurityResponse,
+            2,
+            &mut buffer,
+        );
+
+        assert_eq!(len, CHUNK_REQUEST_LEN);
+        assert_eq!(&buffer[4..len], &[0, 0]);
+        assert!(matches!(
+            Message::from_bytes(&buffer[..len]),
+            Err(ParseError::ChunkedExtendedMessage {
+                chunk_number: 2,
+                data_size: 0,
+                request_chunk: true,
+                message_type: ExtendedMessageType::SecurityResponse,
+            })
+        ));
+
+        let header = Header::from_bytes(&buffer[..2]).unwrap();
+        assert_eq!(header.num_objects(), 1);
+        assert_eq!(header.message_id(), 5);
+    }
+
     #[test]
     fn test_assembler_single_chunk() {
         let mut assembler = ChunkedMessageAssembler::new();
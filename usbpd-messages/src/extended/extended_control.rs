@@ -23,6 +23,8 @@ pub enum ExtendedControlMessageType {
     EprKeepAlive,
     /// The EPR keep-alive ack message shall be sent by a source operating in EPR mode in response to an [`Self::EprKeepAlive`] message.
     EprKeepAliveAck,
+    /// A reserved, or unknown type, with its raw value.
+    Unknown(u8),
 }
 
 impl From<ExtendedControlMessageType> for u8 {
@@ -32,6 +34,7 @@ impl From<ExtendedControlMessageType> for u8 {
             ExtendedControlMessageType::EprGetSinkCap => 2,
             ExtendedControlMessageType::EprKeepAlive => 3,
             ExtendedControlMessageType::EprKeepAliveAck => 4,
+            ExtendedControlMessageType::Unknown(value) => value,
         }
     }
 }
@@ -43,7 +46,7 @@ impl From<u8> for ExtendedControlMessageType {
             2 => ExtendedControlMessageType::EprGetSinkCap,
             3 => ExtendedControlMessageType::EprKeepAlive,
             4 => ExtendedControlMessageType::EprKeepAliveAck,
-            _ => panic!("Cannot convert {} to ExtendedControlMessageType", value), // Illegal values shall panic.
+            _ => ExtendedControlMessageType::Unknown(value),
         }
     }
 }
@@ -71,9 +74,17 @@ impl ExtendedControl {
     }
 
     /// Parse an extended control message from bytes.
+    ///
+    /// Panics, if the buffer is shorter than two bytes. With the `panic-free` feature, a short buffer parses as an
+    /// empty message instead.
     pub fn from_bytes(buf: &[u8]) -> Self {
-        assert!(buf.len() >= 2);
-        Self(LittleEndian::read_u16(buf))
+        match buf.first_chunk() {
+            Some(bytes) => Self(u16::from_le_bytes(*bytes)),
+            #[cfg(feature = "panic-free")]
+            None => Self(0),
+            #[cfg(not(feature = "panic-free"))]
+            None => panic!("Extended control message requires two bytes, found {}", buf.len()),
+        }
     }
 }
 
@@ -82,3 +93,18 @@ impl Default for ExtendedControl {
         Self(0).with_data(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ExtendedControl, ExtendedControlMessageType};
+
+    #[test]
+    fn test_unknown_type() {
+        let control = ExtendedControl::from_bytes(&[0x2a, 0x00]);
+        assert_eq!(control.message_type(), ExtendedControlMessageType::Unknown(0x2a));
+        assert_eq!(u8::from(control.message_type()), 0x2a);
+
+        let control = ExtendedControl::from_bytes(&[0x03, 0x00]);
+        assert_eq!(control.message_type(), ExtendedControlMessageType::EprKeepAlive);
+    }
+}
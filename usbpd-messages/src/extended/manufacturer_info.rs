@@ -0,0 +1,166 @@
+//! Definitions of the Manufacturer_Info extended message content.
+//!
+//! A port partner answers Get_Manufacturer_Info with the Manufacturer Info Data Block (MIDB).
+//!
+//! See [6.5.7] and [6.5.8].
+use heapless::Vec;
+
+/// The maximum length of the manufacturer string, see [Table 6.59].
+pub const MAX_MANUFACTURER_STRING_LEN: usize = 22;
+
+/// The size of the Manufacturer Info Data Block without the manufacturer string.
+pub const MANUFACTURER_INFO_HEADER_SIZE: usize = 4;
+
+/// The target of a Get_Manufacturer_Info message, see [Table 6.58].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ManufacturerInfoTarget {
+    /// The port or cable plug.
+    Port,
+    /// The battery with the given reference.
+    Battery(u8),
+    /// A reserved target.
+    Reserved(u8),
+}
+
+impl ManufacturerInfoTarget {
+    /// Parse the Get Manufacturer Info Data Block.
+    ///
+    /// Returns `None`, if the block is shorter than two bytes.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let [target, reference] = *buf.first_chunk()?;
+
+        Some(match target {
+            0 => Self::Port,
+            1 => Self::Battery(reference),
+            _ => Self::Reserved(target),
+        })
+    }
+
+    /// Serialize the Get Manufacturer Info Data Block, returning its size.
+    ///
+    /// Writes as much of the block, as fits into the buffer.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> usize {
+        let bytes = match *self {
+            Self::Port => [0, 0],
+            Self::Battery(reference) => [1, reference],
+            Self::Reserved(target) => [target, 0],
+        };
+
+        buf.iter_mut().zip(bytes).map(|(byte, value)| *byte = value).count()
+    }
+}
+
+/// The Manufacturer Info Data Block (MIDB) of a Manufacturer_Info message, see [6.5.8].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManufacturerInfo {
+    /// The USB vendor ID.
+    pub vid: u16,
+    /// The USB product ID.
+    pub pid: u16,
+    /// The manufacturer string, without null termination.
+    pub manufacturer_string: Vec<u8, MAX_MANUFACTURER_STRING_LEN>,
+}
+
+impl ManufacturerInfo {
+    /// Create a Manufacturer Info Data Block.
+    ///
+    /// The manufacturer string is truncated to [`MAX_MANUFACTURER_STRING_LEN`] bytes.
+    pub fn new(vid: u16, pid: u16, manufacturer_string: &[u8]) -> Self {
+        let len = manufacturer_string.len().min(MAX_MANUFACTURER_STRING_LEN);
+
+        Self {
+            vid,
+            pid,
+            manufacturer_string: manufacturer_string.iter().take(len).copied().collect(),
+        }
+    }
+
+    /// The answer to a Get_Manufacturer_Info message with an invalid target or reference, see [6.5.8].
+    pub fn not_supported() -> Self {
+        Self::new(0xffff, 0, b"Not Supported")
+    }
+
+    /// The size of the data block in bytes.
+    pub fn data_size(&self) -> usize {
+        MANUFACTURER_INFO_HEADER_SIZE + self.manufacturer_string.len()
+    }
+
+    /// Parse a Manufacturer Info Data Block.
+    ///
+    /// Missing bytes of the IDs read as zero, and the manufacturer string is truncated to
+    /// [`MAX_MANUFACTURER_STRING_LEN`] bytes.
+    pub fn from_bytes(buf: &[u8]) -> Self {
+        let id = |offset: usize| {
+            buf.get(offset..)
+                .and_then(<[u8]>::first_chunk)
+                .map_or(0, |bytes| u16::from_le_bytes(*bytes))
+        };
+
+        Self::new(
+            id(0),
+            id(2),
+            buf.get(MANUFACTURER_INFO_HEADER_SIZE..).unwrap_or_default(),
+        )
+    }
+
+    /// Serialize the Manufacturer Info Data Block, returning its size.
+    ///
+    /// Writes as much of the block, as fits into the buffer.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> usize {
+        let [vid_low, vid_high] = self.vid.to_le_bytes();
+        let [pid_low, pid_high] = self.pid.to_le_bytes();
+        let bytes = [vid_low, vid_high, pid_low, pid_high]
+            .into_iter()
+            .chain(self.manufacturer_string.iter().copied());
+
+        buf.iter_mut().zip(bytes).map(|(byte, value)| *byte = value).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_MANUFACTURER_STRING_LEN, ManufacturerInfo, ManufacturerInfoTarget};
+
+    #[test]
+    fn test_manufacturer_info() {
+        let bytes = [0x09, 0x12, 0x01, 0x00, b'u', b's', b'b', b'p', b'd'];
+        let info = ManufacturerInfo::from_bytes(&bytes);
+
+        assert_eq!(info, ManufacturerInfo::new(0x1209, 0x0001, b"usbpd"));
+        assert_eq!(info.data_size(), bytes.len());
+
+        let mut buf = [0u8; 26];
+        assert_eq!(info.to_bytes(&mut buf), bytes.len());
+        assert_eq!(&buf[..bytes.len()], &bytes);
+
+        let long = ManufacturerInfo::new(0x1209, 0x0001, b"a manufacturer string that is too long");
+        assert_eq!(long.manufacturer_string.len(), MAX_MANUFACTURER_STRING_LEN);
+        assert_eq!(long.to_bytes(&mut buf), buf.len());
+        assert_eq!(long.to_bytes(&mut buf[..3]), 3);
+    }
+
+    #[test]
+    fn test_manufacturer_info_target() {
+        assert_eq!(
+            ManufacturerInfoTarget::from_bytes(&[0, 0]),
+            Some(ManufacturerInfoTarget::Port)
+        );
+        assert_eq!(
+            ManufacturerInfoTarget::from_bytes(&[1, 4]),
+            Some(ManufacturerInfoTarget::Battery(4))
+        );
+        assert_eq!(
+            ManufacturerInfoTarget::from_bytes(&[2, 0]),
+            Some(ManufacturerInfoTarget::Reserved(2))
+        );
+        assert_eq!(ManufacturerInfoTarget::from_bytes(&[0]), None);
+
+        let mut buf = [0u8; 2];
+        assert_eq!(ManufacturerInfoTarget::Battery(4).to_bytes(&mut buf), 2);
+        assert_eq!(buf, [1, 4]);
+    }
+}
@@ -4,12 +4,15 @@
 
 pub mod chunked;
 pub mod extended_control;
+pub mod manufacturer_info;
+pub mod pps_status;
+pub mod status;
 use byteorder::{ByteOrder, LittleEndian};
 use heapless::Vec;
 use proc_bitfield::bitfield;
 
-use crate::protocol_layer::message::data::sink_capabilities::SinkPowerDataObject;
-use crate::protocol_layer::message::data::source_capabilities::PowerDataObject;
+use crate::data::sink_capabilities::SinkPowerDataObject;
+use crate::data::source_capabilities::PowerDataObject;
 
 /// Types of extended messages.
 ///
@@ -24,6 +27,14 @@ pub enum Extended {
     SourceCapabilitiesExtended,
     /// Extended control message payload.
     ExtendedControl(extended_control::ExtendedControl),
+    /// The Status Data Block, e.g. in answer to Get_Status.
+    Status(status::StatusExtended),
+    /// The PPS Status Data Block, in answer to Get_PPS_Status.
+    PpsStatus(pps_status::PpsStatusExtended),
+    /// The Get Manufacturer Info Data Block.
+    GetManufacturerInfo(manufacturer_info::ManufacturerInfoTarget),
+    /// The Manufacturer Info Data Block, in answer to Get_Manufacturer_Info.
+    ManufacturerInfo(manufacturer_info::ManufacturerInfo),
     /// EPR source capabilities list.
     EprSourceCapabilities(Vec<PowerDataObject, 16>),
     /// EPR sink capabilities list.
@@ -38,6 +49,10 @@ impl Extended {
         match self {
             Self::SourceCapabilitiesExtended => 0,
             Self::ExtendedControl(_payload) => 2,
+            Self::Status(_) => status::STATUS_DATA_BLOCK_SIZE as u16,
+            Self::PpsStatus(_) => pps_status::PPS_STATUS_DATA_BLOCK_SIZE as u16,
+            Self::GetManufacturerInfo(_) => 2,
+            Self::ManufacturerInfo(info) => info.data_size() as u16,
             Self::EprSourceCapabilities(pdos) => (pdos.len() * core::mem::size_of::<u32>()) as u16,
             Self::EprSinkCapabilities(pdos) => (pdos.len() * core::mem::size_of::<u32>()) as u16,
             Self::Unknown => 0,
@@ -48,34 +63,28 @@ impl Extended {
     pub fn to_bytes(&self, payload: &mut [u8]) -> usize {
         match self {
             Self::Unknown => 0,
+            #[cfg(feature = "panic-free")]
+            Self::SourceCapabilitiesExtended => 0,
+            #[cfg(not(feature = "panic-free"))]
             Self::SourceCapabilitiesExtended => unimplemented!(),
             Self::ExtendedControl(control) => control.to_bytes(payload),
+            Self::Status(status) => status.to_bytes(payload),
+            Self::PpsStatus(status) => status.to_bytes(payload),
+            Self::GetManufacturerInfo(target) => target.to_bytes(payload),
+            Self::ManufacturerInfo(info) => info.to_bytes(payload),
             Self::EprSourceCapabilities(pdos) => {
-                let mut written = 0;
-                for pdo in pdos {
-                    let raw = match pdo {
-                        PowerDataObject::FixedSupply(p) => p.0,
-                        PowerDataObject::Battery(p) => p.0,
-                        PowerDataObject::VariableSupply(p) => p.0,
-                        PowerDataObject::Augmented(a) => match a {
-                            crate::protocol_layer::message::data::source_capabilities::Augmented::Spr(p) => p.0,
-                            crate::protocol_layer::message::data::source_capabilities::Augmented::Epr(p) => p.0,
-                            crate::protocol_layer::message::data::source_capabilities::Augmented::Unknown(p) => *p,
-                        },
-                        PowerDataObject::Unknown(p) => p.0,
-                    };
-                    LittleEndian::write_u32(&mut payload[written..written + 4], raw);
-                    written += 4;
+                for (pdo, chunk) in pdos.iter().zip(payload.as_chunks_mut::<4>().0) {
+                    *chunk = pdo.raw().to_le_bytes();
                 }
-                written
+
+                pdos.len() * 4
             }
             Self::EprSinkCapabilities(pdos) => {
-                let mut written = 0;
-                for pdo in pdos {
-                    LittleEndian::write_u32(&mut payload[written..written + 4], pdo.to_raw());
-                    written += 4;
+                for (pdo, chunk) in pdos.iter().zip(payload.as_chunks_mut::<4>().0) {
+                    *chunk = pdo.to_raw().to_le_bytes();
                 }
-                written
+
+                pdos.len() * 4
             }
         }
     }
@@ -113,8 +122,16 @@ impl ExtendedHeader {
     }
 
     /// Parse an extended header from bytes.
+    ///
+    /// Panics, if the buffer is shorter than two bytes. With the `panic-free` feature, a short buffer parses as an
+    /// empty header instead.
     pub fn from_bytes(buf: &[u8]) -> Self {
-        assert!(buf.len() >= 2);
-        Self(LittleEndian::read_u16(buf))
+        match buf.first_chunk() {
+            Some(bytes) => Self(u16::from_le_bytes(*bytes)),
+            #[cfg(feature = "panic-free")]
+            None => Self(0),
+            #[cfg(not(feature = "panic-free"))]
+            None => panic!("Extended header requires two bytes, found {}", buf.len()),
+        }
     }
 }
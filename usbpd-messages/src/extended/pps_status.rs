@@ -0,0 +1,142 @@
+//! Definitions of the PPS_Status extended message content.
+//!
+//! A source that operates a programmable power supply answers Get_PPS_Status with the PPS Status Data Block (PPSSDB).
+//!
+//! See [6.5.10].
+use proc_bitfield::bitfield;
+
+use super::status::TemperatureStatus;
+use crate::_20millivolts_mod::_20millivolts;
+use crate::_50milliamperes_mod::_50milliamperes;
+use crate::units::{ElectricCurrent, ElectricPotential};
+
+/// The size of the PPS Status Data Block, see [Table 6.61].
+pub const PPS_STATUS_DATA_BLOCK_SIZE: usize = 4;
+
+/// The raw output voltage of a source that does not support measuring it.
+const OUTPUT_VOLTAGE_NOT_SUPPORTED: u16 = 0xffff;
+
+/// The raw output current of a source that does not support measuring it.
+const OUTPUT_CURRENT_NOT_SUPPORTED: u8 = 0xff;
+
+bitfield! {
+    /// The real time flags of a programmable power supply, see [Table 6.61].
+    #[derive(Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct PpsRealTimeFlags(pub u8): Debug, FromStorage, IntoStorage {
+        /// The supply operates in current limit mode (OMF), instead of constant voltage mode.
+        pub current_limit: bool @ 3,
+        /// The raw temperature flags (PTF), see [`Self::temperature_status`].
+        pub raw_temperature: u8 @ 1..=2,
+    }
+}
+
+impl PpsRealTimeFlags {
+    /// The temperature state of the supply.
+    pub fn temperature_status(&self) -> TemperatureStatus {
+        TemperatureStatus::from_raw(self.raw_temperature())
+    }
+}
+
+/// The PPS Status Data Block (PPSSDB) of a PPS_Status message, see [6.5.10].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpsStatusExtended {
+    /// The output voltage in 20 mV units, or `0xffff`, if not supported.
+    pub raw_output_voltage: u16,
+    /// The output current in 50 mA units, or `0xff`, if not supported.
+    pub raw_output_current: u8,
+    /// The real time flags.
+    pub flags: PpsRealTimeFlags,
+}
+
+impl Default for PpsStatusExtended {
+    fn default() -> Self {
+        Self {
+            raw_output_voltage: OUTPUT_VOLTAGE_NOT_SUPPORTED,
+            raw_output_current: OUTPUT_CURRENT_NOT_SUPPORTED,
+            flags: PpsRealTimeFlags::default(),
+        }
+    }
+}
+
+impl PpsStatusExtended {
+    /// The present output voltage, if the source measures it.
+    pub fn output_voltage(&self) -> Option<ElectricPotential> {
+        (self.raw_output_voltage != OUTPUT_VOLTAGE_NOT_SUPPORTED)
+            .then(|| ElectricPotential::new::<_20millivolts>(self.raw_output_voltage.into()))
+    }
+
+    /// The present output current, if the source measures it.
+    pub fn output_current(&self) -> Option<ElectricCurrent> {
+        (self.raw_output_current != OUTPUT_CURRENT_NOT_SUPPORTED)
+            .then(|| ElectricCurrent::new::<_50milliamperes>(self.raw_output_current.into()))
+    }
+
+    /// Parse a PPS Status Data Block.
+    ///
+    /// Missing trailing bytes read as not supported.
+    pub fn from_bytes(buf: &[u8]) -> Self {
+        let default = Self::default();
+
+        Self {
+            raw_output_voltage: buf
+                .first_chunk()
+                .map_or(default.raw_output_voltage, |bytes| u16::from_le_bytes(*bytes)),
+            raw_output_current: buf.get(2).copied().unwrap_or(default.raw_output_current),
+            flags: buf.get(3).copied().map_or(default.flags, PpsRealTimeFlags),
+        }
+    }
+
+    /// Serialize the PPS Status Data Block, returning its size.
+    ///
+    /// The buffer must be able to hold at least [`PPS_STATUS_DATA_BLOCK_SIZE`] bytes.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> usize {
+        let [voltage_low, voltage_high] = self.raw_output_voltage.to_le_bytes();
+        buf[..PPS_STATUS_DATA_BLOCK_SIZE].copy_from_slice(&[
+            voltage_low,
+            voltage_high,
+            self.raw_output_current,
+            self.flags.0,
+        ]);
+
+        PPS_STATUS_DATA_BLOCK_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::electric_current::milliampere;
+    use uom::si::electric_potential::millivolt;
+
+    use super::{PPS_STATUS_DATA_BLOCK_SIZE, PpsStatusExtended};
+    use crate::extended::status::TemperatureStatus;
+
+    #[test]
+    fn test_pps_status() {
+        // 9.02 V, 2.95 A, warning temperature in current limit mode.
+        let bytes = [0xc3, 0x01, 59, 0b0000_1100];
+        let status = PpsStatusExtended::from_bytes(&bytes);
+
+        assert_eq!(status.output_voltage().unwrap().get::<millivolt>(), 9020);
+        assert_eq!(status.output_current().unwrap().get::<milliampere>(), 2950);
+        assert!(status.flags.current_limit());
+        assert_eq!(status.flags.temperature_status(), TemperatureStatus::Warning);
+
+        let mut buf = [0u8; PPS_STATUS_DATA_BLOCK_SIZE];
+        assert_eq!(status.to_bytes(&mut buf), PPS_STATUS_DATA_BLOCK_SIZE);
+        assert_eq!(buf, bytes);
+    }
+
+    #[test]
+    fn test_pps_status_not_supported() {
+        let status = PpsStatusExtended::from_bytes(&[0xff, 0xff, 0xff, 0]);
+        assert!(status.output_voltage().is_none());
+        assert!(status.output_current().is_none());
+        assert_eq!(status.flags.temperature_status(), TemperatureStatus::NotSupported);
+
+        assert_eq!(PpsStatusExtended::from_bytes(&[]), PpsStatusExtended::default());
+    }
+}
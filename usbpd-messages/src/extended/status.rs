@@ -0,0 +1,217 @@
+//! Definitions of the Status extended message content.
+//!
+//! A port partner answers Get_Status with the Status Data Block (SDB), e.g. after it sent an Alert.
+//!
+//! See [6.5.2].
+use proc_bitfield::bitfield;
+
+/// The size of the Status Data Block, see [Table 6.56].
+///
+/// Port partners of revision 3.0 may send one byte less, without the power state change.
+pub const STATUS_DATA_BLOCK_SIZE: usize = 7;
+
+/// The temperature state of a device, see [6.5.2.5].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TemperatureStatus {
+    /// The temperature state is not reported.
+    #[default]
+    NotSupported,
+    /// Normal operating temperature.
+    Normal,
+    /// The temperature approaches its limit.
+    Warning,
+    /// The device is over temperature.
+    OverTemperature,
+}
+
+impl TemperatureStatus {
+    /// The raw two-bit value.
+    pub const fn raw(self) -> u8 {
+        match self {
+            TemperatureStatus::NotSupported => 0b00,
+            TemperatureStatus::Normal => 0b01,
+            TemperatureStatus::Warning => 0b10,
+            TemperatureStatus::OverTemperature => 0b11,
+        }
+    }
+
+    /// Parse the raw two-bit value, ignoring higher bits.
+    pub const fn from_raw(raw: u8) -> Self {
+        match raw & 0b11 {
+            0b00 => TemperatureStatus::NotSupported,
+            0b01 => TemperatureStatus::Normal,
+            0b10 => TemperatureStatus::Warning,
+            _ => TemperatureStatus::OverTemperature,
+        }
+    }
+}
+
+bitfield! {
+    /// The present input of a device, see [6.5.2.2].
+    #[derive(Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct PresentInput(pub u8): Debug, FromStorage, IntoStorage {
+        /// Supplied by internal power from a source that is not a battery.
+        pub internal_power_non_battery: bool @ 4,
+        /// Supplied by internal power from a battery.
+        pub internal_power_battery: bool @ 3,
+        /// The external power is AC, instead of DC. Only valid with `external_power`.
+        pub external_power_ac: bool @ 2,
+        /// Supplied by external power.
+        pub external_power: bool @ 1,
+    }
+}
+
+bitfield! {
+    /// Protection events of a device, see [6.5.2.4].
+    #[derive(Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct EventFlags(pub u8): Debug, FromStorage, IntoStorage {
+        /// A programmable power supply operates in current limit mode.
+        pub current_limit: bool @ 4,
+        /// An over-voltage protection event occurred.
+        pub ovp: bool @ 3,
+        /// An over-temperature protection event occurred.
+        pub otp: bool @ 2,
+        /// An over-current protection event occurred.
+        pub ocp: bool @ 1,
+    }
+}
+
+bitfield! {
+    /// The reasons, why a source limits its power, see [6.5.2.6].
+    #[derive(Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct PowerStatus(pub u8): Debug, FromStorage, IntoStorage {
+        /// Limited due to the temperature.
+        pub temperature: bool @ 5,
+        /// Limited due to protection events, see [`EventFlags`].
+        pub event_flags: bool @ 4,
+        /// Limited due to insufficient external power.
+        pub insufficient_external_power: bool @ 3,
+        /// Limited due to insufficient power, that is shared between multiple ports.
+        pub insufficient_power: bool @ 2,
+        /// Limited due to the current capability of the cable.
+        pub cable: bool @ 1,
+    }
+}
+
+bitfield! {
+    /// A change of the power state of a device, see [6.5.2.7].
+    #[derive(Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct PowerStateChange(pub u8): Debug, FromStorage, IntoStorage {
+        /// The new power state indicator, e.g. 1 for on, or 2 for blinking.
+        pub indicator: u8 @ 3..=5,
+        /// The new power state, e.g. 1 for S0, or 0 if not supported.
+        pub power_state: u8 @ 0..=2,
+    }
+}
+
+/// The Status Data Block (SDB) of a Status message, see [6.5.2].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatusExtended {
+    /// The internal temperature in °C, if reported. A value of 1 means less than 2 °C.
+    pub internal_temperature: Option<u8>,
+    /// The present input.
+    pub present_input: PresentInput,
+    /// Fixed batteries that supply the device, as a bit field in bits 0-3, and hot-swappable batteries in bits 4-7.
+    pub present_battery_input: u8,
+    /// Protection events.
+    pub event_flags: EventFlags,
+    /// The temperature state.
+    pub temperature_status: TemperatureStatus,
+    /// The reasons for limited power.
+    pub power_status: PowerStatus,
+    /// A change of the power state.
+    pub power_state_change: PowerStateChange,
+}
+
+impl StatusExtended {
+    /// Parse a Status Data Block.
+    ///
+    /// Missing trailing bytes, e.g. of a port partner of an earlier revision, read as zero.
+    pub fn from_bytes(buf: &[u8]) -> Self {
+        let byte = |index: usize| buf.get(index).copied().unwrap_or_default();
+
+        Self {
+            internal_temperature: Some(byte(0)).filter(|temperature| *temperature != 0),
+            present_input: PresentInput(byte(1)),
+            present_battery_input: byte(2),
+            event_flags: EventFlags(byte(3)),
+            temperature_status: TemperatureStatus::from_raw(byte(4) >> 1),
+            power_status: PowerStatus(byte(5)),
+            power_state_change: PowerStateChange(byte(6)),
+        }
+    }
+
+    /// Serialize the Status Data Block, returning its size.
+    ///
+    /// The buffer must be able to hold at least [`STATUS_DATA_BLOCK_SIZE`] bytes.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> usize {
+        buf[..STATUS_DATA_BLOCK_SIZE].copy_from_slice(&[
+            self.internal_temperature.map_or(0, |temperature| temperature.max(1)),
+            self.present_input.0,
+            self.present_battery_input,
+            self.event_flags.0,
+            self.temperature_status.raw() << 1,
+            self.power_status.0,
+            self.power_state_change.0,
+        ]);
+
+        STATUS_DATA_BLOCK_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{STATUS_DATA_BLOCK_SIZE, StatusExtended, TemperatureStatus};
+
+    #[test]
+    fn test_status() {
+        let bytes = [
+            42,
+            0b0000_0110,
+            0x21,
+            0b0000_0010,
+            0b0000_0100,
+            0b0010_0000,
+            0b0000_1001,
+        ];
+        let status = StatusExtended::from_bytes(&bytes);
+
+        assert_eq!(status.internal_temperature, Some(42));
+        assert!(status.present_input.external_power());
+        assert!(status.present_input.external_power_ac());
+        assert!(!status.present_input.internal_power_battery());
+        assert_eq!(status.present_battery_input, 0x21);
+        assert!(status.event_flags.ocp());
+        assert!(!status.event_flags.ovp());
+        assert_eq!(status.temperature_status, TemperatureStatus::Warning);
+        assert!(status.power_status.temperature());
+        assert_eq!(status.power_state_change.power_state(), 1);
+        assert_eq!(status.power_state_change.indicator(), 1);
+
+        let mut buf = [0xffu8; STATUS_DATA_BLOCK_SIZE];
+        assert_eq!(status.to_bytes(&mut buf), STATUS_DATA_BLOCK_SIZE);
+        assert_eq!(buf, bytes);
+    }
+
+    #[test]
+    fn test_status_short() {
+        // A revision 3.0 block, without the power state change.
+        let status = StatusExtended::from_bytes(&[0, 0, 0, 0, 0b0000_0110, 0]);
+
+        assert_eq!(status.internal_temperature, None);
+        assert_eq!(status.temperature_status, TemperatureStatus::OverTemperature);
+        assert_eq!(status.power_state_change.0, 0);
+    }
+}
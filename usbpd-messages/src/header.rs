@@ -6,13 +6,49 @@ use core::convert::TryFrom;
 use byteorder::{ByteOrder, LittleEndian};
 use proc_bitfield::bitfield;
 
-use crate::counters::Counter;
-use crate::protocol_layer::message::ParseError;
-use crate::{DataRole, PowerRole};
+use crate::{DataRole, ParseError, PowerRole};
+
+/// The size of the extended message header in bytes, see [6.2.1.2].
+pub const EXTENDED_HEADER_SIZE: usize = 2;
+
+/// The size of a data object in bytes.
+const DATA_OBJECT_SIZE: usize = size_of::<u32>();
+
+/// The maximum number of data objects in a message, as it is encoded in three bits.
+pub const MAX_NUM_OBJECTS: u8 = 7;
+
+/// A message ID, that counts from zero to [`MessageId::MAX`], and then wraps around.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MessageId(u8);
+
+impl MessageId {
+    /// The maximum message ID, as it is encoded in three bits.
+    pub const MAX: u8 = 7;
+
+    /// Create a message ID from a value, which is taken modulo eight.
+    pub const fn new(value: u8) -> Self {
+        Self(value & Self::MAX)
+    }
+
+    /// The message ID value.
+    pub const fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// Advance to the next message ID, wrapping around after [`Self::MAX`].
+    pub fn increment(&mut self) {
+        *self = Self::new(self.0.wrapping_add(1));
+    }
+
+    /// Reset the message ID to zero.
+    pub fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
 
 bitfield! {
     #[derive(Clone, Copy, PartialEq, Eq)]
-    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     /// Definition of the message header. Every message shall start with it.
     pub struct Header(pub u16): Debug, FromStorage, IntoStorage {
@@ -55,7 +91,7 @@ impl Header {
     /// Create a new header that follows a template.
     pub fn new(
         template: Self,
-        message_id: Counter,
+        message_id: MessageId,
         message_type: MessageType,
         num_objects: u8,
         extended: bool,
@@ -72,12 +108,12 @@ impl Header {
     }
 
     /// Create a new control message header.
-    pub fn new_control(template: Self, message_id: Counter, message_type: ControlMessageType) -> Self {
+    pub fn new_control(template: Self, message_id: MessageId, message_type: ControlMessageType) -> Self {
         Self::new(template, message_id, MessageType::Control(message_type), 0, false)
     }
 
     /// Create a new data message header.
-    pub fn new_data(template: Self, message_id: Counter, message_type: DataMessageType, num_objects: u8) -> Self {
+    pub fn new_data(template: Self, message_id: MessageId, message_type: DataMessageType, num_objects: u8) -> Self {
         Self::new(
             template,
             message_id,
@@ -88,9 +124,11 @@ impl Header {
     }
 
     /// Create a new extended message header.
+    ///
+    /// The caller computes `num_objects`. Prefer [`Self::new_extended_for_payload`], which derives it.
     pub fn new_extended(
         template: Self,
-        message_id: Counter,
+        message_id: MessageId,
         extended_message_type: ExtendedMessageType,
         num_objects: u8,
     ) -> Self {
@@ -103,11 +141,38 @@ impl Header {
         )
     }
 
+    /// Create a new extended message header, for a chunk with `data_len` payload bytes.
+    ///
+    /// The number of data objects covers the extended header and the payload, padded to whole data objects, see
+    /// [6.2.1.1.2].
+    pub fn new_extended_for_payload(
+        template: Self,
+        message_id: MessageId,
+        extended_message_type: ExtendedMessageType,
+        data_len: usize,
+    ) -> Self {
+        let num_objects = (EXTENDED_HEADER_SIZE + data_len).div_ceil(DATA_OBJECT_SIZE);
+        debug_assert!(
+            num_objects <= usize::from(MAX_NUM_OBJECTS),
+            "extended payload of {} bytes exceeds a chunk",
+            data_len
+        );
+
+        let header = Self::new_extended(template, message_id, extended_message_type, num_objects as u8);
+        debug_assert_eq!(header.num_objects(), num_objects);
+        header
+    }
+
     /// Parse a header from its binary representation.
     pub fn from_bytes(buf: &[u8]) -> Result<Self, ParseError> {
-        assert!(buf.len() == 2);
+        let Ok(bytes) = <[u8; 2]>::try_from(buf) else {
+            return Err(ParseError::InvalidLength {
+                expected: 2,
+                found: buf.len(),
+            });
+        };
 
-        let header = Header(LittleEndian::read_u16(buf));
+        let header = Header(u16::from_le_bytes(bytes));
         // Validate spec_revision
         header.spec_revision()?;
         Ok(header)
@@ -132,8 +197,16 @@ impl Header {
     }
 }
 
+/// A compact summary of the message type and ID, as the header is part of every logged message.
+#[cfg(feature = "defmt")]
+impl defmt::Format for Header {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}#{=u8}", self.message_type(), self.message_id())
+    }
+}
+
 /// Specification revieions.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[allow(non_camel_case_types)]
 pub enum SpecificationRevision {
@@ -179,6 +252,50 @@ pub enum MessageType {
     Extended(ExtendedMessageType),
 }
 
+impl MessageType {
+    /// Whether a message of this type always initiates an atomic message sequence (AMS), see [8.3.2.1.3].
+    ///
+    /// Messages that can also be sent as part of an AMS that the port partner initiated (e.g. Source_Capabilities as
+    /// a response to Get_Source_Cap) are not considered as initiating.
+    pub fn initiates_ams(&self) -> bool {
+        match self {
+            MessageType::Control(message_type) => matches!(
+                message_type,
+                ControlMessageType::GotoMin
+                    | ControlMessageType::GetSourceCap
+                    | ControlMessageType::GetSinkCap
+                    | ControlMessageType::DrSwap
+                    | ControlMessageType::PrSwap
+                    | ControlMessageType::VconnSwap
+                    | ControlMessageType::DataReset
+                    | ControlMessageType::GetSourceCapExtended
+                    | ControlMessageType::GetStatus
+                    | ControlMessageType::FrSwap
+                    | ControlMessageType::GetPpsStatus
+                    | ControlMessageType::GetCountryCodes
+                    | ControlMessageType::GetSinkCapExtended
+                    | ControlMessageType::GetSourceInfo
+                    | ControlMessageType::GetRevision
+            ),
+            MessageType::Data(message_type) => matches!(
+                message_type,
+                DataMessageType::Bist
+                    | DataMessageType::Alert
+                    | DataMessageType::GetCountryInfo
+                    | DataMessageType::EnterUsb
+            ),
+            MessageType::Extended(message_type) => matches!(
+                message_type,
+                ExtendedMessageType::GetBatteryCap
+                    | ExtendedMessageType::GetBatteryStatus
+                    | ExtendedMessageType::GetManufacturerInfo
+                    | ExtendedMessageType::SecurityRequest
+                    | ExtendedMessageType::FirmwareUpdateRequest
+            ),
+        }
+    }
+}
+
 /// Types of control messages.
 #[allow(missing_docs)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
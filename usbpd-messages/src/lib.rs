@@ -0,0 +1,521 @@
+//! # USB PD message definitions
+//!
+//! Parsers and serializers for USB Power Delivery messages, modeled after the Universal Serial Bus Power Delivery
+//! Specification: USB PD R3.2 v1.1 (2024/10).
+//!
+//! This crate holds no state and runs no policy. It is used by the `usbpd` policy engines, and can be used on its own,
+//! e.g. by protocol analyzers or test tools.
+
+#![cfg_attr(not(test), no_std)]
+#![warn(missing_docs)]
+
+#[macro_use]
+extern crate uom;
+
+// This mod MUST go first, so that the others see its macros.
+pub(crate) mod fmt;
+
+pub mod data;
+pub mod extended;
+pub mod header;
+
+#[cfg(test)]
+mod dummy;
+#[cfg(test)]
+mod epr_messages_test;
+#[cfg(test)]
+mod test_vectors;
+
+use byteorder::{ByteOrder, LittleEndian};
+use header::{Header, MessageType};
+
+use crate::extended::ExtendedHeader;
+
+/// This module defines the CGS (centimeter-gram-second) unit system
+/// for use in the USB Power Delivery Protocol layer. These units are
+/// defined using the `uom` (units of measurement) library and are
+/// expressed as `u32` values for milliamps, millivolts, and microwatts.
+pub mod units {
+    ISQ!(
+        uom::si,
+        u32,
+        (millimeter, kilogram, second, milliampere, kelvin, mole, candela)
+    );
+}
+
+/// Defines a unit for electric current in 50 mA steps.
+pub mod _50milliamperes_mod {
+    unit! {
+        system: uom::si;
+        quantity: uom::si::electric_current;
+
+        @_50milliamperes: 0.05; "_50mA", "_50milliamps", "_50milliamps";
+    }
+}
+
+/// Defines a unit for electric potential in 50 mV steps.
+pub mod _50millivolts_mod {
+    unit! {
+        system: uom::si;
+        quantity: uom::si::electric_potential;
+
+        @_50millivolts: 0.05; "_50mV", "_50millivolts", "_50millivolts";
+    }
+}
+
+/// Defines a unit for electric potential in 20 mV steps.
+pub mod _20millivolts_mod {
+    unit! {
+        system: uom::si;
+        quantity: uom::si::electric_potential;
+
+        @_20millivolts: 0.02; "_20mV", "_20millivolts", "_20millivolts";
+    }
+}
+
+/// Defines a unit for electric potential in 25 mV steps.
+/// Used by AVS (Adjustable Voltage Supply) per USB PD 3.2 Table 6.26.
+pub mod _25millivolts_mod {
+    unit! {
+        system: uom::si;
+        quantity: uom::si::electric_potential;
+
+        @_25millivolts: 0.025; "_25mV", "_25millivolts", "_25millivolts";
+    }
+}
+
+/// Defines a unit for power in 250 mW steps.
+pub mod _250milliwatts_mod {
+    unit! {
+        system: uom::si;
+        quantity: uom::si::power;
+
+        @_250milliwatts: 0.25; "_250mW", "_250milliwatts", "_250milliwatts";
+    }
+}
+
+/// The power role of the port.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerRole {
+    /// The port is a source.
+    Source,
+    /// The port is a sink.
+    Sink,
+}
+
+impl From<bool> for PowerRole {
+    fn from(value: bool) -> Self {
+        match value {
+            false => Self::Sink,
+            true => Self::Source,
+        }
+    }
+}
+
+impl From<PowerRole> for bool {
+    fn from(role: PowerRole) -> bool {
+        match role {
+            PowerRole::Sink => false,
+            PowerRole::Source => true,
+        }
+    }
+}
+
+/// The data role of the port.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataRole {
+    /// The port is an upstream-facing port.
+    Ufp,
+    /// The port is a downstream-facing port.
+    Dfp,
+}
+
+impl From<bool> for DataRole {
+    fn from(value: bool) -> Self {
+        match value {
+            false => Self::Ufp,
+            true => Self::Dfp,
+        }
+    }
+}
+
+impl From<DataRole> for bool {
+    fn from(role: DataRole) -> bool {
+        match role {
+            DataRole::Ufp => false,
+            DataRole::Dfp => true,
+        }
+    }
+}
+
+/// Errors that can occur during message/header parsing.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// The input buffer has an invalid length.
+    /// * `expected` - The expected length.
+    /// * `found` - The actual length found.
+    #[error("invalid input buffer length (expected {expected:?}, found {found:?})")]
+    InvalidLength {
+        /// The expected length.
+        expected: usize,
+        /// The actual length found.
+        found: usize,
+    },
+    /// The specification revision field is not supported.
+    #[error("unsupported specification revision `{0}`")]
+    UnsupportedSpecificationRevision(u8),
+    /// An unknown or reserved message type was encountered.
+    #[error("unknown or reserved message type `{0}`")]
+    InvalidMessageType(u8),
+    /// An unknown or reserved data message type was encountered.
+    #[error("unknown or reserved data message type `{0}`")]
+    InvalidDataMessageType(u8),
+    /// An unknown or reserved control message type was encountered.
+    #[error("unknown or reserved control message type `{0}`")]
+    InvalidControlMessageType(u8),
+    /// Received a chunked extended message that requires assembly.
+    /// Use `ChunkedMessageAssembler` to handle these messages.
+    #[error("chunked extended message (chunk {chunk_number}, total size {data_size})")]
+    ChunkedExtendedMessage {
+        /// The chunk number (0 = first chunk).
+        chunk_number: u8,
+        /// Total data size across all chunks.
+        data_size: u16,
+        /// Whether this is a chunk request.
+        request_chunk: bool,
+        /// The extended message type.
+        message_type: header::ExtendedMessageType,
+    },
+    /// Received a chunk larger than the maximum allowed size (26 bytes).
+    #[error("chunk size {0} exceeds maximum {1}")]
+    ChunkOverflow(usize, usize),
+    /// Attempt to reuse a ChunkedMessageAssembler that is already processing a message.
+    /// The user must create a new assembler or explicitly call reset() first.
+    #[error("parser already in use, create a new assembler or call reset()")]
+    ParserReuse,
+    /// Other parsing error with a message.
+    #[error("other parse error: {0}")]
+    Other(&'static str),
+}
+
+/// Payload of a USB PD message, if any.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Payload {
+    /// Payload for a data message.
+    Data(data::Data),
+    /// Payload for an extended message.
+    Extended(extended::Extended),
+}
+
+/// A USB PD message.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Message {
+    /// The message header.
+    pub header: Header,
+    /// Optional payload for  messages.
+    pub payload: Option<Payload>,
+}
+
+/// The header summary, and the raw data objects, instead of the decoded payload.
+#[cfg(feature = "defmt")]
+impl defmt::Format for Message {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{} {=[?]:#010x}", self.header, self.raw_words().as_slice())
+    }
+}
+
+impl Message {
+    /// Create a new message from a message header.
+    pub fn new(header: Header) -> Self {
+        Self { header, payload: None }
+    }
+
+    /// Create a new message from a message header and payload data.
+    pub fn new_with_data(header: Header, data: data::Data) -> Self {
+        Self {
+            header,
+            payload: Some(Payload::Data(data)),
+        }
+    }
+
+    /// The raw 32-bit data objects of the message, as transmitted on the wire.
+    ///
+    /// Empty for control messages, and for extended messages whose payload does not consist of data objects.
+    pub fn raw_words(&self) -> heapless::Vec<u32, 16> {
+        let mut words = heapless::Vec::new();
+
+        match self.payload.as_ref() {
+            Some(Payload::Data(data::Data::SourceCapabilities(caps))) => {
+                words.extend(caps.pdos().iter().map(|pdo| pdo.raw()));
+            }
+            Some(Payload::Data(data::Data::SinkCapabilities(caps))) => {
+                words.extend(caps.pdos().iter().map(|pdo| pdo.raw()));
+            }
+            Some(Payload::Data(data::Data::Request(data::request::PowerSource::EprRequest(epr)))) => {
+                words.extend(epr.raw());
+            }
+            Some(Payload::Data(data::Data::Request(power_source))) => {
+                words.extend([power_source.raw()]);
+            }
+            Some(Payload::Data(data::Data::EprMode(mdo))) => {
+                words.extend([mdo.0]);
+            }
+            Some(Payload::Data(data::Data::Alert(ado))) => {
+                words.extend([ado.0]);
+            }
+            Some(Payload::Data(data::Data::Bist(bdo))) => {
+                words.extend([bdo.0]);
+            }
+            Some(Payload::Data(data::Data::VendorDefined((header, vdos)))) => {
+                words.extend([u32::from(*header)]);
+                words.extend(vdos.iter().copied());
+            }
+            Some(Payload::Extended(extended::Extended::EprSourceCapabilities(pdos))) => {
+                words.extend(pdos.iter().map(|pdo| pdo.raw()));
+            }
+            Some(Payload::Extended(extended::Extended::EprSinkCapabilities(pdos))) => {
+                words.extend(pdos.iter().map(|pdo| pdo.raw()));
+            }
+            _ => (),
+        }
+
+        words
+    }
+
+    /// Serialize a message to a slice, returning the number of written bytes.
+    pub fn to_bytes(&self, buffer: &mut [u8]) -> usize {
+        let header_len = self.header.to_bytes(buffer);
+
+        match self.payload.as_ref() {
+            Some(Payload::Data(data)) => header_len + data.to_bytes(&mut buffer[header_len..]),
+            Some(Payload::Extended(extended)) => {
+                // Per USB PD spec 6.2.1.2.1: use chunked mode for compatibility with more PHYs.
+                // Most power supplies don't support unchunked extended messages.
+                let extended_header = ExtendedHeader::new(extended.data_size())
+                    .with_chunked(true)
+                    .with_chunk_number(0);
+                let ext_header_len = extended_header.to_bytes(&mut buffer[header_len..]);
+                header_len + ext_header_len + extended.to_bytes(&mut buffer[header_len + ext_header_len..])
+            }
+            None => header_len,
+        }
+    }
+
+    /// Parse assembled extended message payload into an Extended enum.
+    ///
+    /// This is used after `ChunkedMessageAssembler` has assembled all chunks.
+    ///
+    /// # Arguments
+    /// * `message_type` - The extended message type
+    /// * `payload` - The complete assembled payload data
+    #[allow(clippy::chunks_exact_to_as_chunks)]
+    pub fn parse_extended_payload(message_type: header::ExtendedMessageType, payload: &[u8]) -> extended::Extended {
+        match message_type {
+            header::ExtendedMessageType::ExtendedControl => {
+                if payload.len() >= 2 {
+                    extended::Extended::ExtendedControl(extended::extended_control::ExtendedControl(
+                        LittleEndian::read_u16(payload),
+                    ))
+                } else {
+                    extended::Extended::Unknown
+                }
+            }
+            header::ExtendedMessageType::Status => {
+                extended::Extended::Status(extended::status::StatusExtended::from_bytes(payload))
+            }
+            header::ExtendedMessageType::PpsStatus => {
+                extended::Extended::PpsStatus(extended::pps_status::PpsStatusExtended::from_bytes(payload))
+            }
+            header::ExtendedMessageType::GetManufacturerInfo => {
+                extended::manufacturer_info::ManufacturerInfoTarget::from_bytes(payload)
+                    .map_or(extended::Extended::Unknown, extended::Extended::GetManufacturerInfo)
+            }
+            header::ExtendedMessageType::ManufacturerInfo => {
+                extended::Extended::ManufacturerInfo(extended::manufacturer_info::ManufacturerInfo::from_bytes(payload))
+            }
+            header::ExtendedMessageType::EprSourceCapabilities => extended::Extended::EprSourceCapabilities(
+                payload
+                    .chunks_exact(4)
+                    .take(usize::from(data::ObjectPosition::MAX.get()))
+                    .map(|buf| crate::data::source_capabilities::parse_raw_pdo(LittleEndian::read_u32(buf)))
+                    .collect(),
+            ),
+            _ => extended::Extended::Unknown,
+        }
+    }
+
+    /// Parse an extended message chunk, returning the header info and chunk data.
+    ///
+    /// This is used for handling chunked extended messages when `from_bytes`
+    /// returns `ParseError::ChunkedExtendedMessage`.
+    ///
+    /// Returns (Header, ExtendedHeader, chunk_payload_data).
+    pub fn parse_extended_chunk(data: &[u8]) -> Result<(Header, ExtendedHeader, &[u8]), ParseError> {
+        if data.len() < 4 {
+            return Err(ParseError::InvalidLength {
+                expected: 4,
+                found: data.len(),
+            });
+        }
+
+        let header = Header::from_bytes(&data[..2])?;
+        let ext_header = ExtendedHeader::from_bytes(&data[2..]);
+
+        // Chunk payload starts after headers (2 + 2 = 4 bytes)
+        let chunk_payload = &data[4..];
+
+        Ok((header, ext_header, chunk_payload))
+    }
+
+    /// Parse a message from a slice of bytes.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ParseError> {
+        let Some((header, payload)) = data.split_first_chunk::<2>() else {
+            return Err(ParseError::InvalidLength {
+                expected: 2,
+                found: data.len(),
+            });
+        };
+        let header = Header::from_bytes(header)?;
+        let message = Self::new(header);
+
+        match message.header.message_type() {
+            MessageType::Control(_) => Ok(message),
+            MessageType::Extended(message_type) => {
+                if payload.len() < 2 {
+                    return Err(ParseError::InvalidLength {
+                        expected: 2,
+                        found: payload.len(),
+                    });
+                }
+
+                let ext_header = ExtendedHeader::from_bytes(payload);
+                let data_size = ext_header.data_size() as usize;
+
+                // Check if this is a true multi-chunk message that needs assembly
+                // Single-chunk messages (chunk 0 with all data present) can be parsed directly
+                if ext_header.chunked() {
+                    let is_chunk_request = ext_header.request_chunk();
+                    let chunk_number = ext_header.chunk_number();
+                    let available_payload = payload.len().saturating_sub(2);
+
+                    // Multi-chunk required if:
+                    // - This is a chunk request, OR
+                    // - Chunk number > 0 (continuation chunk), OR
+                    // - Data size exceeds what's available in this chunk
+                    let needs_assembly = is_chunk_request || chunk_number > 0 || data_size > available_payload;
+
+                    if needs_assembly {
+                        return Err(ParseError::ChunkedExtendedMessage {
+                            chunk_number,
+                            data_size: ext_header.data_size(),
+                            request_chunk: is_chunk_request,
+                            message_type,
+                        });
+                    }
+                    // Otherwise, it's a single-chunk message - parse normally
+                }
+                if payload.len() < 2 + data_size {
+                    return Err(ParseError::InvalidLength {
+                        expected: 2 + data_size,
+                        found: payload.len(),
+                    });
+                }
+
+                let payload_bytes = &payload[2..2 + data_size];
+                Ok(Self {
+                    payload: Some(Payload::Extended(Self::parse_extended_payload(
+                        message_type,
+                        payload_bytes,
+                    ))),
+                    ..message
+                })
+            }
+            MessageType::Data(message_type) => data::Data::parse_message(message, message_type, payload, &()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::electric_current::milliampere;
+    use uom::si::electric_potential::millivolt;
+
+    use crate::_20millivolts_mod::_20millivolts;
+    use crate::{Message, ParseError, units};
+
+    #[test]
+    fn test_units() {
+        let current = units::ElectricCurrent::new::<milliampere>(123);
+        let potential = units::ElectricPotential::new::<millivolt>(4560);
+
+        assert_eq!(current.get::<milliampere>(), 123);
+        assert_eq!(potential.get::<millivolt>(), 4560);
+        assert_eq!(potential.get::<_20millivolts>(), 228);
+    }
+
+    #[test]
+    fn test_describe_capabilities() {
+        use std::string::String;
+
+        use crate::data::source_capabilities::SourceCapabilities;
+        use crate::dummy::get_dummy_source_capabilities;
+
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+        let mut text = String::new();
+        capabilities.describe(&mut text).unwrap();
+
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("Source capabilities: unconstrained power"));
+        assert_eq!(lines.next(), Some("  1: Fixed 5.00 V, 3.00 A"));
+        assert_eq!(lines.nth(3), Some("  5: PPS 3.30 V - 11.00 V, 5.00 A"));
+        assert_eq!(lines.count(), 2);
+
+        // Writing into a buffer that is too small fails, instead of truncating silently.
+        let mut short: heapless::String<16> = heapless::String::new();
+        assert!(capabilities.describe(&mut short).is_err());
+    }
+
+    #[test]
+    fn test_extended_header_num_objects() {
+        use crate::extended::chunked::MAX_EXTENDED_MSG_CHUNK_LEN;
+        use crate::header::{ExtendedMessageType, Header, MessageId, SpecificationRevision};
+        use crate::{DataRole, PowerRole};
+
+        let template = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+        let num_objects = |data_len| {
+            Header::new_extended_for_payload(
+                template,
+                MessageId::new(0),
+                ExtendedMessageType::ExtendedControl,
+                data_len,
+            )
+            .num_objects()
+        };
+
+        // A chunk request only holds the extended header, which is padded to one data object.
+        assert_eq!(num_objects(0), 1);
+        // Extended control messages fill exactly one data object.
+        assert_eq!(num_objects(2), 1);
+        assert_eq!(num_objects(3), 2);
+        assert_eq!(num_objects(MAX_EXTENDED_MSG_CHUNK_LEN), 7);
+    }
+
+    #[test]
+    fn test_truncated_message() {
+        assert!(matches!(
+            Message::from_bytes(&[0x01]),
+            Err(ParseError::InvalidLength { .. })
+        ));
+        // An extended header is announced, but missing.
+        assert!(matches!(
+            Message::from_bytes(&[0x91, 0x91]),
+            Err(ParseError::InvalidLength { .. })
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+//! Byte-exact test vectors for every supported message type.
+//!
+//! Each vector is decoded and checked against its message type and raw data objects, then encoded again and checked
+//! against its bytes. Vectors were captured from hardware negotiations (KM003C sniffer), or built from the examples
+//! in the specification. Add a vector, whenever a message type gains a parser.
+//!
+//! Chunked extended messages, which need assembly, are covered by `epr_messages_test`.
+
+use crate::Message;
+use crate::extended::extended_control::ExtendedControlMessageType;
+use crate::header::{ControlMessageType, DataMessageType, ExtendedMessageType, MessageType};
+
+/// A message on the wire, and what it decodes to.
+struct Vector {
+    /// What the message is, and where it was taken from.
+    name: &'static str,
+    /// The serialized message, starting with the message header.
+    bytes: &'static [u8],
+    /// The decoded message type.
+    message_type: MessageType,
+    /// The decoded raw data objects, see [`Message::raw_words`].
+    words: &'static [u32],
+}
+
+const VECTORS: &[Vector] = &[
+    // Control messages, from sink (UFP) or source (DFP), revision 3.x.
+    Vector {
+        name: "GoodCRC, source, #1",
+        bytes: &[0xA1, 0x03],
+        message_type: MessageType::Control(ControlMessageType::GoodCRC),
+        words: &[],
+    },
+    Vector {
+        name: "Accept, source, #2",
+        bytes: &[0xA3, 0x05],
+        message_type: MessageType::Control(ControlMessageType::Accept),
+        words: &[],
+    },
+    Vector {
+        name: "Reject, source, #2",
+        bytes: &[0xA4, 0x05],
+        message_type: MessageType::Control(ControlMessageType::Reject),
+        words: &[],
+    },
+    Vector {
+        name: "Wait, source, #2",
+        bytes: &[0xAC, 0x05],
+        message_type: MessageType::Control(ControlMessageType::Wait),
+        words: &[],
+    },
+    Vector {
+        name: "PS_RDY, source, #3",
+        bytes: &[0xA6, 0x07],
+        message_type: MessageType::Control(ControlMessageType::PsRdy),
+        words: &[],
+    },
+    Vector {
+        name: "Get_Source_Cap, sink, #0",
+        bytes: &[0x87, 0x00],
+        message_type: MessageType::Control(ControlMessageType::GetSourceCap),
+        words: &[],
+    },
+    Vector {
+        name: "Get_Sink_Cap, source, #0",
+        bytes: &[0xA8, 0x01],
+        message_type: MessageType::Control(ControlMessageType::GetSinkCap),
+        words: &[],
+    },
+    Vector {
+        name: "Soft_Reset, sink, #0",
+        bytes: &[0x8D, 0x00],
+        message_type: MessageType::Control(ControlMessageType::SoftReset),
+        words: &[],
+    },
+    Vector {
+        name: "Not_Supported, source, #4",
+        bytes: &[0xB0, 0x09],
+        message_type: MessageType::Control(ControlMessageType::NotSupported),
+        words: &[],
+    },
+    Vector {
+        name: "Get_Status, source, #5",
+        bytes: &[0xB2, 0x0B],
+        message_type: MessageType::Control(ControlMessageType::GetStatus),
+        words: &[],
+    },
+    // Data messages.
+    Vector {
+        name: "Source_Capabilities, 5 V - 20 V, PPS, EPR capable (capture)",
+        bytes: &[
+            0xA1, 0x61, 0x2C, 0x91, 0x91, 0x0A, 0x2C, 0xD1, 0x12, 0x00, 0x2C, 0xC1, 0x13, 0x00, 0x2C, 0xB1, 0x14, 0x00,
+            0xF4, 0x41, 0x16, 0x00, 0x64, 0x32, 0xA4, 0xC9,
+        ],
+        message_type: MessageType::Data(DataMessageType::SourceCapabilities),
+        words: &[
+            0x0A91_912C,
+            0x0012_D12C,
+            0x0013_C12C,
+            0x0014_B12C,
+            0x0016_41F4,
+            0xC9A4_3264,
+        ],
+    },
+    Vector {
+        name: "Request, object position 2, 3 A",
+        bytes: &[0x82, 0x12, 0x2C, 0xB1, 0x04, 0x20],
+        message_type: MessageType::Data(DataMessageType::Request),
+        words: &[0x2004_B12C],
+    },
+    Vector {
+        name: "EPR_Request, 28 V at 5 A, object position 8 (capture)",
+        bytes: &[0x89, 0x28, 0xF4, 0xD1, 0xC7, 0x80, 0xF4, 0xC1, 0x18, 0x00],
+        message_type: MessageType::Data(DataMessageType::EprRequest),
+        words: &[0x80C7_D1F4, 0x0018_C1F4],
+    },
+    Vector {
+        name: "BIST, carrier mode",
+        bytes: &[0xA3, 0x1B, 0x00, 0x00, 0x00, 0x50],
+        message_type: MessageType::Data(DataMessageType::Bist),
+        words: &[0x5000_0000],
+    },
+    Vector {
+        name: "Alert, over-current protection event",
+        bytes: &[0xA6, 0x1D, 0x00, 0x00, 0x00, 0x04],
+        message_type: MessageType::Data(DataMessageType::Alert),
+        words: &[0x0400_0000],
+    },
+    Vector {
+        name: "EPR_Mode, enter (capture)",
+        bytes: &[0x8A, 0x14, 0x00, 0x00, 0x00, 0x01],
+        message_type: MessageType::Data(DataMessageType::EprMode),
+        words: &[0x0100_0000],
+    },
+    Vector {
+        name: "Vendor_Defined, Discover Identity request, version 2.1",
+        bytes: &[0xAF, 0x1F, 0x01, 0xA8, 0x00, 0xFF],
+        message_type: MessageType::Data(DataMessageType::VendorDefined),
+        words: &[0xFF00_A801],
+    },
+    // Extended messages, in a single chunk.
+    Vector {
+        name: "Extended_Control, EPR_KeepAlive (capture)",
+        bytes: &[0x90, 0x9A, 0x02, 0x80, 0x03, 0x00],
+        message_type: MessageType::Extended(ExtendedMessageType::ExtendedControl),
+        words: &[],
+    },
+];
+
+#[test]
+fn test_decode() {
+    for vector in VECTORS {
+        let message = Message::from_bytes(vector.bytes).unwrap_or_else(|error| panic!("{}: {:?}", vector.name, error));
+
+        assert_eq!(message.header.message_type(), vector.message_type, "{}", vector.name);
+        assert_eq!(message.raw_words().as_slice(), vector.words, "{}", vector.name);
+    }
+}
+
+#[test]
+fn test_encode() {
+    for vector in VECTORS {
+        let message = Message::from_bytes(vector.bytes).unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = message.to_bytes(&mut buf);
+        assert_eq!(&buf[..len], vector.bytes, "{}", vector.name);
+    }
+}
+
+#[test]
+fn test_extended_control_payload() {
+    use crate::Payload;
+    use crate::extended::Extended;
+
+    let vector = VECTORS
+        .iter()
+        .find(|vector| vector.message_type == MessageType::Extended(ExtendedMessageType::ExtendedControl))
+        .unwrap();
+
+    let Some(Payload::Extended(Extended::ExtendedControl(control))) =
+        Message::from_bytes(vector.bytes).unwrap().payload
+    else {
+        panic!("{}: expected an extended control payload", vector.name);
+    };
+    assert_eq!(control.message_type(), ExtendedControlMessageType::EprKeepAlive);
+}
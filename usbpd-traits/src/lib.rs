@@ -4,6 +4,24 @@
 #![cfg_attr(not(test), no_std)]
 #![warn(missing_docs)]
 use core::future::Future;
+use core::ops::Deref;
+
+/// The size of the largest frame in bytes: an unchunked extended message with 260 data bytes, and its headers.
+pub const MAX_FRAME_SIZE: usize = 264;
+
+/// A local fault of the driver or PHY, which is unrelated to the behavior of the port partner.
+///
+/// Unlike discarded messages, faults may leave the PHY in a bad state, e.g. with stale data in its FIFOs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DriverFault {
+    /// Received data was lost, because it was not read in time.
+    Overrun,
+    /// A DMA transfer failed.
+    Dma,
+    /// Another fault, e.g. a bus error while talking to an external TCPC.
+    Other,
+}
 
 /// Receive Error.
 #[derive(Debug, Clone, Copy)]
@@ -14,6 +32,12 @@ pub enum DriverRxError {
 
     /// Hard Reset received before or during reception.
     HardReset,
+
+    /// The port partner detached, e.g. VBus or the CC connection was lost.
+    Detached,
+
+    /// A local fault of the driver or PHY.
+    Fault(DriverFault),
 }
 
 /// Transmit Error.
@@ -25,6 +49,64 @@ pub enum DriverTxError {
 
     /// Hard Reset received before or during transmission.
     HardReset,
+
+    /// The port partner detached, e.g. VBus or the CC connection was lost.
+    Detached,
+
+    /// A local fault of the driver or PHY.
+    Fault(DriverFault),
+}
+
+/// A received frame, which borrows the buffer that it was received into.
+///
+/// The buffer may be owned by the driver, e.g. one that was filled by DMA, see [`Driver::receive_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame<'a>(&'a [u8]);
+
+impl<'a> Frame<'a> {
+    /// Create a frame from received bytes, starting with the message header.
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self(data)
+    }
+
+    /// The received bytes.
+    pub const fn data(&self) -> &'a [u8] {
+        self.0
+    }
+}
+
+impl Deref for Frame<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+/// The preferred power role of a dual-role port, when the Type-C connection is established.
+///
+/// Resolving the role is part of the Type-C connection state machine, which the PHY (or TCPC) runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RolePreference {
+    /// No preference, the role results from toggling.
+    #[default]
+    None,
+    /// Prefer the sink role (Try.SNK), e.g. for power banks that charge from any capable source.
+    TrySink,
+    /// Prefer the source role (Try.SRC), e.g. for docks that supply attached devices.
+    TrySource,
+}
+
+/// The termination of the CC line, which presents the power role of the port to the port partner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CcTermination {
+    /// Pull-up resistor (Rp), as a source.
+    Rp,
+    /// Pull-down resistor (Rd), as a sink.
+    Rd,
 }
 
 /// Driver trait, through which the protocol layer talks to the PHY.
@@ -40,15 +122,89 @@ pub trait Driver {
     /// wait_for_good_crc(), since the hardware already verified GoodCRC.
     const HAS_AUTO_RETRY: bool = false;
 
-    /// Wait for availability of VBus voltage.
-    fn wait_for_vbus(&mut self) -> impl Future<Output = ()>;
+    /// If this is `true`, the driver can detect VBus, and the policy engine waits for it in
+    /// [`Driver::wait_for_vbus`] before expecting source capabilities.
+    ///
+    /// Set this to `false`, if attach is gated externally, for example when the policy engine
+    /// is only started after attach was detected.
+    const HAS_VBUS_DETECTION: bool = true;
+
+    /// If this is `true`, the PHY can transmit the BIST carrier signal, see [`Driver::set_bist_carrier_mode`].
+    const HAS_BIST_CARRIER_MODE: bool = false;
+
+    /// If this is `true`, the PHY implements Try.SNK and Try.SRC, see [`Driver::set_role_preference`].
+    const HAS_ROLE_PREFERENCE: bool = false;
+
+    /// If this is `true`, the PHY can switch its CC termination during a power role swap, see
+    /// [`Driver::set_cc_termination`].
+    const HAS_POWER_ROLE_SWAP: bool = false;
+
+    /// If this is `true`, the driver can reinitialize the PHY after persistent faults, see [`Driver::recover`].
+    const HAS_RECOVERY: bool = false;
+
+    /// Wait until VBus is present at vSafe5V.
+    ///
+    /// Only called if [`Driver::HAS_VBUS_DETECTION`] is `true`. Returns immediately by default.
+    fn wait_for_vbus(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
 
     /// Receive a packet.
+    ///
+    /// Drivers that detect detach return [`DriverRxError::Detached`] once the port partner is gone, and until it is
+    /// attached again. The same applies to [`DriverTxError::Detached`] on transmission.
     fn receive(&mut self, buffer: &mut [u8]) -> impl Future<Output = Result<usize, DriverRxError>>;
 
+    /// Receive a packet, and pass it to `handler` in place.
+    ///
+    /// The protocol layer receives through this method. Drivers that own their receive buffers, e.g. ones that are
+    /// filled by DMA, can implement it to hand out frames without copying them. By default, this receives with
+    /// [`Driver::receive`] into a buffer of [`MAX_FRAME_SIZE`] bytes.
+    fn receive_with<R>(
+        &mut self,
+        handler: impl FnOnce(Frame<'_>) -> R,
+    ) -> impl Future<Output = Result<R, DriverRxError>> {
+        async move {
+            let mut buffer = [0u8; MAX_FRAME_SIZE];
+            let length = self.receive(&mut buffer).await?;
+
+            Ok(handler(Frame::new(&buffer[..length])))
+        }
+    }
+
     /// Transmit a packet.
     fn transmit(&mut self, data: &[u8]) -> impl Future<Output = Result<(), DriverTxError>>;
 
     /// Transmit a hard reset signal.
     fn transmit_hard_reset(&mut self) -> impl Future<Output = Result<(), DriverTxError>>;
+
+    /// Start, or stop transmitting the BIST carrier signal, a continuous BMC-encoded sequence of alternating ones
+    /// and zeros.
+    ///
+    /// Only called if [`Driver::HAS_BIST_CARRIER_MODE`] is `true`. Does nothing by default.
+    fn set_bist_carrier_mode(&mut self, _enabled: bool) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Set the preferred power role, which applies from the next Type-C connection on.
+    ///
+    /// Only called if [`Driver::HAS_ROLE_PREFERENCE`] is `true`. Does nothing by default.
+    fn set_role_preference(&mut self, _preference: RolePreference) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Switch the CC termination during a power role swap, while the connection is kept.
+    ///
+    /// Only called if [`Driver::HAS_POWER_ROLE_SWAP`] is `true`. Does nothing by default.
+    fn set_cc_termination(&mut self, _termination: CcTermination) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Reinitialize the PHY after persistent [`DriverFault`]s, e.g. by flushing its FIFOs, and restarting DMA.
+    ///
+    /// The connection, and thereby the contract, must be kept. The policy engine calls this before escalating to a
+    /// hard reset. Only called if [`Driver::HAS_RECOVERY`] is `true`. Does nothing by default.
+    fn recover(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
 }
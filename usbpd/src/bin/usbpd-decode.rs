@@ -0,0 +1,268 @@
+//! Decode USB PD messages from captures.
+//!
+//! Reads hex dumps or analyzer CSV exports from a file (or stdin, if no file is given), and prints the messages, as
+//! decoded by the crate's parser. Each line holds one message, starting with the message header. For example:
+//!
+//! ```text
+//! # A hex dump
+//! A1 61 2C 91 01 08
+//! 0xA1, 0x61, 0x2C, 0x91, 0x01, 0x08
+//!
+//! # An analyzer export, with the raw message in one of the columns
+//! time,sop,raw
+//! 0.0125,SOP,A1612C910108
+//! ```
+//!
+//! Chunked extended messages are assembled across lines. With `--strict`, messages with unknown payloads count as
+//! failures. The exit code reports whether all messages were decoded, so that captures can serve as tests of parsing
+//! completeness.
+//!
+//! Build with `cargo run --features std --bin usbpd-decode -- capture.csv`.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::ExitCode;
+
+use usbpd::protocol_layer::message::data::Data;
+use usbpd::protocol_layer::message::extended::Extended;
+use usbpd::protocol_layer::message::extended::chunked::{ChunkResult, ChunkedMessageAssembler};
+use usbpd::protocol_layer::message::header::MessageType;
+use usbpd::protocol_layer::message::{Message, ParseError, Payload};
+
+/// Parse a field of hex bytes, e.g. `A1 61`, `0xA1, 0x61` or `A161`.
+///
+/// Returns `None`, if the field holds anything but hex bytes.
+fn parse_hex(field: &str) -> Option<Vec<u8>> {
+    let mut digits = String::new();
+
+    for token in field
+        .trim()
+        .trim_matches('"')
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .filter(|token| !token.is_empty())
+    {
+        let token = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+
+        if token.is_empty() || token.len() % 2 != 0 || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        digits.push_str(token);
+    }
+
+    let bytes: Vec<u8> = (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect();
+
+    // At least a message header.
+    (bytes.len() >= 2).then_some(bytes)
+}
+
+/// Extract the raw message from a line, alongside the remaining columns for context.
+fn parse_line(line: &str) -> Option<(String, Vec<u8>)> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+        return None;
+    }
+
+    // A plain hex dump.
+    if let Some(bytes) = parse_hex(line) {
+        return Some((String::new(), bytes));
+    }
+
+    // A CSV export. The raw message is the longest column of hex bytes, other columns (e.g. timestamps) are context.
+    let fields: Vec<&str> = line.split(',').collect();
+    let (index, bytes) = fields
+        .iter()
+        .enumerate()
+        .filter_map(|(index, field)| parse_hex(field).map(|bytes| (index, bytes)))
+        .max_by_key(|(_, bytes)| bytes.len())?;
+
+    let context: Vec<&str> = fields
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != index)
+        .map(|(_, field)| field.trim())
+        .collect();
+
+    Some((format!("[{}] ", context.join(", ")), bytes))
+}
+
+/// The result of decoding a single line.
+enum Decoded {
+    /// A complete message.
+    Message(Message),
+    /// A chunk of an extended message, that is not complete yet, or a chunk request.
+    Chunk(String),
+}
+
+/// Decodes messages, assembling chunked extended messages.
+#[derive(Default)]
+struct Decoder {
+    assembler: ChunkedMessageAssembler,
+}
+
+impl Decoder {
+    fn decode(&mut self, bytes: &[u8]) -> Result<Decoded, ParseError> {
+        match Message::from_bytes(bytes) {
+            Err(ParseError::ChunkedExtendedMessage { .. }) => {
+                let (header, ext_header, chunk_data) = Message::parse_extended_chunk(bytes)?;
+
+                if !self.assembler.is_in_progress() && ext_header.chunk_number() == 0 {
+                    self.assembler.reset();
+                }
+
+                match self.assembler.process_chunk(header, ext_header, chunk_data)? {
+                    ChunkResult::Complete(data) => {
+                        let MessageType::Extended(message_type) = header.message_type() else {
+                            unreachable!()
+                        };
+                        self.assembler.reset();
+
+                        Ok(Decoded::Message(Message {
+                            header,
+                            payload: Some(Payload::Extended(Message::parse_extended_payload(message_type, &data))),
+                        }))
+                    }
+                    ChunkResult::NeedMoreChunks(next) => Ok(Decoded::Chunk(format!(
+                        "{:?} chunk {}, waiting for chunk {}",
+                        header.message_type(),
+                        ext_header.chunk_number(),
+                        next
+                    ))),
+                    ChunkResult::ChunkRequested(number) => Ok(Decoded::Chunk(format!(
+                        "{:?} chunk request for chunk {}",
+                        header.message_type(),
+                        number
+                    ))),
+                }
+            }
+            result => result.map(Decoded::Message),
+        }
+    }
+}
+
+/// Whether the parser knows the message's payload.
+fn is_known(message: &Message) -> bool {
+    !matches!(
+        message.payload,
+        Some(Payload::Data(Data::Unknown)) | Some(Payload::Extended(Extended::Unknown))
+    )
+}
+
+fn main() -> ExitCode {
+    let mut strict = false;
+    let mut path = None;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            "-h" | "--help" => {
+                println!("Usage: usbpd-decode [--strict] [FILE]");
+                return ExitCode::SUCCESS;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let reader: Box<dyn BufRead> = match path {
+        Some(path) => match File::open(&path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("Cannot open {path}: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut decoder = Decoder::default();
+    let mut decoded = 0;
+    let mut unknown = 0;
+    let mut failed = 0;
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Cannot read input: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let Some((context, bytes)) = parse_line(&line) else {
+            continue;
+        };
+
+        match decoder.decode(&bytes) {
+            Ok(Decoded::Message(message)) => {
+                if is_known(&message) {
+                    decoded += 1;
+                } else {
+                    unknown += 1;
+                }
+
+                println!("{context}{message:?}");
+
+                let words = message.raw_words();
+                if !words.is_empty() {
+                    println!("    raw: {:08X?}", words.as_slice());
+                }
+            }
+            Ok(Decoded::Chunk(description)) => println!("{context}{description}"),
+            Err(err) => {
+                failed += 1;
+                println!("{context}line {}: {err} ({bytes:02X?})", number + 1);
+            }
+        }
+    }
+
+    println!("{decoded} decoded, {unknown} with unknown payload, {failed} failed");
+
+    if failed > 0 || (strict && unknown > 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoded, Decoder, is_known, parse_hex, parse_line};
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("A1 61 2C"), Some(vec![0xA1, 0x61, 0x2C]));
+        assert_eq!(parse_hex("0xA1, 0x61"), Some(vec![0xA1, 0x61]));
+        assert_eq!(parse_hex("\"a1612c\""), Some(vec![0xA1, 0x61, 0x2C]));
+        assert_eq!(parse_hex("A1"), None);
+        assert_eq!(parse_hex("0.0125"), None);
+        assert_eq!(parse_hex("SOP"), None);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("# comment"), None);
+        assert_eq!(parse_line("time,sop,raw"), None);
+
+        let (context, bytes) = parse_line("0.0125,SOP,A1612C910108").unwrap();
+        assert_eq!(context, "[0.0125, SOP] ");
+        assert_eq!(bytes, vec![0xA1, 0x61, 0x2C, 0x91, 0x01, 0x08]);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut decoder = Decoder::default();
+
+        // EPR Mode (Enter)
+        let (_, bytes) = parse_line("8A 14 00 00 00 01").unwrap();
+        assert!(matches!(decoder.decode(&bytes), Ok(Decoded::Message(_))));
+
+        // Truncated data object.
+        assert!(matches!(decoder.decode(&[0x8A, 0x14, 0x00]), Ok(Decoded::Message(message)) if !is_known(&message)));
+    }
+}
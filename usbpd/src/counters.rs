@@ -1,15 +1,24 @@
 //! Definition of counters, used for retry attempts, and message IDs.
+//!
+//! There are two kinds of counters:
+//! - A bounded [`Counter`] counts attempts, and reports when it exceeds its maximum value.
+//! - A [`MessageId`] wraps around silently, as message IDs are a continuous sequence, see [6.2.1.1.3].
+//!
+//! [`MessageIds`] holds both message IDs of an SOP* communication, and implements their reset rules.
+
+pub use crate::protocol_layer::message::header::MessageId;
 
 /// Counter error variants.
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(thiserror::Error, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// The counter wrapped around its maximum allowed value and was reset.
+    #[error("counter exceeded")]
     Exceeded,
 }
 
-/// A counter structure, used for detecting overruns (e.g. retries).
+/// A bounded counter, used for detecting overruns (e.g. retries).
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Counter {
@@ -25,8 +34,8 @@ pub enum CounterType {
     Busy,
     Caps,
     DiscoverIdentity,
+    DriverFault,
     HardReset,
-    MessageId,
     Retry,
 }
 
@@ -38,38 +47,32 @@ impl Counter {
             CounterType::Busy => 5,
             CounterType::Caps => 50,
             CounterType::DiscoverIdentity => 20,
+            // Not part of the spec: consecutive driver faults that are tolerated, before the driver is recovered.
+            CounterType::DriverFault => 3,
             // Per USB PD Spec Table 6.70: nHardResetCount = 2
             // Per spec 8.3.3.3.8: Give up when HardResetCounter > nHardResetCount (i.e., > 2).
             // Since increment() returns Err on wrap (value becomes 0), we need max_value = 3
             // to allow counter values 1, 2, 3 before wrapping, giving 3 hard reset attempts.
             CounterType::HardReset => 3,
-            CounterType::MessageId => 7,
             CounterType::Retry => 2,
         };
 
         Self { value: 0, max_value }
     }
 
+    /// The present counter value.
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+
     /// The maximum allowed counter value.
     pub fn max_value(&self) -> u8 {
         self.max_value
     }
 
-    /// Create a new counter of a provided type from an initial value.
-    pub fn new_from_value(counter_type: CounterType, value: u8) -> Self {
-        let mut counter = Self::new(counter_type);
-        counter.set(value);
-        counter
-    }
-
     /// Set a new counter value, clamped to the maximum counter value.
     pub fn set(&mut self, value: u8) {
-        self.value = value % (self.max_value + 1);
-    }
-
-    /// The counter value.
-    pub fn value(&self) -> u8 {
-        self.value
+        self.value = value.checked_rem(self.max_value.wrapping_add(1)).unwrap_or(value);
     }
 
     /// Increment a counter.
@@ -86,3 +89,136 @@ impl Counter {
         self.value = 0;
     }
 }
+
+/// The message IDs of one SOP* communication: the MessageIDCounter for outgoing messages, and the stored MessageID
+/// of the last received message.
+///
+/// The reset rules are given in [6.7.1]. They apply per SOP*, so that a soft reset on SOP does not affect the IDs of
+/// SOP' communication with the cable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MessageIds {
+    tx: MessageId,
+    rx: Option<MessageId>,
+}
+
+impl MessageIds {
+    /// Message IDs after attach: the counter is zero, and no ID is stored.
+    pub const fn new() -> Self {
+        Self {
+            tx: MessageId::new(0),
+            rx: None,
+        }
+    }
+
+    /// The ID for the next outgoing message.
+    pub const fn tx(&self) -> MessageId {
+        self.tx
+    }
+
+    /// The stored ID of the last received message, if any was received since the last reset.
+    pub const fn rx(&self) -> Option<MessageId> {
+        self.rx
+    }
+
+    /// Advance the counter, after the outgoing message was acknowledged with GoodCrc.
+    pub fn acknowledge(&mut self) {
+        self.tx.increment();
+    }
+
+    /// Store the ID of a received message, returning `true`, if it is a retransmission of the last one.
+    ///
+    /// The first message after a reset is never a retransmission. A Soft_Reset message resets both IDs before it is
+    /// stored, so it is never discarded, even if its ID matches the stored one.
+    pub fn receive(&mut self, message_id: u8, soft_reset: bool) -> bool {
+        if soft_reset {
+            self.reset();
+        }
+
+        let message_id = MessageId::new(message_id);
+        let retransmission = self.rx == Some(message_id);
+        self.rx = Some(message_id);
+
+        retransmission
+    }
+
+    /// Reset both IDs, on transmission or reception of a Soft_Reset message on this SOP*, or of Hard Reset signaling,
+    /// which affects all SOP*.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Counter, CounterType, Error, MessageId, MessageIds};
+
+    #[test]
+    fn test_bounded_counter() {
+        let mut counter = Counter::new(CounterType::HardReset);
+
+        for _ in 0..counter.max_value() {
+            assert!(counter.increment().is_ok());
+        }
+
+        assert!(matches!(counter.increment(), Err(Error::Exceeded)));
+
+        // The counter starts over after an overrun.
+        assert!(counter.increment().is_ok());
+    }
+
+    #[test]
+    fn test_message_id_wraps() {
+        let mut message_id = MessageId::default();
+
+        for expected in (0..=MessageId::MAX).cycle().skip(1).take(8 * 5) {
+            message_id.increment();
+            assert_eq!(message_id.value(), expected);
+        }
+
+        assert_eq!(MessageId::new(9).value(), 1);
+    }
+
+    #[test]
+    fn test_message_ids_retransmission() {
+        let mut message_ids = MessageIds::new();
+
+        // Any first ID is new, and repeated IDs are retransmissions.
+        assert!(!message_ids.receive(5, false));
+        assert!(message_ids.receive(5, false));
+        assert!(!message_ids.receive(6, false));
+        assert_eq!(message_ids.rx(), Some(MessageId::new(6)));
+    }
+
+    #[test]
+    fn test_message_ids_soft_reset() {
+        let mut message_ids = MessageIds::new();
+        message_ids.acknowledge();
+        assert!(!message_ids.receive(0, false));
+
+        // A Soft_Reset with the stored ID is still processed, and resets the counter.
+        assert!(!message_ids.receive(0, true));
+        assert_eq!(message_ids.tx().value(), 0);
+        assert_eq!(message_ids.rx(), Some(MessageId::new(0)));
+
+        // A retransmission of the Soft_Reset is processed again, as the partner restarted the AMS.
+        assert!(!message_ids.receive(0, true));
+
+        // A transmitted Soft_Reset clears the stored ID.
+        message_ids.acknowledge();
+        message_ids.reset();
+        assert_eq!(message_ids, MessageIds::new());
+    }
+
+    #[test]
+    fn test_message_ids_hard_reset() {
+        let mut message_ids = MessageIds::new();
+        message_ids.acknowledge();
+        message_ids.receive(3, false);
+
+        message_ids.reset();
+        assert_eq!(message_ids.tx().value(), 0);
+        assert!(message_ids.rx().is_none());
+        assert!(!message_ids.receive(3, false));
+    }
+}
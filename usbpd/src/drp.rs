@@ -0,0 +1,391 @@
+//! A dual-role port (DRP), that is either sink or source, and swaps its power role with PR_Swap.
+//!
+//! The initial power role is resolved by the Type-C connection state machine of the PHY. Afterwards, either port
+//! partner may request a power role swap. The device policy manager of the present role decides on swaps of the
+//! port partner, see `evaluate_power_role_swap`, and requests swaps with the `PowerRoleSwap` event of the
+//! [sink](crate::sink::device_policy_manager::Event::PowerRoleSwap), or the
+//! [source](crate::source::device_policy_manager::Event::PowerRoleSwap).
+//!
+//! During a swap, the policy engine of the present role runs the `PE_PRS_*` states. Then, [`DualRole`] hands the
+//! driver to a new policy engine for the other role, which starts over, as required by the specification. The data
+//! role is kept. A new source waits for tSwapSourceStart, before it advertises its capabilities.
+//!
+//! Swaps need a driver that can switch its CC termination, see [`Driver::HAS_POWER_ROLE_SWAP`].
+use usbpd_traits::Driver;
+
+use crate::PowerRole;
+use crate::sink::config::SinkConfig;
+use crate::sink::device_policy_manager::DevicePolicyManager as SinkDevicePolicyManager;
+use crate::sink::policy_engine::{Error as SinkError, Sink};
+use crate::source::device_policy_manager::DevicePolicyManager as SourceDevicePolicyManager;
+use crate::source::policy_engine::{Error as SourceError, Source};
+use crate::timers::Timer;
+use crate::trace::Tracer;
+
+/// The policy engine of the present power role, and the idle device policy manager of the other one.
+// Only one policy engine exists at a time, so the size of the smaller variant is not wasted in practice.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+enum Role<DRIVER: Driver, TIMER: Timer, SNK: SinkDevicePolicyManager, SRC: SourceDevicePolicyManager, TRACER: Tracer> {
+    Sink(Sink<DRIVER, TIMER, SNK, TRACER>, SRC),
+    Source(Source<DRIVER, TIMER, SRC, TRACER>, SNK),
+}
+
+/// Errors that can occur in the policy engine of the present power role.
+#[derive(thiserror::Error, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// An error of the sink policy engine.
+    #[error("sink: {0}")]
+    Sink(#[from] SinkError),
+    /// An error of the source policy engine.
+    #[error("source: {0}")]
+    Source(#[from] SourceError),
+}
+
+/// Implementation of a dual-role port, with a device policy manager for each power role.
+///
+/// See spec, [8.3.3.19]
+#[derive(Debug)]
+pub struct DualRole<
+    DRIVER: Driver,
+    TIMER: Timer,
+    SNK: SinkDevicePolicyManager,
+    SRC: SourceDevicePolicyManager,
+    TRACER: Tracer = (),
+> {
+    /// Only `None` while the driver is handed from one policy engine to the other.
+    role: Option<Role<DRIVER, TIMER, SNK, SRC, TRACER>>,
+    config: SinkConfig,
+    tracer: TRACER,
+}
+
+impl<DRIVER: Driver, TIMER: Timer, SNK: SinkDevicePolicyManager, SRC: SourceDevicePolicyManager>
+    DualRole<DRIVER, TIMER, SNK, SRC>
+{
+    /// Create a new dual-role port with a given `driver`, starting in the resolved `power_role`.
+    pub fn new(driver: DRIVER, power_role: PowerRole, sink_policy_manager: SNK, source_policy_manager: SRC) -> Self {
+        Self::new_with_config_and_tracer(
+            driver,
+            power_role,
+            sink_policy_manager,
+            source_policy_manager,
+            SinkConfig::new(),
+            (),
+        )
+    }
+}
+
+impl<DRIVER: Driver, TIMER: Timer, SNK: SinkDevicePolicyManager, SRC: SourceDevicePolicyManager, TRACER: Tracer>
+    DualRole<DRIVER, TIMER, SNK, SRC, TRACER>
+{
+    /// Create a new dual-role port with a given `driver`, starting in the resolved `power_role`, with a configuration
+    /// of the sink, that records protocol events of either role to `tracer`.
+    pub fn new_with_config_and_tracer(
+        driver: DRIVER,
+        power_role: PowerRole,
+        sink_policy_manager: SNK,
+        source_policy_manager: SRC,
+        config: SinkConfig,
+        tracer: TRACER,
+    ) -> Self {
+        let role = match power_role {
+            PowerRole::Sink => Role::Sink(
+                Sink::new_with_config_and_tracer(driver, sink_policy_manager, config, tracer.clone()),
+                source_policy_manager,
+            ),
+            PowerRole::Source => Role::Source(
+                Source::new_with_tracer(driver, source_policy_manager, tracer.clone()),
+                sink_policy_manager,
+            ),
+        };
+
+        Self {
+            role: Some(role),
+            config,
+            tracer,
+        }
+    }
+
+    /// The present power role.
+    pub fn power_role(&self) -> PowerRole {
+        match &self.role {
+            Some(Role::Source(..)) => PowerRole::Source,
+            _ => PowerRole::Sink,
+        }
+    }
+
+    /// The sink policy engine, while the port is the sink.
+    pub fn sink(&self) -> Option<&Sink<DRIVER, TIMER, SNK, TRACER>> {
+        match &self.role {
+            Some(Role::Sink(sink, _)) => Some(sink),
+            _ => None,
+        }
+    }
+
+    /// The source policy engine, while the port is the source.
+    pub fn source(&self) -> Option<&Source<DRIVER, TIMER, SRC, TRACER>> {
+        match &self.role {
+            Some(Role::Source(source, _)) => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Run a single step in the state machine of the present role, and swap roles, if due.
+    async fn run_step(&mut self) -> Result<(), Error> {
+        let result = match self.role.as_mut() {
+            Some(Role::Sink(sink, _)) => sink.run_step().await.map_err(Error::from),
+            Some(Role::Source(source, _)) => source.run_step().await.map_err(Error::from),
+            None => Ok(()),
+        };
+
+        match result {
+            Err(Error::Sink(SinkError::PowerRoleSwapped) | Error::Source(SourceError::PowerRoleSwapped)) => {
+                self.swap();
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Hand the driver to a new policy engine for the other power role.
+    fn swap(&mut self) {
+        let Some(role) = self.role.take() else {
+            return;
+        };
+
+        let role = match role {
+            Role::Sink(sink, source_policy_manager) => {
+                let data_role = sink.data_role();
+                let (driver, sink_policy_manager) = sink.into_parts();
+
+                let mut source = Source::new_with_tracer(driver, source_policy_manager, self.tracer.clone());
+                source.swapped_in(data_role);
+                Role::Source(source, sink_policy_manager)
+            }
+            Role::Source(source, sink_policy_manager) => {
+                let data_role = source.data_role();
+                let (driver, source_policy_manager) = source.into_parts();
+
+                let mut sink =
+                    Sink::new_with_config_and_tracer(driver, sink_policy_manager, self.config, self.tracer.clone());
+                sink.swapped_in(data_role);
+                Role::Sink(sink, source_policy_manager)
+            }
+        };
+
+        info!("Power role swapped");
+        self.role = Some(role);
+    }
+
+    /// Run the state machine of the present role continuously, and swap roles on the way.
+    ///
+    /// The loop is only broken for unrecoverable errors of either policy engine, for example on detach.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        loop {
+            self.run_step().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use usbpd_traits::CcTermination;
+
+    use super::DualRole;
+    use crate::counters::MessageId;
+    use crate::dummy::{
+        DUMMY_CAPABILITIES, DummyDriver, DummyTimer, MAX_DATA_MESSAGE_SIZE, get_dummy_source_capabilities,
+    };
+    use crate::protocol_layer::message::Message;
+    use crate::protocol_layer::message::data::request::{FixedVariableSupply, PowerSource};
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::protocol_layer::message::data::{Data, ObjectPosition};
+    use crate::protocol_layer::message::header::{
+        ControlMessageType, DataMessageType, Header, MessageType, SpecificationRevision,
+    };
+    use crate::sink::device_policy_manager::DevicePolicyManager as SinkDevicePolicyManager;
+    use crate::source::device_policy_manager::DevicePolicyManager as SourceDevicePolicyManager;
+    use crate::timers::{Timer, TimerType};
+    use crate::{DataRole, PowerRole};
+
+    /// A timer that expires immediately for tSwapSourceStart, and never for other durations.
+    struct SwapSourceStartTimer {}
+
+    impl Timer for SwapSourceStartTimer {
+        async fn after_millis(_milliseconds: u64) {
+            core::future::pending().await
+        }
+
+        async fn after_micros(microseconds: u64) {
+            if microseconds != TimerType::SwapSourceStart.duration_micros() {
+                core::future::pending().await
+            }
+        }
+    }
+
+    /// A device that accepts all power role swaps.
+    struct SwappingDevice {}
+
+    impl SinkDevicePolicyManager for SwappingDevice {
+        async fn evaluate_power_role_swap(&mut self) -> bool {
+            true
+        }
+    }
+
+    impl SourceDevicePolicyManager for SwappingDevice {
+        fn source_capabilities(&self) -> SourceCapabilities {
+            SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()))
+        }
+
+        async fn evaluate_power_role_swap(&mut self) -> bool {
+            true
+        }
+    }
+
+    fn inject(driver: &mut DummyDriver<MAX_DATA_MESSAGE_SIZE>, message: Message) {
+        let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+        let len = message.to_bytes(&mut buf);
+        driver.inject_received_data(&buf[..len]);
+    }
+
+    fn inject_control(
+        driver: &mut DummyDriver<MAX_DATA_MESSAGE_SIZE>,
+        template: Header,
+        message_type: ControlMessageType,
+        message_id: u8,
+    ) {
+        let header = Header::new_control(template, MessageId::new(message_id), message_type);
+        inject(driver, Message::new(header));
+    }
+
+    fn inject_request(driver: &mut DummyDriver<MAX_DATA_MESSAGE_SIZE>, template: Header, message_id: u8) {
+        let request = PowerSource::FixedVariableSupply(FixedVariableSupply::new(ObjectPosition::VSAFE_5V, 100, 100));
+        let header = Header::new_data(template, MessageId::new(message_id), DataMessageType::Request, 1);
+        inject(driver, Message::new_with_data(header, Data::Request(request)));
+    }
+
+    /// Take all transmitted messages.
+    fn transmitted(driver: &mut DummyDriver<MAX_DATA_MESSAGE_SIZE>) -> std::vec::Vec<Message> {
+        let mut messages = std::vec::Vec::new();
+        while driver.has_transmitted_data() {
+            messages.push(Message::from_bytes(&driver.probe_transmitted_data()).unwrap());
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_source_to_sink() {
+        let sink_template = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+        let new_source_template = Header::new_template(DataRole::Ufp, PowerRole::Source, SpecificationRevision::R3_X);
+        let mut driver = DummyDriver::new();
+
+        // Negotiate a contract as the source.
+        inject_control(&mut driver, sink_template, ControlMessageType::GoodCRC, 0);
+        inject_request(&mut driver, sink_template, 0);
+        inject_control(&mut driver, sink_template, ControlMessageType::GoodCRC, 1);
+        inject_control(&mut driver, sink_template, ControlMessageType::GoodCRC, 2);
+
+        // The sink requests the swap, and turns VBUS on, after the source turned it off.
+        inject_control(&mut driver, sink_template, ControlMessageType::PrSwap, 1);
+        inject_control(&mut driver, sink_template, ControlMessageType::GoodCRC, 3);
+        inject_control(&mut driver, sink_template, ControlMessageType::GoodCRC, 4);
+        inject_control(&mut driver, new_source_template, ControlMessageType::PsRdy, 2);
+
+        let mut dual_role: DualRole<_, DummyTimer, _, _> =
+            DualRole::new(driver, PowerRole::Source, SwappingDevice {}, SwappingDevice {});
+
+        // `Startup` -> `SendCapabilities` -> `NegotiateCapability` -> `TransitionSupply` -> `Ready`
+        // -> `PrsEvaluateSwap` -> `PrsTransitionToOff` -> `PrsWaitSourceOn`
+        for _ in 0..8 {
+            dual_role.run_step().await.unwrap();
+        }
+        assert!(matches!(dual_role.power_role(), PowerRole::Sink));
+
+        let Some(super::Role::Sink(sink, _)) = dual_role.role.as_mut() else {
+            panic!("expected the sink role");
+        };
+        let driver = sink.driver();
+        assert_eq!(driver.cc_termination(), Some(CcTermination::Rd));
+
+        let messages = transmitted(driver);
+        let message_types: std::vec::Vec<_> = messages.iter().map(|message| message.header.message_type()).collect();
+        assert_eq!(
+            message_types[4..],
+            [
+                MessageType::Control(ControlMessageType::GoodCRC),
+                MessageType::Control(ControlMessageType::Accept),
+                MessageType::Control(ControlMessageType::PsRdy),
+                MessageType::Control(ControlMessageType::GoodCRC),
+            ]
+        );
+
+        // The former source sends PS_RDY as the source, and acknowledges the PS_RDY of the new source as the sink.
+        let ps_rdy = &messages[messages.len() - 2].header;
+        assert!(matches!(ps_rdy.port_power_role(), PowerRole::Source));
+        let good_crc = &messages[messages.len() - 1].header;
+        assert!(matches!(good_crc.port_power_role(), PowerRole::Sink));
+        assert!(matches!(good_crc.port_data_role(), DataRole::Dfp));
+    }
+
+    #[tokio::test]
+    async fn test_sink_to_source() {
+        let source_template = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R3_X);
+        let new_sink_template = Header::new_template(DataRole::Dfp, PowerRole::Sink, SpecificationRevision::R3_X);
+        let mut driver = DummyDriver::new();
+
+        // Negotiate a contract as the sink.
+        driver.inject_received_data(&DUMMY_CAPABILITIES);
+        inject_control(&mut driver, source_template, ControlMessageType::GoodCRC, 0);
+        inject_control(&mut driver, source_template, ControlMessageType::Accept, 1);
+        inject_control(&mut driver, source_template, ControlMessageType::PsRdy, 2);
+
+        // The source requests the swap, and turns VBUS off.
+        inject_control(&mut driver, source_template, ControlMessageType::PrSwap, 3);
+        inject_control(&mut driver, source_template, ControlMessageType::GoodCRC, 1);
+        inject_control(&mut driver, source_template, ControlMessageType::PsRdy, 4);
+        inject_control(&mut driver, source_template, ControlMessageType::GoodCRC, 2);
+
+        // The new sink acknowledges the first capabilities of the new source, and requests.
+        inject_control(&mut driver, new_sink_template, ControlMessageType::GoodCRC, 0);
+        inject_request(&mut driver, new_sink_template, 0);
+
+        let mut dual_role: DualRole<_, SwapSourceStartTimer, _, _> =
+            DualRole::new(driver, PowerRole::Sink, SwappingDevice {}, SwappingDevice {});
+
+        // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability` -> `TransitionSink`
+        // -> `Ready` -> `PrsEvaluateSwap` -> `PrsTransitionToOff` -> `PrsSourceOn`
+        for _ in 0..9 {
+            dual_role.run_step().await.unwrap();
+        }
+        assert!(matches!(dual_role.power_role(), PowerRole::Source));
+
+        // `Startup` (after tSwapSourceStart) -> `SendCapabilities` -> `NegotiateCapability`
+        for _ in 0..2 {
+            dual_role.run_step().await.unwrap();
+        }
+
+        let Some(super::Role::Source(source, _)) = dual_role.role.as_mut() else {
+            panic!("expected the source role");
+        };
+        let driver = source.driver();
+        assert_eq!(driver.cc_termination(), Some(CcTermination::Rp));
+
+        let messages = transmitted(driver);
+        let ps_rdy = messages
+            .iter()
+            .find(|message| message.header.message_type() == MessageType::Control(ControlMessageType::PsRdy))
+            .unwrap();
+        assert!(matches!(ps_rdy.header.port_power_role(), PowerRole::Source));
+
+        // The new source keeps the data role, and advertises its capabilities, before it acknowledges the request.
+        let capabilities = &messages[messages.len() - 2];
+        assert_eq!(
+            capabilities.header.message_type(),
+            MessageType::Data(DataMessageType::SourceCapabilities)
+        );
+        assert!(matches!(capabilities.header.port_power_role(), PowerRole::Source));
+        assert!(matches!(capabilities.header.port_data_role(), DataRole::Ufp));
+        assert_eq!(capabilities.header.message_id(), 0);
+    }
+}
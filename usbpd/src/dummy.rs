@@ -3,13 +3,14 @@ use std::future::pending;
 use std::vec::Vec;
 
 use uom::si::power::watt;
-use usbpd_traits::Driver;
+use usbpd_traits::{CcTermination, Driver, DriverTxError};
 
 use crate::protocol_layer::message::data::request::EprRequestDataObject;
 use crate::protocol_layer::message::data::source_capabilities::{
-    Augmented, FixedSupply, PowerDataObject, SprProgrammablePowerSupply,
+    Augmented, FixedSupply, PowerDataObject, SourceCapabilities, SprProgrammablePowerSupply,
 };
 use crate::sink::device_policy_manager::DevicePolicyManager as SinkDevicePolicyManager;
+use crate::source::device_policy_manager::DevicePolicyManager as SourceDevicePolicyManager;
 use crate::timers::Timer;
 use crate::units::Power;
 
@@ -34,8 +35,7 @@ pub const DUMMY_EPR_SOURCE_CAPS_CHUNK_1: [u8; 18] = [
 ];
 
 /// Maximum size of a non-extended USB PD message in bytes.
-/// Per USB PD spec, this is 2 bytes header + 7 data objects * 4 bytes = 30 bytes.
-pub const MAX_DATA_MESSAGE_SIZE: usize = 30;
+pub const MAX_DATA_MESSAGE_SIZE: usize = crate::protocol_layer::MAX_DATA_MESSAGE_SIZE;
 
 /// A dummy sink device that implements the sink device policy manager.
 pub struct DummySinkDevice {}
@@ -60,6 +60,7 @@ impl DummySinkEprDevice {
 }
 
 impl SinkDevicePolicyManager for DummySinkEprDevice {
+    #[allow(clippy::collapsible_if)]
     async fn get_event(
         &mut self,
         source_capabilities: &crate::protocol_layer::message::data::source_capabilities::SourceCapabilities,
@@ -91,7 +92,6 @@ impl SinkDevicePolicyManager for DummySinkEprDevice {
         // Per USB PD Spec R3.2 Section 6.5.15.1, EPR PDOs always start at position 8
         let first_epr_pdo = source_capabilities
             .epr_pdos()
-            .filter(|(_, pdo)| !pdo.is_zero_padding())
             .find(|(_, pdo)| matches!(pdo, PowerDataObject::FixedSupply(_)));
 
         if let Some((position, pdo)) = first_epr_pdo {
@@ -99,7 +99,6 @@ impl SinkDevicePolicyManager for DummySinkEprDevice {
             use crate::protocol_layer::message::data::request::FixedVariableSupply;
 
             let mut rdo = FixedVariableSupply(0)
-                .with_object_position(position)
                 .with_usb_communications_capable(true)
                 .with_no_usb_suspend(true);
 
@@ -112,7 +111,7 @@ impl SinkDevicePolicyManager for DummySinkEprDevice {
             }
 
             // Create EPR request with RDO and PDO copy
-            PowerSource::EprRequest(EprRequestDataObject { rdo: rdo.0, pdo: *pdo })
+            PowerSource::EprRequest(EprRequestDataObject::new(position, pdo, rdo))
         } else {
             // Fall back to default 5V
             PowerSource::new_fixed(CurrentRequest::Highest, VoltageRequest::Safe5V, source_capabilities).unwrap()
@@ -120,6 +119,15 @@ impl SinkDevicePolicyManager for DummySinkEprDevice {
     }
 }
 
+/// A dummy source device that advertises [`get_dummy_source_capabilities`].
+pub struct DummySourceDevice {}
+
+impl SourceDevicePolicyManager for DummySourceDevice {
+    fn source_capabilities(&self) -> SourceCapabilities {
+        SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()))
+    }
+}
+
 /// A dummy timer for testing.
 pub struct DummyTimer {}
 
@@ -134,6 +142,11 @@ impl Timer for DummyTimer {
 pub struct DummyDriver<const N: usize> {
     rx_vec: Vec<heapless::Vec<u8, N>>,
     tx_vec: Vec<heapless::Vec<u8, N>>,
+    detached: bool,
+    discards: u32,
+    tx_error: Option<DriverTxError>,
+    recoveries: usize,
+    cc_termination: Option<CcTermination>,
 }
 
 impl<const N: usize> Default for DummyDriver<N> {
@@ -141,6 +154,11 @@ impl<const N: usize> Default for DummyDriver<N> {
         Self {
             rx_vec: Vec::new(),
             tx_vec: Vec::new(),
+            detached: false,
+            discards: 0,
+            tx_error: None,
+            recoveries: 0,
+            cc_termination: None,
         }
     }
 }
@@ -165,6 +183,33 @@ impl<const N: usize> DummyDriver<N> {
         self.tx_vec.remove(0)
     }
 
+    /// Simulate a detach (or re-attach) of the port partner.
+    ///
+    /// While detached, reception and transmission fail.
+    pub fn set_detached(&mut self, detached: bool) {
+        self.detached = detached;
+    }
+
+    /// Discard the next `count` transmissions, as if the line was busy.
+    pub fn discard_transmissions(&mut self, count: u32) {
+        self.discards = count;
+    }
+
+    /// Fail the next transmission with the given error, e.g. a local fault or a detach mid-message.
+    pub fn fail_transmission(&mut self, error: DriverTxError) {
+        self.tx_error = Some(error);
+    }
+
+    /// The number of times that the driver was recovered.
+    pub fn recoveries(&self) -> usize {
+        self.recoveries
+    }
+
+    /// The CC termination, that was last set during a power role swap, if any.
+    pub fn cc_termination(&self) -> Option<CcTermination> {
+        self.cc_termination
+    }
+
     /// Check if there's transmitted data available to probe.
     pub fn has_transmitted_data(&self) -> bool {
         !self.tx_vec.is_empty()
@@ -172,7 +217,15 @@ impl<const N: usize> DummyDriver<N> {
 }
 
 impl<const N: usize> Driver for DummyDriver<N> {
+    const HAS_BIST_CARRIER_MODE: bool = true;
+    const HAS_RECOVERY: bool = true;
+    const HAS_POWER_ROLE_SWAP: bool = true;
+
     async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usbpd_traits::DriverRxError> {
+        if self.detached {
+            return Err(usbpd_traits::DriverRxError::Detached);
+        }
+
         // If no data available, wait indefinitely (like real hardware would)
         if self.rx_vec.is_empty() {
             pending().await
@@ -186,6 +239,19 @@ impl<const N: usize> Driver for DummyDriver<N> {
     }
 
     async fn transmit(&mut self, data: &[u8]) -> Result<(), usbpd_traits::DriverTxError> {
+        if self.detached {
+            return Err(usbpd_traits::DriverTxError::Detached);
+        }
+
+        if self.discards > 0 {
+            self.discards -= 1;
+            return Err(usbpd_traits::DriverTxError::Discarded);
+        }
+
+        if let Some(error) = self.tx_error.take() {
+            return Err(error);
+        }
+
         let mut vec = heapless::Vec::new();
         vec.extend_from_slice(data).unwrap();
         self.tx_vec.push(vec);
@@ -201,6 +267,14 @@ impl<const N: usize> Driver for DummyDriver<N> {
     async fn wait_for_vbus(&mut self) {
         // Do nothing.
     }
+
+    async fn recover(&mut self) {
+        self.recoveries += 1;
+    }
+
+    async fn set_cc_termination(&mut self, termination: CcTermination) {
+        self.cc_termination = Some(termination);
+    }
 }
 
 /// Dummy capabilities to deserialize.
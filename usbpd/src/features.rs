@@ -0,0 +1,84 @@
+//! A report of what this build of the stack supports, for host tools and logs.
+//!
+//! Parts of the stack are compiled in, depending on cargo features. [`capabilities`] reports them at runtime, such
+//! that a firmware can record exactly which stack it runs:
+//!
+//! ```
+//! let capabilities = usbpd::capabilities();
+//! assert!(capabilities.epr);
+//! println!("usbpd {} (bist: {})", capabilities.version, capabilities.bist);
+//! ```
+use crate::protocol_layer::message::data::vendor_defined::VdmVersion;
+use crate::protocol_layer::message::header::SpecificationRevision;
+
+/// The features of the stack, as compiled into the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StackCapabilities {
+    /// The version of this crate.
+    pub version: &'static str,
+    /// The lowest specification revision that the stack falls back to, for older port partners.
+    pub min_revision: SpecificationRevision,
+    /// The highest specification revision that the stack speaks.
+    pub max_revision: SpecificationRevision,
+    /// The highest structured VDM version.
+    pub vdm_version: VdmVersion,
+    /// Sink policy engine.
+    pub sink: bool,
+    /// Source policy engine.
+    pub source: bool,
+    /// Extended power range (EPR) mode.
+    pub epr: bool,
+    /// Vendor defined messages.
+    pub vdm: bool,
+    /// Extended messages, received and sent in chunks.
+    pub extended_messages: bool,
+    /// Built-in self-test (BIST) modes, with the `bist` feature.
+    pub bist: bool,
+    /// Per-source interoperability workarounds, with the `quirks` feature.
+    pub quirks: bool,
+    /// Capability summaries in the log and trace, with the `capability-summary` feature.
+    pub capability_summary: bool,
+    /// Recording of timer durations, with the `timer-audit` feature.
+    pub timer_audit: bool,
+    /// Errors instead of panics on unexpected input, with the `panic-free` feature.
+    pub panic_free: bool,
+    /// Serialization of stored contracts and traces, with the `serde` feature.
+    pub serde: bool,
+}
+
+/// The features of the stack, as compiled into the firmware.
+pub const fn capabilities() -> StackCapabilities {
+    StackCapabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        min_revision: SpecificationRevision::R2_0,
+        max_revision: SpecificationRevision::R3_X,
+        vdm_version: VdmVersion::LATEST,
+        sink: true,
+        source: true,
+        epr: true,
+        vdm: true,
+        extended_messages: true,
+        bist: cfg!(feature = "bist"),
+        quirks: cfg!(feature = "quirks"),
+        capability_summary: cfg!(feature = "capability-summary"),
+        timer_audit: cfg!(feature = "timer-audit"),
+        panic_free: cfg!(feature = "panic-free"),
+        serde: cfg!(feature = "serde"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::capabilities;
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = capabilities();
+
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert!(capabilities.min_revision <= capabilities.max_revision);
+        assert_eq!(capabilities.bist, cfg!(feature = "bist"));
+        assert_eq!(capabilities.panic_free, cfg!(feature = "panic-free"));
+    }
+}
@@ -6,13 +6,15 @@ use core::fmt::{Debug, Display, LowerHex};
 #[cfg(all(feature = "defmt", feature = "log"))]
 compile_error!("You may not enable both `defmt` and `log` features.");
 
+// Assertions and panics use `core` formatting in tests, such that test assertions may format any `Debug` value.
+
 #[collapse_debuginfo(yes)]
 macro_rules! assert {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::assert!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::assert!($($x)*);
         }
     };
@@ -22,9 +24,9 @@ macro_rules! assert {
 macro_rules! assert_eq {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::assert_eq!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::assert_eq!($($x)*);
         }
     };
@@ -34,9 +36,9 @@ macro_rules! assert_eq {
 macro_rules! assert_ne {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::assert_ne!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::assert_ne!($($x)*);
         }
     };
@@ -46,9 +48,9 @@ macro_rules! assert_ne {
 macro_rules! debug_assert {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::debug_assert!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::debug_assert!($($x)*);
         }
     };
@@ -58,9 +60,9 @@ macro_rules! debug_assert {
 macro_rules! debug_assert_eq {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::debug_assert_eq!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::debug_assert_eq!($($x)*);
         }
     };
@@ -70,9 +72,9 @@ macro_rules! debug_assert_eq {
 macro_rules! debug_assert_ne {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::debug_assert_ne!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::debug_assert_ne!($($x)*);
         }
     };
@@ -82,9 +84,9 @@ macro_rules! debug_assert_ne {
 macro_rules! todo {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::todo!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::todo!($($x)*);
         }
     };
@@ -94,9 +96,9 @@ macro_rules! todo {
 macro_rules! unreachable {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::unreachable!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::unreachable!($($x)*);
         }
     };
@@ -106,9 +108,9 @@ macro_rules! unreachable {
 macro_rules! panic {
     ($($x:tt)*) => {
         {
-            #[cfg(not(feature = "defmt"))]
+            #[cfg(any(not(feature = "defmt"), test))]
             ::core::panic!($($x)*);
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "defmt", not(test)))]
             ::defmt::panic!($($x)*);
         }
     };
@@ -213,6 +215,33 @@ macro_rules! unwrap {
     }
 }
 
+/// Like `unreachable!`, but returns the error instead, with the `panic-free` feature.
+#[collapse_debuginfo(yes)]
+macro_rules! unreachable_or_return {
+    ($error:expr) => {{
+        #[cfg(feature = "panic-free")]
+        return ::core::result::Result::Err(::core::convert::Into::into($error));
+        #[cfg(not(feature = "panic-free"))]
+        {
+            let _ = $error;
+            unreachable!();
+        }
+    }};
+}
+
+/// Like `assert!`, but returns the error instead, with the `panic-free` feature.
+#[collapse_debuginfo(yes)]
+macro_rules! assert_or_return {
+    ($cond:expr, $error:expr) => {{
+        #[cfg(feature = "panic-free")]
+        if !$cond {
+            return ::core::result::Result::Err(::core::convert::Into::into($error));
+        }
+        #[cfg(not(feature = "panic-free"))]
+        assert!($cond);
+    }};
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct NoneError;
 
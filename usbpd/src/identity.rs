@@ -0,0 +1,109 @@
+//! Product identity of the local device.
+//!
+//! A single [`DeviceIdentity`] holds the vendor and product registration data (VID, PID, bcdDevice, XID). It is the
+//! source for all messages that report the device identity, such as the Discover Identity response (see [6.4.4.3.1])
+//! and the Manufacturer_Info data block (see [6.5.7]).
+use crate::protocol_layer::message::data::vendor_defined::{CertStatVDO, ProductVDO, VdmIdentityHeader};
+pub use crate::protocol_layer::message::extended::manufacturer_info::MAX_MANUFACTURER_STRING_LEN;
+use crate::protocol_layer::message::extended::manufacturer_info::ManufacturerInfo;
+
+/// Vendor and product registration data of the local device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceIdentity {
+    /// The USB vendor ID, as assigned by the USB-IF.
+    pub vid: u16,
+    /// The USB product ID, as assigned by the vendor.
+    pub pid: u16,
+    /// The device version number (bcdDevice).
+    pub bcd_device: u16,
+    /// The XID, as assigned by the USB-IF to certified products. Zero, if not certified.
+    pub xid: u32,
+    /// The manufacturer string, as reported in Manufacturer_Info.
+    ///
+    /// Truncated to [`MAX_MANUFACTURER_STRING_LEN`] bytes.
+    pub manufacturer_string: &'static str,
+}
+
+impl DeviceIdentity {
+    /// Create a new identity from vendor and product ID.
+    pub const fn new(vid: u16, pid: u16) -> Self {
+        Self {
+            vid,
+            pid,
+            bcd_device: 0,
+            xid: 0,
+            manufacturer_string: "",
+        }
+    }
+
+    /// Set the device version number.
+    pub const fn with_bcd_device(self, bcd_device: u16) -> Self {
+        Self { bcd_device, ..self }
+    }
+
+    /// Set the XID.
+    pub const fn with_xid(self, xid: u32) -> Self {
+        Self { xid, ..self }
+    }
+
+    /// Set the manufacturer string.
+    pub const fn with_manufacturer_string(self, manufacturer_string: &'static str) -> Self {
+        Self {
+            manufacturer_string,
+            ..self
+        }
+    }
+
+    /// Apply the vendor ID to an ID header VDO.
+    ///
+    /// The remaining fields (product types, data capabilities, connector type) depend on the device role.
+    pub fn id_header_vdo(&self, header: VdmIdentityHeader) -> VdmIdentityHeader {
+        header.with_vid(self.vid)
+    }
+
+    /// The Cert Stat VDO.
+    pub fn cert_stat_vdo(&self) -> CertStatVDO {
+        CertStatVDO(0).with_xid(self.xid)
+    }
+
+    /// The Product VDO.
+    pub fn product_vdo(&self) -> ProductVDO {
+        ProductVDO(0).with_pid(self.pid).with_bcd_device(self.bcd_device)
+    }
+
+    /// The Manufacturer_Info data block of the port.
+    pub fn manufacturer_info(&self) -> ManufacturerInfo {
+        ManufacturerInfo::new(self.vid, self.pid, self.manufacturer_string.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceIdentity, MAX_MANUFACTURER_STRING_LEN};
+    use crate::protocol_layer::message::data::vendor_defined::VdmIdentityHeader;
+    use crate::protocol_layer::message::extended::manufacturer_info::MANUFACTURER_INFO_HEADER_SIZE;
+
+    const IDENTITY: DeviceIdentity = DeviceIdentity::new(0x1209, 0x0001)
+        .with_bcd_device(0x0102)
+        .with_xid(0xdead_beef)
+        .with_manufacturer_string("usbpd");
+
+    #[test]
+    fn test_vdos() {
+        assert_eq!(IDENTITY.id_header_vdo(VdmIdentityHeader(0)).vid(), 0x1209);
+        assert_eq!(IDENTITY.cert_stat_vdo().0, 0xdead_beef);
+        assert_eq!(IDENTITY.product_vdo().0, 0x0001_0102);
+    }
+
+    #[test]
+    fn test_manufacturer_info() {
+        let mut buf = [0u8; MANUFACTURER_INFO_HEADER_SIZE + MAX_MANUFACTURER_STRING_LEN];
+
+        let size = IDENTITY.manufacturer_info().to_bytes(&mut buf);
+        assert_eq!(&buf[..size], &[0x09, 0x12, 0x01, 0x00, b'u', b's', b'b', b'p', b'd']);
+
+        let long = IDENTITY.with_manufacturer_string("a manufacturer string that is too long");
+        assert_eq!(long.manufacturer_info().to_bytes(&mut buf), buf.len());
+    }
+}
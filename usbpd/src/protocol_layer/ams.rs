@@ -0,0 +1,106 @@
+//! Type-state handling of atomic message sequences (AMS), for policy engines built on the
+//! [`CallbackProtocolLayer`](super::callback::CallbackProtocolLayer).
+//!
+//! A policy engine takes the single [`AmsSlot`] of a protocol layer with
+//! [`CallbackProtocolLayer::take_ams_slot`](super::callback::CallbackProtocolLayer::take_ams_slot). From then on,
+//! messages that initiate an AMS (see [`MessageType::initiates_ams`]) are only transmitted with
+//! [`CallbackProtocolLayer::transmit_initiating`](super::callback::CallbackProtocolLayer::transmit_initiating), which
+//! consumes an [`AmsToken`] and hands out the [`ActiveAms`]. Responses and the remaining messages of an AMS are
+//! transmitted as usual.
+//!
+//! Tokens and active sequences borrow the slot mutably, so that starting a second AMS while another one is ongoing
+//! does not compile:
+//!
+//! ```compile_fail
+//! use usbpd::protocol_layer::callback::{CallbackProtocolLayer, Config};
+//! use usbpd::protocol_layer::message::header::{Header, SpecificationRevision};
+//! use usbpd::{DataRole, PowerRole};
+//!
+//! let header = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+//! let mut protocol_layer = CallbackProtocolLayer::new(Config::default(), header);
+//! let mut slot = protocol_layer.take_ams_slot().unwrap();
+//!
+//! let first = slot.begin();
+//! let second = slot.begin();
+//! drop(first);
+//! ```
+//!
+//! The AMS ends when the [`ActiveAms`] is dropped, or explicitly with [`ActiveAms::end`]:
+//!
+//! ```
+//! use usbpd::protocol_layer::callback::{CallbackProtocolLayer, Config};
+//! use usbpd::protocol_layer::message::Message;
+//! use usbpd::protocol_layer::message::header::{ControlMessageType, Header, SpecificationRevision};
+//! use usbpd::{DataRole, PowerRole};
+//!
+//! let header = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+//! let mut protocol_layer = CallbackProtocolLayer::new(Config::default(), header);
+//! let mut slot = protocol_layer.take_ams_slot().unwrap();
+//!
+//! let message = Message::new(protocol_layer.new_control_header(ControlMessageType::GetSourceCap));
+//! assert!(protocol_layer.transmit(&message).is_err());
+//!
+//! let (_actions, ams) = protocol_layer.transmit_initiating(slot.begin(), &message).unwrap();
+//! // ... handle the actions, and receive Source_Capabilities.
+//! ams.end();
+//! ```
+use core::marker::PhantomData;
+
+use super::message::header::MessageType;
+
+/// The right to initiate atomic message sequences on one protocol layer.
+///
+/// There is at most one slot per protocol layer, such that only one AMS can be initiated at a time.
+#[derive(Debug)]
+pub struct AmsSlot {
+    _private: (),
+}
+
+impl AmsSlot {
+    /// Create the slot, only to be handed out by the protocol layer.
+    pub(crate) fn new() -> Self {
+        Self { _private: () }
+    }
+
+    /// Prepare a new AMS.
+    ///
+    /// The slot stays borrowed until the AMS ends.
+    pub fn begin(&mut self) -> AmsToken<'_> {
+        AmsToken { _slot: PhantomData }
+    }
+}
+
+/// The permission to initiate one AMS.
+#[must_use]
+#[derive(Debug)]
+pub struct AmsToken<'s> {
+    _slot: PhantomData<&'s mut AmsSlot>,
+}
+
+impl<'s> AmsToken<'s> {
+    /// Turn the token into an active AMS, after its initiating message was handed to the PHY.
+    pub(crate) fn activate(self, initiator: MessageType) -> ActiveAms<'s> {
+        ActiveAms {
+            initiator,
+            _slot: PhantomData,
+        }
+    }
+}
+
+/// An ongoing AMS, that the local port initiated.
+#[must_use]
+#[derive(Debug)]
+pub struct ActiveAms<'s> {
+    initiator: MessageType,
+    _slot: PhantomData<&'s mut AmsSlot>,
+}
+
+impl ActiveAms<'_> {
+    /// The type of the message that initiated the AMS.
+    pub fn initiator(&self) -> MessageType {
+        self.initiator
+    }
+
+    /// End the AMS, e.g. after receiving the final response, or on failure.
+    pub fn end(self) {}
+}
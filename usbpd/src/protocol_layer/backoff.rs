@@ -0,0 +1,108 @@
+//! Backoff strategies for transmissions that the driver discarded.
+//!
+//! A driver discards a transmission, when the line is busy, e.g. because the port partner transmits at the same time.
+//! Retrying immediately can livelock against a port partner that does the same. A [`Backoff`] spreads the retries
+//! over a growing, but bounded window of time.
+use core::fmt::Debug;
+
+/// A strategy for delaying the retry of a discarded transmission.
+///
+/// The protocol layer holds a `&'static dyn Backoff`, such that it can be part of a `const` configuration.
+pub trait Backoff: Debug + Sync {
+    /// The delay in µs before the next attempt, after `discards` consecutive discarded transmissions.
+    ///
+    /// `entropy` is taken from the configured [`Rng`](crate::rng::Rng). Without one, it is taken from
+    /// [`Timer::now_micros`](crate::timers::Timer::now_micros), or zero, if the timer provides no timestamps.
+    /// `discards` is at least one.
+    fn delay_micros(&self, discards: u32, entropy: u64) -> u64;
+}
+
+/// A randomized, truncated binary exponential backoff.
+///
+/// After `n` discards, the delay is a random number of slots in `0..2^n`, with `n` capped at the maximum exponent.
+///
+/// ```
+/// use usbpd::protocol_layer::backoff::ExponentialBackoff;
+/// use usbpd::sink::config::SinkConfig;
+///
+/// const CONFIG: SinkConfig = SinkConfig::new().with_backoff(Some(&ExponentialBackoff::DEFAULT));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExponentialBackoff {
+    slot_micros: u64,
+    max_exponent: u8,
+}
+
+impl ExponentialBackoff {
+    /// Slots of 100 µs, with delays of up to 3.1 ms - short compared to most response timers.
+    pub const DEFAULT: Self = Self::new(100, 5);
+
+    /// Create a new backoff with the duration of a slot in µs, and the maximum exponent.
+    ///
+    /// Panics, if the exponent is larger than 16. When used in a `const` context, this check happens at compile
+    /// time. With the `panic-free` feature, the exponent is clamped to 16 instead.
+    pub const fn new(slot_micros: u64, max_exponent: u8) -> Self {
+        #[cfg(not(feature = "panic-free"))]
+        core::assert!(max_exponent <= 16, "Backoff exponent must not exceed 16");
+
+        #[cfg(feature = "panic-free")]
+        let max_exponent = if max_exponent > 16 { 16 } else { max_exponent };
+
+        Self {
+            slot_micros,
+            max_exponent,
+        }
+    }
+
+    /// The longest possible delay in µs.
+    pub const fn max_delay_micros(&self) -> u64 {
+        ((1 << self.max_exponent) - 1) * self.slot_micros
+    }
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn delay_micros(&self, discards: u32, entropy: u64) -> u64 {
+        let exponent = discards.min(u32::from(self.max_exponent));
+        let slots = mix(entropy) % (1 << exponent);
+
+        slots * self.slot_micros
+    }
+}
+
+/// Spread the bits of a timestamp, such that its low bits differ between consecutive calls (SplitMix64 finalizer).
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backoff, ExponentialBackoff};
+
+    #[test]
+    fn test_exponential_backoff() {
+        let backoff = ExponentialBackoff::new(10, 3);
+        assert_eq!(backoff.max_delay_micros(), 70);
+
+        for entropy in 0..100 {
+            assert!(backoff.delay_micros(1, entropy) <= 10);
+            assert!(backoff.delay_micros(2, entropy) <= 30);
+
+            // The window stops growing at the maximum exponent.
+            let delay = backoff.delay_micros(10, entropy);
+            assert!(delay <= backoff.max_delay_micros());
+            assert_eq!(delay % 10, 0);
+        }
+
+        // The delay is randomized.
+        assert!((0..100).any(|entropy| backoff.delay_micros(3, entropy) != backoff.delay_micros(3, 0)));
+    }
+}
@@ -0,0 +1,619 @@
+//! Interrupt-driven (callback) operation mode of the protocol layer.
+//!
+//! Instead of awaiting driver futures, the application calls into the protocol layer from its interrupt handlers
+//! (or a bare-metal superloop, or RTIC tasks):
+//! - [`CallbackProtocolLayer::on_rx_frame`] when the PHY received a frame,
+//! - [`CallbackProtocolLayer::on_tx_complete`] when the PHY finished transmitting a frame,
+//! - [`CallbackProtocolLayer::on_tx_discarded`] when the PHY discarded a transmission,
+//! - [`CallbackProtocolLayer::on_timer_expired`] when a previously armed timer expired.
+//!
+//! Every callback returns a list of [`Action`]s that the application must execute in order, for example frames to
+//! transmit, or timers to arm.
+//!
+//! Chunked extended messages that require assembly are not supported in this mode.
+//!
+//! Policy engines can opt into type-state handling of the AMS that they initiate, see [`super::ams`].
+use heapless::Vec;
+
+use super::ams::{ActiveAms, AmsSlot, AmsToken};
+use super::message::header::{ControlMessageType, Header, MessageType};
+use super::message::{Message, ParseError};
+use super::sans_io::ProtocolCore;
+use super::stats::Stats;
+use super::{MAX_MESSAGE_SIZE, ProtocolError, RxError, TxError};
+use crate::timers::TimerType;
+
+/// A raw frame, as exchanged with the PHY.
+pub type Frame = Vec<u8, MAX_MESSAGE_SIZE>;
+
+/// The frame of the first `size` bytes of a serialized message.
+fn frame(buffer: [u8; MAX_MESSAGE_SIZE], size: usize) -> Frame {
+    let mut frame = Frame::from_array(buffer);
+    frame.truncate(size);
+    frame
+}
+
+/// The maximum number of actions that a single callback can produce.
+pub const MAX_ACTIONS: usize = 4;
+
+/// A list of actions, returned by every callback.
+pub type Actions = Vec<Action, MAX_ACTIONS>;
+
+/// Identifiers of timers that the protocol layer arms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimerId {
+    /// Waiting for the GoodCRC response to a transmitted message (tReceive).
+    CrcReceive,
+}
+
+impl TimerId {
+    /// The type of timer, as defined by the specification.
+    pub fn timer_type(&self) -> TimerType {
+        match self {
+            TimerId::CrcReceive => TimerType::CRCReceive,
+        }
+    }
+}
+
+/// Actions that the application shall perform on behalf of the protocol layer.
+#[derive(Debug)]
+pub enum Action {
+    /// Transmit a frame with the PHY, and call [`CallbackProtocolLayer::on_tx_complete`] when done.
+    Transmit(Frame),
+    /// Transmit hard reset signaling.
+    TransmitHardReset,
+    /// Arm a timer that expires after the given number of microseconds.
+    ///
+    /// On expiry, call [`CallbackProtocolLayer::on_timer_expired`].
+    StartTimer(TimerId, u64),
+    /// Disarm a timer.
+    StopTimer(TimerId),
+    /// A new message was received (retransmissions are filtered).
+    Received(Message),
+    /// The last transmitted message was acknowledged by the port partner.
+    TransmitSucceeded,
+    /// The last transmitted message was discarded, because the port partner sent a message in the meantime.
+    TransmitDiscarded,
+    /// Transmitting the last message failed.
+    TransmitFailed(ProtocolError),
+    /// A received frame could not be processed.
+    ReceiveFailed(RxError),
+}
+
+/// Configuration of the callback protocol layer, mirroring the [`usbpd_traits::Driver`] capabilities.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// The hardware sends GoodCRC messages on its own.
+    pub has_auto_good_crc: bool,
+    /// The hardware retries transmission on its own, and only reports completion after receiving GoodCRC.
+    pub has_auto_retry: bool,
+    /// Emit the GoodCRC transmission as the first action, ahead of actions that concern pending transmissions.
+    pub good_crc_priority: bool,
+}
+
+/// Kinds of frames that were handed to the PHY, and are not yet reported as complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InFlight {
+    GoodCrc,
+    Message,
+}
+
+/// The state of message transmission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxState {
+    /// No message transmission in progress.
+    Idle,
+    /// The message frame was handed to the PHY.
+    Transmitting,
+    /// The message frame was transmitted, waiting for GoodCRC.
+    WaitForGoodCrc,
+}
+
+/// A sans-io protocol layer, driven by callbacks.
+#[derive(Debug)]
+pub struct CallbackProtocolLayer {
+    config: Config,
+    core: ProtocolCore,
+    tx_state: TxState,
+    tx_frame: Frame,
+    in_flight: heapless::Deque<InFlight, 2>,
+    /// Whether the [`AmsSlot`] was handed out, so that AMS-initiating messages require an [`AmsToken`].
+    ams_slot_taken: bool,
+}
+
+impl CallbackProtocolLayer {
+    /// Create a new callback protocol layer from a configuration and default header.
+    pub fn new(config: Config, default_header: Header) -> Self {
+        Self {
+            config,
+            core: ProtocolCore::new(default_header),
+            tx_state: TxState::Idle,
+            tx_frame: Vec::new(),
+            in_flight: heapless::Deque::new(),
+            ams_slot_taken: false,
+        }
+    }
+
+    /// Reset the protocol layer, e.g. after a soft reset.
+    pub fn reset(&mut self) {
+        let ams_slot_taken = self.ams_slot_taken;
+        *self = Self::new(self.config, *self.core.header());
+        self.ams_slot_taken = ams_slot_taken;
+    }
+
+    /// Take the single [`AmsSlot`] of this protocol layer, or `None` if it was taken before.
+    ///
+    /// Afterwards, messages that initiate an AMS are only transmitted with [`Self::transmit_initiating`].
+    pub fn take_ams_slot(&mut self) -> Option<AmsSlot> {
+        if self.ams_slot_taken {
+            None
+        } else {
+            self.ams_slot_taken = true;
+            Some(AmsSlot::new())
+        }
+    }
+
+    /// The header template that is used for outgoing messages.
+    pub fn header(&self) -> &Header {
+        self.core.header()
+    }
+
+    /// The collected statistics.
+    pub fn stats(&self) -> &Stats {
+        self.core.stats()
+    }
+
+    /// Whether a message transmission is in progress.
+    pub fn is_transmitting(&self) -> bool {
+        self.tx_state != TxState::Idle
+    }
+
+    /// Build a message header, using the current transmit message ID.
+    pub fn new_control_header(&self, message_type: ControlMessageType) -> Header {
+        Header::new_control(*self.core.header(), self.core.tx_message(), message_type)
+    }
+
+    /// Transmit a message.
+    ///
+    /// Returns an error, if the message is invalid, or another message transmission is still in progress. After the
+    /// [`AmsSlot`] was taken, messages that initiate an AMS fail with [`TxError::AmsTokenRequired`].
+    pub fn transmit(&mut self, message: &Message) -> Result<Actions, ProtocolError> {
+        if self.ams_slot_taken && message.header.message_type().initiates_ams() {
+            return Err(TxError::AmsTokenRequired.into());
+        }
+
+        self.start_transmission(message)
+    }
+
+    /// Transmit the message that initiates an AMS, consuming the token.
+    ///
+    /// Any message type may initiate the AMS, e.g. Source_Capabilities, which is not always an initiating message.
+    /// On error, the AMS did not start.
+    pub fn transmit_initiating<'s>(
+        &mut self,
+        token: AmsToken<'s>,
+        message: &Message,
+    ) -> Result<(Actions, ActiveAms<'s>), ProtocolError> {
+        let actions = self.start_transmission(message)?;
+        Ok((actions, token.activate(message.header.message_type())))
+    }
+
+    /// Start the transmission of a message.
+    fn start_transmission(&mut self, message: &Message) -> Result<Actions, ProtocolError> {
+        assert_or_return!(
+            message.header.message_type() != MessageType::Control(ControlMessageType::GoodCRC),
+            ProtocolError::UnexpectedMessage
+        );
+
+        if self.is_transmitting() {
+            return Err(ProtocolError::UnexpectedMessage);
+        }
+
+        ProtocolCore::validate_outgoing_message(message)?;
+
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let size = message.to_bytes(&mut buffer);
+
+        self.tx_frame = frame(buffer, size);
+        self.core.start_transmission();
+        self.tx_state = TxState::Transmitting;
+
+        let mut actions = Actions::new();
+        self.push_transmit(&mut actions, InFlight::Message);
+        Ok(actions)
+    }
+
+    /// Transmit hard reset signaling.
+    pub fn transmit_hard_reset(&mut self) -> Actions {
+        let mut actions = self.on_hard_reset();
+        self.push(&mut actions, Action::TransmitHardReset);
+        actions
+    }
+
+    /// Call when the PHY reports hard reset signaling.
+    pub fn on_hard_reset(&mut self) -> Actions {
+        let mut actions = Actions::new();
+        if self.tx_state == TxState::WaitForGoodCrc {
+            self.push(&mut actions, Action::StopTimer(TimerId::CrcReceive));
+        }
+
+        self.reset();
+        actions
+    }
+
+    /// Call when the PHY completed the transmission of a frame.
+    pub fn on_tx_complete(&mut self) -> Actions {
+        let mut actions = Actions::new();
+
+        match self.in_flight.pop_front() {
+            Some(InFlight::Message) if self.tx_state == TxState::Transmitting => {
+                if self.config.has_auto_retry {
+                    // The hardware already verified GoodCRC reception.
+                    self.core.acknowledge();
+                    self.succeed(&mut actions);
+                } else {
+                    self.tx_state = TxState::WaitForGoodCrc;
+                    let timer_id = TimerId::CrcReceive;
+                    self.push(
+                        &mut actions,
+                        Action::StartTimer(timer_id, timer_id.timer_type().duration_micros()),
+                    );
+                }
+            }
+            _ => (),
+        }
+
+        actions
+    }
+
+    /// Call when the PHY discarded a transmission, e.g. due to a concurrent reception.
+    pub fn on_tx_discarded(&mut self) -> Actions {
+        let mut actions = Actions::new();
+
+        match self.in_flight.pop_front() {
+            Some(InFlight::Message) if self.tx_state == TxState::Transmitting => {
+                if self.config.has_auto_retry {
+                    // All hardware retries are exhausted.
+                    self.tx_state = TxState::Idle;
+                    let error = self.core.retries_exceeded();
+                    self.push(&mut actions, Action::TransmitFailed(error));
+                } else {
+                    self.push_transmit(&mut actions, InFlight::Message);
+                }
+            }
+            Some(InFlight::GoodCrc) => {
+                if let Some(good_crc) = self.good_crc_frame() {
+                    self.push_frame(&mut actions, good_crc, InFlight::GoodCrc);
+                }
+            }
+            _ => (),
+        }
+
+        actions
+    }
+
+    /// Call when a timer that was armed by a [`Action::StartTimer`] expired.
+    pub fn on_timer_expired(&mut self, timer_id: TimerId) -> Actions {
+        let mut actions = Actions::new();
+
+        match timer_id {
+            TimerId::CrcReceive => {
+                if self.tx_state != TxState::WaitForGoodCrc {
+                    return actions;
+                }
+
+                match self.core.retry() {
+                    Ok(()) => {
+                        self.tx_state = TxState::Transmitting;
+                        self.push_transmit(&mut actions, InFlight::Message);
+                    }
+                    Err(error) => {
+                        self.tx_state = TxState::Idle;
+                        self.push(&mut actions, Action::TransmitFailed(error));
+                    }
+                }
+            }
+        }
+
+        actions
+    }
+
+    /// Call when the PHY received a frame.
+    pub fn on_rx_frame(&mut self, frame: &[u8]) -> Actions {
+        let mut actions = Actions::new();
+
+        let message = match Message::from_bytes(frame) {
+            Ok(message) => message,
+            Err(ParseError::ChunkedExtendedMessage { .. }) => {
+                self.push(&mut actions, Action::ReceiveFailed(RxError::UnsupportedMessage));
+                return actions;
+            }
+            Err(error) => {
+                self.push(&mut actions, Action::ReceiveFailed(error.into()));
+                return actions;
+            }
+        };
+
+        // Update specification revision, based on the received frame.
+        if let Err(error) = self.core.update_spec_revision(&message.header) {
+            self.push(&mut actions, Action::ReceiveFailed(error.into()));
+            return actions;
+        }
+
+        if matches!(
+            message.header.message_type(),
+            MessageType::Control(ControlMessageType::GoodCRC)
+        ) {
+            if self.tx_state == TxState::WaitForGoodCrc {
+                self.push(&mut actions, Action::StopTimer(TimerId::CrcReceive));
+
+                match self.core.handle_good_crc(&message) {
+                    Ok(()) => self.succeed(&mut actions),
+                    Err(error) => {
+                        self.tx_state = TxState::Idle;
+                        self.push(&mut actions, Action::TransmitFailed(error.into()));
+                    }
+                }
+            }
+
+            return actions;
+        }
+
+        let is_retransmission = self.core.update_rx_message_counter(&message);
+
+        if self.config.good_crc_priority {
+            self.push_good_crc(&mut actions);
+        }
+
+        // A message from the port partner interrupts an ongoing transmission.
+        if self.tx_state == TxState::WaitForGoodCrc {
+            self.tx_state = TxState::Idle;
+            self.push(&mut actions, Action::StopTimer(TimerId::CrcReceive));
+            self.push(&mut actions, Action::TransmitDiscarded);
+        }
+
+        if !self.config.good_crc_priority {
+            self.push_good_crc(&mut actions);
+        }
+
+        if !is_retransmission {
+            self.push(&mut actions, Action::Received(message));
+        }
+
+        actions
+    }
+
+    /// Report a successful transmission.
+    fn succeed(&mut self, actions: &mut Actions) {
+        self.tx_state = TxState::Idle;
+        self.push(actions, Action::TransmitSucceeded);
+    }
+
+    /// Transmit GoodCRC for the last received message, unless the hardware does so.
+    fn push_good_crc(&mut self, actions: &mut Actions) {
+        if self.config.has_auto_good_crc {
+            return;
+        }
+
+        if let Some(good_crc) = self.good_crc_frame() {
+            self.core.record_good_crc(None, None);
+            self.push_frame(actions, good_crc, InFlight::GoodCrc);
+        }
+    }
+
+    /// Serialize a GoodCRC message for the last received message.
+    fn good_crc_frame(&self) -> Option<Frame> {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let size = self.core.good_crc_message()?.to_bytes(&mut buffer);
+        Some(frame(buffer, size))
+    }
+
+    fn push_transmit(&mut self, actions: &mut Actions, kind: InFlight) {
+        self.push_frame(actions, self.tx_frame.clone(), kind);
+    }
+
+    fn push_frame(&mut self, actions: &mut Actions, tx_frame: Frame, kind: InFlight) {
+        if self.in_flight.push_back(kind).is_err() {
+            // The PHY did not report completion of earlier frames, forget about the oldest.
+            _ = self.in_flight.pop_front();
+            _ = self.in_flight.push_back(kind);
+        }
+
+        self.push(actions, Action::Transmit(tx_frame));
+    }
+
+    fn push(&self, actions: &mut Actions, action: Action) {
+        if actions.push(action).is_err() {
+            error!("Callback protocol layer action list overflow");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, CallbackProtocolLayer, Config, TimerId};
+    use crate::counters::{Counter, CounterType, MessageId};
+    use crate::dummy::DUMMY_CAPABILITIES;
+    use crate::protocol_layer::message::header::{ControlMessageType, Header, MessageType, SpecificationRevision};
+    use crate::protocol_layer::message::{Message, Payload};
+    use crate::protocol_layer::{ProtocolError, TxError};
+    use crate::{DataRole, PowerRole};
+
+    fn get_protocol_layer() -> CallbackProtocolLayer {
+        CallbackProtocolLayer::new(
+            Config::default(),
+            Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X),
+        )
+    }
+
+    fn control_frame(message_type: ControlMessageType, message_id: u8) -> heapless::Vec<u8, 2> {
+        let header = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R3_X);
+        let mut buf = [0u8; 2];
+        Message::new(Header::new_control(header, MessageId::new(message_id), message_type)).to_bytes(&mut buf);
+
+        heapless::Vec::from_slice(&buf).unwrap()
+    }
+
+    fn assert_good_crc(action: &Action, message_id: u8) {
+        let Action::Transmit(frame) = action else {
+            panic!("Expected GoodCRC transmission, got {:?}", action);
+        };
+
+        let message = Message::from_bytes(frame).unwrap();
+        assert_eq!(
+            message.header.message_type(),
+            MessageType::Control(ControlMessageType::GoodCRC)
+        );
+        assert_eq!(message.header.message_id(), message_id);
+    }
+
+    #[test]
+    fn test_receive() {
+        let mut protocol_layer = get_protocol_layer();
+
+        let actions = protocol_layer.on_rx_frame(&DUMMY_CAPABILITIES);
+        assert_eq!(actions.len(), 2);
+        assert_good_crc(&actions[0], 0);
+        assert!(matches!(
+            &actions[1],
+            Action::Received(Message {
+                payload: Some(Payload::Data(_)),
+                ..
+            })
+        ));
+        assert!(protocol_layer.on_tx_complete().is_empty());
+
+        // A retransmission is acknowledged, but not reported again.
+        let actions = protocol_layer.on_rx_frame(&DUMMY_CAPABILITIES);
+        assert_eq!(actions.len(), 1);
+        assert_good_crc(&actions[0], 0);
+    }
+
+    #[test]
+    fn test_transmit() {
+        let mut protocol_layer = get_protocol_layer();
+        let message = Message::new(protocol_layer.new_control_header(ControlMessageType::GetSourceCap));
+
+        let actions = protocol_layer.transmit(&message).unwrap();
+        assert!(matches!(&actions[..], [Action::Transmit(_)]));
+        assert!(protocol_layer.transmit(&message).is_err());
+
+        let actions = protocol_layer.on_tx_complete();
+        assert!(matches!(&actions[..], [Action::StartTimer(TimerId::CrcReceive, 1_100)]));
+
+        let actions = protocol_layer.on_rx_frame(&control_frame(ControlMessageType::GoodCRC, 0));
+        assert!(matches!(
+            &actions[..],
+            [Action::StopTimer(TimerId::CrcReceive), Action::TransmitSucceeded]
+        ));
+        assert!(!protocol_layer.is_transmitting());
+
+        // The next message uses the incremented message ID.
+        let header = protocol_layer.new_control_header(ControlMessageType::GetSourceCap);
+        assert_eq!(header.message_id(), 1);
+    }
+
+    #[test]
+    fn test_transmit_retries() {
+        let mut protocol_layer = get_protocol_layer();
+        let message = Message::new(protocol_layer.new_control_header(ControlMessageType::GetSourceCap));
+
+        protocol_layer.transmit(&message).unwrap();
+        let max_retries = Counter::new(CounterType::Retry).max_value();
+
+        for _ in 0..max_retries {
+            protocol_layer.on_tx_complete();
+            let actions = protocol_layer.on_timer_expired(TimerId::CrcReceive);
+            assert!(matches!(&actions[..], [Action::Transmit(_)]));
+        }
+
+        protocol_layer.on_tx_complete();
+        let actions = protocol_layer.on_timer_expired(TimerId::CrcReceive);
+        assert!(matches!(
+            &actions[..],
+            [Action::TransmitFailed(ProtocolError::TransmitRetriesExceeded(_))]
+        ));
+        assert!(!protocol_layer.is_transmitting());
+    }
+
+    #[test]
+    fn test_transmit_discarded_by_reception() {
+        let mut protocol_layer = get_protocol_layer();
+        let message = Message::new(protocol_layer.new_control_header(ControlMessageType::GetSourceCap));
+
+        protocol_layer.transmit(&message).unwrap();
+        protocol_layer.on_tx_complete();
+
+        let actions = protocol_layer.on_rx_frame(&control_frame(ControlMessageType::Ping, 3));
+        assert!(matches!(
+            &actions[..],
+            [
+                Action::StopTimer(TimerId::CrcReceive),
+                Action::TransmitDiscarded,
+                Action::Transmit(_),
+                Action::Received(_)
+            ]
+        ));
+        assert_good_crc(&actions[2], 3);
+    }
+
+    #[test]
+    fn test_ams_token() {
+        let mut protocol_layer = get_protocol_layer();
+        let get_source_cap = Message::new(protocol_layer.new_control_header(ControlMessageType::GetSourceCap));
+
+        let mut slot = protocol_layer.take_ams_slot().unwrap();
+        assert!(protocol_layer.take_ams_slot().is_none());
+
+        // Initiating messages require a token.
+        assert!(matches!(
+            protocol_layer.transmit(&get_source_cap),
+            Err(ProtocolError::TxError(TxError::AmsTokenRequired))
+        ));
+
+        let (actions, ams) = protocol_layer
+            .transmit_initiating(slot.begin(), &get_source_cap)
+            .unwrap();
+        assert!(matches!(&actions[..], [Action::Transmit(_)]));
+        assert_eq!(ams.initiator(), MessageType::Control(ControlMessageType::GetSourceCap));
+
+        protocol_layer.on_tx_complete();
+        protocol_layer.on_rx_frame(&control_frame(ControlMessageType::GoodCRC, 0));
+        ams.end();
+
+        // Responses do not require a token, and the slot survives a reset.
+        protocol_layer.reset();
+        let accept = Message::new(protocol_layer.new_control_header(ControlMessageType::Accept));
+        assert!(protocol_layer.transmit(&accept).is_ok());
+        assert!(protocol_layer.take_ams_slot().is_none());
+    }
+
+    #[test]
+    fn test_good_crc_priority() {
+        let mut protocol_layer = CallbackProtocolLayer::new(
+            Config {
+                good_crc_priority: true,
+                ..Default::default()
+            },
+            Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X),
+        );
+        let message = Message::new(protocol_layer.new_control_header(ControlMessageType::GetSourceCap));
+
+        protocol_layer.transmit(&message).unwrap();
+        protocol_layer.on_tx_complete();
+
+        let actions = protocol_layer.on_rx_frame(&control_frame(ControlMessageType::Ping, 3));
+        assert_good_crc(&actions[0], 3);
+        assert!(matches!(
+            &actions[1..],
+            [
+                Action::StopTimer(TimerId::CrcReceive),
+                Action::TransmitDiscarded,
+                Action::Received(_)
+            ]
+        ));
+        assert_eq!(protocol_layer.stats().good_crc_transmitted, 1);
+    }
+}
@@ -0,0 +1,180 @@
+//! PHY timing conformance checks, for validating a driver and MCU combination.
+//!
+//! [`measure_timing`] transmits a frame repeatedly through a [`Driver`], and measures the GoodCRC turnaround of
+//! the port partner, and the gap between the received GoodCRC and the next transmission. The GoodCRC latency of
+//! the local protocol layer is taken from its [`Stats`], see [`TimingReport::with_stats`].
+//!
+//! Measurements need timestamps from [`Timer::now_micros`]. They include the software overhead of the driver,
+//! so they are an upper bound for turnaround times, and a lower bound for gaps.
+use usbpd_traits::Driver;
+
+use super::ProtocolError;
+use super::raw::transmit_frame;
+use super::stats::{Stats, T_INTER_FRAME_GAP_MICROS, T_RECEIVE_MIN_MICROS, T_TRANSMIT_MICROS};
+use crate::timers::Timer;
+
+/// The range of measured durations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    /// The number of measured samples.
+    pub samples: u32,
+    /// The shortest measured duration in µs.
+    pub min_micros: Option<u64>,
+    /// The longest measured duration in µs.
+    pub max_micros: Option<u64>,
+}
+
+impl Measurement {
+    /// Record a measured duration.
+    pub fn record(&mut self, micros: u64) {
+        self.samples = self.samples.saturating_add(1);
+        self.min_micros = Some(self.min_micros.map_or(micros, |m| m.min(micros)));
+        self.max_micros = Some(self.max_micros.map_or(micros, |m| m.max(micros)));
+    }
+}
+
+/// A report of measured PHY timings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimingReport {
+    /// The time from handing a frame to the driver, until the GoodCRC of the port partner was received.
+    pub good_crc_turnaround: Measurement,
+    /// The time from receiving a GoodCRC, until the next frame was handed to the driver.
+    pub inter_frame_gap: Measurement,
+    /// The maximum GoodCRC latency of the local protocol layer in µs, from its statistics.
+    pub good_crc_latency_max_micros: Option<u64>,
+}
+
+impl TimingReport {
+    /// Add the GoodCRC latency from the statistics of a protocol layer.
+    pub fn with_stats(self, stats: &Stats) -> Self {
+        Self {
+            good_crc_latency_max_micros: stats.good_crc_latency_max_micros,
+            ..self
+        }
+    }
+
+    /// Whether all GoodCRC responses arrived before tReceive, the earliest time that the transmitter retries.
+    pub fn good_crc_turnaround_ok(&self) -> bool {
+        self.good_crc_turnaround
+            .max_micros
+            .is_none_or(|max| max < T_RECEIVE_MIN_MICROS)
+    }
+
+    /// Whether all measured gaps are at least tInterFrameGap.
+    pub fn inter_frame_gap_ok(&self) -> bool {
+        self.inter_frame_gap
+            .min_micros
+            .is_none_or(|min| min >= T_INTER_FRAME_GAP_MICROS)
+    }
+
+    /// Whether the local GoodCRC latency is within tTransmit.
+    pub fn good_crc_latency_ok(&self) -> bool {
+        self.good_crc_latency_max_micros
+            .is_none_or(|max| max <= T_TRANSMIT_MICROS)
+    }
+
+    /// Whether all measured timings are within their budgets. Timings that were not measured do not count.
+    pub fn is_conformant(&self) -> bool {
+        self.good_crc_turnaround_ok() && self.inter_frame_gap_ok() && self.good_crc_latency_ok()
+    }
+}
+
+/// Transmit a `frame` for the given number of `iterations`, waiting for GoodCRC each time, and measure the timing.
+///
+/// The frame is sent as is, see [`transmit_frame`]. A port partner, or a test fixture, must acknowledge each one.
+pub async fn measure_timing<DRIVER: Driver, TIMER: Timer>(
+    driver: &mut DRIVER,
+    frame: &[u8],
+    iterations: u32,
+) -> Result<TimingReport, ProtocolError> {
+    let mut report = TimingReport::default();
+    let mut last_good_crc = None;
+
+    for _ in 0..iterations {
+        let start = TIMER::now_micros();
+        if let (Some(last_good_crc), Some(start)) = (last_good_crc, start) {
+            report.inter_frame_gap.record(start.saturating_sub(last_good_crc));
+        }
+
+        transmit_frame::<DRIVER, TIMER>(driver, frame, true).await?;
+
+        last_good_crc = TIMER::now_micros();
+        if let (Some(start), Some(end)) = (start, last_good_crc) {
+            report.good_crc_turnaround.record(end.saturating_sub(start));
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::pending;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::{Measurement, TimingReport, measure_timing};
+    use crate::counters::MessageId;
+    use crate::dummy::{DummyDriver, MAX_DATA_MESSAGE_SIZE};
+    use crate::protocol_layer::message::Message;
+    use crate::protocol_layer::message::header::{ControlMessageType, Header, SpecificationRevision};
+    use crate::protocol_layer::stats::Stats;
+    use crate::timers::Timer;
+    use crate::{DataRole, PowerRole};
+
+    static CLOCK_MICROS: AtomicU64 = AtomicU64::new(0);
+
+    /// A timer, whose clock advances by 100 µs, whenever it is read.
+    struct SteppingTimer {}
+
+    impl Timer for SteppingTimer {
+        async fn after_millis(_milliseconds: u64) {
+            pending().await
+        }
+
+        fn now_micros() -> Option<u64> {
+            Some(CLOCK_MICROS.fetch_add(100, Ordering::Relaxed))
+        }
+    }
+
+    fn control_frame(template: Header, message_type: ControlMessageType) -> [u8; 2] {
+        let mut buffer = [0u8; 2];
+        Message::new(Header::new_control(template, MessageId::new(0), message_type)).to_bytes(&mut buffer);
+        buffer
+    }
+
+    #[tokio::test]
+    async fn test_measure_timing() {
+        let sink = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+        let source = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R3_X);
+        let mut driver = DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new();
+        for _ in 0..3 {
+            driver.inject_received_data(&control_frame(source, ControlMessageType::GoodCRC));
+        }
+
+        let frame = control_frame(sink, ControlMessageType::Ping);
+        let report = measure_timing::<_, SteppingTimer>(&mut driver, &frame, 3)
+            .await
+            .unwrap();
+
+        let expected = Measurement {
+            samples: 3,
+            min_micros: Some(100),
+            max_micros: Some(100),
+        };
+        assert_eq!(report.good_crc_turnaround, expected);
+        assert_eq!(report.inter_frame_gap.samples, 2);
+        assert!(report.is_conformant());
+
+        let stats = Stats {
+            good_crc_latency_max_micros: Some(250),
+            ..Default::default()
+        };
+        let report = report.with_stats(&stats);
+        assert!(!report.good_crc_latency_ok());
+        assert!(!report.is_conformant());
+
+        assert!(TimingReport::default().is_conformant());
+    }
+}
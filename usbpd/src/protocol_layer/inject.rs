@@ -0,0 +1,54 @@
+//! Injection of protocol errors at the boundary between protocol layer and policy engine, for testing the error
+//! handling of policy engines.
+//!
+//! Every injection fires once, and is consumed afterwards.
+use std::vec::Vec;
+
+use super::ProtocolError;
+
+/// Where to inject a protocol error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InjectionPoint {
+    /// Fail the nth message reception (counting from zero), without receiving from the driver.
+    Receive(usize),
+    /// Fail the next policy engine step in the state with the given name.
+    State(&'static str),
+}
+
+/// Protocol errors to inject, and the state that is required to find the injection points.
+#[derive(Debug, Default)]
+pub(crate) struct ErrorInjector {
+    injections: Vec<(InjectionPoint, ProtocolError)>,
+    receives: usize,
+}
+
+impl ErrorInjector {
+    /// Inject an error at the given point.
+    pub(crate) fn inject(&mut self, point: InjectionPoint, error: ProtocolError) -> &mut Self {
+        self.injections.push((point, error));
+        self
+    }
+
+    /// Whether all injected errors fired.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.injections.is_empty()
+    }
+
+    /// Count a message reception, and return the error to fail it with, if any.
+    pub(crate) fn on_receive(&mut self) -> Option<ProtocolError> {
+        let receive = self.receives;
+        self.receives += 1;
+
+        self.take(InjectionPoint::Receive(receive))
+    }
+
+    /// Return the error to fail a policy engine step in the named state with, if any.
+    pub(crate) fn on_state(&mut self, state: &'static str) -> Option<ProtocolError> {
+        self.take(InjectionPoint::State(state))
+    }
+
+    fn take(&mut self, point: InjectionPoint) -> Option<ProtocolError> {
+        let index = self.injections.iter().position(|(p, _)| *p == point)?;
+        Some(self.injections.remove(index).1)
+    }
+}
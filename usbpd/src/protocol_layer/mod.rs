@@ -10,7 +10,18 @@
 //!
 //! At this point in time, the protocol layer does not support extended messages.
 
-pub mod message;
+pub mod ams;
+pub mod backoff;
+pub mod callback;
+pub mod conformance;
+#[cfg(test)]
+pub(crate) mod inject;
+pub use usbpd_messages as message;
+pub mod raw;
+mod sans_io;
+pub mod stats;
+#[cfg(feature = "tx-queue")]
+pub mod tx_queue;
 
 use core::future::Future;
 use core::marker::PhantomData;
@@ -19,20 +30,46 @@ use byteorder::{ByteOrder, LittleEndian};
 use embassy_futures::select::{Either, select};
 use heapless::Vec;
 use message::Message;
+use message::data::vendor_defined::{VdmHeader, VdmHeaderStructured};
 use message::data::{Data, request};
 use message::extended::extended_control::ExtendedControlMessageType;
-use message::header::{ControlMessageType, DataMessageType, ExtendedMessageType, Header, MessageType};
-use usbpd_traits::{Driver, DriverRxError, DriverTxError};
-
-use crate::PowerRole;
-use crate::counters::{Counter, CounterType, Error as CounterError};
+use message::header::{
+    ControlMessageType, DataMessageType, ExtendedMessageType, Header, MessageType, SpecificationRevision,
+};
+use usbpd_traits::{CcTermination, Driver, DriverFault, DriverRxError, DriverTxError, Frame, RolePreference};
+
+use crate::counters::{Counter, CounterType};
+use crate::protocol_layer::backoff::Backoff;
+use crate::protocol_layer::message::data::alert::AlertDataObject;
 use crate::protocol_layer::message::data::epr_mode::EprModeDataObject;
 use crate::protocol_layer::message::extended::Extended;
+use crate::protocol_layer::message::extended::chunked::{MAX_EXTENDED_MSG_CHUNK_LEN, MAX_EXTENDED_MSG_LEN};
 use crate::protocol_layer::message::{ParseError, Payload};
-use crate::timers::{Timer, TimerType};
+use crate::protocol_layer::sans_io::ProtocolCore;
+use crate::protocol_layer::stats::{GoodCrcConfig, Stats};
+use crate::rng::Rng;
+use crate::timers::{Timer, TimerOverrides, TimerType};
+use crate::trace::{TraceEvent, Tracer};
+use crate::vdm::InitiatorStep;
+use crate::{DataRole, PowerRole};
 
 /// Maximum message size including headers and payload.
-const MAX_MESSAGE_SIZE: usize = 272;
+///
+/// Fits an unchunked extended message with [`MAX_EXTENDED_MSG_LEN`] data bytes, as well as an assembled chunked one.
+pub(crate) const MAX_MESSAGE_SIZE: usize =
+    (MSG_HEADER_SIZE + EXT_HEADER_SIZE + MAX_EXTENDED_MSG_LEN).next_multiple_of(DATA_OBJECT_SIZE);
+
+/// Maximum size of a non-extended message, or of a chunk of an extended message, including headers.
+pub const MAX_DATA_MESSAGE_SIZE: usize = MSG_HEADER_SIZE + MAX_DATA_OBJECTS * DATA_OBJECT_SIZE;
+
+// A chunk with its headers must fit into a data message, see spec, [6.2.1.2.1]
+const _: () = core::assert!(MSG_HEADER_SIZE + EXT_HEADER_SIZE + MAX_EXTENDED_MSG_CHUNK_LEN <= MAX_DATA_MESSAGE_SIZE);
+// Any frame that a driver can receive must fit into the receive buffers.
+const _: () = core::assert!(usbpd_traits::MAX_FRAME_SIZE <= MAX_MESSAGE_SIZE);
+
+/// The number of consecutive discarded transmissions, after which a transmission fails with
+/// [`TxError::DiscardStorm`].
+pub const DISCARD_STORM_THRESHOLD: u32 = 100;
 
 /// Size of the message header in bytes.
 const MSG_HEADER_SIZE: usize = 2;
@@ -40,8 +77,14 @@ const MSG_HEADER_SIZE: usize = 2;
 /// Size of the extended message header in bytes.
 const EXT_HEADER_SIZE: usize = 2;
 
+/// Size of a data object in bytes.
+const DATA_OBJECT_SIZE: usize = 4;
+
+/// The maximum number of data objects in a message.
+const MAX_DATA_OBJECTS: usize = 7;
+
 /// Errors that can occur in the protocol layer.
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ProtocolError {
     /// An error occured during data reception.
@@ -58,8 +101,50 @@ pub enum ProtocolError {
     UnexpectedMessage,
 }
 
+impl From<ParseError> for ProtocolError {
+    fn from(parse_error: ParseError) -> Self {
+        RxError::from(parse_error).into()
+    }
+}
+
+/// The cause of a protocol error, which determines how to recover from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorOrigin {
+    /// A local fault of the driver or PHY, e.g. a receive overrun. The port partner is not at fault, so the
+    /// connection is kept, and the failed step is retried.
+    Driver,
+    /// The behavior of the port partner, e.g. a timeout, or an unexpected message. Recovered with a soft or hard
+    /// reset.
+    Partner,
+    /// Hard reset signaling, or detach, as reported by the driver.
+    Connection,
+    /// A message that this library does not transmit, e.g. because it violates the specification.
+    Local,
+}
+
+impl ProtocolError {
+    /// The cause of the error.
+    pub fn origin(&self) -> ErrorOrigin {
+        match self {
+            ProtocolError::RxError(error) => error.origin(),
+            ProtocolError::TxError(error) => error.origin(),
+            ProtocolError::TransmitRetriesExceeded(_) | ProtocolError::UnexpectedMessage => ErrorOrigin::Partner,
+        }
+    }
+
+    /// The driver fault that caused the error, if any.
+    pub fn driver_fault(&self) -> Option<DriverFault> {
+        match self {
+            ProtocolError::RxError(RxError::DriverFault(fault))
+            | ProtocolError::TxError(TxError::DriverFault(fault)) => Some(*fault),
+            _ => None,
+        }
+    }
+}
+
 /// Errors that can occur during reception of data.
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RxError {
     /// Port partner requested soft reset.
@@ -68,6 +153,9 @@ pub enum RxError {
     /// Driver reported a hard reset.
     #[error("hard reset")]
     HardReset,
+    /// Driver reported that the port partner detached.
+    #[error("detached")]
+    Detached,
     /// A timeout during message reception.
     #[error("receive timeout")]
     ReceiveTimeout,
@@ -80,73 +168,268 @@ pub enum RxError {
     /// The received acknowledgement does not match the last transmitted message's ID.
     #[error("wrong tx id `{0}` acknowledged")]
     AcknowledgeMismatch(u8),
+    /// Driver reported a local fault.
+    #[error("driver fault `{0:?}`")]
+    DriverFault(DriverFault),
+}
+
+impl RxError {
+    /// The cause of the error.
+    pub fn origin(&self) -> ErrorOrigin {
+        match self {
+            RxError::DriverFault(_) => ErrorOrigin::Driver,
+            RxError::HardReset | RxError::Detached => ErrorOrigin::Connection,
+            RxError::SoftReset
+            | RxError::ReceiveTimeout
+            | RxError::UnsupportedMessage
+            | RxError::ParseError(_)
+            | RxError::AcknowledgeMismatch(_) => ErrorOrigin::Partner,
+        }
+    }
 }
 
 /// Errors that can occur during transmission of data.
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TxError {
     /// Driver reported a hard reset.
     #[error("hard reset")]
     HardReset,
+    /// Driver reported that the port partner detached.
+    #[error("detached")]
+    Detached,
+    /// The driver discarded the transmission [`DISCARD_STORM_THRESHOLD`] times in a row.
+    ///
+    /// Points to a noisy line, or a port partner that is stuck transmitting.
+    #[error("transmission discarded `{0}` times in a row")]
+    DiscardStorm(u32),
     /// unchunked_extended_messages_supported must be false (library uses chunked mode).
     #[error("unchunked extended messages not supported")]
     UnchunkedExtendedMessagesNotSupported,
     /// AVS voltage LSB 2 bits must be zero per USB PD 3.2 Table 6.26.
     #[error("AVS voltage alignment invalid")]
     AvsVoltageAlignmentInvalid,
+    /// A message that initiates an AMS was transmitted without an [`AmsToken`](ams::AmsToken).
+    #[error("AMS token required")]
+    AmsTokenRequired,
+    /// More data objects were given than fit into a message.
+    #[error("too many data objects")]
+    TooManyDataObjects,
+    /// Driver reported a local fault.
+    #[error("driver fault `{0:?}`")]
+    DriverFault(DriverFault),
 }
 
-#[derive(Debug)]
-struct Counters {
-    _busy: Counter,
-    _caps: Counter, // Unused, optional.
-    _discover_identity: Counter,
-    rx_message: Option<Counter>,
-    tx_message: Counter,
-    retry: Counter,
+impl TxError {
+    /// The cause of the error.
+    pub fn origin(&self) -> ErrorOrigin {
+        match self {
+            TxError::DriverFault(_) => ErrorOrigin::Driver,
+            TxError::HardReset | TxError::Detached => ErrorOrigin::Connection,
+            TxError::DiscardStorm(_) => ErrorOrigin::Partner,
+            TxError::UnchunkedExtendedMessagesNotSupported
+            | TxError::AvsVoltageAlignmentInvalid
+            | TxError::AmsTokenRequired
+            | TxError::TooManyDataObjects => ErrorOrigin::Local,
+        }
+    }
+}
+
+/// An AMS of the port partner that was refused with Reject or Wait.
+///
+/// Kept by the protocol layer, such that retries of the port partner can be correlated with the refusal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RefusedAms {
+    /// The type of the message that initiated the refused AMS.
+    pub request: MessageType,
+    /// The response, either Reject or Wait.
+    pub response: ControlMessageType,
+    /// The number of times that the port partner retried the AMS since.
+    pub retries: u8,
+}
+
+/// A frame that was received while waiting for GoodCrc.
+enum GoodCrcFrame {
+    /// The GoodCrc message.
+    GoodCrc(Message),
+    /// Another message, with a copy of the frame, if it is kept for the next reception.
+    Other(MessageType, Option<Vec<u8, MAX_MESSAGE_SIZE>>),
 }
 
-impl Default for Counters {
-    fn default() -> Self {
-        Counters {
-            _busy: Counter::new(CounterType::Busy),
-            _caps: Counter::new(CounterType::Caps),
-            _discover_identity: Counter::new(CounterType::DiscoverIdentity),
-            rx_message: None,
-            tx_message: Counter::new(CounterType::MessageId),
-            retry: Counter::new(CounterType::Retry),
+impl GoodCrcFrame {
+    fn parse(frame: &[u8], keep: bool) -> Result<Self, ParseError> {
+        let header = Header::from_bytes(frame.get(..MSG_HEADER_SIZE).ok_or(ParseError::InvalidLength {
+            expected: MSG_HEADER_SIZE,
+            found: frame.len(),
+        })?)?;
+
+        if header.message_type() == MessageType::Control(ControlMessageType::GoodCRC) {
+            Ok(Self::GoodCrc(Message::from_bytes(frame)?))
+        } else {
+            let kept = if keep { Vec::from_slice(frame).ok() } else { None };
+            Ok(Self::Other(header.message_type(), kept))
         }
     }
 }
 
 /// The USB PD protocol layer.
 #[derive(Debug)]
-pub(crate) struct ProtocolLayer<DRIVER: Driver, TIMER: Timer> {
+pub(crate) struct ProtocolLayer<DRIVER: Driver, TIMER: Timer, TRACER: Tracer = ()> {
     driver: DRIVER,
-    counters: Counters,
-    default_header: Header,
+    tracer: TRACER,
+    core: ProtocolCore,
+    good_crc_config: GoodCrcConfig,
+    timer_overrides: TimerOverrides,
+    /// The strategy for delaying retries of discarded transmissions, if any.
+    backoff: Option<&'static dyn Backoff>,
+    /// The source of random numbers, if any, see [`Self::entropy`].
+    rng: Option<&'static dyn Rng>,
+    /// Whether to collect statistics.
+    collect_stats: bool,
+    /// Whether the driver only listens, so that neither GoodCrc nor chunk requests are sent, and neither message IDs
+    /// nor retries are tracked.
+    listen_only: bool,
+    /// The maximum size of an assembled chunked message, at most [`MAX_MESSAGE_SIZE`].
+    chunk_buffer_size: usize,
+    /// Time of the last frame reception, for measuring GoodCrc latency.
+    rx_timestamp_micros: Option<u64>,
     extended_rx_buffer: Vec<u8, MAX_MESSAGE_SIZE>,
     extended_rx_expected: Option<(ExtendedMessageType, u16, u8)>,
+    /// A frame that arrived while waiting for GoodCrc, kept for the next reception.
+    pending_rx_frame: Option<Vec<u8, MAX_MESSAGE_SIZE>>,
+    /// The payload of the last received message, after its message header, and extended header, if any.
+    rx_payload: Vec<u8, MAX_MESSAGE_SIZE>,
+    /// The type of the last received message, apart from GoodCrc.
+    last_rx_message_type: Option<MessageType>,
+    refused_ams: Option<RefusedAms>,
+    /// Consecutive driver faults, see [`Self::on_driver_fault`].
+    driver_faults: Counter,
+    /// Whether the driver was recovered since the policy engine last made progress.
+    driver_recovered: bool,
+    /// Protocol errors to inject, for testing the error handling of policy engines.
+    #[cfg(test)]
+    injector: inject::ErrorInjector,
     _timer: PhantomData<TIMER>,
 }
 
 impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     /// Create a new protocol layer from a driver and default header.
+    #[cfg(test)]
     pub fn new(driver: DRIVER, default_header: Header) -> Self {
+        Self::new_with_tracer(driver, default_header, ())
+    }
+}
+
+impl<DRIVER: Driver, TIMER: Timer, TRACER: Tracer> ProtocolLayer<DRIVER, TIMER, TRACER> {
+    /// Create a new protocol layer from a driver and default header, that records events to `tracer`.
+    pub fn new_with_tracer(driver: DRIVER, default_header: Header, tracer: TRACER) -> Self {
         Self {
             driver,
-            counters: Default::default(),
-            default_header,
+            tracer,
+            core: ProtocolCore::new(default_header),
+            good_crc_config: Default::default(),
+            timer_overrides: TimerOverrides::new(),
+            backoff: None,
+            rng: None,
+            collect_stats: true,
+            listen_only: false,
+            chunk_buffer_size: MAX_MESSAGE_SIZE,
+            rx_timestamp_micros: None,
             extended_rx_buffer: Vec::new(),
             extended_rx_expected: None,
+            pending_rx_frame: None,
+            rx_payload: Vec::new(),
+            last_rx_message_type: None,
+            refused_ams: None,
+            driver_faults: Counter::new(CounterType::DriverFault),
+            driver_recovered: false,
+            #[cfg(test)]
+            injector: Default::default(),
             _timer: PhantomData,
         }
     }
 
+    /// Record a protocol event.
+    pub fn trace(&self, event: TraceEvent) {
+        self.tracer.record(TIMER::now_micros(), event);
+    }
+
     /// Reset the protocol layer.
     pub fn reset(&mut self) {
-        self.counters = Default::default();
+        self.core.reset();
+        self.refused_ams = None;
+    }
+
+    /// The last AMS of the port partner that was refused, see [`Self::transmit_reject`] and [`Self::transmit_wait`].
+    #[allow(unused)]
+    pub fn refused_ams(&self) -> Option<&RefusedAms> {
+        self.refused_ams.as_ref()
+    }
+
+    /// Configure GoodCrc responses.
+    pub fn set_good_crc_config(&mut self, config: GoodCrcConfig) {
+        self.good_crc_config = config;
+    }
+
+    /// Replace the durations of selected timers.
+    pub fn set_timer_overrides(&mut self, timer_overrides: TimerOverrides) {
+        self.timer_overrides = timer_overrides;
+    }
+
+    /// Delay retries of discarded transmissions with `backoff`, or retry immediately, if `None`.
+    pub fn set_backoff(&mut self, backoff: Option<&'static dyn Backoff>) {
+        self.backoff = backoff;
+    }
+
+    /// Take random numbers from `rng`, or derive them from timestamps, if `None`.
+    pub fn set_rng(&mut self, rng: Option<&'static dyn Rng>) {
+        self.rng = rng;
+    }
+
+    /// A random number from the configured generator, or else the current timestamp, or zero.
+    fn entropy(&self) -> u64 {
+        match self.rng {
+            Some(rng) => rng.next_u64(),
+            None => TIMER::now_micros().unwrap_or_default(),
+        }
+    }
+
+    /// Enable or disable the collection of statistics.
+    pub fn set_collect_stats(&mut self, collect_stats: bool) {
+        self.collect_stats = collect_stats;
+    }
+
+    /// Enable or disable listen-only operation, for drivers that observe traffic between other ports.
+    ///
+    /// Received messages are neither acknowledged nor checked for retransmissions. Transmissions are passed to the
+    /// driver once, without waiting for GoodCrc.
+    pub fn set_listen_only(&mut self, listen_only: bool) {
+        self.listen_only = listen_only;
+    }
+
+    /// Limit the size of assembled chunked messages, capped at [`MAX_MESSAGE_SIZE`].
+    pub fn set_chunk_buffer_size(&mut self, chunk_buffer_size: usize) {
+        self.chunk_buffer_size = chunk_buffer_size.min(MAX_MESSAGE_SIZE);
+    }
+
+    /// The raw payload of the last received message, after its message header, and extended header, if any.
+    ///
+    /// For assembled chunked messages, this is the payload of all chunks.
+    pub fn rx_payload(&self) -> &[u8] {
+        &self.rx_payload
+    }
+
+    /// The collected statistics.
+    pub fn stats(&self) -> &Stats {
+        self.core.stats()
+    }
+
+    /// Record the time until an explicit contract was established, see [`Stats`].
+    pub(crate) fn record_time_to_contract(&mut self, micros: u64, budget_micros: Option<u64>) {
+        if self.collect_stats {
+            self.core.record_time_to_contract(micros, budget_micros);
+        }
     }
 
     /// Allows tests to access the driver directly.
@@ -155,35 +438,109 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         &mut self.driver
     }
 
-    /// Allows tests to access the default header directly.
+    /// Allows tests to inject protocol errors into receptions, and policy engine steps.
     #[cfg(test)]
+    pub(crate) fn injector(&mut self) -> &mut inject::ErrorInjector {
+        &mut self.injector
+    }
+
+    /// The header template for outgoing messages.
     pub fn header(&self) -> &Header {
-        &self.default_header
+        self.core.header()
+    }
+
+    /// Set the roles in the header template for outgoing messages, e.g. after a power role swap.
+    pub fn set_roles(&mut self, data_role: DataRole, power_role: PowerRole) {
+        self.core.set_roles(data_role, power_role);
+    }
+
+    /// Release the driver, e.g. for handing it to the policy engine of the other power role.
+    pub fn into_driver(self) -> DRIVER {
+        self.driver
     }
 
     fn get_message_buffer() -> [u8; MAX_MESSAGE_SIZE] {
         [0u8; MAX_MESSAGE_SIZE]
     }
 
-    /// Get a timer future for a given type.
-    pub fn get_timer(timer_type: TimerType) -> impl Future<Output = ()> {
-        TimerType::get_timer::<TIMER>(timer_type)
+    /// Get a timer future for a given type, with its duration overridden, if configured.
+    pub fn get_timer(&self, timer_type: TimerType) -> impl Future<Output = ()> + use<DRIVER, TIMER, TRACER> {
+        self.timer_overrides
+            .get_traced_timer::<TIMER, TRACER>(timer_type, &self.tracer)
+    }
+
+    /// Receive a frame, starting with one that was kept while waiting for GoodCrc.
+    async fn receive_frame(&mut self, buffer: &mut [u8]) -> Result<usize, DriverRxError> {
+        if let Some(frame) = self.pending_rx_frame.take() {
+            buffer[..frame.len()].copy_from_slice(&frame);
+            return Ok(frame.len());
+        }
+
+        self.receive_driver_frame(buffer).await
+    }
+
+    /// Receive a frame from the driver into `buffer`, and count it.
+    ///
+    /// Frames that do not fit into the buffer are discarded.
+    async fn receive_driver_frame(&mut self, buffer: &mut [u8]) -> Result<usize, DriverRxError> {
+        let copied = self
+            .receive_driver_frame_with(|frame| {
+                let destination = buffer.get_mut(..frame.len())?;
+                destination.copy_from_slice(&frame);
+                Some(frame.len())
+            })
+            .await?;
+
+        copied.ok_or(DriverRxError::Discarded)
+    }
+
+    /// Receive a frame from the driver, pass it to `handler` in place, and count it.
+    async fn receive_driver_frame_with<R>(&mut self, handler: impl FnOnce(Frame<'_>) -> R) -> Result<R, DriverRxError> {
+        let result = self.driver.receive_with(handler).await;
+
+        if self.collect_stats {
+            match result {
+                Ok(_) => self.core.record_frame_received(),
+                Err(DriverRxError::Discarded) => self.core.record_frame_discarded(),
+                Err(DriverRxError::HardReset | DriverRxError::Detached | DriverRxError::Fault(_)) => (),
+            }
+        }
+
+        result
     }
 
-    /// Receive a simple (non-chunked) message from the driver.
-    /// Used by wait_for_good_crc to avoid recursion with chunked message handling.
-    async fn receive_simple(&mut self) -> Result<Message, RxError> {
+    /// Receive frames from the driver, until a GoodCrc message arrives.
+    ///
+    /// Other messages of the port partner, e.g. when it interleaves its own AMS, are kept for the next reception,
+    /// instead of aborting the transmission. Only the first such message is kept. The port partner retransmits
+    /// any further ones, since they are not acknowledged, and retransmissions are detected on reception.
+    ///
+    /// Frames are parsed in place, and only copied, if they are kept.
+    async fn receive_good_crc(&mut self) -> Result<Message, RxError> {
         loop {
-            let mut buffer = Self::get_message_buffer();
+            let keep = self.pending_rx_frame.is_none();
 
-            let length = match self.driver.receive(&mut buffer).await {
-                Ok(length) => length,
+            let frame = match self
+                .receive_driver_frame_with(|frame| GoodCrcFrame::parse(&frame, keep))
+                .await
+            {
+                Ok(frame) => frame?,
                 Err(DriverRxError::Discarded) => continue,
                 Err(DriverRxError::HardReset) => return Err(RxError::HardReset),
+                Err(DriverRxError::Detached) => return Err(RxError::Detached),
+                Err(DriverRxError::Fault(fault)) => return Err(RxError::DriverFault(fault)),
             };
 
-            let message = Message::from_bytes(&buffer[..length])?;
-            return Ok(message);
+            match frame {
+                GoodCrcFrame::GoodCrc(message) => return Ok(message),
+                GoodCrcFrame::Other(message_type, Some(kept)) => {
+                    trace!("Keep {:?}, received while waiting for GoodCrc", message_type);
+                    self.pending_rx_frame = Some(kept);
+                }
+                GoodCrcFrame::Other(message_type, None) => {
+                    trace!("Drop {:?}, received while waiting for GoodCrc", message_type);
+                }
+            }
         }
     }
 
@@ -191,32 +548,10 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     async fn wait_for_good_crc(&mut self) -> Result<(), RxError> {
         trace!("Wait for GoodCrc");
 
-        let timeout_fut = Self::get_timer(TimerType::CRCReceive);
+        let timeout_fut = self.get_timer(TimerType::CRCReceive);
         let receive_fut = async {
-            let message = self.receive_simple().await?;
-
-            if matches!(
-                message.header.message_type(),
-                MessageType::Control(ControlMessageType::GoodCRC)
-            ) {
-                trace!(
-                    "Received GoodCrc, TX message count: {}, expected: {}",
-                    message.header.message_id(),
-                    self.counters.tx_message.value()
-                );
-                if message.header.message_id() == self.counters.tx_message.value() {
-                    // See spec, [6.7.1.1]
-                    self.counters.retry.reset();
-                    _ = self.counters.tx_message.increment();
-                    Ok(())
-                } else {
-                    Err(RxError::AcknowledgeMismatch(message.header.message_id()))
-                }
-            } else if matches!(message.header.message_type(), MessageType::Control(_)) {
-                Err(ParseError::InvalidControlMessageType(message.header.message_type_raw()).into())
-            } else {
-                Err(ParseError::InvalidMessageType(message.header.message_type_raw()).into())
-            }
+            let message = self.receive_good_crc().await?;
+            self.core.handle_good_crc(&message)
         };
 
         match select(timeout_fut, receive_fut).await {
@@ -225,58 +560,33 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         }
     }
 
-    /// Validate an outgoing message for spec compliance.
-    ///
-    /// This catches common mistakes when constructing messages:
-    /// - unchunked_extended_messages_supported should always be false
-    /// - AVS voltage LSB 2 bits should be zero (per USB PD 3.2 Table 6.26)
-    ///
-    /// Only validates outgoing messages - never called when parsing received data.
-    /// Returns an error if validation fails, allowing the caller to handle it appropriately.
-    fn validate_outgoing_message(message: &Message) -> Result<(), TxError> {
-        if let Some(Payload::Data(message::data::Data::Request(power_source))) = &message.payload {
-            use message::data::request::PowerSource;
-            match power_source {
-                PowerSource::FixedVariableSupply(rdo) => {
-                    if rdo.unchunked_extended_messages_supported() {
-                        return Err(TxError::UnchunkedExtendedMessagesNotSupported);
-                    }
-                }
-                PowerSource::Pps(rdo) => {
-                    if rdo.unchunked_extended_messages_supported() {
-                        return Err(TxError::UnchunkedExtendedMessagesNotSupported);
-                    }
-                }
-                PowerSource::EprRequest(epr) => {
-                    // Check the raw RDO for validation
-                    let rdo_bits = epr.rdo;
-                    let unchunked = (rdo_bits >> 23) & 1 == 1;
-                    if unchunked {
-                        return Err(TxError::UnchunkedExtendedMessagesNotSupported);
-                    }
-
-                    // Check if this looks like an AVS request (bits 30-31 = 00, bits 28-29 = 11)
-                    let is_avs = ((rdo_bits >> 30) & 0x3 == 0) && ((rdo_bits >> 28) & 0x3 == 3);
-                    if is_avs {
-                        let voltage = (rdo_bits >> 9) & 0xFFF;
-                        if (voltage as u16) & 0x3 != 0 {
-                            return Err(TxError::AvsVoltageAlignmentInvalid);
-                        }
-                    }
-                }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
-
     async fn transmit_inner(&mut self, buffer: &[u8]) -> Result<(), TxError> {
+        let mut discards: u32 = 0;
+
         loop {
             match self.driver.transmit(buffer).await {
                 Ok(_) => return Ok(()),
                 Err(DriverTxError::HardReset) => return Err(TxError::HardReset),
+                Err(DriverTxError::Detached) => return Err(TxError::Detached),
+                Err(DriverTxError::Fault(fault)) => return Err(TxError::DriverFault(fault)),
                 Err(DriverTxError::Discarded) => {
-                    // Retry transmission.
+                    // Retry transmission, after backing off from the contending port partner.
+                    discards = discards.saturating_add(1);
+                    if self.collect_stats {
+                        self.core.record_transmission_discarded();
+                    }
+
+                    if discards >= DISCARD_STORM_THRESHOLD {
+                        warn!("Transmission discarded {} times in a row", discards);
+                        return Err(TxError::DiscardStorm(discards));
+                    }
+
+                    if let Some(backoff) = self.backoff {
+                        let delay_micros = backoff.delay_micros(discards, self.entropy());
+                        if delay_micros > 0 {
+                            TIMER::after_micros(delay_micros).await;
+                        }
+                    }
                 }
             }
         }
@@ -287,55 +597,70 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     // GoodCrc message transmission is handled separately.
     // See `transmit_good_crc()` instead.
     pub async fn transmit(&mut self, message: Message) -> Result<(), ProtocolError> {
-        assert_ne!(
-            message.header.message_type(),
-            MessageType::Control(ControlMessageType::GoodCRC)
+        let message_type = message.header.message_type();
+        let result = self.transmit_untraced(message).await;
+
+        if result.is_ok() {
+            self.trace(TraceEvent::MessageTransmitted(message_type));
+        }
+
+        result
+    }
+
+    /// Transmit a message, without recording it.
+    async fn transmit_untraced(&mut self, message: Message) -> Result<(), ProtocolError> {
+        assert_or_return!(
+            message.header.message_type() != MessageType::Control(ControlMessageType::GoodCRC),
+            ProtocolError::UnexpectedMessage
         );
 
         // Validate outgoing message for spec compliance
-        Self::validate_outgoing_message(&message)?;
+        ProtocolCore::validate_outgoing_message(&message)?;
 
         trace!("Transmit message: {:?}", message);
 
         let mut buffer = Self::get_message_buffer();
         let size = message.to_bytes(&mut buffer);
 
-        if DRIVER::HAS_AUTO_RETRY {
+        self.transmit_bytes(&buffer[..size]).await
+    }
+
+    /// Transmit a serialized message, with retries, and wait for GoodCrc.
+    async fn transmit_bytes(&mut self, buffer: &[u8]) -> Result<(), ProtocolError> {
+        if self.listen_only {
+            trace!("Transmit without GoodCRC (listen only)");
+            Ok(self.transmit_inner(buffer).await?)
+        } else if DRIVER::HAS_AUTO_RETRY {
             // Hardware handles retries and verifies GoodCRC reception.
             // Call driver.transmit() directly (not transmit_inner()) because
             // Discarded here means all hardware retries exhausted — no point
             // retrying in software.
-            match self.driver.transmit(&buffer[..size]).await {
+            match self.driver.transmit(buffer).await {
                 Ok(()) => {
-                    self.counters.retry.reset();
-                    _ = self.counters.tx_message.increment();
+                    self.core.acknowledge();
                     trace!("Transmit success (hardware retry)");
                     Ok(())
                 }
                 Err(DriverTxError::HardReset) => Err(TxError::HardReset.into()),
-                Err(DriverTxError::Discarded) => {
-                    Err(ProtocolError::TransmitRetriesExceeded(self.counters.retry.max_value()))
-                }
+                Err(DriverTxError::Detached) => Err(TxError::Detached.into()),
+                Err(DriverTxError::Fault(fault)) => Err(TxError::DriverFault(fault).into()),
+                Err(DriverTxError::Discarded) => Err(self.core.retries_exceeded()),
             }
         } else {
             // Software retry loop
-            self.counters.retry.reset();
+            self.core.start_transmission();
 
             loop {
-                match self.transmit_inner(&buffer[..size]).await {
+                match self.transmit_inner(buffer).await {
                     Ok(_) => match self.wait_for_good_crc().await {
                         Ok(()) => {
                             trace!("Transmit success");
                             return Ok(());
                         }
-                        Err(RxError::ReceiveTimeout) => match self.counters.retry.increment() {
-                            Ok(_) => {
-                                // Retry transmission, until the retry counter is exceeded.
-                            }
-                            Err(CounterError::Exceeded) => {
-                                return Err(ProtocolError::TransmitRetriesExceeded(self.counters.retry.max_value()));
-                            }
-                        },
+                        Err(RxError::ReceiveTimeout) => {
+                            // Retry transmission, until the retry counter is exceeded.
+                            self.core.retry()?;
+                        }
                         Err(other) => return Err(other.into()),
                     },
                     Err(other) => return Err(other.into()),
@@ -346,19 +671,21 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
 
     /// Send a GoodCrc message to the port partner.
     async fn transmit_good_crc(&mut self) -> Result<(), ProtocolError> {
-        trace!(
-            "Transmit message GoodCrc for RX message count: {}",
-            self.counters.rx_message.unwrap().value()
-        );
-
         let mut buffer = Self::get_message_buffer();
 
-        let size = Message::new(Header::new_control(
-            self.default_header,
-            self.counters.rx_message.unwrap(), // A message must have been received before.
-            ControlMessageType::GoodCRC,
-        ))
-        .to_bytes(&mut buffer);
+        // A message must have been received before.
+        let Some(good_crc) = self.core.good_crc_message() else {
+            unreachable_or_return!(ProtocolError::UnexpectedMessage)
+        };
+        let size = good_crc.to_bytes(&mut buffer);
+
+        let latency_micros = match (self.rx_timestamp_micros.take(), TIMER::now_micros()) {
+            (Some(received), Some(now)) => Some(now.saturating_sub(received)),
+            _ => None,
+        };
+        if self.collect_stats {
+            self.core.record_good_crc(latency_micros, self.good_crc_config.budget);
+        }
 
         Ok(self.transmit_inner(&buffer[..size]).await?)
     }
@@ -367,7 +694,11 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     ///
     /// Returns `Ok(true)` if this was a retransmission (caller should continue to next message),
     /// `Ok(false)` if this is a new message to process, or `Err` on failure.
-    async fn handle_rx_ack(&mut self, message: &Message) -> Result<bool, RxError> {
+    async fn handle_rx_ack(&mut self, message: &Message) -> Result<bool, ProtocolError> {
+        if self.listen_only {
+            return Ok(false);
+        }
+
         let is_good_crc = matches!(
             message.header.message_type(),
             MessageType::Control(ControlMessageType::GoodCRC)
@@ -376,14 +707,27 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         let is_retransmission = if is_good_crc {
             false
         } else {
-            self.update_rx_message_counter(message)
+            self.core.update_rx_message_counter(message)
         };
 
         if !DRIVER::HAS_AUTO_GOOD_CRC && !is_good_crc {
             match self.transmit_good_crc().await {
                 Ok(()) => {}
-                Err(ProtocolError::TxError(TxError::HardReset)) => return Err(RxError::HardReset),
-                Err(_) => return Err(RxError::UnsupportedMessage),
+                Err(ProtocolError::TxError(error)) => match error {
+                    TxError::HardReset => return Err(RxError::HardReset.into()),
+                    TxError::Detached => return Err(RxError::Detached.into()),
+                    TxError::DriverFault(fault) => return Err(RxError::DriverFault(fault).into()),
+                    // The port partner keeps the line busy, which is handled like for any other transmission.
+                    TxError::DiscardStorm(_) => return Err(error.into()),
+                    TxError::UnchunkedExtendedMessagesNotSupported
+                    | TxError::AvsVoltageAlignmentInvalid
+                    | TxError::AmsTokenRequired
+                    | TxError::TooManyDataObjects => {
+                        // A GoodCrc message is always valid.
+                        unreachable_or_return!(error)
+                    }
+                },
+                Err(error) => return Err(error),
             }
         }
 
@@ -397,21 +741,67 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     }
 
     /// Receive a message, assembling chunked extended messages as needed.
-    async fn receive_message_inner(&mut self) -> Result<Message, RxError> {
+    async fn receive_message_inner(&mut self) -> Result<Message, ProtocolError> {
+        let result = self.receive_message_untraced().await;
+
+        match &result {
+            Ok(message) => {
+                let message_type = message.header.message_type();
+                if message_type != MessageType::Control(ControlMessageType::GoodCRC) {
+                    self.trace(TraceEvent::MessageReceived(message_type));
+                    self.last_rx_message_type = Some(message_type);
+
+                    if let Some(refused) = self.refused_ams.as_mut()
+                        && refused.request == message_type
+                    {
+                        refused.retries = refused.retries.saturating_add(1);
+                        debug!(
+                            "Port partner retries {:?} after {:?}, retry {}",
+                            message_type, refused.response, refused.retries
+                        );
+                    }
+                }
+            }
+            Err(ProtocolError::RxError(RxError::HardReset)) => self.trace(TraceEvent::HardResetReceived),
+            Err(_) => (),
+        }
+
+        result
+    }
+
+    /// Receive a message, without recording it.
+    async fn receive_message_untraced(&mut self) -> Result<Message, ProtocolError> {
         loop {
             let mut buffer = Self::get_message_buffer();
 
-            let length = match self.driver.receive(&mut buffer).await {
+            let length = match self.receive_frame(&mut buffer).await {
                 Ok(length) => length,
                 Err(DriverRxError::Discarded) => continue,
-                Err(DriverRxError::HardReset) => return Err(RxError::HardReset),
+                Err(DriverRxError::HardReset) => return Err(RxError::HardReset.into()),
+                Err(DriverRxError::Detached) => return Err(RxError::Detached.into()),
+                Err(DriverRxError::Fault(fault)) => return Err(RxError::DriverFault(fault).into()),
             };
+            self.rx_timestamp_micros = TIMER::now_micros();
 
             // Parse header early to handle chunking.
             let header = Header::from_bytes(&buffer[..MSG_HEADER_SIZE])?;
             let message_type = header.message_type();
 
-            if matches!(message_type, MessageType::Extended(_)) {
+            // With GoodCrc priority, acknowledge based on the header alone, before parsing the payload.
+            let acknowledged = self.good_crc_config.priority
+                && !matches!(
+                    message_type,
+                    MessageType::Control(ControlMessageType::GoodCRC | ControlMessageType::Reserved)
+                        | MessageType::Data(DataMessageType::Reserved)
+                );
+            if acknowledged {
+                self.core.update_spec_revision(&header)?;
+                if self.handle_rx_ack(&Message::new(header)).await? {
+                    continue; // Retransmission
+                }
+            }
+
+            if let MessageType::Extended(msg_type) = message_type {
                 let ext_header_end = MSG_HEADER_SIZE + EXT_HEADER_SIZE;
                 let ext_header =
                     message::extended::ExtendedHeader::from_bytes(&buffer[MSG_HEADER_SIZE..ext_header_end]);
@@ -419,13 +809,8 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
                 let total_size = ext_header.data_size();
                 let chunked = ext_header.chunked();
                 let chunk_number = ext_header.chunk_number();
-                let msg_type = match message_type {
-                    MessageType::Extended(mt) => mt,
-                    _ => unreachable!(),
-                };
-
                 // Update specification revision, based on the received frame.
-                self.default_header = self.default_header.with_spec_revision(header.spec_revision()?);
+                self.core.update_spec_revision(&header)?;
 
                 if chunked {
                     trace!(
@@ -437,7 +822,7 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
 
                     // Update RX counters and acknowledge.
                     let tmp_message = Message { header, payload: None };
-                    if self.handle_rx_ack(&tmp_message).await? {
+                    if !acknowledged && self.handle_rx_ack(&tmp_message).await? {
                         continue; // Retransmission
                     }
 
@@ -446,10 +831,18 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
                         _ => (total_size, 0),
                     };
 
+                    // A chunk that was assembled before, but arrives with a new message ID, is repeated by the
+                    // port partner. Do not append it again, but request the next chunk once more.
+                    if chunk_number != 0 && chunk_number + 1 == expected_next {
+                        trace!("Received repeated chunk {}", chunk_number);
+                        self.transmit_chunk_request(msg_type, expected_next).await?;
+                        continue;
+                    }
+
                     // Ensure chunks arrive in order.
                     if expected_next != 0 && chunk_number != expected_next {
                         self.reset_chunked_rx();
-                        return Err(RxError::UnsupportedMessage);
+                        return Err(RxError::UnsupportedMessage.into());
                     }
 
                     if chunk_number == 0 || expected_next == 0 {
@@ -459,13 +852,13 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
                         self.extended_rx_expected = Some((msg_type, expected_total, expected_next + 1));
                     }
 
-                    if self.extended_rx_buffer.len() + payload.len() > self.extended_rx_buffer.capacity() {
+                    if self.extended_rx_buffer.len() + payload.len() > self.chunk_buffer_size {
                         self.reset_chunked_rx();
-                        return Err(RxError::UnsupportedMessage);
+                        return Err(RxError::UnsupportedMessage.into());
                     }
                     if self.extended_rx_buffer.extend_from_slice(payload).is_err() {
                         self.reset_chunked_rx();
-                        return Err(RxError::UnsupportedMessage);
+                        return Err(RxError::UnsupportedMessage.into());
                     }
 
                     if self.extended_rx_buffer.len() < total_size as usize {
@@ -481,24 +874,11 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
 
                     // All chunks received, parse payload.
                     let ext_payload = &self.extended_rx_buffer[..total_size as usize];
-                    let parsed_payload = match msg_type {
-                        ExtendedMessageType::ExtendedControl => {
-                            Payload::Extended(message::extended::Extended::ExtendedControl(
-                                message::extended::extended_control::ExtendedControl::from_bytes(ext_payload),
-                            ))
-                        }
-                        ExtendedMessageType::EprSourceCapabilities => {
-                            Payload::Extended(message::extended::Extended::EprSourceCapabilities(
-                                ext_payload
-                                    .chunks_exact(4)
-                                    .map(|buf| {
-                                        message::data::source_capabilities::parse_raw_pdo(LittleEndian::read_u32(buf))
-                                    })
-                                    .collect(),
-                            ))
-                        }
-                        _ => Payload::Extended(message::extended::Extended::Unknown),
-                    };
+                    let parsed_payload = Payload::Extended(Message::parse_extended_payload(msg_type, ext_payload));
+
+                    self.rx_payload.clear();
+                    // Cannot fail, both buffers are of the same size.
+                    let _ = self.rx_payload.extend_from_slice(ext_payload);
 
                     self.extended_rx_expected = None;
                     let mut message = Message::new(header);
@@ -512,20 +892,38 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
             // Non-extended or unchunked extended messages.
             let message = Message::from_bytes(&buffer[..length])?;
 
+            let payload = match message_type {
+                MessageType::Extended(_) => {
+                    let start = MSG_HEADER_SIZE + EXT_HEADER_SIZE;
+                    let ext_header = message::extended::ExtendedHeader::from_bytes(&buffer[MSG_HEADER_SIZE..start]);
+                    &buffer[start.min(length)..(start + usize::from(ext_header.data_size())).min(length)]
+                }
+                _ => &buffer[MSG_HEADER_SIZE.min(length)..length],
+            };
+            self.rx_payload.clear();
+            // Cannot fail, the payload is part of the receive buffer.
+            let _ = self.rx_payload.extend_from_slice(payload);
+
             // Update specification revision, based on the received frame.
-            self.default_header = self.default_header.with_spec_revision(message.header.spec_revision()?);
+            self.core.update_spec_revision(&message.header)?;
 
             match message.header.message_type() {
                 MessageType::Control(ControlMessageType::Reserved) | MessageType::Data(DataMessageType::Reserved) => {
                     trace!("Unsupported message type in header: {:?}", message.header);
-                    return Err(RxError::UnsupportedMessage);
+                    return Err(RxError::UnsupportedMessage.into());
+                }
+                MessageType::Control(ControlMessageType::SoftReset) => {
+                    // A Soft_Reset resets the message IDs, and is acknowledged as a new message. See spec, [6.7.1]
+                    if !acknowledged {
+                        self.handle_rx_ack(&message).await?;
+                    }
+                    return Err(RxError::SoftReset.into());
                 }
-                MessageType::Control(ControlMessageType::SoftReset) => return Err(RxError::SoftReset),
                 _ => (),
             }
 
             // Handle GoodCRC and retransmissions.
-            if self.handle_rx_ack(&message).await? {
+            if !acknowledged && self.handle_rx_ack(&message).await? {
                 continue; // Retransmission
             }
 
@@ -536,38 +934,12 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
 
     /// Receive a message.
     pub async fn receive_message(&mut self) -> Result<Message, ProtocolError> {
-        self.receive_message_inner().await.map_err(|err| err.into())
-    }
-
-    /// Updates the received message counter.
-    ///
-    /// If receiving the first message after protocol layer reset, copy its ID.
-    /// Otherwise, compare the received ID with the stored ID. If they are equal, this is a retransmission.
-    ///
-    /// Returns `true`, if this was a retransmission.
-    fn update_rx_message_counter(&mut self, rx_message: &Message) -> bool {
-        match self.counters.rx_message.as_mut() {
-            None => {
-                trace!(
-                    "Received first message after protocol layer reset with RX counter value: {}",
-                    rx_message.header.message_id()
-                );
-                self.counters.rx_message = Some(Counter::new_from_value(
-                    CounterType::MessageId,
-                    rx_message.header.message_id(),
-                ));
-                false
-            }
-            Some(counter) => {
-                if rx_message.header.message_id() == counter.value() {
-                    trace!("Received retransmission of RX counter value: {}", counter.value());
-                    true
-                } else {
-                    counter.set(rx_message.header.message_id());
-                    false
-                }
-            }
+        #[cfg(test)]
+        if let Some(error) = self.injector.on_receive() {
+            return Err(error);
         }
+
+        self.receive_message_inner().await
     }
 
     /// Wait until a message of one of the chosen types is received, or a timeout occurs.
@@ -579,10 +951,38 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         // GoodCrc message reception is handled separately.
         // See `wait_for_good_crc()` instead.
         for message_type in message_types {
-            assert_ne!(*message_type, MessageType::Control(ControlMessageType::GoodCRC));
+            assert_or_return!(
+                *message_type != MessageType::Control(ControlMessageType::GoodCRC),
+                ProtocolError::UnexpectedMessage
+            );
         }
 
-        let timeout_fut = Self::get_timer(timer_type);
+        self.receive_message_matching(
+            |message| {
+                message_types
+                    .contains(&message.header.message_type())
+                    .then_some(message)
+            },
+            timer_type,
+        )
+        .await
+    }
+
+    /// Wait until a message is received that `filter` accepts, or a timeout occurs.
+    ///
+    /// The filter returns `None` for messages that are unexpected, e.g. an EPR mode message with the wrong action,
+    /// and the extracted value otherwise. GoodCrc messages never reach the filter.
+    pub async fn receive_message_matching<T>(
+        &mut self,
+        mut filter: impl FnMut(Message) -> Option<T>,
+        timer_type: TimerType,
+    ) -> Result<T, ProtocolError> {
+        #[cfg(test)]
+        if let Some(error) = self.injector.on_receive() {
+            return Err(error);
+        }
+
+        let timeout_fut = self.get_timer(timer_type);
         let receive_fut = async {
             loop {
                 match self.receive_message_inner().await {
@@ -593,14 +993,10 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
                         ) {
                             continue;
                         }
-                        return if message_types.contains(&message.header.message_type()) {
-                            Ok(message)
-                        } else {
-                            Err(ProtocolError::UnexpectedMessage)
-                        };
+                        return filter(message).ok_or(ProtocolError::UnexpectedMessage);
                     }
-                    Err(RxError::ParseError(_)) => unreachable!(),
-                    Err(other) => return Err(other.into()),
+                    Err(error @ ProtocolError::RxError(RxError::ParseError(_))) => unreachable_or_return!(error),
+                    Err(other) => return Err(other),
                 }
             }
         };
@@ -615,17 +1011,52 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     ///
     // See spec, [6.7.1.1]
     pub async fn hard_reset(&mut self) -> Result<(), ProtocolError> {
-        self.counters.tx_message.reset();
-        self.counters.retry.reset();
+        self.core.hard_reset();
 
         loop {
             match self.driver.transmit_hard_reset().await {
                 Ok(_) | Err(DriverTxError::HardReset) => break,
+                Err(DriverTxError::Detached) => return Err(TxError::Detached.into()),
+                Err(DriverTxError::Fault(fault)) => return Err(TxError::DriverFault(fault).into()),
                 Err(DriverTxError::Discarded) => (),
             }
         }
 
         trace!("Performed hard reset");
+        self.trace(TraceEvent::HardResetTransmitted);
+        Ok(())
+    }
+
+    /// Transmit the BIST carrier signal for tBISTContMode.
+    ///
+    /// Returns `false` without transmitting, if the driver does not support it. See spec, [6.4.3.1]
+    #[cfg(feature = "bist")]
+    pub async fn bist_carrier_mode(&mut self) -> bool {
+        if !DRIVER::HAS_BIST_CARRIER_MODE {
+            return false;
+        }
+
+        self.driver.set_bist_carrier_mode(true).await;
+        self.get_timer(TimerType::BISTContMode).await;
+        self.driver.set_bist_carrier_mode(false).await;
+
+        trace!("Finished BIST carrier mode");
+        true
+    }
+
+    /// Perform a soft reset, after a failed AMS.
+    ///
+    /// Resets the protocol layer, transmits Soft_Reset, and waits for the port partner to Accept. See spec, [6.8.1]
+    pub async fn soft_reset(&mut self) -> Result<(), ProtocolError> {
+        self.reset();
+
+        self.transmit_control_message(ControlMessageType::SoftReset).await?;
+        self.receive_message_type(
+            &[MessageType::Control(ControlMessageType::Accept)],
+            TimerType::SenderResponse,
+        )
+        .await?;
+
         Ok(())
     }
 
@@ -634,6 +1065,58 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         self.driver.wait_for_vbus().await
     }
 
+    /// Select Try.SNK or Try.SRC behavior of a dual-role port, for the next Type-C connection.
+    ///
+    /// Returns `false`, if the driver does not support role preferences.
+    pub async fn set_role_preference(&mut self, preference: RolePreference) -> bool {
+        if !DRIVER::HAS_ROLE_PREFERENCE {
+            return false;
+        }
+
+        self.driver.set_role_preference(preference).await;
+        true
+    }
+
+    /// Switch the CC termination during a power role swap.
+    ///
+    /// Returns `false`, if the driver does not support power role swaps.
+    pub async fn set_cc_termination(&mut self, termination: CcTermination) -> bool {
+        if !DRIVER::HAS_POWER_ROLE_SWAP {
+            return false;
+        }
+
+        self.driver.set_cc_termination(termination).await;
+        true
+    }
+
+    /// Count a driver fault, and recover the driver, if faults persist.
+    ///
+    /// Returns `false`, if faults persist after recovery, or if the driver cannot recover, such that the policy
+    /// engine must escalate to a hard reset.
+    pub async fn on_driver_fault(&mut self) -> bool {
+        if self.driver_faults.increment().is_ok() {
+            return true;
+        }
+        if !DRIVER::HAS_RECOVERY || self.driver_recovered {
+            return false;
+        }
+
+        warn!("Driver faults persist, recovering the driver");
+        self.driver.recover().await;
+        self.driver_recovered = true;
+
+        // Partially received chunked messages are lost with the PHY's state.
+        self.extended_rx_buffer.clear();
+        self.extended_rx_expected = None;
+        true
+    }
+
+    /// Forget past driver faults, after the policy engine made progress.
+    pub fn clear_driver_faults(&mut self) {
+        self.driver_faults.reset();
+        self.driver_recovered = false;
+    }
+
     /// Wait for the source to provide its capabilities.
     pub async fn wait_for_source_capabilities(&mut self) -> Result<Message, ProtocolError> {
         self.receive_message_type(
@@ -649,66 +1132,226 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     /// Transmit a control message of the provided type.
     pub async fn transmit_control_message(&mut self, message_type: ControlMessageType) -> Result<(), ProtocolError> {
         let message = Message::new(Header::new_control(
-            self.default_header,
-            self.counters.tx_message,
+            *self.core.header(),
+            self.core.tx_message(),
             message_type,
         ));
 
         self.transmit(message).await
     }
 
+    /// Refuse the last received message with Reject.
+    ///
+    /// The refused AMS is kept, see [`Self::refused_ams`].
+    pub async fn transmit_reject(&mut self) -> Result<(), ProtocolError> {
+        self.transmit_refusal(ControlMessageType::Reject).await
+    }
+
+    /// Refuse the last received message with Wait, after which the port partner may retry.
+    ///
+    /// The refused AMS is kept, see [`Self::refused_ams`].
+    #[allow(unused)]
+    pub async fn transmit_wait(&mut self) -> Result<(), ProtocolError> {
+        self.transmit_refusal(ControlMessageType::Wait).await
+    }
+
+    /// Respond to the last received message, if it is not supported.
+    ///
+    /// Not_Supported only exists as of revision 3.0. Before, Reject is used instead. See spec, [6.3.16]
+    pub async fn transmit_not_supported(&mut self) -> Result<(), ProtocolError> {
+        match self.core.header().spec_revision() {
+            Ok(SpecificationRevision::R1_0 | SpecificationRevision::R2_0) => self.transmit_reject().await,
+            _ => self.transmit_control_message(ControlMessageType::NotSupported).await,
+        }
+    }
+
+    async fn transmit_refusal(&mut self, response: ControlMessageType) -> Result<(), ProtocolError> {
+        self.transmit_control_message(response).await?;
+
+        self.refused_ams = self.last_rx_message_type.map(|request| RefusedAms {
+            request,
+            response,
+            retries: 0,
+        });
+
+        Ok(())
+    }
+
     /// Transmit an extended control message of the provided type.
     pub async fn transmit_extended_control_message(
         &mut self,
         message_type: ExtendedControlMessageType,
     ) -> Result<(), ProtocolError> {
-        // Per USB PD spec 6.2.1.1.2: for extended messages, num_objects must be non-zero.
-        // ExtendedControl = 2-byte extended header + 2-byte data = 4 bytes = 1 data object.
-        let mut message = Message::new(Header::new_extended(
-            self.default_header,
-            self.counters.tx_message,
+        self.transmit_extended(
             ExtendedMessageType::ExtendedControl,
-            1,
+            Extended::ExtendedControl(
+                message::extended::extended_control::ExtendedControl::default().with_message_type(message_type),
+            ),
+        )
+        .await
+    }
+
+    /// Transmit an extended message of the provided type, that fits into a single chunk.
+    pub async fn transmit_extended(
+        &mut self,
+        message_type: ExtendedMessageType,
+        payload: Extended,
+    ) -> Result<(), ProtocolError> {
+        let mut message = Message::new(Header::new_extended_for_payload(
+            *self.core.header(),
+            self.core.tx_message(),
+            message_type,
+            payload.data_size().into(),
         ));
 
-        message.payload = Some(Payload::Extended(Extended::ExtendedControl(
-            message::extended::extended_control::ExtendedControl::default().with_message_type(message_type),
-        )));
+        message.payload = Some(Payload::Extended(payload));
 
         self.transmit(message).await
     }
 
     /// Transmit an EPR mode data message.
-    pub async fn transmit_epr_mode(
+    pub async fn transmit_epr_mode(&mut self, mode: message::data::epr_mode::EprMode) -> Result<(), ProtocolError> {
+        let header = Header::new_data(*self.core.header(), self.core.tx_message(), DataMessageType::EprMode, 1);
+
+        self.transmit(Message::new_with_data(
+            header,
+            Data::EprMode(EprModeDataObject::from(mode)),
+        ))
+        .await
+    }
+
+    /// Transmit an alert data message, e.g. to inform the port partner about a fault.
+    pub async fn transmit_alert(&mut self, ado: AlertDataObject) -> Result<(), ProtocolError> {
+        let header = Header::new_data(*self.core.header(), self.core.tx_message(), DataMessageType::Alert, 1);
+
+        self.transmit(Message::new_with_data(header, Data::Alert(ado))).await
+    }
+
+    /// Transmit a data message of any type, with up to seven raw data objects.
+    ///
+    /// For messages that have no typed representation, e.g. responses of the device policy manager to messages
+    /// that the policy engine does not handle.
+    pub async fn transmit_data_objects(
         &mut self,
-        action: message::data::epr_mode::Action,
-        data: u8,
+        message_type: DataMessageType,
+        data_objects: &[u32],
     ) -> Result<(), ProtocolError> {
         let header = Header::new_data(
-            self.default_header,
-            self.counters.tx_message,
-            DataMessageType::EprMode,
-            1,
+            *self.core.header(),
+            self.core.tx_message(),
+            message_type,
+            data_objects.len() as u8,
         );
 
-        let mdo = EprModeDataObject::default().with_action(action).with_data(data);
+        trace!("Transmit data message {:?}: {:?}", message_type, data_objects);
+
+        let mut buffer = Self::get_message_buffer();
+        let mut size = header.to_bytes(&mut buffer);
+        for data_object in data_objects {
+            LittleEndian::write_u32(&mut buffer[size..size + DATA_OBJECT_SIZE], *data_object);
+            size += DATA_OBJECT_SIZE;
+        }
+
+        let result = self.transmit_bytes(&buffer[..size]).await;
+        if result.is_ok() {
+            self.trace(TraceEvent::MessageTransmitted(MessageType::Data(message_type)));
+        }
 
-        self.transmit(Message::new_with_data(header, Data::EprMode(mdo))).await
+        result
     }
 
     /// Request a certain power level from the source.
     pub async fn request_power(&mut self, power_source_request: request::PowerSource) -> Result<(), ProtocolError> {
         // Only sinks can request from a supply.
-        assert!(matches!(self.default_header.port_power_role(), PowerRole::Sink));
+        assert_or_return!(
+            matches!(self.core.header().port_power_role(), PowerRole::Sink),
+            ProtocolError::UnexpectedMessage
+        );
 
         let message_type = power_source_request.message_type();
         let num_objects = power_source_request.num_objects();
-        let header = Header::new_data(self.default_header, self.counters.tx_message, message_type, num_objects);
+        let header = Header::new_data(*self.core.header(), self.core.tx_message(), message_type, num_objects);
 
         self.transmit(Message::new_with_data(header, Data::Request(power_source_request)))
             .await
     }
 
+    /// Transmit a vendor defined message (VDM) with up to six VDOs.
+    pub async fn transmit_vdm(&mut self, header: VdmHeader, vdos: &[u32]) -> Result<(), ProtocolError> {
+        let vdos: Vec<u32, 7> = Vec::from_slice(vdos).map_err(|_| TxError::TooManyDataObjects)?;
+        let message_header = Header::new_data(
+            *self.core.header(),
+            self.core.tx_message(),
+            DataMessageType::VendorDefined,
+            vdos.len() as u8 + 1,
+        );
+
+        self.transmit(Message::new_with_data(
+            message_header,
+            Data::VendorDefined((header, vdos)),
+        ))
+        .await
+    }
+
+    /// Request a structured VDM, and wait for the response of the port partner.
+    ///
+    /// Responder BUSY is handled by retrying after tVDMBusy, until the busy counter is exceeded. Returns the final
+    /// response, which is either ACK, NAK, or BUSY (retries exhausted). See spec, [6.4.4.2.2]
+    pub async fn request_structured_vdm(
+        &mut self,
+        header: VdmHeaderStructured,
+        vdos: &[u32],
+    ) -> Result<Message, ProtocolError> {
+        let mut initiator = crate::vdm::Initiator::new(header);
+
+        loop {
+            self.transmit_vdm(VdmHeader::Structured(header), vdos).await?;
+
+            loop {
+                let response = self
+                    .receive_message_type(
+                        &[MessageType::Data(DataMessageType::VendorDefined)],
+                        initiator.response_timer(),
+                    )
+                    .await?;
+
+                let Some(Payload::Data(Data::VendorDefined((VdmHeader::Structured(response_header), _)))) =
+                    &response.payload
+                else {
+                    continue;
+                };
+
+                match initiator.on_response(response_header) {
+                    InitiatorStep::Complete(_) => return Ok(response),
+                    InitiatorStep::RetryAfterBusy => {
+                        self.get_timer(TimerType::VDMBusy).await;
+                        break;
+                    }
+                    InitiatorStep::Ignore => (),
+                }
+            }
+        }
+    }
+
+    /// Request the status of a programmable power supply with Get_PPS_Status, and wait for PPS_Status.
+    ///
+    /// Returns `None`, if the source answers Not_Supported. See spec, [6.5.10]
+    pub async fn get_pps_status(
+        &mut self,
+    ) -> Result<Option<message::extended::pps_status::PpsStatusExtended>, ProtocolError> {
+        self.transmit_control_message(ControlMessageType::GetPpsStatus).await?;
+
+        self.receive_message_matching(
+            |message| match message.payload {
+                Some(Payload::Extended(Extended::PpsStatus(status))) => Some(Some(status)),
+                _ => (message.header.message_type() == MessageType::Control(ControlMessageType::NotSupported))
+                    .then_some(None),
+            },
+            TimerType::SenderResponse,
+        )
+        .await
+    }
+
     /// Transmit a chunk request message per USB PD spec 6.12.2.1.2.4.
     ///
     /// A chunk request is an extended message with:
@@ -719,43 +1362,49 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         message_type: ExtendedMessageType,
         chunk_number: u8,
     ) -> Result<(), RxError> {
-        trace!("Transmit chunk request for {:?} chunk {}", message_type, chunk_number);
-
-        // Build extended header for chunk request
-        let ext_header = message::extended::ExtendedHeader::default()
-            .with_chunked(true)
-            .with_request_chunk(true)
-            .with_chunk_number(chunk_number);
+        if self.listen_only {
+            // The observed port partner requests the chunks itself.
+            return Ok(());
+        }
 
-        // Build message header - num_objects = 1 for the extended header word
-        let header = Header::new_extended(self.default_header, self.counters.tx_message, message_type, 1);
+        trace!("Transmit chunk request for {:?} chunk {}", message_type, chunk_number);
 
-        // Build message bytes manually
         let mut buffer = Self::get_message_buffer();
-        let mut offset = header.to_bytes(&mut buffer);
-        offset += ext_header.to_bytes(&mut buffer[offset..]);
-        // Pad to 4-byte Data Object boundary per USB PD spec.
-        // Extended header is 2 bytes, so add 2 bytes padding to complete the Data Object.
-        // Buffer is already zeroed, so just advance offset.
-        offset += 2;
+        let offset = message::extended::chunked::chunk_request_to_bytes(
+            *self.core.header(),
+            self.core.tx_message(),
+            message_type,
+            chunk_number,
+            &mut buffer,
+        );
 
         // Transmit and wait for GoodCRC
         if DRIVER::HAS_AUTO_RETRY {
             match self.driver.transmit(&buffer[..offset]).await {
                 Ok(()) => {
-                    self.counters.retry.reset();
-                    _ = self.counters.tx_message.increment();
+                    self.core.acknowledge();
                     Ok(())
                 }
                 Err(DriverTxError::HardReset) => Err(RxError::HardReset),
+                Err(DriverTxError::Detached) => Err(RxError::Detached),
+                Err(DriverTxError::Fault(fault)) => Err(RxError::DriverFault(fault)),
                 Err(DriverTxError::Discarded) => Err(RxError::ReceiveTimeout),
             }
         } else {
             match self.transmit_inner(&buffer[..offset]).await {
                 Ok(_) => self.wait_for_good_crc().await,
                 Err(TxError::HardReset) => Err(RxError::HardReset),
-                Err(TxError::UnchunkedExtendedMessagesNotSupported | TxError::AvsVoltageAlignmentInvalid) => {
-                    unreachable!("validation should happen before transmit_inner")
+                Err(TxError::Detached) => Err(RxError::Detached),
+                Err(TxError::DiscardStorm(_)) => Err(RxError::ReceiveTimeout),
+                Err(TxError::DriverFault(fault)) => Err(RxError::DriverFault(fault)),
+                Err(
+                    TxError::UnchunkedExtendedMessagesNotSupported
+                    | TxError::AvsVoltageAlignmentInvalid
+                    | TxError::AmsTokenRequired
+                    | TxError::TooManyDataObjects,
+                ) => {
+                    // Validation happens before `transmit_inner`.
+                    unreachable_or_return!(RxError::UnsupportedMessage)
                 }
             }
         }
@@ -772,8 +1421,8 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
     ) -> Result<(), ProtocolError> {
         let num_objects = capabilities.num_objects();
         let header = Header::new_data(
-            self.default_header,
-            self.counters.tx_message,
+            *self.core.header(),
+            self.core.tx_message(),
             DataMessageType::SinkCapabilities,
             num_objects,
         );
@@ -782,6 +1431,23 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
             .await
     }
 
+    /// Transmit source capabilities in response to Get_Source_Cap, e.g. as a dual-role power port.
+    pub async fn transmit_source_capabilities(
+        &mut self,
+        capabilities: message::data::source_capabilities::SourceCapabilities,
+    ) -> Result<(), ProtocolError> {
+        let num_objects = capabilities.num_objects();
+        let header = Header::new_data(
+            *self.core.header(),
+            self.core.tx_message(),
+            DataMessageType::SourceCapabilities,
+            num_objects,
+        );
+
+        self.transmit(Message::new_with_data(header, Data::SourceCapabilities(capabilities)))
+            .await
+    }
+
     /// Transmit EPR sink capabilities in response to EPR_Get_Sink_Cap.
     ///
     /// Per USB PD Spec R3.2 Section 8.3.3.3.10, sinks respond to EPR_Get_Sink_Cap
@@ -794,11 +1460,12 @@ impl<DRIVER: Driver, TIMER: Timer> ProtocolLayer<DRIVER, TIMER> {
         let pdos: heapless::Vec<_, 7> = capabilities.0.iter().cloned().collect();
         let extended_payload = message::extended::Extended::EprSinkCapabilities(pdos);
 
-        let header = Header::new_extended(
-            self.default_header,
-            self.counters.tx_message,
+        // The message is sent as a single chunk, see `Message::to_bytes`.
+        let header = Header::new_extended_for_payload(
+            *self.core.header(),
+            self.core.tx_message(),
             ExtendedMessageType::EprSinkCapabilities,
-            0, // num_objects is Reserved (0) for unchunked extended messages per spec 6.2.1.1.2
+            extended_payload.data_size().into(),
         );
 
         let mut message = Message::new(header);
@@ -813,10 +1480,12 @@ mod tests {
 
     use core::iter::zip;
 
-    use super::ProtocolLayer;
+    use super::message::Message;
     use super::message::data::Data;
     use super::message::data::source_capabilities::SourceCapabilities;
-    use super::message::header::Header;
+    use super::message::header::{ControlMessageType, Header, MessageType};
+    use super::stats::{GoodCrcConfig, LatencyBudget};
+    use super::{ProtocolError, ProtocolLayer, TxError};
     use crate::dummy::{
         DUMMY_CAPABILITIES, DummyDriver, DummyTimer, MAX_DATA_MESSAGE_SIZE, get_dummy_source_capabilities,
     };
@@ -848,4 +1517,580 @@ mod tests {
             panic!()
         }
     }
+
+    #[tokio::test]
+    async fn test_good_crc_priority() {
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer.set_good_crc_config(GoodCrcConfig {
+            budget: Some(LatencyBudget::T_TRANSMIT),
+            priority: true,
+        });
+
+        protocol_layer.driver.inject_received_data(&DUMMY_CAPABILITIES);
+        protocol_layer.receive_message().await.unwrap();
+
+        let good_crc = Message::from_bytes(&protocol_layer.driver.probe_transmitted_data()).unwrap();
+        assert!(matches!(
+            good_crc.header.message_type(),
+            MessageType::Control(ControlMessageType::GoodCRC)
+        ));
+        assert!(!protocol_layer.driver.has_transmitted_data());
+
+        let stats = protocol_layer.stats();
+        assert_eq!(stats.good_crc_transmitted, 1);
+        assert_eq!(stats.good_crc_budget_violations, 0);
+    }
+
+    #[tokio::test]
+    async fn test_listen_only() {
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer.set_listen_only(true);
+
+        // A repeated message is not discarded as retransmission.
+        protocol_layer.driver.inject_received_data(&DUMMY_CAPABILITIES);
+        protocol_layer.driver.inject_received_data(&DUMMY_CAPABILITIES);
+        protocol_layer.receive_message().await.unwrap();
+        protocol_layer.receive_message().await.unwrap();
+
+        assert!(!protocol_layer.driver.has_transmitted_data());
+        assert_eq!(protocol_layer.stats().good_crc_transmitted, 0);
+
+        // Transmissions do not wait for GoodCRC.
+        protocol_layer
+            .transmit_control_message(ControlMessageType::GetSourceCap)
+            .await
+            .unwrap();
+        let message = Message::from_bytes(&protocol_layer.driver.probe_transmitted_data()).unwrap();
+        assert_eq!(
+            message.header.message_type(),
+            MessageType::Control(ControlMessageType::GetSourceCap)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_soft_reset() {
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        let mut protocol_layer = get_protocol_layer();
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+
+        for (message_type, message_id) in [(ControlMessageType::GoodCRC, 0), (ControlMessageType::Accept, 0)] {
+            let message = Message::new(Header::new_control(template, MessageId::new(message_id), message_type));
+            let mut buffer = [0u8; 2];
+            message.to_bytes(&mut buffer);
+            protocol_layer.driver.inject_received_data(&buffer);
+        }
+
+        protocol_layer.soft_reset().await.unwrap();
+
+        let soft_reset = Message::from_bytes(&protocol_layer.driver.probe_transmitted_data()).unwrap();
+        assert_eq!(
+            soft_reset.header.message_type(),
+            MessageType::Control(ControlMessageType::SoftReset)
+        );
+        assert_eq!(soft_reset.header.message_id(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_message_while_waiting_for_good_crc() {
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        let mut protocol_layer = get_protocol_layer();
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+
+        // The source interleaves Get_Sink_Cap, before acknowledging the transmission.
+        for message_type in [ControlMessageType::GetSinkCap, ControlMessageType::GoodCRC] {
+            let message = Message::new(Header::new_control(template, MessageId::new(0), message_type));
+            let mut buffer = [0u8; 2];
+            message.to_bytes(&mut buffer);
+            protocol_layer.driver.inject_received_data(&buffer);
+        }
+
+        protocol_layer
+            .transmit_control_message(ControlMessageType::GetSourceCap)
+            .await
+            .unwrap();
+
+        let message = protocol_layer.receive_message().await.unwrap();
+        assert_eq!(
+            message.header.message_type(),
+            MessageType::Control(ControlMessageType::GetSinkCap)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repeated_chunks() {
+        use super::message::extended::ExtendedHeader;
+        use super::message::header::ExtendedMessageType;
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        const CHUNK_SIZE: usize = 26;
+
+        let mut protocol_layer = get_protocol_layer();
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+
+        let payload: heapless::Vec<u8, 60> = (0..60).collect();
+        let chunk = |message_id: u8, chunk_number: u8| {
+            let mut buffer = [0u8; MAX_DATA_MESSAGE_SIZE];
+            let header = Header::new_extended(
+                template,
+                MessageId::new(message_id),
+                ExtendedMessageType::ManufacturerInfo,
+                0,
+            );
+            let mut size = header.to_bytes(&mut buffer);
+            size += ExtendedHeader::new(payload.len() as u16)
+                .with_chunked(true)
+                .with_chunk_number(chunk_number)
+                .to_bytes(&mut buffer[size..]);
+
+            let data = payload.chunks(CHUNK_SIZE).nth(chunk_number as usize).unwrap();
+            buffer[size..size + data.len()].copy_from_slice(data);
+            heapless::Vec::<u8, MAX_DATA_MESSAGE_SIZE>::from_slice(&buffer[..size + data.len()]).unwrap()
+        };
+        let good_crc = |message_id: u8| {
+            let mut buffer = [0u8; 2];
+            Message::new(Header::new_control(
+                template,
+                MessageId::new(message_id),
+                ControlMessageType::GoodCRC,
+            ))
+            .to_bytes(&mut buffer);
+            buffer
+        };
+
+        protocol_layer.driver.inject_received_data(&chunk(0, 0));
+        protocol_layer.driver.inject_received_data(&good_crc(0));
+        protocol_layer.driver.inject_received_data(&chunk(1, 1));
+        protocol_layer.driver.inject_received_data(&good_crc(1));
+        // A duplicated frame, and a chunk that the source repeats with a new message ID.
+        protocol_layer.driver.inject_received_data(&chunk(1, 1));
+        protocol_layer.driver.inject_received_data(&chunk(2, 1));
+        protocol_layer.driver.inject_received_data(&good_crc(2));
+        protocol_layer.driver.inject_received_data(&chunk(3, 2));
+
+        let message = protocol_layer.receive_message().await.unwrap();
+        assert_eq!(
+            message.header.message_type(),
+            MessageType::Extended(ExtendedMessageType::ManufacturerInfo)
+        );
+        assert_eq!(protocol_layer.extended_rx_buffer, payload);
+
+        let mut requested_chunks: heapless::Vec<u8, 4> = heapless::Vec::new();
+        while protocol_layer.driver.has_transmitted_data() {
+            let frame = protocol_layer.driver.probe_transmitted_data();
+            if let Ok((_, extended_header, _)) = Message::parse_extended_chunk(&frame) {
+                requested_chunks.push(extended_header.chunk_number()).unwrap();
+            }
+        }
+        assert_eq!(requested_chunks, [1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_receive_message_matching() {
+        use super::ProtocolError;
+        use crate::counters::MessageId;
+        use crate::timers::TimerType;
+        use crate::{DataRole, PowerRole};
+
+        let mut protocol_layer = get_protocol_layer();
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+
+        for (message_type, message_id) in [(ControlMessageType::Wait, 0), (ControlMessageType::Reject, 1)] {
+            let message = Message::new(Header::new_control(template, MessageId::new(message_id), message_type));
+            let mut buffer = [0u8; 2];
+            message.to_bytes(&mut buffer);
+            protocol_layer.driver.inject_received_data(&buffer);
+        }
+
+        let is_reject = |message: Message| {
+            (message.header.message_type() == MessageType::Control(ControlMessageType::Reject))
+                .then_some(message.header.message_id())
+        };
+
+        assert!(matches!(
+            protocol_layer
+                .receive_message_matching(is_reject, TimerType::SenderResponse)
+                .await,
+            Err(ProtocolError::UnexpectedMessage)
+        ));
+        assert!(matches!(
+            protocol_layer
+                .receive_message_matching(is_reject, TimerType::SenderResponse)
+                .await,
+            Ok(1)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refused_ams() {
+        use super::RefusedAms;
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        let mut protocol_layer = get_protocol_layer();
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+
+        // Get_Sink_Cap, GoodCrc for Wait, and the retried Get_Sink_Cap.
+        for (message_type, message_id) in [
+            (ControlMessageType::GetSinkCap, 0),
+            (ControlMessageType::GoodCRC, 0),
+            (ControlMessageType::GetSinkCap, 1),
+        ] {
+            let message = Message::new(Header::new_control(template, MessageId::new(message_id), message_type));
+            let mut buffer = [0u8; 2];
+            message.to_bytes(&mut buffer);
+            protocol_layer.driver.inject_received_data(&buffer);
+        }
+
+        protocol_layer.receive_message().await.unwrap();
+        protocol_layer.transmit_wait().await.unwrap();
+        protocol_layer.receive_message().await.unwrap();
+
+        assert_eq!(
+            protocol_layer.refused_ams(),
+            Some(&RefusedAms {
+                request: MessageType::Control(ControlMessageType::GetSinkCap),
+                response: ControlMessageType::Wait,
+                retries: 1,
+            })
+        );
+
+        protocol_layer.reset();
+        assert!(protocol_layer.refused_ams().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_pps_status() {
+        use super::message::extended::Extended;
+        use super::message::extended::pps_status::{PPS_STATUS_DATA_BLOCK_SIZE, PpsStatusExtended};
+        use super::message::header::ExtendedMessageType;
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        let mut protocol_layer = get_protocol_layer();
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+        let status = PpsStatusExtended {
+            raw_output_voltage: 451,
+            raw_output_current: 59,
+            ..Default::default()
+        };
+
+        let mut buffer = [0u8; 16];
+        for (message_id, payload) in [(0, Some(status)), (1, None)] {
+            let good_crc = Message::new(Header::new_control(
+                template,
+                MessageId::new(message_id),
+                ControlMessageType::GoodCRC,
+            ));
+            let len = good_crc.to_bytes(&mut buffer);
+            protocol_layer.driver.inject_received_data(&buffer[..len]);
+
+            let message = match payload {
+                Some(status) => {
+                    let mut message = Message::new(Header::new_extended_for_payload(
+                        template,
+                        MessageId::new(message_id),
+                        ExtendedMessageType::PpsStatus,
+                        PPS_STATUS_DATA_BLOCK_SIZE,
+                    ));
+                    message.payload = Some(Payload::Extended(Extended::PpsStatus(status)));
+                    message
+                }
+                None => Message::new(Header::new_control(
+                    template,
+                    MessageId::new(message_id),
+                    ControlMessageType::NotSupported,
+                )),
+            };
+            let len = message.to_bytes(&mut buffer);
+            protocol_layer.driver.inject_received_data(&buffer[..len]);
+
+            assert_eq!(protocol_layer.get_pps_status().await.unwrap(), payload);
+
+            let request = Message::from_bytes(&protocol_layer.driver.probe_transmitted_data()).unwrap();
+            assert_eq!(
+                request.header.message_type(),
+                MessageType::Control(ControlMessageType::GetPpsStatus)
+            );
+
+            // The GoodCrc for the response.
+            protocol_layer.driver.probe_transmitted_data();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backoff() {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        use super::backoff::Backoff;
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        /// A backoff that records the number of discards it was consulted with.
+        #[derive(Debug)]
+        struct RecordingBackoff {
+            last_discards: AtomicU32,
+        }
+
+        impl Backoff for RecordingBackoff {
+            fn delay_micros(&self, discards: u32, _entropy: u64) -> u64 {
+                self.last_discards.store(discards, Ordering::Relaxed);
+                0
+            }
+        }
+
+        static BACKOFF: RecordingBackoff = RecordingBackoff {
+            last_discards: AtomicU32::new(0),
+        };
+
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer.set_backoff(Some(&BACKOFF));
+        protocol_layer.driver.discard_transmissions(3);
+
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+        let mut buffer = [0u8; 2];
+        Message::new(Header::new_control(
+            template,
+            MessageId::new(0),
+            ControlMessageType::GoodCRC,
+        ))
+        .to_bytes(&mut buffer);
+        protocol_layer.driver.inject_received_data(&buffer);
+
+        protocol_layer
+            .transmit_control_message(ControlMessageType::GetSourceCap)
+            .await
+            .unwrap();
+        assert_eq!(BACKOFF.last_discards.load(Ordering::Relaxed), 3);
+        assert!(protocol_layer.driver.has_transmitted_data());
+    }
+
+    #[tokio::test]
+    async fn test_discard_storm() {
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer
+            .driver
+            .discard_transmissions(super::DISCARD_STORM_THRESHOLD);
+
+        assert!(matches!(
+            protocol_layer
+                .transmit_control_message(ControlMessageType::GetSourceCap)
+                .await,
+            Err(ProtocolError::TxError(TxError::DiscardStorm(
+                super::DISCARD_STORM_THRESHOLD
+            )))
+        ));
+        assert!(!protocol_layer.driver.has_transmitted_data());
+        assert_eq!(
+            protocol_layer.stats().transmissions_discarded,
+            super::DISCARD_STORM_THRESHOLD
+        );
+    }
+
+    #[tokio::test]
+    async fn test_good_crc_discard_storm() {
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer.driver.inject_received_data(&DUMMY_CAPABILITIES);
+        protocol_layer
+            .driver
+            .discard_transmissions(super::DISCARD_STORM_THRESHOLD);
+
+        // The storm is reported as such, not as an unsupported message.
+        assert!(matches!(
+            protocol_layer.receive_message().await,
+            Err(ProtocolError::TxError(TxError::DiscardStorm(
+                super::DISCARD_STORM_THRESHOLD
+            )))
+        ));
+        assert!(!protocol_layer.driver.has_transmitted_data());
+    }
+
+    #[tokio::test]
+    async fn test_good_crc_driver_fault() {
+        use usbpd_traits::{DriverFault, DriverTxError};
+
+        use super::{ErrorOrigin, RxError};
+
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer.driver.inject_received_data(&DUMMY_CAPABILITIES);
+        protocol_layer
+            .driver
+            .fail_transmission(DriverTxError::Fault(DriverFault::Dma));
+
+        // A local fault while acknowledging a message is not blamed on the port partner.
+        let error = protocol_layer.receive_message().await.unwrap_err();
+        assert!(matches!(
+            error,
+            ProtocolError::RxError(RxError::DriverFault(DriverFault::Dma))
+        ));
+        assert_eq!(error.origin(), ErrorOrigin::Driver);
+    }
+
+    #[tokio::test]
+    async fn test_good_crc_detached() {
+        use usbpd_traits::DriverTxError;
+
+        use super::{ErrorOrigin, RxError};
+
+        let mut protocol_layer = get_protocol_layer();
+        protocol_layer.driver.inject_received_data(&DUMMY_CAPABILITIES);
+        protocol_layer.driver.fail_transmission(DriverTxError::Detached);
+
+        // A detach while acknowledging a message is a lost connection, not an unsupported message.
+        let error = protocol_layer.receive_message().await.unwrap_err();
+        assert!(matches!(error, ProtocolError::RxError(RxError::Detached)));
+        assert_eq!(error.origin(), ErrorOrigin::Connection);
+    }
+
+    #[test]
+    fn test_error_origin() {
+        use usbpd_traits::DriverFault;
+
+        use super::{ErrorOrigin, RxError};
+
+        let overrun: ProtocolError = RxError::DriverFault(DriverFault::Overrun).into();
+        assert_eq!(overrun.origin(), ErrorOrigin::Driver);
+        assert_eq!(overrun.driver_fault(), Some(DriverFault::Overrun));
+
+        let timeout: ProtocolError = RxError::ReceiveTimeout.into();
+        assert_eq!(timeout.origin(), ErrorOrigin::Partner);
+        assert_eq!(timeout.driver_fault(), None);
+
+        assert_eq!(ProtocolError::UnexpectedMessage.origin(), ErrorOrigin::Partner);
+        assert_eq!(ProtocolError::from(TxError::Detached).origin(), ErrorOrigin::Connection);
+        assert_eq!(
+            ProtocolError::from(TxError::AmsTokenRequired).origin(),
+            ErrorOrigin::Local
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receive_in_place() {
+        use usbpd_traits::{Driver, DriverRxError, DriverTxError, Frame};
+
+        use crate::counters::MessageId;
+        use crate::{DataRole, PowerRole};
+
+        /// A driver that receives into its own buffer, like one that is filled by DMA.
+        struct DmaDriver {
+            inner: DummyDriver<MAX_DATA_MESSAGE_SIZE>,
+            dma: [u8; MAX_DATA_MESSAGE_SIZE],
+        }
+
+        impl Driver for DmaDriver {
+            async fn receive(&mut self, _buffer: &mut [u8]) -> Result<usize, DriverRxError> {
+                unreachable!("frames are handed out in place")
+            }
+
+            async fn receive_with<R>(&mut self, handler: impl FnOnce(Frame<'_>) -> R) -> Result<R, DriverRxError> {
+                let length = self.inner.receive(&mut self.dma).await?;
+                Ok(handler(Frame::new(&self.dma[..length])))
+            }
+
+            async fn transmit(&mut self, data: &[u8]) -> Result<(), DriverTxError> {
+                self.inner.transmit(data).await
+            }
+
+            async fn transmit_hard_reset(&mut self) -> Result<(), DriverTxError> {
+                self.inner.transmit_hard_reset().await
+            }
+        }
+
+        let mut protocol_layer: ProtocolLayer<_, DummyTimer> = ProtocolLayer::new(
+            DmaDriver {
+                inner: DummyDriver::new(),
+                dma: [0; MAX_DATA_MESSAGE_SIZE],
+            },
+            Header::new_template(
+                DataRole::Ufp,
+                PowerRole::Sink,
+                super::message::header::SpecificationRevision::R3_X,
+            ),
+        );
+
+        protocol_layer.driver.inner.inject_received_data(&DUMMY_CAPABILITIES);
+        let message = protocol_layer.receive_message().await.unwrap();
+        assert!(matches!(
+            message.payload,
+            Some(Payload::Data(Data::SourceCapabilities(_)))
+        ));
+
+        let template = Header::new_template(
+            DataRole::Dfp,
+            PowerRole::Source,
+            super::message::header::SpecificationRevision::R3_X,
+        );
+        let mut buffer = [0u8; 2];
+        Message::new(Header::new_control(
+            template,
+            MessageId::new(0),
+            ControlMessageType::GoodCRC,
+        ))
+        .to_bytes(&mut buffer);
+        protocol_layer.driver.inner.inject_received_data(&buffer);
+
+        protocol_layer
+            .transmit_control_message(ControlMessageType::GetSinkCap)
+            .await
+            .unwrap();
+        assert_eq!(protocol_layer.stats().frames_received, 2);
+    }
+
+    #[test]
+    fn test_message_sizes() {
+        // 2 bytes header + 7 data objects * 4 bytes.
+        assert_eq!(super::MAX_DATA_MESSAGE_SIZE, 30);
+        // 2 bytes header + 2 bytes extended header + 260 data bytes.
+        assert_eq!(super::MAX_MESSAGE_SIZE, 264);
+    }
+
+    #[test]
+    fn test_error_chain() {
+        use core::error::Error;
+
+        use super::message::ParseError;
+
+        let error = crate::sink::policy_engine::Error::from(ProtocolError::from(ParseError::InvalidMessageType(0)));
+
+        assert_eq!(error.to_string(), "protocol error");
+        let protocol_error = error.source().unwrap();
+        assert_eq!(protocol_error.to_string(), "RX error");
+        let rx_error = protocol_error.source().unwrap();
+        assert_eq!(rx_error.to_string(), "parse error");
+        let parse_error = rx_error.source().unwrap();
+        assert_eq!(parse_error.to_string(), "unknown or reserved message type `0`");
+        assert!(parse_error.source().is_none());
+    }
 }
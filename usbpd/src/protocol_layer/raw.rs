@@ -0,0 +1,125 @@
+//! Transmission of crafted frames through a [`Driver`], independent of the policy engine.
+//!
+//! Intended for factory test firmware that exercises the PHY. Frames are passed to the driver as they are, without
+//! message ID counters, retries, or retransmission detection.
+use embassy_futures::select::{Either, select};
+use usbpd_traits::{Driver, DriverRxError, DriverTxError};
+
+use super::message::header::{ControlMessageType, Header, MessageType};
+use super::{MAX_MESSAGE_SIZE, ProtocolError, RxError, TxError};
+use crate::timers::{Timer, TimerType};
+
+/// Transmit a raw `frame`, which starts with the message header.
+///
+/// Transmissions that the driver discards are repeated. If `wait_for_good_crc` is set, wait up to tReceive for the
+/// GoodCrc of the port partner, ignoring any other frames, and return its header. The acknowledged message ID must
+/// match the one of the frame, if the frame has a header.
+pub async fn transmit_frame<DRIVER: Driver, TIMER: Timer>(
+    driver: &mut DRIVER,
+    frame: &[u8],
+    wait_for_good_crc: bool,
+) -> Result<Option<Header>, ProtocolError> {
+    loop {
+        match driver.transmit(frame).await {
+            Ok(()) => break,
+            Err(DriverTxError::Discarded) => (),
+            Err(DriverTxError::HardReset) => return Err(TxError::HardReset.into()),
+            Err(DriverTxError::Detached) => return Err(TxError::Detached.into()),
+            Err(DriverTxError::Fault(fault)) => return Err(TxError::DriverFault(fault).into()),
+        }
+    }
+
+    if !wait_for_good_crc {
+        return Ok(None);
+    }
+
+    let good_crc = match select(
+        TimerType::get_timer::<TIMER>(TimerType::CRCReceive),
+        receive_good_crc(driver),
+    )
+    .await
+    {
+        Either::First(_) => return Err(RxError::ReceiveTimeout.into()),
+        Either::Second(result) => result?,
+    };
+
+    if let Ok(header) = Header::from_bytes(frame)
+        && header.message_id() != good_crc.message_id()
+    {
+        return Err(RxError::AcknowledgeMismatch(good_crc.message_id()).into());
+    }
+
+    Ok(Some(good_crc))
+}
+
+/// Receive frames, until one with a GoodCrc header arrives.
+async fn receive_good_crc<DRIVER: Driver>(driver: &mut DRIVER) -> Result<Header, RxError> {
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+    loop {
+        let length = match driver.receive(&mut buffer).await {
+            Ok(length) => length,
+            Err(DriverRxError::Discarded) => continue,
+            Err(DriverRxError::HardReset) => return Err(RxError::HardReset),
+            Err(DriverRxError::Detached) => return Err(RxError::Detached),
+            Err(DriverRxError::Fault(fault)) => return Err(RxError::DriverFault(fault)),
+        };
+
+        if let Ok(header) = Header::from_bytes(&buffer[..length])
+            && header.message_type() == MessageType::Control(ControlMessageType::GoodCRC)
+        {
+            return Ok(header);
+        }
+
+        trace!("Ignore frame of {} bytes, while waiting for GoodCrc", length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transmit_frame;
+    use crate::counters::MessageId;
+    use crate::dummy::{DummyDriver, DummyTimer, MAX_DATA_MESSAGE_SIZE};
+    use crate::protocol_layer::message::Message;
+    use crate::protocol_layer::message::header::{ControlMessageType, Header, SpecificationRevision};
+    use crate::protocol_layer::{ProtocolError, RxError};
+    use crate::{DataRole, PowerRole};
+
+    fn control_frame(template: Header, message_type: ControlMessageType, message_id: u8) -> [u8; 2] {
+        let mut buffer = [0u8; 2];
+        Message::new(Header::new_control(template, MessageId::new(message_id), message_type)).to_bytes(&mut buffer);
+        buffer
+    }
+
+    #[tokio::test]
+    async fn test_transmit_frame() {
+        let sink = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
+        let source = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R3_X);
+        let mut driver = DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new();
+
+        // Without waiting, the frame is passed through as is.
+        let frame = control_frame(sink, ControlMessageType::GetSourceCap, 5);
+        assert!(
+            transmit_frame::<_, DummyTimer>(&mut driver, &frame, false)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(driver.probe_transmitted_data().as_slice(), &frame);
+
+        // Other frames are skipped, until the GoodCrc arrives.
+        driver.inject_received_data(&control_frame(source, ControlMessageType::Ping, 0));
+        driver.inject_received_data(&control_frame(source, ControlMessageType::GoodCRC, 5));
+        let good_crc = transmit_frame::<_, DummyTimer>(&mut driver, &frame, true)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(good_crc.message_id(), 5);
+
+        driver.inject_received_data(&control_frame(source, ControlMessageType::GoodCRC, 4));
+        assert!(matches!(
+            transmit_frame::<_, DummyTimer>(&mut driver, &frame, true).await,
+            Err(ProtocolError::RxError(RxError::AcknowledgeMismatch(4)))
+        ));
+    }
+}
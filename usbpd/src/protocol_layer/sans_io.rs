@@ -0,0 +1,402 @@
+//! The sans-io core of the protocol layer.
+//!
+//! Holds the protocol state that does not depend on I/O: message ID and retry counters, the header template,
+//! validation of outgoing messages, and retransmission decisions.
+//!
+//! The async [`ProtocolLayer`](super::ProtocolLayer) and the
+//! [`CallbackProtocolLayer`](super::callback::CallbackProtocolLayer) are front-ends on top of this core.
+use super::message::header::{ControlMessageType, Header, MessageType, SpecificationRevision};
+use super::message::{Message, ParseError, Payload};
+use super::stats::{LatencyBudget, Stats};
+use super::{ProtocolError, RxError, TxError};
+use crate::counters::{Counter, CounterType, Error as CounterError, MessageId, MessageIds};
+use crate::{DataRole, PowerRole};
+
+#[derive(Debug)]
+struct Counters {
+    _busy: Counter,
+    _discover_identity: Counter,
+    message_ids: MessageIds,
+    retry: Counter,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Counters {
+            _busy: Counter::new(CounterType::Busy),
+            _discover_identity: Counter::new(CounterType::DiscoverIdentity),
+            message_ids: MessageIds::new(),
+            retry: Counter::new(CounterType::Retry),
+        }
+    }
+}
+
+/// The I/O-free protocol state machine.
+#[derive(Debug)]
+pub(crate) struct ProtocolCore {
+    counters: Counters,
+    default_header: Header,
+    /// The highest specification revision to operate at, as given by the initial header template.
+    max_spec_revision: SpecificationRevision,
+    stats: Stats,
+}
+
+impl ProtocolCore {
+    /// Create a new protocol core from a default header.
+    pub fn new(default_header: Header) -> Self {
+        Self {
+            counters: Default::default(),
+            default_header,
+            max_spec_revision: default_header.spec_revision().unwrap_or(SpecificationRevision::R3_X),
+            stats: Default::default(),
+        }
+    }
+
+    /// Reset all counters, as required for a soft reset, or on attach.
+    pub fn reset(&mut self) {
+        self.counters = Default::default();
+    }
+
+    /// Reset the message IDs and the retry counter, as required for a hard reset.
+    ///
+    // See spec, [6.7.1]
+    pub fn hard_reset(&mut self) {
+        self.counters.message_ids.reset();
+        self.counters.retry.reset();
+    }
+
+    /// The header template for outgoing messages.
+    pub fn header(&self) -> &Header {
+        &self.default_header
+    }
+
+    /// The message ID counter for the next outgoing message.
+    pub fn tx_message(&self) -> MessageId {
+        self.counters.message_ids.tx()
+    }
+
+    /// The collected statistics.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Record a GoodCrc transmission, see [`Stats`].
+    pub fn record_good_crc(&mut self, latency_micros: Option<u64>, budget: Option<LatencyBudget>) {
+        self.stats.record_good_crc(latency_micros, budget);
+    }
+
+    /// Record the reception of a frame, see [`Stats`].
+    pub fn record_frame_received(&mut self) {
+        self.stats.record_frame_received();
+    }
+
+    /// Record a frame that the driver discarded, see [`Stats`].
+    pub fn record_frame_discarded(&mut self) {
+        self.stats.record_frame_discarded();
+    }
+
+    /// Record a transmission that the driver discarded, see [`Stats`].
+    pub fn record_transmission_discarded(&mut self) {
+        self.stats.record_transmission_discarded();
+    }
+
+    /// Record the time until an explicit contract was established, see [`Stats`].
+    pub fn record_time_to_contract(&mut self, micros: u64, budget_micros: Option<u64>) {
+        self.stats.record_time_to_contract(micros, budget_micros);
+    }
+
+    /// Update the specification revision, based on a received frame.
+    ///
+    /// The revision never exceeds the one of the initial header template.
+    pub fn update_spec_revision(&mut self, header: &Header) -> Result<(), ParseError> {
+        let spec_revision = header.spec_revision()?.min(self.max_spec_revision);
+        self.default_header = self.default_header.with_spec_revision(spec_revision);
+        Ok(())
+    }
+
+    /// Set the roles in the header template, e.g. after a power role swap.
+    pub fn set_roles(&mut self, data_role: DataRole, power_role: PowerRole) {
+        self.default_header = self
+            .default_header
+            .with_port_data_role(data_role)
+            .with_port_power_role(power_role);
+    }
+
+    /// Updates the received message counter.
+    ///
+    /// If receiving the first message after protocol layer reset, copy its ID.
+    /// Otherwise, compare the received ID with the stored ID. If they are equal, this is a retransmission.
+    /// A Soft_Reset message resets the message IDs, and is never a retransmission, see [`MessageIds::receive`].
+    ///
+    /// Returns `true`, if this was a retransmission.
+    pub fn update_rx_message_counter(&mut self, rx_message: &Message) -> bool {
+        let soft_reset = matches!(
+            rx_message.header.message_type(),
+            MessageType::Control(ControlMessageType::SoftReset)
+        );
+        if soft_reset {
+            self.counters.retry.reset();
+        }
+
+        let is_retransmission = self
+            .counters
+            .message_ids
+            .receive(rx_message.header.message_id(), soft_reset);
+        if is_retransmission {
+            trace!(
+                "Received retransmission of RX counter value: {}",
+                rx_message.header.message_id()
+            );
+        }
+
+        is_retransmission
+    }
+
+    /// Build the GoodCrc message for the last received message.
+    ///
+    /// Returns `None`, if no message was received since the last reset.
+    pub fn good_crc_message(&self) -> Option<Message> {
+        let rx_message = self.counters.message_ids.rx()?;
+        trace!("Transmit message GoodCrc for RX message count: {}", rx_message.value());
+
+        Some(Message::new(Header::new_control(
+            self.default_header,
+            rx_message,
+            ControlMessageType::GoodCRC,
+        )))
+    }
+
+    /// Prepare the transmission of a new message.
+    pub fn start_transmission(&mut self) {
+        self.counters.retry.reset();
+    }
+
+    /// The outgoing message was acknowledged by the port partner.
+    ///
+    // See spec, [6.7.1.1]
+    pub fn acknowledge(&mut self) {
+        self.counters.retry.reset();
+        self.counters.message_ids.acknowledge();
+    }
+
+    /// Evaluate a message that was received while waiting for GoodCrc.
+    ///
+    /// Acknowledges the outgoing message, if the message is a GoodCrc with the expected message ID.
+    pub fn handle_good_crc(&mut self, message: &Message) -> Result<(), RxError> {
+        if matches!(
+            message.header.message_type(),
+            MessageType::Control(ControlMessageType::GoodCRC)
+        ) {
+            trace!(
+                "Received GoodCrc, TX message count: {}, expected: {}",
+                message.header.message_id(),
+                self.counters.message_ids.tx().value()
+            );
+            if message.header.message_id() == self.counters.message_ids.tx().value() {
+                self.acknowledge();
+                Ok(())
+            } else {
+                Err(RxError::AcknowledgeMismatch(message.header.message_id()))
+            }
+        } else if matches!(message.header.message_type(), MessageType::Control(_)) {
+            Err(ParseError::InvalidControlMessageType(message.header.message_type_raw()).into())
+        } else {
+            Err(ParseError::InvalidMessageType(message.header.message_type_raw()).into())
+        }
+    }
+
+    /// Count a missing GoodCrc, deciding whether the message shall be retransmitted.
+    pub fn retry(&mut self) -> Result<(), ProtocolError> {
+        match self.counters.retry.increment() {
+            Ok(_) => Ok(()),
+            Err(CounterError::Exceeded) => Err(self.retries_exceeded()),
+        }
+    }
+
+    /// The error that is reported when all retries were used up.
+    pub fn retries_exceeded(&self) -> ProtocolError {
+        ProtocolError::TransmitRetriesExceeded(self.counters.retry.max_value())
+    }
+
+    /// Validate an outgoing message for spec compliance.
+    ///
+    /// This catches common mistakes when constructing messages:
+    /// - unchunked_extended_messages_supported should always be false
+    /// - AVS voltage LSB 2 bits should be zero (per USB PD 3.2 Table 6.26)
+    ///
+    /// Only validates outgoing messages - never called when parsing received data.
+    /// Returns an error if validation fails, allowing the caller to handle it appropriately.
+    pub fn validate_outgoing_message(message: &Message) -> Result<(), TxError> {
+        if let Some(Payload::Data(super::message::data::Data::Request(power_source))) = &message.payload {
+            use super::message::data::request::PowerSource;
+            match power_source {
+                PowerSource::FixedVariableSupply(rdo) => {
+                    if rdo.unchunked_extended_messages_supported() {
+                        return Err(TxError::UnchunkedExtendedMessagesNotSupported);
+                    }
+                }
+                PowerSource::Pps(rdo) => {
+                    if rdo.unchunked_extended_messages_supported() {
+                        return Err(TxError::UnchunkedExtendedMessagesNotSupported);
+                    }
+                }
+                PowerSource::EprRequest(epr) => {
+                    // Check the raw RDO for validation
+                    let rdo_bits = epr.rdo;
+                    let unchunked = (rdo_bits >> 23) & 1 == 1;
+                    if unchunked {
+                        return Err(TxError::UnchunkedExtendedMessagesNotSupported);
+                    }
+
+                    // Check if this looks like an AVS request (bits 30-31 = 00, bits 28-29 = 11)
+                    let is_avs = ((rdo_bits >> 30) & 0x3 == 0) && ((rdo_bits >> 28) & 0x3 == 3);
+                    if is_avs {
+                        let voltage = (rdo_bits >> 9) & 0xFFF;
+                        if (voltage as u16) & 0x3 != 0 {
+                            return Err(TxError::AvsVoltageAlignmentInvalid);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtocolCore;
+    use crate::counters::{Counter, CounterType, MessageId};
+    use crate::protocol_layer::message::Message;
+    use crate::protocol_layer::message::header::{ControlMessageType, Header, MessageType, SpecificationRevision};
+    use crate::protocol_layer::{ProtocolError, RxError};
+    use crate::{DataRole, PowerRole};
+
+    fn get_core() -> ProtocolCore {
+        ProtocolCore::new(Header::new_template(
+            DataRole::Ufp,
+            PowerRole::Sink,
+            SpecificationRevision::R3_X,
+        ))
+    }
+
+    fn source_message(message_type: ControlMessageType, message_id: u8) -> Message {
+        let template = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R2_0);
+        Message::new(Header::new_control(template, MessageId::new(message_id), message_type))
+    }
+
+    #[test]
+    fn test_retransmission_detection() {
+        let mut core = get_core();
+        assert!(core.good_crc_message().is_none());
+
+        let message = source_message(ControlMessageType::Accept, 5);
+        assert!(!core.update_rx_message_counter(&message));
+        assert!(core.update_rx_message_counter(&message));
+
+        let good_crc = core.good_crc_message().unwrap();
+        assert_eq!(
+            good_crc.header.message_type(),
+            MessageType::Control(ControlMessageType::GoodCRC)
+        );
+        assert_eq!(good_crc.header.message_id(), 5);
+
+        assert!(!core.update_rx_message_counter(&source_message(ControlMessageType::Accept, 6)));
+
+        core.reset();
+        assert!(!core.update_rx_message_counter(&source_message(ControlMessageType::Accept, 6)));
+    }
+
+    #[test]
+    fn test_good_crc_handling() {
+        let mut core = get_core();
+
+        assert!(matches!(
+            core.handle_good_crc(&source_message(ControlMessageType::GoodCRC, 1)),
+            Err(RxError::AcknowledgeMismatch(1))
+        ));
+        assert!(matches!(
+            core.handle_good_crc(&source_message(ControlMessageType::Accept, 0)),
+            Err(RxError::ParseError(_))
+        ));
+        assert_eq!(core.tx_message().value(), 0);
+
+        core.handle_good_crc(&source_message(ControlMessageType::GoodCRC, 0))
+            .unwrap();
+        assert_eq!(core.tx_message().value(), 1);
+
+        core.hard_reset();
+        assert_eq!(core.tx_message().value(), 0);
+        assert!(core.good_crc_message().is_none());
+    }
+
+    #[test]
+    fn test_soft_reset_is_never_a_retransmission() {
+        let mut core = get_core();
+
+        assert!(!core.update_rx_message_counter(&source_message(ControlMessageType::Accept, 0)));
+        core.acknowledge();
+
+        // The Soft_Reset carries ID zero, like the previous message, and must still be processed.
+        assert!(!core.update_rx_message_counter(&source_message(ControlMessageType::SoftReset, 0)));
+        assert_eq!(core.tx_message().value(), 0);
+        assert_eq!(core.good_crc_message().unwrap().header.message_id(), 0);
+
+        // The message that follows the Soft_Reset AMS is new as well.
+        assert!(!core.update_rx_message_counter(&source_message(ControlMessageType::Accept, 1)));
+    }
+
+    #[test]
+    fn test_message_id_wrap() {
+        let mut core = get_core();
+
+        // A long exchange, in which both message IDs wrap several times.
+        for index in 0..=(4 * MessageId::MAX) {
+            let message_id = index % (MessageId::MAX + 1);
+
+            let message = source_message(ControlMessageType::Accept, message_id);
+            assert!(!core.update_rx_message_counter(&message));
+            assert!(core.update_rx_message_counter(&message));
+
+            assert_eq!(core.tx_message().value(), message_id);
+            core.handle_good_crc(&source_message(ControlMessageType::GoodCRC, message_id))
+                .unwrap();
+        }
+
+        assert_eq!(core.tx_message().value(), 5);
+    }
+
+    #[test]
+    fn test_retries() {
+        let mut core = get_core();
+        core.start_transmission();
+
+        let max_retries = Counter::new(CounterType::Retry).max_value();
+        for _ in 0..max_retries {
+            core.retry().unwrap();
+        }
+
+        assert!(matches!(core.retry(), Err(ProtocolError::TransmitRetriesExceeded(_))));
+    }
+
+    #[test]
+    fn test_spec_revision_update() {
+        let mut core = get_core();
+        core.update_spec_revision(&source_message(ControlMessageType::Accept, 0).header)
+            .unwrap();
+        assert!(matches!(core.header().spec_revision(), Ok(SpecificationRevision::R2_0)));
+    }
+
+    #[test]
+    fn test_spec_revision_cap() {
+        let mut core = ProtocolCore::new(Header::new_template(
+            DataRole::Ufp,
+            PowerRole::Sink,
+            SpecificationRevision::R2_0,
+        ));
+        let header = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R3_X);
+        core.update_spec_revision(&header).unwrap();
+        assert!(matches!(core.header().spec_revision(), Ok(SpecificationRevision::R2_0)));
+    }
+}
@@ -0,0 +1,208 @@
+//! Statistics of the protocol layer.
+//!
+//! Integrators can inspect these to validate timing on their hardware, for example the GoodCRC response latency.
+//! Latencies are only measured, if the [`Timer`](crate::timers::Timer) implementation provides timestamps.
+
+/// The maximum time from reception of a message until the start of the GoodCRC response (tTransmit, in µs).
+///
+/// See spec, [6.6.1]
+pub const T_TRANSMIT_MICROS: u64 = 195;
+
+/// The minimum time that a port partner waits for GoodCRC, before retrying (tReceive, in µs).
+///
+/// See spec, [6.6.1]
+pub const T_RECEIVE_MIN_MICROS: u64 = 900;
+
+/// The minimum time between the end of a received frame, and the start of the next transmission (tInterFrameGap, in µs).
+///
+/// See spec, [6.6.1]
+pub const T_INTER_FRAME_GAP_MICROS: u64 = 25;
+
+/// The maximum time that a sink waits for source capabilities after attach (tTypeCSinkWaitCap, in µs).
+///
+/// A basis for time to contract budgets, see
+/// [`SinkConfig::with_time_to_contract_budget`](crate::sink::config::SinkConfig::with_time_to_contract_budget).
+///
+/// See spec, [6.6.4.1]
+pub const T_TYPEC_SINK_WAIT_CAP_MAX_MICROS: u64 = 620_000;
+
+/// A budget for the latency between frame reception and GoodCRC transmission.
+///
+/// The latency is measured from the driver returning a received frame, until the GoodCRC frame is handed to the
+/// driver for transmission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LatencyBudget {
+    micros: u64,
+    strict: bool,
+}
+
+impl LatencyBudget {
+    /// The budget that the specification demands (tTransmit).
+    pub const T_TRANSMIT: Self = Self::new(T_TRANSMIT_MICROS);
+
+    /// Create a new budget in microseconds.
+    ///
+    /// Panics, if the budget is zero, or not shorter than tReceive, after which the port partner retries anyway.
+    /// When used in a `const` context, this check happens at compile time. With the `panic-free` feature, the budget
+    /// is clamped to the valid range instead.
+    pub const fn new(micros: u64) -> Self {
+        #[cfg(not(feature = "panic-free"))]
+        {
+            core::assert!(micros > 0, "GoodCRC latency budget must not be zero");
+            core::assert!(
+                micros < T_RECEIVE_MIN_MICROS,
+                "GoodCRC latency budget must be shorter than tReceive"
+            );
+        }
+
+        #[cfg(feature = "panic-free")]
+        let micros = if micros == 0 {
+            1
+        } else if micros >= T_RECEIVE_MIN_MICROS {
+            T_RECEIVE_MIN_MICROS - 1
+        } else {
+            micros
+        };
+
+        Self { micros, strict: false }
+    }
+
+    /// Panic on a budget violation, instead of only counting it.
+    ///
+    /// Useful for bring-up and test builds. Has no effect with the `panic-free` feature.
+    pub const fn strict(self) -> Self {
+        Self { strict: true, ..self }
+    }
+
+    /// The budget in microseconds.
+    pub const fn micros(&self) -> u64 {
+        self.micros
+    }
+
+    /// Whether a budget violation panics.
+    pub const fn is_strict(&self) -> bool {
+        self.strict
+    }
+}
+
+/// Configuration of GoodCRC responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GoodCrcConfig {
+    /// The latency budget to check measured latencies against.
+    pub budget: Option<LatencyBudget>,
+    /// Send GoodCRC directly after reading the message header, ahead of payload parsing and pending transmissions.
+    pub priority: bool,
+}
+
+/// Statistics, as collected by the protocol layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stats {
+    /// The number of transmitted GoodCRC messages.
+    pub good_crc_transmitted: u32,
+    /// The last measured GoodCRC latency in µs.
+    pub good_crc_latency_last_micros: Option<u64>,
+    /// The maximum measured GoodCRC latency in µs.
+    pub good_crc_latency_max_micros: Option<u64>,
+    /// The number of GoodCRC transmissions that exceeded the latency budget.
+    pub good_crc_budget_violations: u32,
+    /// The number of frames that were received from the driver, including GoodCRC and retransmissions.
+    pub frames_received: u32,
+    /// The number of frames that the driver discarded, e.g. due to CRC errors.
+    pub frames_discarded: u32,
+    /// The number of transmissions that the driver discarded, e.g. due to bus contention.
+    pub transmissions_discarded: u32,
+    /// The last measured time in µs from attach (or hard reset) until an explicit contract was established.
+    pub time_to_contract_micros: Option<u64>,
+    /// The number of contracts that took longer than the time to contract budget.
+    pub time_to_contract_budget_violations: u32,
+}
+
+impl Stats {
+    /// Record the reception of a frame.
+    pub(crate) fn record_frame_received(&mut self) {
+        self.frames_received = self.frames_received.wrapping_add(1);
+    }
+
+    /// Record a frame that the driver discarded.
+    pub(crate) fn record_frame_discarded(&mut self) {
+        self.frames_discarded = self.frames_discarded.wrapping_add(1);
+    }
+
+    /// Record a transmission that the driver discarded.
+    pub(crate) fn record_transmission_discarded(&mut self) {
+        self.transmissions_discarded = self.transmissions_discarded.wrapping_add(1);
+    }
+
+    /// Record the time until an explicit contract was established.
+    pub(crate) fn record_time_to_contract(&mut self, micros: u64, budget_micros: Option<u64>) {
+        self.time_to_contract_micros = Some(micros);
+
+        if let Some(budget_micros) = budget_micros
+            && micros > budget_micros
+        {
+            self.time_to_contract_budget_violations = self.time_to_contract_budget_violations.wrapping_add(1);
+            warn!("Time to contract {} us exceeds budget of {} us", micros, budget_micros);
+        }
+    }
+
+    /// Record a GoodCRC transmission, with its latency, if it was measured.
+    pub(crate) fn record_good_crc(&mut self, latency_micros: Option<u64>, budget: Option<LatencyBudget>) {
+        self.good_crc_transmitted = self.good_crc_transmitted.wrapping_add(1);
+
+        let Some(latency) = latency_micros else {
+            return;
+        };
+
+        self.good_crc_latency_last_micros = Some(latency);
+        self.good_crc_latency_max_micros = Some(self.good_crc_latency_max_micros.map_or(latency, |m| m.max(latency)));
+
+        if let Some(budget) = budget
+            && latency > budget.micros()
+        {
+            self.good_crc_budget_violations = self.good_crc_budget_violations.wrapping_add(1);
+            warn!(
+                "GoodCRC latency {} us exceeds budget of {} us",
+                latency,
+                budget.micros()
+            );
+
+            #[cfg(not(feature = "panic-free"))]
+            if budget.is_strict() {
+                panic!("GoodCRC latency budget exceeded");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LatencyBudget, Stats};
+
+    #[test]
+    fn test_record_good_crc() {
+        const BUDGET: LatencyBudget = LatencyBudget::new(100);
+        let mut stats = Stats::default();
+
+        stats.record_good_crc(None, Some(BUDGET));
+        assert_eq!(stats.good_crc_transmitted, 1);
+        assert_eq!(stats.good_crc_latency_max_micros, None);
+
+        stats.record_good_crc(Some(150), Some(BUDGET));
+        stats.record_good_crc(Some(50), Some(BUDGET));
+        assert_eq!(stats.good_crc_transmitted, 3);
+        assert_eq!(stats.good_crc_latency_last_micros, Some(50));
+        assert_eq!(stats.good_crc_latency_max_micros, Some(150));
+        assert_eq!(stats.good_crc_budget_violations, 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "panic-free"))]
+    #[should_panic]
+    fn test_strict_budget() {
+        let mut stats = Stats::default();
+        stats.record_good_crc(Some(200), Some(LatencyBudget::T_TRANSMIT.strict()));
+    }
+}
@@ -0,0 +1,113 @@
+//! A queue of messages, that auxiliary tasks submit for transmission by a policy engine.
+//!
+//! The policy engine owns the protocol layer, which assigns message IDs in the order of transmission. Auxiliary tasks,
+//! such as a VDM responder, do not transmit themselves. They submit messages to a shared [`TxQueue`], which the
+//! policy engine drains in its ready state, one message per step. Thereby, queued messages never interleave with an
+//! AMS, and message IDs stay in order.
+//!
+//! The queue needs no lock, and can be shared as a `static`:
+//!
+//! ```
+//! use usbpd::protocol_layer::message::data::vendor_defined::{VdmHeader, VdmHeaderUnstructured};
+//! use usbpd::protocol_layer::tx_queue::{Outbound, TxQueue};
+//! use usbpd::sink::config::SinkConfig;
+//!
+//! static TX_QUEUE: TxQueue = TxQueue::new();
+//!
+//! const CONFIG: SinkConfig = SinkConfig::new().with_tx_queue(Some(&TX_QUEUE));
+//!
+//! // In an auxiliary task.
+//! let header = VdmHeader::Unstructured(VdmHeaderUnstructured(0).with_standard_or_vid(0x1234));
+//! TX_QUEUE.submit(Outbound::Vdm(header, heapless::Vec::new())).unwrap();
+//! ```
+//!
+//! The policy engine only looks at the queue, when it enters the ready state. To have a message transmitted
+//! promptly, wake the policy engine after submitting it, by returning
+//! [`Event::None`](crate::sink::device_policy_manager::Event::None) from the device policy manager.
+use core::fmt::{Debug, Formatter};
+
+use heapless::mpmc;
+
+use super::message::data::vendor_defined::VdmHeader;
+use super::message::extended::extended_control::ExtendedControlMessageType;
+
+/// The number of messages that fit into a [`TxQueue`].
+pub const TX_QUEUE_SIZE: usize = 4;
+
+/// A message to transmit, that expects no response.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Outbound {
+    /// A vendor defined message (VDM) with up to six VDOs, e.g. an Attention, or an unstructured VDM.
+    ///
+    /// The version of structured VDMs is set to the version that was negotiated with the port partner.
+    Vdm(VdmHeader, heapless::Vec<u32, 6>),
+    /// An extended control message.
+    ExtendedControl(ExtendedControlMessageType),
+}
+
+/// A queue of messages, that are transmitted in the order of submission.
+pub struct TxQueue {
+    queue: mpmc::Queue<Outbound, TX_QUEUE_SIZE>,
+}
+
+impl TxQueue {
+    /// Create an empty queue.
+    // A task that is preempted while it submits may let other operations fail, until it resumes. Submissions are
+    // retried by the caller, and the policy engine takes a message on its next step.
+    #[expect(deprecated)]
+    pub const fn new() -> Self {
+        Self {
+            queue: mpmc::Queue::new(),
+        }
+    }
+
+    /// Submit a message for transmission.
+    ///
+    /// Returns the message, if the queue is full, or if another task is preempted while submitting.
+    pub fn submit(&self, outbound: Outbound) -> Result<(), Outbound> {
+        self.queue.enqueue(outbound)
+    }
+
+    /// Take the next message for transmission.
+    pub(crate) fn take(&self) -> Option<Outbound> {
+        self.queue.dequeue()
+    }
+}
+
+impl Default for TxQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for TxQueue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TxQueue").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outbound, TX_QUEUE_SIZE, TxQueue};
+    use crate::protocol_layer::message::extended::extended_control::ExtendedControlMessageType;
+
+    #[test]
+    fn test_order() {
+        let queue = TxQueue::new();
+        let outbound = |raw| Outbound::ExtendedControl(ExtendedControlMessageType::Unknown(raw));
+
+        for raw in 0..TX_QUEUE_SIZE as u8 {
+            queue.submit(outbound(raw)).unwrap();
+        }
+        assert!(queue.submit(outbound(0xff)).is_err());
+
+        for raw in 0..TX_QUEUE_SIZE as u8 {
+            assert!(matches!(
+                queue.take(),
+                Some(Outbound::ExtendedControl(ExtendedControlMessageType::Unknown(taken))) if taken == raw
+            ));
+        }
+        assert!(queue.take().is_none());
+    }
+}
@@ -0,0 +1,74 @@
+//! Randomness, e.g. for randomized backoff, and for the nonces of security messages.
+//!
+//! The stack does not depend on `rand`. Instead, the application may provide a random number generator, such as a
+//! hardware RNG peripheral, as a `static` [`Rng`]:
+//!
+//! ```
+//! use core::sync::atomic::{AtomicU64, Ordering};
+//!
+//! use usbpd::rng::Rng;
+//! use usbpd::sink::config::SinkConfig;
+//!
+//! #[derive(Debug)]
+//! struct HardwareRng(AtomicU64);
+//!
+//! impl Rng for HardwareRng {
+//!     fn next_u64(&self) -> u64 {
+//!         // Read a hardware RNG here instead.
+//!         self.0.fetch_add(1, Ordering::Relaxed)
+//!     }
+//! }
+//!
+//! static RNG: HardwareRng = HardwareRng(AtomicU64::new(0));
+//!
+//! const CONFIG: SinkConfig = SinkConfig::new().with_rng(Some(&RNG));
+//! ```
+//!
+//! Without one, randomness is derived from timestamps of the [`Timer`](crate::timers::Timer), which suffices for
+//! spreading backoff delays, but not for nonces.
+use core::fmt::Debug;
+
+/// A source of random numbers.
+///
+/// Random numbers are taken through a shared reference, such that the generator can be part of a `const`
+/// configuration. Generators with state need interior mutability, e.g. atomics, or a critical section.
+pub trait Rng: Debug + Sync {
+    /// A random number.
+    fn next_u64(&self) -> u64;
+
+    /// Fill `buffer` with random bytes, e.g. for a nonce.
+    ///
+    /// By default, the bytes are taken from [`Self::next_u64`].
+    fn fill_bytes(&self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(size_of::<u64>()) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::Rng;
+
+    #[derive(Debug)]
+    struct Counter(AtomicU64);
+
+    impl Rng for Counter {
+        fn next_u64(&self) -> u64 {
+            self.0.fetch_add(1, Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn test_fill_bytes() {
+        let rng = Counter(AtomicU64::new(0x0102));
+
+        let mut buffer = [0u8; 10];
+        rng.fill_bytes(&mut buffer);
+        assert_eq!(buffer, [0x02, 0x01, 0, 0, 0, 0, 0, 0, 0x03, 0x01]);
+        assert_eq!(rng.0.load(Ordering::Relaxed), 0x0104);
+    }
+}
@@ -0,0 +1,302 @@
+//! Configuration of the sink's behavior.
+use crate::protocol_layer::MAX_MESSAGE_SIZE;
+use crate::protocol_layer::backoff::Backoff;
+use crate::protocol_layer::message::header::SpecificationRevision;
+use crate::protocol_layer::stats::GoodCrcConfig;
+#[cfg(feature = "tx-queue")]
+use crate::protocol_layer::tx_queue::TxQueue;
+use crate::rng::Rng;
+use crate::timers::{TimerOverrides, TimerType};
+use crate::units::Power;
+use crate::vdm::AttentionRateLimit;
+
+/// How the sink treats source capabilities with PDOs of unknown kind, e.g. from a newer revision of the
+/// specification.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnknownPdoPolicy {
+    /// Leave unknown PDOs in the capabilities, where requests that are built with
+    /// [`PowerSource`](crate::protocol_layer::message::data::request::PowerSource) constructors skip them.
+    #[default]
+    Ignore,
+    /// Do not trust capabilities with unknown PDOs, and only request vSafe5V, which every source offers.
+    Reject,
+    /// Pass the raw values of unknown PDOs to
+    /// [`DevicePolicyManager::unknown_pdo`](super::device_policy_manager::DevicePolicyManager::unknown_pdo), before
+    /// requesting power.
+    Forward,
+}
+
+/// Behavioral knobs of the sink policy engine.
+///
+/// Passed to [`Sink::new_with_config`](super::policy_engine::Sink::new_with_config). The default follows the
+/// specification.
+///
+/// ```
+/// use usbpd::sink::config::SinkConfig;
+/// use usbpd::timers::TimerType;
+///
+/// const CONFIG: SinkConfig = SinkConfig::new()
+///     .with_timer_override(TimerType::SinkWaitCap, 620_000)
+///     .with_epr(false)
+///     .with_stats(false);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SinkConfig {
+    good_crc: GoodCrcConfig,
+    timer_overrides: TimerOverrides,
+    backoff: Option<&'static dyn Backoff>,
+    rng: Option<&'static dyn Rng>,
+    max_spec_revision: SpecificationRevision,
+    epr_enabled: bool,
+    auto_epr: Option<Power>,
+    chunk_buffer_size: usize,
+    stats_enabled: bool,
+    listen_only: bool,
+    tolerate_missing_accept: bool,
+    time_to_contract_budget: Option<u64>,
+    attention_rate_limit: Option<AttentionRateLimit>,
+    unknown_pdo_policy: UnknownPdoPolicy,
+    #[cfg(feature = "tx-queue")]
+    tx_queue: Option<&'static TxQueue>,
+}
+
+impl Default for SinkConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SinkConfig {
+    /// Create the default configuration.
+    pub const fn new() -> Self {
+        Self {
+            good_crc: GoodCrcConfig {
+                budget: None,
+                priority: false,
+            },
+            timer_overrides: TimerOverrides::new(),
+            backoff: None,
+            rng: None,
+            max_spec_revision: SpecificationRevision::R3_X,
+            epr_enabled: true,
+            auto_epr: None,
+            chunk_buffer_size: MAX_MESSAGE_SIZE,
+            stats_enabled: true,
+            listen_only: false,
+            tolerate_missing_accept: false,
+            time_to_contract_budget: None,
+            attention_rate_limit: Some(AttentionRateLimit::DEFAULT),
+            unknown_pdo_policy: UnknownPdoPolicy::Ignore,
+            #[cfg(feature = "tx-queue")]
+            tx_queue: None,
+        }
+    }
+
+    /// Configure GoodCRC responses, such as the latency budget, or their priority.
+    pub const fn with_good_crc(mut self, good_crc: GoodCrcConfig) -> Self {
+        self.good_crc = good_crc;
+        self
+    }
+
+    /// Override the duration of a timer in microseconds.
+    pub const fn with_timer_override(mut self, timer_type: TimerType, microseconds: u64) -> Self {
+        self.timer_overrides = self.timer_overrides.with(timer_type, microseconds);
+        self
+    }
+
+    /// Replace all timer overrides.
+    pub const fn with_timer_overrides(mut self, timer_overrides: TimerOverrides) -> Self {
+        self.timer_overrides = timer_overrides;
+        self
+    }
+
+    /// Delay retries of transmissions that the driver discarded due to bus contention, see
+    /// [`Backoff`](crate::protocol_layer::backoff::Backoff).
+    ///
+    /// By default, discarded transmissions are retried immediately.
+    pub const fn with_backoff(mut self, backoff: Option<&'static dyn Backoff>) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Take random numbers from `rng`, e.g. for randomized backoff, see [`Rng`](crate::rng::Rng).
+    ///
+    /// By default, randomness is derived from timestamps of the timer.
+    pub const fn with_rng(mut self, rng: Option<&'static dyn Rng>) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Never operate at a higher specification revision than `revision`, even if the source supports it.
+    pub const fn with_max_spec_revision(mut self, revision: SpecificationRevision) -> Self {
+        self.max_spec_revision = revision;
+        self
+    }
+
+    /// Allow or forbid EPR mode.
+    ///
+    /// If forbidden, requests of the device policy manager to enter EPR mode, or to get EPR source capabilities,
+    /// are ignored.
+    pub const fn with_epr(mut self, enabled: bool) -> Self {
+        self.epr_enabled = enabled;
+        self
+    }
+
+    /// Enter EPR mode automatically with the given operational PDP, once an explicit contract with an EPR capable
+    /// source is established.
+    pub const fn with_auto_epr(mut self, operational_pdp: Option<Power>) -> Self {
+        self.auto_epr = operational_pdp;
+        self
+    }
+
+    /// Limit the size of assembled chunked extended messages in bytes.
+    ///
+    /// Larger messages are rejected. The size is capped at the size of the internal receive buffer.
+    pub const fn with_chunk_buffer_size(mut self, size: usize) -> Self {
+        self.chunk_buffer_size = if size < MAX_MESSAGE_SIZE {
+            size
+        } else {
+            MAX_MESSAGE_SIZE
+        };
+        self
+    }
+
+    /// Enable or disable the collection of statistics, see [`Stats`](crate::protocol_layer::stats::Stats).
+    pub const fn with_stats(mut self, enabled: bool) -> Self {
+        self.stats_enabled = enabled;
+        self
+    }
+
+    /// Operate with a driver that only listens, e.g. an analyzer, or a passive tap.
+    ///
+    /// The protocol layer then neither sends GoodCRC, nor tracks message IDs or retries, so that all parsing and
+    /// state tracking can be used for sniffing.
+    pub const fn with_listen_only(mut self, enabled: bool) -> Self {
+        self.listen_only = enabled;
+        self
+    }
+
+    /// Tolerate sources that answer a request with PS_RDY, without sending Accept first.
+    ///
+    /// Such sources are not compliant, and the specification demands a soft reset. If tolerated, the PS_RDY completes
+    /// the request with a warning instead, and the device policy manager is notified of
+    /// [`TransitionAnomaly::MissingAccept`](super::device_policy_manager::TransitionAnomaly::MissingAccept).
+    pub const fn with_tolerate_missing_accept(mut self, enabled: bool) -> Self {
+        self.tolerate_missing_accept = enabled;
+        self
+    }
+
+    /// Warn, if establishing an explicit contract after attach (or hard reset) takes longer than `micros`.
+    ///
+    /// Violations are counted in [`Stats`](crate::protocol_layer::stats::Stats), together with the measured time.
+    /// A budget is typically based on
+    /// [`T_TYPEC_SINK_WAIT_CAP_MAX_MICROS`](crate::protocol_layer::stats::T_TYPEC_SINK_WAIT_CAP_MAX_MICROS), plus the
+    /// response and power transition times of the source. Measuring requires timestamps from the
+    /// [`Timer`](crate::timers::Timer).
+    pub const fn with_time_to_contract_budget(mut self, micros: Option<u64>) -> Self {
+        self.time_to_contract_budget = micros;
+        self
+    }
+
+    /// Limit the rate of Attention messages that are delivered to the device policy manager, or `None` to deliver all.
+    ///
+    /// Excess messages are queued, and delivered in later windows. When the queue is full, messages are dropped, and
+    /// the device policy manager is notified with
+    /// [`DevicePolicyManager::attention_dropped`](super::device_policy_manager::DevicePolicyManager::attention_dropped).
+    /// Rate limiting requires timestamps from the [`Timer`](crate::timers::Timer).
+    pub const fn with_attention_rate_limit(mut self, limit: Option<AttentionRateLimit>) -> Self {
+        self.attention_rate_limit = limit;
+        self
+    }
+
+    /// Choose how capabilities with PDOs of unknown kind are treated.
+    pub const fn with_unknown_pdo_policy(mut self, policy: UnknownPdoPolicy) -> Self {
+        self.unknown_pdo_policy = policy;
+        self
+    }
+
+    /// Transmit the messages that auxiliary tasks submit to `tx_queue`, see
+    /// [`tx_queue`](crate::protocol_layer::tx_queue).
+    #[cfg(feature = "tx-queue")]
+    pub const fn with_tx_queue(mut self, tx_queue: Option<&'static TxQueue>) -> Self {
+        self.tx_queue = tx_queue;
+        self
+    }
+
+    /// The GoodCRC configuration.
+    pub const fn good_crc(&self) -> GoodCrcConfig {
+        self.good_crc
+    }
+
+    /// The timer overrides.
+    pub const fn timer_overrides(&self) -> &TimerOverrides {
+        &self.timer_overrides
+    }
+
+    /// The backoff strategy for discarded transmissions, if any.
+    pub const fn backoff(&self) -> Option<&'static dyn Backoff> {
+        self.backoff
+    }
+
+    /// The random number generator, if any.
+    pub const fn rng(&self) -> Option<&'static dyn Rng> {
+        self.rng
+    }
+
+    /// The highest specification revision to operate at.
+    pub const fn max_spec_revision(&self) -> SpecificationRevision {
+        self.max_spec_revision
+    }
+
+    /// Whether EPR mode is allowed.
+    pub const fn epr_enabled(&self) -> bool {
+        self.epr_enabled
+    }
+
+    /// The operational PDP for automatic EPR mode entry, if enabled.
+    pub const fn auto_epr(&self) -> Option<Power> {
+        self.auto_epr
+    }
+
+    /// The maximum size of assembled chunked extended messages in bytes.
+    pub const fn chunk_buffer_size(&self) -> usize {
+        self.chunk_buffer_size
+    }
+
+    /// Whether statistics are collected.
+    pub const fn stats_enabled(&self) -> bool {
+        self.stats_enabled
+    }
+
+    /// Whether the driver only listens.
+    pub const fn listen_only(&self) -> bool {
+        self.listen_only
+    }
+
+    /// Whether PS_RDY without a preceding Accept is tolerated.
+    pub const fn tolerate_missing_accept(&self) -> bool {
+        self.tolerate_missing_accept
+    }
+
+    /// The time to contract budget in µs, if any.
+    pub const fn time_to_contract_budget(&self) -> Option<u64> {
+        self.time_to_contract_budget
+    }
+
+    /// The rate limit of Attention messages, if any.
+    pub const fn attention_rate_limit(&self) -> Option<AttentionRateLimit> {
+        self.attention_rate_limit
+    }
+
+    /// The treatment of PDOs of unknown kind.
+    pub const fn unknown_pdo_policy(&self) -> UnknownPdoPolicy {
+        self.unknown_pdo_policy
+    }
+
+    /// The queue of messages that auxiliary tasks submit, if any.
+    #[cfg(feature = "tx-queue")]
+    pub const fn tx_queue(&self) -> Option<&'static TxQueue> {
+        self.tx_queue
+    }
+}
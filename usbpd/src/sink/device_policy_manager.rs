@@ -4,13 +4,28 @@
 //! or renegotiate the power contract.
 use core::future::Future;
 
-use crate::protocol_layer::message::data::{epr_mode, request, sink_capabilities, source_capabilities};
-use crate::units::Power;
+use crate::identity::DeviceIdentity;
+use crate::protocol_layer::message::data::alert::AlertDataObject;
+use crate::protocol_layer::message::data::vendor_defined::{VdmHeader, VdmHeaderStructured};
+use crate::protocol_layer::message::data::{ObjectPosition, epr_mode, request, sink_capabilities, source_capabilities};
+use crate::protocol_layer::message::extended::pps_status::PpsStatusExtended;
+use crate::protocol_layer::message::extended::status::StatusExtended;
+use crate::protocol_layer::message::header::{DataMessageType, MessageType};
+use crate::sink::policy_engine::Diagnosis;
+use crate::sink::power_transition::CurrentRamp;
+use crate::status::DeviceStatus;
+use crate::units::{ElectricCurrent, ElectricPotential, Power};
+use crate::vdm::CableIdentity;
 
 /// Events that the device policy manager can send to the policy engine.
+///
+/// Events are taken from [`DevicePolicyManager::poll_event`] and [`DevicePolicyManager::get_event`], while the
+/// policy engine is ready.
 #[derive(Debug)]
 pub enum Event {
-    /// Empty event.
+    /// Empty event, which lets the policy engine re-enter the ready state.
+    ///
+    /// Prefer returning `None` from [`DevicePolicyManager::poll_event`], if there is no event.
     None,
     /// Request SPR source capabilities.
     RequestSprSourceCapabilities,
@@ -35,8 +50,186 @@ pub enum Event {
     /// After receiving caps, negotiation proceeds as normal SPR negotiation.
     /// See spec Table 8.46: "Steps for Exiting EPR Mode (Sink Initiated)"
     ExitEprMode,
+    /// Change the EPR Sink Operational PDP, while in EPR mode.
+    ///
+    /// The operational PDP is only sent at EPR mode entry. Therefore, EPR mode is exited, the SPR contract is
+    /// negotiated, and EPR mode is entered again with the new operational PDP. Ignored outside of EPR mode, or if the
+    /// operational PDP is unchanged. See [`Sink::epr_operational_pdp`](crate::sink::policy_engine::Sink::epr_operational_pdp).
+    ChangeEprOperationalPdp(Power),
+    /// Request the status of the programmable power supply with Get_PPS_Status, e.g. for telemetry during PPS
+    /// operation.
+    ///
+    /// The response is forwarded to [`DevicePolicyManager::pps_status_received`]. See spec, [6.5.10]
+    RequestPpsStatus,
     /// Request a certain power level.
     RequestPower(request::PowerSource),
+    /// Send a structured VDM request with up to six VDOs, e.g. Discover Modes or Enter Mode.
+    ///
+    /// The response (ACK, NAK, or BUSY after all retries) is forwarded to
+    /// [`DevicePolicyManager::vendor_defined_message`].
+    RequestVdm(VdmHeaderStructured, heapless::Vec<u32, 6>),
+    /// Perform a soft reset, e.g. after the device detected a failed AMS.
+    ///
+    /// Sends Soft_Reset, and waits for Accept. Afterwards, the source resends its capabilities, and the contract is
+    /// negotiated again. If the soft reset fails, a hard reset follows. See spec, [6.8.1]
+    SoftReset,
+    /// Request a power role swap with PR_Swap, to become the source of a dual-role port.
+    ///
+    /// Ignored, if the driver does not support power role swaps. If the source accepts, the sink stops with
+    /// [`Error::PowerRoleSwapped`](crate::sink::policy_engine::Error::PowerRoleSwapped), see [`crate::drp`].
+    PowerRoleSwap,
+}
+
+/// The response of a source that refused a power request, or EPR mode entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Refusal {
+    /// The source rejected the request.
+    Reject,
+    /// The source cannot meet the request at this time, and the sink may retry later.
+    Wait,
+}
+
+/// The reason, why source capabilities that the sink requested with Get_Source_Cap or EPR_Get_Source_Cap were not
+/// evaluated.
+///
+/// See [`DevicePolicyManager::capabilities_discarded`], and spec [8.3.3.3.12].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CapabilitiesDiscarded {
+    /// SPR capabilities were received in EPR mode. Requesting from them would require leaving EPR mode first.
+    SprInEprMode,
+    /// EPR capabilities were received in SPR mode. Requesting from them would require entering EPR mode first.
+    EprInSprMode,
+    /// The source answered with the other kind of capabilities than requested.
+    NotRequested,
+}
+
+/// Unexpected behavior of the source around a power transition.
+///
+/// See [`DevicePolicyManager::transition_anomaly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransitionAnomaly {
+    /// The source sent capabilities after accepting a request, instead of PS_RDY.
+    CapabilitiesDuringTransition {
+        /// Whether the capabilities differ from those that the request was based on.
+        changed: bool,
+    },
+    /// The source sent PS_RDY, without an accepted request outstanding.
+    UnexpectedPsRdy,
+    /// The source answered a request with PS_RDY, without sending Accept first.
+    ///
+    /// Only reported, if tolerated, see [`SinkConfig::with_tolerate_missing_accept`](super::config::SinkConfig).
+    MissingAccept,
+    /// The source sent Soft_Reset after accepting a request, instead of PS_RDY.
+    ///
+    /// The source may have changed its output already, so the contract is neither kept, nor completed. Per spec
+    /// 6.8.1, the sink resets the port partner with a hard reset.
+    SoftResetDuringTransition,
+}
+
+/// How the policy engine proceeds, after all hard resets failed to get a response from the source.
+///
+/// See [`DevicePolicyManager::hard_resets_exhausted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HardResetsExhausted {
+    /// Give up, and return [`Error::PortPartnerUnresponsive`](crate::sink::policy_engine::Error) from the policy
+    /// engine.
+    GiveUp,
+    /// Wait for the given cooldown in ms, and start over with another series of hard resets.
+    RetryAfter(u64),
+    /// Remain a USB Type-C sink at vSafe5V, without a contract, until the source sends capabilities.
+    FallBackToTypeC,
+}
+
+/// A contract that the device prefers, and that the policy engine requests without asking the device policy manager.
+///
+/// See [`DevicePolicyManager::preferred_contract`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContractHint {
+    /// The voltage of the fixed supply.
+    pub voltage: ElectricPotential,
+    /// The operating current.
+    pub current: ElectricCurrent,
+}
+
+impl ContractHint {
+    /// The request for the hinted contract, if the source offers a fixed supply with the voltage, and at least the
+    /// current.
+    pub fn power_source(
+        &self,
+        source_capabilities: &source_capabilities::SourceCapabilities,
+    ) -> Option<request::PowerSource> {
+        let power_source = request::PowerSource::new_fixed(
+            request::CurrentRequest::Specific(self.current),
+            request::VoltageRequest::Specific(self.voltage),
+            source_capabilities,
+        )
+        .ok()?;
+
+        (!power_source.capability_mismatch()).then_some(power_source)
+    }
+}
+
+/// The most recent source capability advertisements, that the sink policy engine retained.
+///
+/// The number of retained advertisements is a const generic parameter of the [`Sink`](super::policy_engine::Sink).
+/// See [`DevicePolicyManager::capabilities_received`].
+#[derive(Debug, Clone, Copy)]
+pub struct CapabilityHistory<'h> {
+    older: &'h [source_capabilities::SourceCapabilities],
+    newer: &'h [source_capabilities::SourceCapabilities],
+}
+
+impl<'h> CapabilityHistory<'h> {
+    /// Create a history from the retained advertisements, in the order of arrival, as split into two slices by a
+    /// ring buffer.
+    pub(crate) fn new(
+        older: &'h [source_capabilities::SourceCapabilities],
+        newer: &'h [source_capabilities::SourceCapabilities],
+    ) -> Self {
+        Self { older, newer }
+    }
+
+    /// The advertisements, from newest to oldest.
+    pub fn iter(&self) -> impl Iterator<Item = &'h source_capabilities::SourceCapabilities> {
+        self.newer.iter().rev().chain(self.older.iter().rev())
+    }
+
+    /// The most recent advertisement.
+    pub fn latest(&self) -> Option<&'h source_capabilities::SourceCapabilities> {
+        self.iter().next()
+    }
+
+    /// The advertisement before the most recent one.
+    pub fn previous(&self) -> Option<&'h source_capabilities::SourceCapabilities> {
+        self.iter().nth(1)
+    }
+
+    /// The number of retained advertisements.
+    pub fn len(&self) -> usize {
+        self.older.len() + self.newer.len()
+    }
+
+    /// Whether no advertisements were retained.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The response to a data or extended message, that the policy engine does not handle.
+///
+/// See [`DevicePolicyManager::unhandled_message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnhandledMessageResponse {
+    /// Respond with Not_Supported.
+    NotSupported,
+    /// Ignore the message, without a response.
+    Ignore,
+    /// Respond with a data message of the given type, with up to seven raw data objects.
+    Data(DataMessageType, heapless::Vec<u32, 7>),
 }
 
 /// Trait for the device policy manager.
@@ -48,27 +241,127 @@ pub trait DevicePolicyManager {
         async {}
     }
 
+    /// Notify the device that requested source capabilities were not evaluated, and the contract remains in place.
+    ///
+    /// The capabilities were already passed to [`Self::inform`].
+    fn capabilities_discarded(
+        &mut self,
+        _source_capabilities: &source_capabilities::SourceCapabilities,
+        _reason: CapabilitiesDiscarded,
+    ) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Inform the device about the history of source capabilities, whenever capabilities are evaluated.
+    ///
+    /// Called before [`Self::request`], with the new capabilities as the latest entry. Allows for hysteresis, e.g.
+    /// with sources that flap between power budgets.
+    fn capabilities_received(&mut self, _history: CapabilityHistory<'_>) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// A contract that the device prefers, for shortening the time to power.
+    ///
+    /// Queried whenever SPR source capabilities are evaluated. If the source offers the hinted contract, the policy
+    /// engine requests it immediately, and [`Self::request`] is not called. Defaults to no hint.
+    fn preferred_contract(&self) -> Option<ContractHint> {
+        None
+    }
+
     /// Request a power source.
     ///
-    /// Defaults to 5 V at maximum current.
+    /// Defaults to 5 V at maximum current. With the `panic-free` feature, falls back to 5 V at 500 mA, if the source
+    /// capabilities lack a vSafe5V supply.
     fn request(
         &mut self,
         source_capabilities: &source_capabilities::SourceCapabilities,
     ) -> impl Future<Output = request::PowerSource> {
         async {
-            request::PowerSource::new_fixed(
+            let request = request::PowerSource::new_fixed(
                 request::CurrentRequest::Highest,
                 request::VoltageRequest::Safe5V,
                 source_capabilities,
-            )
-            .unwrap()
+            );
+
+            #[cfg(feature = "panic-free")]
+            let request = request.unwrap_or(request::PowerSource::FixedVariableSupply(
+                request::FixedVariableSupply::new(ObjectPosition::VSAFE_5V, 50, 50),
+            ));
+            #[cfg(not(feature = "panic-free"))]
+            let request = request.unwrap();
+
+            request
         }
     }
 
+    /// Inspect a PDO of unknown kind in the source capabilities, before [`Self::request`] is called.
+    ///
+    /// Only called with [`UnknownPdoPolicy::Forward`](super::config::UnknownPdoPolicy::Forward). Devices that know
+    /// the PDO from a newer revision of the specification can request it with a raw request data object.
+    fn unknown_pdo(&mut self, _position: ObjectPosition, _raw: u32) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// The USB related attributes of power requests.
+    ///
+    /// Applied by the policy engine to every request that it sends, including the ones from [`Self::request`] and
+    /// [`Event::RequestPower`]. Defaults to no USB suspend and USB communications capable.
+    fn request_attributes(&self) -> request::RequestAttributes {
+        request::RequestAttributes::default()
+    }
+
+    /// Notify the device of unexpected behavior of the source around a power transition.
+    ///
+    /// The policy engine recovers with a soft reset, or with a hard reset during the transition, unless the anomaly is
+    /// tolerated. This is for diagnostics only.
+    fn transition_anomaly(&mut self, _anomaly: TransitionAnomaly) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Notify the device that the source refused a power request, while an explicit contract is in place.
+    ///
+    /// The existing contract is maintained. This includes the periodic re-requests of a PPS contract, after which
+    /// the source keeps the current APDO operating point.
+    fn request_refused(&mut self, _refused: &request::PowerSource, _refusal: Refusal) -> impl Future<Output = ()> {
+        async {}
+    }
+
     /// Notify the device that it shall transition to a new power level.
     ///
-    /// The device is informed about the request that was accepted by the source.
-    fn transition_power(&mut self, _accepted: &request::PowerSource) -> impl Future<Output = ()> {
+    /// The device is informed about the request that was accepted by the source, and about the current that it may
+    /// draw while ramping up to the new operating current.
+    fn transition_power(&mut self, _accepted: &request::PowerSource, _ramp: &CurrentRamp) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Notify the device that it shall reduce its load to Sink Standby, i.e. draw no more than
+    /// [`sink_standby_power`](crate::sink::power_transition::sink_standby_power).
+    ///
+    /// Called before requesting a programmable supply voltage, that differs from the present one by more than a small
+    /// step. The load shall be reduced, when the future completes. See spec, [7.2.3]
+    fn enter_standby(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Notify the device that Sink Standby ends.
+    ///
+    /// Called after PS_RDY, before [`Self::transition_power`], whose ramp the load shall follow. Also called, if the
+    /// source refused the request, in which case the previous load may be restored right away.
+    fn exit_standby(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Decide whether to accept a power role swap, that the source requested with PR_Swap.
+    ///
+    /// Only asked, if the driver supports power role swaps. Defaults to rejecting it.
+    fn evaluate_power_role_swap(&mut self) -> impl Future<Output = bool> {
+        async { false }
+    }
+
+    /// Drive VBUS to vSafe5V, as the new source of a power role swap.
+    ///
+    /// Called after the former source turned VBUS off, and before PS_RDY is sent. See spec, [8.3.3.19]
+    fn vsafe5v(&mut self) -> impl Future<Output = ()> {
         async {}
     }
 
@@ -85,6 +378,15 @@ pub trait DevicePolicyManager {
         async {}
     }
 
+    /// Decide how to proceed, after all hard resets failed to get a response from the source.
+    ///
+    /// Per spec 8.3.3.3.8, the sink assumes that the source is non-responsive, when the HardResetCounter exceeds
+    /// nHardResetCount. Products differ in how aggressively they retry broken sources, so the device decides, with
+    /// the `diagnosis` of the likely cause. Defaults to giving up.
+    fn hard_resets_exhausted(&mut self, _diagnosis: Diagnosis) -> impl Future<Output = HardResetsExhausted> {
+        async { HardResetsExhausted::GiveUp }
+    }
+
     /// Notify the device that EPR mode entry failed.
     ///
     /// Per USB PD Spec R3.2 Section 8.3.3.26.2.1, when the source responds with
@@ -101,6 +403,15 @@ pub trait DevicePolicyManager {
         async {}
     }
 
+    /// Notify the device that the source refused EPR mode entry with Reject or Wait, instead of an EPR_Mode response.
+    ///
+    /// Unlike [`Self::epr_mode_entry_failed`], no soft reset follows, and the SPR contract remains in place. After
+    /// Wait, the sink retries entry after tSinkRequest, up to nBusyCount times, and only reports Wait, when the source
+    /// stays busy for all of them.
+    fn epr_mode_entry_refused(&mut self, _refusal: Refusal) -> impl Future<Output = ()> {
+        async {}
+    }
+
     /// Get the sink's power capabilities.
     ///
     /// Per USB PD Spec R3.2 Section 6.4.1.6, sinks respond to Get_Sink_Cap messages
@@ -109,14 +420,138 @@ pub trait DevicePolicyManager {
     ///
     /// All sinks shall minimally offer one PDO at vSafe5V. The default implementation
     /// returns a single 5V @ 100mA PDO.
+    ///
+    /// Use [`sink_capabilities::SinkCapabilities::builder`] to declare further PDOs, as well as dual-role,
+    /// unconstrained power and higher capability flags. A fixed table can be declared as a constant with
+    /// [`sink_capabilities::SinkCapabilities::from_array`] instead, so that it is not rebuilt on every request.
     fn sink_capabilities(&self) -> sink_capabilities::SinkCapabilities {
         // Default: 5V @ 100mA (1A = 100 * 10mA)
         sink_capabilities::SinkCapabilities::new_vsafe5v_only(100)
     }
 
-    /// The policy engine gets and evaluates device policy events when ready.
+    /// Get the source capabilities of the device, for answering Get_Source_Cap of the port partner.
+    ///
+    /// Only dual-role power devices have source capabilities. By default, there are none, and the policy engine
+    /// responds with Not_Supported (or Reject, for revision 2.0 port partners).
+    fn source_capabilities(&self) -> Option<source_capabilities::SourceCapabilities> {
+        None
+    }
+
+    /// Handle a received vendor defined message (VDM).
+    ///
+    /// Applications with alternate modes can forward the message to their [`crate::vdm::SvidHandlers`].
+    /// Return `true`, if the message was handled. Otherwise, the policy engine responds with Not_Supported,
+    /// which is also the default.
+    fn vendor_defined_message(&mut self, _header: &VdmHeader, _vdos: &[u32]) -> impl Future<Output = bool> {
+        async { false }
+    }
+
+    /// Notify the device of an Alert from the source, with the status that the source reported afterwards.
+    ///
+    /// Per spec 8.3.3.3.7, the policy engine answers an Alert with Get_Status. The `status` is `None`, if the source
+    /// did not answer with a Status message.
+    fn alert_received(
+        &mut self,
+        _alert: AlertDataObject,
+        _status: Option<&StatusExtended>,
+    ) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Notify the device of the status of the programmable power supply, in answer to [`Event::RequestPpsStatus`].
+    ///
+    /// The `status` is `None`, if the source answered Not_Supported, or did not answer at all.
+    fn pps_status_received(&mut self, _status: Option<&PpsStatusExtended>) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Notify the device that a received Attention message was dropped.
+    ///
+    /// Attention messages are rate limited, see
+    /// [`SinkConfig::with_attention_rate_limit`](super::config::SinkConfig::with_attention_rate_limit). Excess
+    /// messages are queued, and dropped, when the queue is full.
+    fn attention_dropped(&mut self, _header: &VdmHeaderStructured) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Handle a data or extended message in the ready state, that the policy engine does not handle itself.
     ///
-    /// By default, this is a future that never resolves.
+    /// The `payload` holds the raw bytes after the message header, and the extended header, if any. By default,
+    /// the policy engine responds with Not_Supported.
+    fn unhandled_message(
+        &mut self,
+        _message_type: MessageType,
+        _payload: &[u8],
+    ) -> impl Future<Output = UnhandledMessageResponse> {
+        async { UnhandledMessageResponse::NotSupported }
+    }
+
+    /// Notify the device of an extended control message of unknown type.
+    ///
+    /// The policy engine responds with Not_Supported, and stays in its present mode. This is for logging only.
+    fn unknown_extended_control(&mut self, _message_type: u8) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Notify the device that the port partner started, or ended the BIST shared capacity test mode.
+    ///
+    /// See spec, [6.4.3.3]
+    #[cfg(feature = "bist")]
+    fn bist_shared_test_mode(&mut self, _active: bool) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Discover the identity of the attached cable (SOP').
+    ///
+    /// Called before EPR mode entry, if no cable identity is cached yet. The result is cached by the policy engine
+    /// until detach, or hard reset, see [`crate::sink::policy_engine::Sink::cable_identity`].
+    /// By default, the cable identity is unknown.
+    fn discover_cable_identity(&mut self) -> impl Future<Output = Option<CableIdentity>> {
+        async { None }
+    }
+
+    /// Register workarounds for sources with known interoperability issues.
+    ///
+    /// Called once, when the policy engine is created. By default, no quirks are registered.
+    #[cfg(feature = "quirks")]
+    fn register_quirks(&mut self, _registry: &mut crate::sink::quirks::QuirkRegistry) {}
+
+    /// Called on every iteration of the policy engine loop, before it performs the next step.
+    ///
+    /// Allows feeding a hardware watchdog in proportion to the liveness of the policy engine, such that a driver
+    /// future that never completes is detected. Note that the ready state waits for messages, timers, and
+    /// [`Self::get_event`] without iterating. Return [`Event::None`] from [`Self::get_event`] periodically, for the
+    /// loop to iterate while ready.
+    fn tick(&mut self) {}
+
+    /// Get the product identity of the device.
+    ///
+    /// Used for answering Get_Manufacturer_Info of the port partner. By default, the device has no identity, and the
+    /// policy engine responds with Not_Supported (or Reject, for revision 2.0 port partners).
+    fn identity(&self) -> Option<DeviceIdentity> {
+        None
+    }
+
+    /// Get the present status of the device, such as its temperature and power consumption.
+    ///
+    /// Used for answering Get_Status of the port partner. By default, no telemetry is reported.
+    fn status(&self) -> DeviceStatus {
+        DeviceStatus::new()
+    }
+
+    /// Poll for a device policy event, without waiting.
+    ///
+    /// Called on every entry to the ready state, before waiting for messages, timers, and [`Self::get_event`].
+    /// Return `None`, if there is no event, which is also the default. Suits devices, whose events are
+    /// flags or state changes that can be checked synchronously.
+    fn poll_event(&mut self, _source_capabilities: &source_capabilities::SourceCapabilities) -> Option<Event> {
+        None
+    }
+
+    /// The policy engine awaits device policy events when ready, concurrently with messages and timers.
+    ///
+    /// By default, this is a future that never resolves. Devices without asynchronous event sources need not
+    /// implement this, see [`Self::poll_event`] instead.
     ///
     /// <div class="warning">
     /// The function must be safe to cancel. To determine whether your own methods are cancellation safe,
@@ -124,6 +559,15 @@ pub trait DevicePolicyManager {
     /// that always happens at an .await. If your function behaves correctly even if it is restarted while waiting
     /// at an .await, then it is cancellation safe.
     /// </div>
+    ///
+    /// The future is cancelled, whenever a message or timer leaves the ready state first. Before that, the policy
+    /// engine polls it once more. If it is complete by then, the event is kept, and handled on the next entry to the
+    /// ready state. Thereby, taking the event from its source before the first `.await` is safe. Taking it at a later
+    /// `.await`, and awaiting further futures afterwards, is not: the event is lost, if the future is cancelled in
+    /// between.
+    ///
+    /// Messages that arrive together with an event are handled first. Kept events are handled in the order they were
+    /// produced, before any new event is polled. A hard reset discards them.
     fn get_event(
         &mut self,
         _source_capabilities: &source_capabilities::SourceCapabilities,
@@ -0,0 +1,29 @@
+//! Identification of the attached source.
+//!
+//! A source is identified by its vendor and product ID, as reported in a Discover Identity response, or by a
+//! fingerprint of its source capabilities, for sources that do not respond to Discover Identity.
+use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+
+/// What is known about the identity of the attached source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceIdentity {
+    /// The vendor and product ID, from a Discover Identity response of the source.
+    pub product: Option<(u16, u16)>,
+    /// The fingerprint of the SPR source capabilities, see [`fingerprint`].
+    pub fingerprint: Option<u32>,
+}
+
+/// A fingerprint of source capabilities, for matching sources without a known identity.
+///
+/// The FNV-1a hash of the raw PDOs.
+pub fn fingerprint(capabilities: &SourceCapabilities) -> u32 {
+    capabilities
+        .pdos()
+        .iter()
+        .flat_map(|pdo| pdo.raw().to_le_bytes())
+        .fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
+}
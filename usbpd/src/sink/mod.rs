@@ -1,4 +1,11 @@
 //! The sink implementation.
 
+pub mod config;
 pub mod device_policy_manager;
+pub mod identity;
+pub mod persistence;
 pub mod policy_engine;
+pub mod power_budget;
+pub mod power_transition;
+#[cfg(feature = "quirks")]
+pub mod quirks;
@@ -0,0 +1,201 @@
+//! Persistence of the negotiated contract, for fast renegotiation with the same source after a reboot.
+//!
+//! The application takes a [`StoredContract`] from
+//! [`Sink::stored_contract`](super::policy_engine::Sink::stored_contract), after a contract was negotiated, and
+//! stores it in flash as a compact blob (see [`StoredContract::to_bytes`]). With the `serde` feature, it may also be
+//! serialized with any serde format, such as postcard.
+//!
+//! On the next boot, the device policy manager loads the blob, and repeats the stored request, if the source
+//! matches:
+//!
+//! ```
+//! use usbpd::protocol_layer::message::data::request::PowerSource;
+//! use usbpd::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+//! use usbpd::sink::device_policy_manager::DevicePolicyManager;
+//! use usbpd::sink::persistence::StoredContract;
+//!
+//! struct Device {
+//!     stored: Option<StoredContract>,
+//! }
+//!
+//! impl DevicePolicyManager for Device {
+//!     async fn request(&mut self, source_capabilities: &SourceCapabilities) -> PowerSource {
+//!         if let Some(power_source) = self
+//!             .stored
+//!             .filter(|stored| stored.matches(source_capabilities))
+//!             .and_then(|stored| stored.power_source(source_capabilities))
+//!         {
+//!             return power_source;
+//!         }
+//!
+//!         // Evaluate the capabilities as usual.
+//!         # unimplemented!()
+//!     }
+//! }
+//! ```
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::identity::{SourceIdentity, fingerprint};
+use crate::protocol_layer::message::data::ObjectPosition;
+use crate::protocol_layer::message::data::request::{EprRequestDataObject, PowerSource, RawDataObject};
+use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+
+/// The size of a stored contract in bytes, see [`StoredContract::to_bytes`].
+pub const STORED_CONTRACT_SIZE: usize = 16;
+
+/// The version of the blob format, which is stored in its first byte.
+const FORMAT_VERSION: u8 = 1;
+
+const FLAG_PRODUCT: u8 = 1 << 0;
+const FLAG_EPR: u8 = 1 << 1;
+
+/// Errors that can occur when loading a stored contract.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LoadError {
+    /// The blob is shorter than [`STORED_CONTRACT_SIZE`].
+    #[error("stored contract is too short")]
+    InvalidLength,
+    /// The blob was stored in another format, e.g. by a different version of this crate.
+    #[error("unsupported stored contract format `{0}`")]
+    UnsupportedVersion(u8),
+}
+
+/// A negotiated contract, together with the identity of the source that it was negotiated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredContract {
+    /// The fingerprint of the SPR source capabilities, see [`fingerprint`].
+    pub fingerprint: u32,
+    /// The vendor and product ID of the source, if it responded to Discover Identity.
+    pub product: Option<(u16, u16)>,
+    /// The raw request data object of the contract.
+    pub request: u32,
+    /// Whether the contract was negotiated in EPR mode.
+    pub epr: bool,
+}
+
+impl StoredContract {
+    /// Create a stored contract from the identity of the source, and the accepted request.
+    ///
+    /// Returns `None`, if the source capabilities were never fingerprinted.
+    pub fn new(identity: &SourceIdentity, power_source: &PowerSource, epr: bool) -> Option<Self> {
+        Some(Self {
+            fingerprint: identity.fingerprint?,
+            product: identity.product,
+            request: power_source.raw(),
+            epr,
+        })
+    }
+
+    /// Whether a source that offers the given SPR capabilities is the one that the contract was negotiated with.
+    pub fn matches(&self, capabilities: &SourceCapabilities) -> bool {
+        fingerprint(capabilities) == self.fingerprint
+    }
+
+    /// The stored request, interpreted for the given capabilities.
+    ///
+    /// EPR requests are only restored from EPR capabilities, which the source offers after it matched with its SPR
+    /// capabilities. Returns `None`, if the capabilities lack the requested object position.
+    pub fn power_source(&self, capabilities: &SourceCapabilities) -> Option<PowerSource> {
+        let raw = RawDataObject(self.request);
+        let position = ObjectPosition::new(raw.object_position())?;
+        let pdo = capabilities.offered_pdo_at(position)?;
+
+        if self.epr {
+            if !capabilities.is_epr_capabilities() {
+                return None;
+            }
+            return Some(PowerSource::EprRequest(EprRequestDataObject::new(
+                position,
+                pdo,
+                self.request,
+            )));
+        }
+
+        match PowerSource::from_raw(raw, capabilities) {
+            PowerSource::Unknown(_) => None,
+            power_source => Some(power_source),
+        }
+    }
+
+    /// Serialize into a compact blob.
+    ///
+    /// The first byte holds the format version, the last two bytes are reserved (zero).
+    pub fn to_bytes(&self) -> [u8; STORED_CONTRACT_SIZE] {
+        let mut buf = [0u8; STORED_CONTRACT_SIZE];
+        let (vid, pid) = self.product.unwrap_or_default();
+
+        buf[0] = FORMAT_VERSION;
+        buf[1] = if self.product.is_some() { FLAG_PRODUCT } else { 0 } | if self.epr { FLAG_EPR } else { 0 };
+        LittleEndian::write_u16(&mut buf[2..4], vid);
+        LittleEndian::write_u16(&mut buf[4..6], pid);
+        LittleEndian::write_u32(&mut buf[6..10], self.fingerprint);
+        LittleEndian::write_u32(&mut buf[10..14], self.request);
+        buf
+    }
+
+    /// Deserialize from a blob, as created by [`Self::to_bytes`].
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, LoadError> {
+        let Some(buf) = buf.first_chunk::<STORED_CONTRACT_SIZE>() else {
+            return Err(LoadError::InvalidLength);
+        };
+        if buf[0] != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(buf[0]));
+        }
+
+        let flags = buf[1];
+        let product = (flags & FLAG_PRODUCT != 0)
+            .then(|| (LittleEndian::read_u16(&buf[2..4]), LittleEndian::read_u16(&buf[4..6])));
+
+        Ok(Self {
+            fingerprint: LittleEndian::read_u32(&buf[6..10]),
+            product,
+            request: LittleEndian::read_u32(&buf[10..14]),
+            epr: flags & FLAG_EPR != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoadError, STORED_CONTRACT_SIZE, StoredContract};
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::ObjectPosition;
+    use crate::protocol_layer::message::data::request::{FixedVariableSupply, PowerSource};
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::identity::{SourceIdentity, fingerprint};
+
+    #[test]
+    fn test_round_trip() {
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+        let identity = SourceIdentity {
+            product: Some((0x1234, 0x5678)),
+            fingerprint: Some(fingerprint(&capabilities)),
+        };
+        let accepted =
+            PowerSource::FixedVariableSupply(FixedVariableSupply::new(ObjectPosition::new(2).unwrap(), 300, 300));
+
+        let stored = StoredContract::new(&identity, &accepted, false).unwrap();
+        let loaded = StoredContract::from_bytes(&stored.to_bytes()).unwrap();
+        assert_eq!(loaded, stored);
+
+        assert!(loaded.matches(&capabilities));
+        let restored = loaded.power_source(&capabilities).unwrap();
+        assert!(matches!(restored, PowerSource::FixedVariableSupply(_)));
+        assert_eq!(restored.raw(), accepted.raw());
+
+        // Without a fingerprint, the source cannot be recognized.
+        assert!(StoredContract::new(&SourceIdentity::default(), &accepted, false).is_none());
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert_eq!(StoredContract::from_bytes(&[1, 0, 0]), Err(LoadError::InvalidLength));
+        assert_eq!(
+            StoredContract::from_bytes(&[0xff; STORED_CONTRACT_SIZE]),
+            Err(LoadError::UnsupportedVersion(0xff))
+        );
+    }
+}
@@ -1,24 +1,45 @@
 //! Policy engine for the implementation of a sink.
+use core::future::Future;
 use core::marker::PhantomData;
+use core::pin::pin;
+use core::task::Poll;
 
-use embassy_futures::select::{Either3, select3};
+use embassy_futures::select::{Either3, Either4, select3, select4};
+use heapless::Deque;
 use uom::si::power::watt;
-use usbpd_traits::Driver;
+use usbpd_traits::{CcTermination, Driver, RolePreference};
 
+use super::config::{SinkConfig, UnknownPdoPolicy};
 use super::device_policy_manager::DevicePolicyManager;
+use super::identity::{self, SourceIdentity};
+use super::persistence::StoredContract;
+use super::power_transition::{CurrentRamp, programmable_standby_required};
+#[cfg(feature = "quirks")]
+use super::quirks::{QuirkRegistry, Quirks};
 use crate::counters::Counter;
-use crate::protocol_layer::message::data::epr_mode::{self, Action};
+use crate::protocol_layer::message::data::alert::AlertDataObject;
+use crate::protocol_layer::message::data::epr_mode::{self, Action, EprMode};
 use crate::protocol_layer::message::data::request::PowerSource;
 use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
-use crate::protocol_layer::message::data::{Data, request};
+use crate::protocol_layer::message::data::vendor_defined::{VdmHeader, VdmHeaderStructured, VdmVersion};
+use crate::protocol_layer::message::data::{Data, ObjectPosition, request};
 use crate::protocol_layer::message::extended::extended_control::ExtendedControlMessageType;
+use crate::protocol_layer::message::extended::manufacturer_info::{ManufacturerInfo, ManufacturerInfoTarget};
 use crate::protocol_layer::message::header::{
-    ControlMessageType, DataMessageType, ExtendedMessageType, Header, MessageType, SpecificationRevision,
+    ControlMessageType, DataMessageType, ExtendedMessageType, Header, MessageType,
+};
+use crate::protocol_layer::message::{Message, Payload, extended};
+use crate::protocol_layer::stats::{GoodCrcConfig, Stats};
+#[cfg(feature = "tx-queue")]
+use crate::protocol_layer::tx_queue::{Outbound, TxQueue};
+use crate::protocol_layer::{ErrorOrigin, ProtocolError, ProtocolLayer, RxError, TxError};
+use crate::sink::device_policy_manager::{
+    CapabilitiesDiscarded, CapabilityHistory, Event, HardResetsExhausted, Refusal, TransitionAnomaly,
+    UnhandledMessageResponse,
 };
-use crate::protocol_layer::message::{Payload, extended};
-use crate::protocol_layer::{ProtocolError, ProtocolLayer, RxError, TxError};
-use crate::sink::device_policy_manager::Event;
 use crate::timers::{Timer, TimerType};
+use crate::trace::{TraceEvent, Tracer};
+use crate::vdm::{AttentionQueue, CableIdentity, Offer};
 use crate::{DataRole, PowerRole, units};
 
 #[cfg(test)]
@@ -54,36 +75,130 @@ enum State {
     SelectCapability(request::PowerSource),
     TransitionSink(request::PowerSource),
     /// Ready state. The bool indicates if we entered due to receiving a Wait message,
-    /// which requires running SinkRequestTimer before allowing re-request.
+    /// which requires running SinkRequestTimer before allowing re-request, or re-entry into EPR mode.
     Ready(request::PowerSource, bool),
     SendNotSupported(request::PowerSource),
     SendSoftReset,
     SoftReset,
     HardReset,
     TransitionToDefault,
+    /// Remain a USB Type-C sink at vSafe5V after all hard resets were exhausted, until the source sends capabilities.
+    TypeCFallback,
     /// Give sink capabilities. The Mode indicates whether to send Sink_Capabilities (Spr)
     /// or EPR_Sink_Capabilities (Epr) per spec 8.3.3.3.10.
     GiveSinkCap(Mode, request::PowerSource),
+    /// Answer Get_Source_Cap of the port partner.
+    GiveSourceCap(request::PowerSource),
+    /// Answer Get_Manufacturer_Info of the port partner.
+    GiveManufacturerInfo(request::PowerSource, ManufacturerInfoTarget),
+    /// Answer Get_Status of the port partner.
+    GiveSinkStatus(request::PowerSource),
     GetSourceCap(Mode, request::PowerSource),
+    /// Get the status of the source with Get_Status, after it sent an Alert.
+    GetSourceStatus(request::PowerSource, AlertDataObject),
+    /// Get the status of the programmable power supply with Get_PPS_Status, on request of the DPM.
+    GetPpsStatus(request::PowerSource),
+    /// Send a structured VDM request, and forward the response to the DPM.
+    SendVdm(request::PowerSource, VdmHeaderStructured, heapless::Vec<u32, 6>),
+
+    // Power role swap states (PE_PRS_SNK_SRC_*)
+    /// Decide on a PR_Swap of the source.
+    PrsEvaluateSwap(request::PowerSource),
+    /// Send PR_Swap, and wait for the response of the source.
+    PrsSendSwap(request::PowerSource),
+    /// Wait for the former source to turn VBUS off.
+    PrsTransitionToOff,
+    /// Assert Rp, turn VBUS on, and send PS_RDY as the new source.
+    PrsSourceOn,
 
     // EPR states
     EprModeEntry(request::PowerSource, units::Power),
     EprEntryWaitForResponse(request::PowerSource),
     EprWaitForCapabilities(request::PowerSource),
-    EprSendExit,
+    /// Send EPR_Mode (Exit), and re-enter EPR mode with the operational PDP afterwards, if any.
+    EprSendExit(Option<units::Power>),
     EprExitReceived(request::PowerSource),
     EprKeepAlive(request::PowerSource),
+
+    // BIST states
+    #[cfg(feature = "bist")]
+    BistCarrierMode,
+    #[cfg(feature = "bist")]
+    BistTestData,
+}
+
+impl State {
+    /// The name of the state, for tracing.
+    fn name(&self) -> &'static str {
+        match self {
+            State::Startup => "Startup",
+            State::Discovery => "Discovery",
+            State::WaitForCapabilities => "WaitForCapabilities",
+            State::EvaluateCapabilities(_) => "EvaluateCapabilities",
+            State::SelectCapability(_) => "SelectCapability",
+            State::TransitionSink(_) => "TransitionSink",
+            State::Ready(..) => "Ready",
+            State::SendNotSupported(_) => "SendNotSupported",
+            State::SendSoftReset => "SendSoftReset",
+            State::SoftReset => "SoftReset",
+            State::HardReset => "HardReset",
+            State::TransitionToDefault => "TransitionToDefault",
+            State::TypeCFallback => "TypeCFallback",
+            State::GiveSinkCap(..) => "GiveSinkCap",
+            State::GiveSourceCap(_) => "GiveSourceCap",
+            State::GiveManufacturerInfo(..) => "GiveManufacturerInfo",
+            State::GiveSinkStatus(_) => "GiveSinkStatus",
+            State::GetSourceCap(..) => "GetSourceCap",
+            State::GetSourceStatus(..) => "GetSourceStatus",
+            State::GetPpsStatus(_) => "GetPpsStatus",
+            State::SendVdm(..) => "SendVdm",
+            State::PrsEvaluateSwap(_) => "PrsEvaluateSwap",
+            State::PrsSendSwap(_) => "PrsSendSwap",
+            State::PrsTransitionToOff => "PrsTransitionToOff",
+            State::PrsSourceOn => "PrsSourceOn",
+            State::EprModeEntry(..) => "EprModeEntry",
+            State::EprEntryWaitForResponse(_) => "EprEntryWaitForResponse",
+            State::EprWaitForCapabilities(_) => "EprWaitForCapabilities",
+            State::EprSendExit(_) => "EprSendExit",
+            State::EprExitReceived(_) => "EprExitReceived",
+            State::EprKeepAlive(_) => "EprKeepAlive",
+            #[cfg(feature = "bist")]
+            State::BistCarrierMode => "BistCarrierMode",
+            #[cfg(feature = "bist")]
+            State::BistTestData => "BistTestData",
+        }
+    }
+}
+
+/// Only the name of the state.
+#[cfg(feature = "defmt")]
+impl defmt::Format for State {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.name())
+    }
 }
 
+/// The number of device policy manager events that can be pending.
+const PENDING_EVENT_COUNT: usize = 4;
+
 /// Implementation of the sink policy engine.
 /// See spec, [8.3.3.3]
+///
+/// The sink retains the last `HISTORY` source capability advertisements, see [`Self::capability_history`].
 #[derive(Debug)]
-pub struct Sink<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> {
+pub struct Sink<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager, TRACER: Tracer = (), const HISTORY: usize = 1> {
     device_policy_manager: DPM,
-    protocol_layer: ProtocolLayer<DRIVER, TIMER>,
+    protocol_layer: ProtocolLayer<DRIVER, TIMER, TRACER>,
+    tracer: TRACER,
     contract: Contract,
+    /// The request that the source accepted for the explicit contract.
+    accepted_power_source: Option<PowerSource>,
+    /// The request that was transmitted to the source, while its Accept and PS_RDY are outstanding.
+    pending_request: Option<PowerSource>,
     hard_reset_counter: Counter,
     source_capabilities: Option<SourceCapabilities>,
+    /// The most recent source capability advertisements, oldest first. Kept across hard resets.
+    capability_history: Deque<SourceCapabilities, HISTORY>,
     mode: Mode,
     state: State,
     /// Tracks whether a Get_Source_Cap request is pending.
@@ -91,67 +206,391 @@ pub struct Sink<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> {
     /// Source_Capabilities message that was not requested via Get_Source_Cap
     /// shall trigger a Hard Reset.
     get_source_cap_pending: bool,
+    /// The cable identity, cached until detach or hard reset.
+    cable_identity: Option<CableIdentity>,
+    /// The structured VDM version that is common with the source, until detach or hard reset.
+    vdm_version: VdmVersion,
+    /// Rate limited Attention messages, that are not yet delivered to the device policy manager.
+    attentions: AttentionQueue,
+    /// Statistics at the time of the last received source capabilities, for diagnosing silent sources.
+    stats_at_capabilities: Stats,
+    /// The time of attach or hard reset, until the next explicit contract, for measuring the time to contract.
+    attached_at_micros: Option<u64>,
+    config: SinkConfig,
+    /// Events that the device policy manager produced, while the `Ready` state was left for another reason.
+    ///
+    /// They are handled in the order they were produced, one per entry to the `Ready` state, and before polling the
+    /// device policy manager for new events.
+    pending_events: Deque<Event, PENDING_EVENT_COUNT>,
+    /// Whether the device policy manager was asked to enter Sink Standby.
+    standby: bool,
+    /// Whether automatic EPR mode entry was attempted since the last hard reset or attach.
+    auto_epr_attempted: bool,
+    /// The request to retry, after the source answered Wait.
+    request_retry: Option<PowerSource>,
+    /// The operational PDP to retry EPR mode entry with, after the source answered Wait.
+    epr_entry_retry: Option<units::Power>,
+    /// Counts the Wait responses to EPR mode entry, see spec [6.6.7.3] (nBusyCount).
+    epr_entry_busy_counter: Counter,
+    /// The EPR Sink Operational PDP that was sent with the last EPR mode entry.
+    epr_operational_pdp: Option<units::Power>,
+    /// The operational PDP to re-enter EPR mode with, after EPR mode was exited for changing it.
+    epr_reentry: Option<units::Power>,
+    /// The quirks that the device policy manager registered.
+    #[cfg(feature = "quirks")]
+    quirk_registry: QuirkRegistry,
+    /// What is known about the identity of the source, cleared on detach or hard reset.
+    source_identity: SourceIdentity,
+    /// The quirks of the attached source.
+    #[cfg(feature = "quirks")]
+    active_quirks: Quirks,
 
     _timer: PhantomData<TIMER>,
 }
 
+/// The likely cause of a port partner that stopped responding, after all hard resets were exhausted.
+///
+/// Based on the frames that were observed since the last received source capabilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Diagnosis {
+    /// No PD traffic was observed. Points to hardware causes, such as CC line wiring, or a source without PD.
+    NoTraffic,
+    /// Only frames with errors, such as CRC errors, were observed. Points to signal integrity issues.
+    CorruptTraffic,
+    /// Valid PD traffic was observed, but no (acceptable) source capabilities. Points to firmware causes.
+    NoCapabilities,
+    /// Unknown, because statistics collection is disabled.
+    Unknown,
+}
+
+impl Diagnosis {
+    /// Diagnose from the statistics at the last received source capabilities, and the current statistics.
+    pub fn from_stats(before: &Stats, now: &Stats) -> Self {
+        let received = now.frames_received.wrapping_sub(before.frames_received);
+        let discarded = now.frames_discarded.wrapping_sub(before.frames_discarded);
+
+        match (received, discarded) {
+            (0, 0) => Self::NoTraffic,
+            (0, _) => Self::CorruptTraffic,
+            _ => Self::NoCapabilities,
+        }
+    }
+}
+
 /// Errors that can occur in the sink policy engine state machine.
-#[derive(Debug)]
+#[derive(thiserror::Error, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
-    /// The port partner is unresponsive.
-    PortPartnerUnresponsive,
+    /// The port partner is unresponsive, with a diagnosis of the likely cause.
+    #[error("port partner unresponsive (`{0:?}`)")]
+    PortPartnerUnresponsive(Diagnosis),
+    /// The port partner detached, as reported by the driver.
+    ///
+    /// All state of the attach was invalidated, and the sink starts over, when it is run again.
+    #[error("detached")]
+    Detached,
+    /// The power role was swapped, and the port is the source now.
+    ///
+    /// Continue with a source policy engine on the same driver, see [`Sink::into_parts`], and [`crate::drp`].
+    #[error("power role swapped")]
+    PowerRoleSwapped,
+    /// The power role swap failed after it was accepted, e.g. because the source did not turn VBUS off in time.
+    ///
+    /// Per spec 6.8.1, the port shall perform Type-C error recovery.
+    #[error("power role swap failed")]
+    PowerRoleSwapFailed,
     /// A protocol error has occured.
-    Protocol(ProtocolError),
+    #[error("protocol error")]
+    Protocol(#[from] ProtocolError),
 }
 
-impl From<ProtocolError> for Error {
-    fn from(protocol_error: ProtocolError) -> Self {
-        Error::Protocol(protocol_error)
+impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager, const HISTORY: usize>
+    Sink<DRIVER, TIMER, DPM, (), HISTORY>
+{
+    /// Create a new sink policy engine with a given `driver`.
+    pub fn new(driver: DRIVER, device_policy_manager: DPM) -> Self {
+        Self::new_with_tracer(driver, device_policy_manager, ())
+    }
+
+    /// Create a new sink policy engine with a given `driver`, and a non-default configuration.
+    pub fn new_with_config(driver: DRIVER, device_policy_manager: DPM, config: SinkConfig) -> Self {
+        Self::new_with_config_and_tracer(driver, device_policy_manager, config, ())
     }
 }
 
-impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER, DPM> {
+impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager, TRACER: Tracer, const HISTORY: usize>
+    Sink<DRIVER, TIMER, DPM, TRACER, HISTORY>
+{
     /// Create a fresh protocol layer with initial state.
-    fn new_protocol_layer(driver: DRIVER) -> ProtocolLayer<DRIVER, TIMER> {
-        let header = Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X);
-        ProtocolLayer::new(driver, header)
+    fn new_protocol_layer(driver: DRIVER, tracer: TRACER, config: &SinkConfig) -> ProtocolLayer<DRIVER, TIMER, TRACER> {
+        let header = Header::new_template(DataRole::Ufp, PowerRole::Sink, config.max_spec_revision());
+        let mut protocol_layer = ProtocolLayer::new_with_tracer(driver, header, tracer);
+        protocol_layer.set_good_crc_config(config.good_crc());
+        protocol_layer.set_timer_overrides(*config.timer_overrides());
+        protocol_layer.set_backoff(config.backoff());
+        protocol_layer.set_rng(config.rng());
+        protocol_layer.set_collect_stats(config.stats_enabled());
+        protocol_layer.set_listen_only(config.listen_only());
+        protocol_layer.set_chunk_buffer_size(config.chunk_buffer_size());
+        protocol_layer
     }
 
-    /// Create a new sink policy engine with a given `driver`.
-    pub fn new(driver: DRIVER, device_policy_manager: DPM) -> Self {
-        Self {
+    /// Create a new sink policy engine with a given `driver`, that records protocol events to `tracer`.
+    ///
+    /// See [`crate::trace`].
+    pub fn new_with_tracer(driver: DRIVER, device_policy_manager: DPM, tracer: TRACER) -> Self {
+        Self::new_with_config_and_tracer(driver, device_policy_manager, SinkConfig::new(), tracer)
+    }
+
+    /// Create a new sink policy engine with a given `driver` and configuration, that records protocol events to
+    /// `tracer`.
+    pub fn new_with_config_and_tracer(
+        driver: DRIVER,
+        device_policy_manager: DPM,
+        config: SinkConfig,
+        tracer: TRACER,
+    ) -> Self {
+        #[cfg_attr(not(feature = "quirks"), allow(unused_mut))]
+        let mut sink = Self {
             device_policy_manager,
-            protocol_layer: Self::new_protocol_layer(driver),
+            protocol_layer: Self::new_protocol_layer(driver, tracer.clone(), &config),
+            tracer,
             state: State::Discovery,
             contract: Default::default(),
+            accepted_power_source: None,
+            pending_request: None,
             hard_reset_counter: Counter::new(crate::counters::CounterType::HardReset),
             source_capabilities: None,
+            capability_history: Deque::new(),
             mode: Mode::Spr,
             get_source_cap_pending: false,
+            cable_identity: None,
+            vdm_version: VdmVersion::LATEST,
+            attentions: AttentionQueue::new(config.attention_rate_limit()),
+            stats_at_capabilities: Stats::default(),
+            attached_at_micros: None,
+            config,
+            pending_events: Deque::new(),
+            standby: false,
+            auto_epr_attempted: false,
+            request_retry: None,
+            epr_entry_retry: None,
+            epr_operational_pdp: None,
+            epr_reentry: None,
+            epr_entry_busy_counter: Counter::new(crate::counters::CounterType::Busy),
+            #[cfg(feature = "quirks")]
+            quirk_registry: QuirkRegistry::new(),
+            source_identity: SourceIdentity::default(),
+            #[cfg(feature = "quirks")]
+            active_quirks: Quirks::new(),
             _timer: PhantomData,
-        }
+        };
+
+        #[cfg(feature = "quirks")]
+        sink.device_policy_manager.register_quirks(&mut sink.quirk_registry);
+
+        sink
     }
 
     /// Set a new driver when re-attached.
+    ///
+    /// Like on detach, the contract, EPR mode, and all caches of the previous attach are invalidated, and the sink
+    /// starts over from `Startup`. This also applies, if `run` was cancelled in any state.
     pub fn re_attach(&mut self, driver: DRIVER) {
-        self.protocol_layer = Self::new_protocol_layer(driver);
+        self.protocol_layer = Self::new_protocol_layer(driver, self.tracer.clone(), &self.config);
+        self.reset_attachment();
+    }
+
+    /// Allows tests of other modules to access the driver directly.
+    #[cfg(test)]
+    pub(crate) fn driver(&mut self) -> &mut DRIVER {
+        self.protocol_layer.driver()
+    }
+
+    /// The data role of the port, which is kept across power role swaps.
+    pub fn data_role(&self) -> DataRole {
+        self.protocol_layer.header().port_data_role()
+    }
+
+    /// Release the driver and the device policy manager, e.g. after [`Error::PowerRoleSwapped`].
+    pub fn into_parts(self) -> (DRIVER, DPM) {
+        (self.protocol_layer.into_driver(), self.device_policy_manager)
+    }
+
+    /// Take over the port from a source, after a power role swap, keeping its data role.
+    pub(crate) fn swapped_in(&mut self, data_role: DataRole) {
+        self.protocol_layer.set_roles(data_role, PowerRole::Sink);
+    }
+
+    /// The configuration of the sink.
+    pub fn config(&self) -> &SinkConfig {
+        &self.config
+    }
+
+    /// The last received source capabilities, SPR or EPR, if any.
+    ///
+    /// They are cleared on hard reset, and when re-attached.
+    pub fn source_capabilities(&self) -> Option<&SourceCapabilities> {
+        self.source_capabilities.as_ref()
+    }
+
+    /// The last `HISTORY` source capability advertisements, SPR or EPR.
+    ///
+    /// They are kept across hard resets, and cleared when re-attached.
+    pub fn capability_history(&self) -> CapabilityHistory<'_> {
+        let (older, newer) = self.capability_history.as_slices();
+        CapabilityHistory::new(older, newer)
+    }
+
+    /// The cached identity of the attached cable, if known.
+    pub fn cable_identity(&self) -> Option<&CableIdentity> {
+        self.cable_identity.as_ref()
+    }
+
+    /// The structured VDM version that is common with the source.
+    ///
+    /// It is learned from the structured VDMs of the source, and used for all structured VDMs that the sink sends.
+    /// Until then, it is the latest supported version.
+    pub fn vdm_version(&self) -> VdmVersion {
+        self.vdm_version
+    }
+
+    /// The EPR Sink Operational PDP that was sent at EPR mode entry, while in EPR mode.
+    ///
+    /// It can only be changed by exiting and re-entering EPR mode, see [`Event::ChangeEprOperationalPdp`].
+    pub fn epr_operational_pdp(&self) -> Option<units::Power> {
+        self.epr_operational_pdp.filter(|_| self.mode == Mode::Epr)
+    }
+
+    /// Configure GoodCRC responses, such as the latency budget, or their priority.
+    pub fn set_good_crc_config(&mut self, config: GoodCrcConfig) {
+        self.config = self.config.with_good_crc(config);
+        self.protocol_layer.set_good_crc_config(config);
+    }
+
+    /// What is known about the identity of the attached source.
+    pub fn source_identity(&self) -> &SourceIdentity {
+        &self.source_identity
+    }
+
+    /// The negotiated contract, and the identity of the source, for storing them across reboots.
+    ///
+    /// Returns `None` without an explicit contract.
+    pub fn stored_contract(&self) -> Option<StoredContract> {
+        StoredContract::new(
+            &self.source_identity,
+            self.accepted_power_source.as_ref()?,
+            self.mode == Mode::Epr,
+        )
+    }
+
+    /// The quirks that apply to the attached source.
+    #[cfg(feature = "quirks")]
+    pub fn active_quirks(&self) -> Quirks {
+        self.active_quirks
+    }
+
+    /// The number of hard resets that were signaled, since source capabilities were last received.
+    pub fn hard_reset_count(&self) -> u8 {
+        self.hard_reset_counter.value()
+    }
+
+    /// Statistics, as collected by the protocol layer.
+    pub fn stats(&self) -> &Stats {
+        self.protocol_layer.stats()
+    }
+
+    /// Complete the power transition to `power_source`, after the source sent PS_RDY.
+    async fn complete_transition(&mut self, power_source: PowerSource) -> State {
+        let ramp = match self.source_capabilities.as_ref() {
+            Some(capabilities) => CurrentRamp::new(self.accepted_power_source.as_ref(), &power_source, capabilities),
+            // Requests are only made from source capabilities.
+            None => CurrentRamp::unramped(),
+        };
+
+        self.contract = Contract::TransitionToExplicit;
+        self.accepted_power_source = Some(power_source);
+
+        if let (Some(attached), Some(now)) = (self.attached_at_micros.take(), TIMER::now_micros()) {
+            self.protocol_layer
+                .record_time_to_contract(now.saturating_sub(attached), self.config.time_to_contract_budget());
+        }
+
+        if core::mem::take(&mut self.standby) {
+            self.device_policy_manager.exit_standby().await;
+        }
+        self.device_policy_manager.transition_power(&power_source, &ramp).await;
+        State::Ready(power_source, false)
     }
 
     /// Run a single step in the policy engine state machine.
-    async fn run_step(&mut self) -> Result<(), Error> {
+    pub(crate) async fn run_step(&mut self) -> Result<(), Error> {
+        self.device_policy_manager.tick();
+
+        #[cfg(test)]
+        let result = match self.protocol_layer.injector().on_state(self.state.name()) {
+            Some(error) => Err(error.into()),
+            None => self.update_state().await,
+        };
+        #[cfg(not(test))]
         let result = self.update_state().await;
         if result.is_ok() {
+            self.protocol_layer.clear_driver_faults();
             return Ok(());
         }
+        if let Err(Error::PowerRoleSwapped | Error::PowerRoleSwapFailed) = result {
+            return result;
+        }
 
         if let Err(Error::Protocol(protocol_error)) = result {
+            self.protocol_layer
+                .trace(TraceEvent::ProtocolError(protocol_error.clone()));
+
+            if matches!(
+                protocol_error,
+                ProtocolError::RxError(RxError::Detached) | ProtocolError::TxError(TxError::Detached)
+            ) {
+                self.reset_attachment();
+                return Err(Error::Detached);
+            }
+
+            // A local fault of the driver is not caused by the port partner, so retry the step without a reset, unless
+            // faults persist after recovering the driver.
+            if protocol_error.origin() == ErrorOrigin::Driver {
+                warn!("Driver fault {:?} in sink state transition", protocol_error);
+                if !self.protocol_layer.on_driver_fault().await {
+                    self.set_state(State::HardReset);
+                }
+                return Ok(());
+            }
+
+            // Per spec 6.8.1: once a power role swap was accepted, protocol errors lead to error recovery.
+            if matches!(self.state, State::PrsTransitionToOff | State::PrsSourceOn) {
+                return Err(Error::PowerRoleSwapFailed);
+            }
+
+            if let (State::TransitionSink(_), ProtocolError::RxError(RxError::SoftReset)) =
+                (&self.state, &protocol_error)
+            {
+                warn!("Soft_Reset during power transition");
+                self.device_policy_manager
+                    .transition_anomaly(TransitionAnomaly::SoftResetDuringTransition)
+                    .await;
+            }
+
             let new_state = match (&self.mode, &self.state, protocol_error) {
                 // Handle when hard reset is signaled by the driver itself.
                 (_, _, ProtocolError::RxError(RxError::HardReset) | ProtocolError::TxError(TxError::HardReset)) => {
                     Some(State::TransitionToDefault)
                 }
 
+                // Per USB PD Spec R3.2 Section 8.3.3.3.6 and Table 6.72:
+                // Any Protocol Error during power transition (PE_SNK_Transition_Sink state)
+                // shall trigger a Hard Reset, not a Soft Reset. This includes a Soft_Reset from the source,
+                // as the accepted request can neither be completed, nor rolled back.
+                (_, State::TransitionSink(_), _) => Some(State::HardReset),
+
                 // Handle when soft reset is signaled by the driver itself.
                 (_, _, ProtocolError::RxError(RxError::SoftReset)) => Some(State::SoftReset),
 
@@ -161,6 +600,9 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                     Some(State::HardReset)
                 }
 
+                // A line that stays busy would fail a Soft_Reset as well, so reset the port partner right away.
+                (_, _, ProtocolError::TxError(TxError::DiscardStorm(_))) => Some(State::HardReset),
+
                 // Per spec 8.3.3.3.3: SinkWaitCapTimer timeout triggers Hard Reset.
                 (_, State::WaitForCapabilities, ProtocolError::RxError(RxError::ReceiveTimeout)) => {
                     Some(State::HardReset)
@@ -171,11 +613,6 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                     Some(State::HardReset)
                 }
 
-                // Per USB PD Spec R3.2 Section 8.3.3.3.6 and Table 6.72:
-                // Any Protocol Error during power transition (PE_SNK_Transition_Sink state)
-                // shall trigger a Hard Reset, not a Soft Reset.
-                (_, State::TransitionSink(_), _) => Some(State::HardReset),
-
                 // Unexpected messages indicate a protocol error and demand a soft reset.
                 // Per spec 6.8.1 Table 6.72 (for non-power-transitioning states).
                 // Note: This must come AFTER TransitionSink check above.
@@ -200,7 +637,7 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
             };
 
             if let Some(state) = new_state {
-                self.state = state
+                self.set_state(state);
             }
 
             Ok(())
@@ -210,15 +647,39 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
         }
     }
 
+    /// Transmit the BIST carrier signal for tBISTContMode, e.g. for emissions testing or driver calibration.
+    ///
+    /// Returns `false`, if the driver does not support it. Call this only while the state machine is not running.
+    #[cfg(feature = "bist")]
+    pub async fn bist_carrier_mode(&mut self) -> bool {
+        self.protocol_layer.bist_carrier_mode().await
+    }
+
+    /// Select Try.SNK or Try.SRC behavior of a dual-role port, for the next Type-C connection.
+    ///
+    /// Returns `false`, if the driver does not support it.
+    pub async fn set_role_preference(&mut self, preference: RolePreference) -> bool {
+        self.protocol_layer.set_role_preference(preference).await
+    }
+
     /// Run the sink's state machine continuously.
     ///
-    /// The loop is only broken for unrecoverable errors, for example if the port partner is unresponsive.
+    /// The loop is only broken for unrecoverable errors, for example if the port partner is unresponsive, or on
+    /// detach ([`Error::Detached`]).
     pub async fn run(&mut self) -> Result<(), Error> {
         loop {
             self.run_step().await?;
         }
     }
 
+    /// The evaluated source capabilities, which are present in all states that follow `EvaluateCapabilities`.
+    fn evaluated_capabilities(source_capabilities: &Option<SourceCapabilities>) -> Result<&SourceCapabilities, Error> {
+        match source_capabilities {
+            Some(source_capabilities) => Ok(source_capabilities),
+            None => unreachable_or_return!(ProtocolError::UnexpectedMessage),
+        }
+    }
+
     /// Wait for source capabilities message (either Source_Capabilities or EPR_Source_Capabilities).
     ///
     /// Per USB PD Spec R3.2 Section 8.3.3.3.3 (PE_SNK_Wait_for_Capabilities):
@@ -229,7 +690,7 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
     /// Per spec section 6.4.1.2.2, after a Soft Reset while in EPR Mode, the source sends
     /// EPR_Source_Capabilities. Therefore this function must handle both message types.
     async fn wait_for_source_capabilities(
-        protocol_layer: &mut ProtocolLayer<DRIVER, TIMER>,
+        protocol_layer: &mut ProtocolLayer<DRIVER, TIMER, TRACER>,
     ) -> Result<SourceCapabilities, Error> {
         let message = protocol_layer.wait_for_source_capabilities().await?;
         trace!("Source capabilities: {:?}", message);
@@ -237,7 +698,7 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
         let capabilities = match message.payload {
             Some(Payload::Data(Data::SourceCapabilities(caps))) => caps,
             Some(Payload::Extended(extended::Extended::EprSourceCapabilities(pdos))) => SourceCapabilities(pdos),
-            _ => unreachable!(),
+            _ => unreachable_or_return!(ProtocolError::UnexpectedMessage),
         };
 
         Ok(capabilities)
@@ -247,14 +708,19 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
         let new_state = match &self.state {
             State::Startup => {
                 self.contract = Default::default();
+                self.accepted_power_source = None;
+                self.pending_request = None;
                 self.protocol_layer.reset();
                 self.mode = Mode::Spr;
 
                 State::Discovery
             }
             State::Discovery => {
-                self.protocol_layer.wait_for_vbus().await;
+                if DRIVER::HAS_VBUS_DETECTION {
+                    self.protocol_layer.wait_for_vbus().await;
+                }
                 self.source_capabilities = None;
+                self.attached_at_micros = TIMER::now_micros();
 
                 State::WaitForCapabilities
             }
@@ -262,57 +728,160 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                 State::EvaluateCapabilities(Self::wait_for_source_capabilities(&mut self.protocol_layer).await?)
             }
             State::EvaluateCapabilities(capabilities) => {
+                #[cfg(feature = "capability-summary")]
+                {
+                    let summary =
+                        crate::trace::CapabilitiesSummary::new(capabilities, self.source_capabilities.as_ref());
+                    info!(
+                        "Source capabilities: {} PDOs, max {} W, EPR {}, changed {}",
+                        summary.count, summary.max_power_watts, summary.epr_mode_capable, summary.changed
+                    );
+                    self.protocol_layer.trace(TraceEvent::CapabilitiesEvaluated(summary));
+                }
+
                 // Sink now knows that it is attached.
                 self.source_capabilities = Some(capabilities.clone());
 
+                if self.capability_history.is_full() {
+                    self.capability_history.pop_front();
+                }
+                // Cannot fail, there is space now.
+                let _ = self.capability_history.push_back(capabilities.clone());
+
+                // EPR capabilities differ from the SPR capabilities, which identify the source.
+                if self.mode == Mode::Spr {
+                    self.source_identity.fingerprint = Some(identity::fingerprint(capabilities));
+                    #[cfg(feature = "quirks")]
+                    self.apply_quirks();
+                }
+
                 self.hard_reset_counter.reset();
+                self.stats_at_capabilities = *self.protocol_layer.stats();
 
-                let request = self
-                    .device_policy_manager
-                    .request(self.source_capabilities.as_ref().unwrap())
+                let (older, newer) = self.capability_history.as_slices();
+                self.device_policy_manager
+                    .capabilities_received(CapabilityHistory::new(older, newer))
                     .await;
 
+                let capabilities = Self::evaluated_capabilities(&self.source_capabilities)?;
+                let unknown_pdo_policy = self.config.unknown_pdo_policy();
+                if unknown_pdo_policy == UnknownPdoPolicy::Forward {
+                    for (position, raw) in capabilities.unknown_pdos() {
+                        self.device_policy_manager.unknown_pdo(position, raw).await;
+                    }
+                }
+
+                let hinted = match self.mode {
+                    Mode::Spr => self
+                        .device_policy_manager
+                        .preferred_contract()
+                        .and_then(|hint| hint.power_source(capabilities)),
+                    Mode::Epr => None,
+                };
+
+                let request =
+                    if unknown_pdo_policy == UnknownPdoPolicy::Reject && capabilities.unknown_pdos().next().is_some() {
+                        warn!("Capabilities with unknown PDOs, requesting vSafe5V");
+                        vsafe_5v_request(capabilities)
+                    } else {
+                        match hinted {
+                            Some(request) => {
+                                debug!("Requesting the preferred contract");
+                                request
+                            }
+                            None => self.device_policy_manager.request(capabilities).await,
+                        }
+                    };
+
                 State::SelectCapability(request)
             }
             State::SelectCapability(power_source) => {
+                let power_source = &power_source.with_attributes(self.device_policy_manager.request_attributes());
+
+                // Per spec 7.2.3: large programmable supply voltage changes require Sink Standby.
+                if !self.standby
+                    && let (Some(accepted), Some(capabilities)) =
+                        (self.accepted_power_source.as_ref(), self.source_capabilities.as_ref())
+                    && programmable_standby_required(accepted, power_source, capabilities)
+                {
+                    self.device_policy_manager.enter_standby().await;
+                    self.standby = true;
+                }
+
                 self.protocol_layer.request_power(*power_source).await?;
+                self.pending_request = Some(*power_source);
+
+                let responses = [
+                    MessageType::Control(ControlMessageType::Accept),
+                    MessageType::Control(ControlMessageType::Wait),
+                    MessageType::Control(ControlMessageType::Reject),
+                    MessageType::Control(ControlMessageType::PsRdy),
+                ];
+                let responses = if self.tolerate_missing_accept() {
+                    &responses[..]
+                } else {
+                    &responses[..3]
+                };
 
                 let message_type = self
                     .protocol_layer
-                    .receive_message_type(
-                        &[
-                            MessageType::Control(ControlMessageType::Accept),
-                            MessageType::Control(ControlMessageType::Wait),
-                            MessageType::Control(ControlMessageType::Reject),
-                        ],
-                        TimerType::SenderResponse,
-                    )
+                    .receive_message_type(responses, TimerType::SenderResponse)
                     .await?
                     .header
                     .message_type();
 
                 let MessageType::Control(control_message_type) = message_type else {
-                    unreachable!()
+                    unreachable_or_return!(ProtocolError::UnexpectedMessage)
                 };
 
+                // Only an accepted request is completed by a PS_RDY.
+                if control_message_type != ControlMessageType::Accept {
+                    self.pending_request = None;
+                }
+
                 match (self.contract, control_message_type) {
                     (_, ControlMessageType::Accept) => State::TransitionSink(*power_source),
+                    // Only received, if tolerated by the configuration.
+                    (_, ControlMessageType::PsRdy) => {
+                        warn!("Source sent PS_RDY without Accept");
+                        self.device_policy_manager
+                            .transition_anomaly(TransitionAnomaly::MissingAccept)
+                            .await;
+                        self.complete_transition(*power_source).await
+                    }
                     (Contract::Safe5V, ControlMessageType::Wait | ControlMessageType::Reject) => {
                         State::WaitForCapabilities
                     }
-                    (Contract::Explicit, ControlMessageType::Reject) => State::Ready(*power_source, false),
+                    // Per spec 8.3.3.3.5: with an explicit contract, Reject or Wait leads back to Ready, and the
+                    // existing contract remains in place. This also applies to PPS periodic re-requests.
+                    (Contract::Explicit, ControlMessageType::Reject) => {
+                        self.device_policy_manager
+                            .request_refused(power_source, Refusal::Reject)
+                            .await;
+                        State::Ready(self.accepted_power_source.unwrap_or(*power_source), false)
+                    }
                     (Contract::Explicit, ControlMessageType::Wait) => {
+                        self.request_retry = Some(*power_source);
+                        self.epr_entry_retry = None;
+                        self.device_policy_manager
+                            .request_refused(power_source, Refusal::Wait)
+                            .await;
                         // Per spec 8.3.3.3.7: On entry to Ready as result of Wait,
                         // initialize and run SinkRequestTimer.
-                        State::Ready(*power_source, true)
+                        State::Ready(self.accepted_power_source.unwrap_or(*power_source), true)
                     }
-                    _ => unreachable!(),
+                    _ => unreachable_or_return!(ProtocolError::UnexpectedMessage),
                 }
             }
             State::TransitionSink(power_source) => {
-                self.protocol_layer
+                let message = self
+                    .protocol_layer
                     .receive_message_type(
-                        &[MessageType::Control(ControlMessageType::PsRdy)],
+                        &[
+                            MessageType::Control(ControlMessageType::PsRdy),
+                            MessageType::Data(DataMessageType::SourceCapabilities),
+                            MessageType::Extended(ExtendedMessageType::EprSourceCapabilities),
+                        ],
                         match self.mode {
                             Mode::Epr => TimerType::PSTransitionEpr,
                             Mode::Spr => TimerType::PSTransitionSpr,
@@ -320,9 +889,43 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                     )
                     .await?;
 
-                self.contract = Contract::TransitionToExplicit;
-                self.device_policy_manager.transition_power(power_source).await;
-                State::Ready(*power_source, false)
+                let pending_request = self.pending_request.take();
+
+                // A source that advertises capabilities after Accept, has likely changed them without completing the
+                // transition. Per spec 8.3.3.3.6, this is a protocol error that leads to a hard reset.
+                let capabilities = match message.payload {
+                    Some(Payload::Data(Data::SourceCapabilities(capabilities))) => Some(capabilities),
+                    Some(Payload::Extended(extended::Extended::EprSourceCapabilities(pdos))) => {
+                        Some(SourceCapabilities(pdos))
+                    }
+                    _ => None,
+                };
+                if let Some(capabilities) = capabilities {
+                    let changed = self.source_capabilities.as_ref().is_none_or(|current| {
+                        !current
+                            .pdos()
+                            .iter()
+                            .map(|pdo| pdo.raw())
+                            .eq(capabilities.pdos().iter().map(|pdo| pdo.raw()))
+                    });
+                    warn!("Source capabilities during power transition, changed: {}", changed);
+                    self.device_policy_manager
+                        .transition_anomaly(TransitionAnomaly::CapabilitiesDuringTransition { changed })
+                        .await;
+                    self.set_state(State::HardReset);
+                    return Ok(());
+                }
+
+                // The PS_RDY must complete the request that the source accepted.
+                if pending_request.is_none_or(|pending| pending.raw() != power_source.raw()) {
+                    self.device_policy_manager
+                        .transition_anomaly(TransitionAnomaly::UnexpectedPsRdy)
+                        .await;
+                    self.set_state(State::HardReset);
+                    return Ok(());
+                }
+
+                self.complete_transition(*power_source).await
             }
             State::Ready(power_source, after_wait) => {
                 // TODO: Entry: Init. and run DiscoverIdentityTimer(4)
@@ -331,40 +934,127 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                 //
                 // Timers implemented:
                 // - SinkRequestTimer: Per spec 8.3.3.3.7, after receiving Wait, wait tSinkRequest
-                //   before allowing re-request. On timeout, transition to SelectCapability.
+                //   before allowing re-request. On timeout, transition to SelectCapability, or back to
+                //   EprModeEntry, if the Wait answered EPR mode entry.
                 // - SinkPPSPeriodicTimer: triggers SelectCapability in SPR PPS mode
                 // - SinkEPRKeepAliveTimer: triggers EprKeepAlive in EPR mode
                 self.contract = Contract::Explicit;
 
-                let receive_fut = self.protocol_layer.receive_message();
-                let event_fut = self
-                    .device_policy_manager
-                    .get_event(self.source_capabilities.as_ref().unwrap());
-                let pps_periodic_fut = async {
-                    match power_source {
-                        PowerSource::Pps(_) => TimerType::get_timer::<TIMER>(TimerType::SinkPPSPeriodic).await,
-                        _ => core::future::pending().await,
-                    }
-                };
-                let epr_keep_alive_fut = async {
-                    match self.mode {
-                        Mode::Epr => TimerType::get_timer::<TIMER>(TimerType::SinkEPRKeepAlive).await,
-                        Mode::Spr => core::future::pending().await,
-                    }
-                };
-                // Per spec 8.3.3.3.7: SinkRequestTimer runs concurrently when re-entering
-                // Ready after a Wait response. On timeout, transition to SelectCapability.
-                // Per spec 6.6.4.1: Ensures minimum tSinkRequest (100ms) delay before re-request.
-                let sink_request_fut = async {
-                    if *after_wait {
-                        TimerType::get_timer::<TIMER>(TimerType::SinkRequest).await
-                    } else {
-                        core::future::pending().await
-                    }
+                // A retry of a request or EPR mode entry is cancelled, when Ready is left before the SinkRequestTimer
+                // expired.
+                if !after_wait {
+                    self.request_retry = None;
+                    self.epr_entry_retry = None;
+                }
+
+                // The source refused a request, for which the sink entered standby.
+                if core::mem::take(&mut self.standby) {
+                    self.device_policy_manager.exit_standby().await;
+                }
+
+                if let Some(operational_pdp) = self.auto_epr_pdp() {
+                    self.auto_epr_attempted = true;
+                    self.set_state(State::EprModeEntry(*power_source, operational_pdp));
+                    return Ok(());
+                }
+
+                // EPR mode was exited for changing the operational PDP, and is entered again from the SPR contract.
+                if self.mode == Mode::Spr
+                    && let Some(operational_pdp) = self.epr_reentry.take()
+                {
+                    self.set_state(State::EprModeEntry(*power_source, operational_pdp));
+                    return Ok(());
+                }
+
+                let source_capabilities = Self::evaluated_capabilities(&self.source_capabilities)?;
+                if let Some(event) = self
+                    .pending_events
+                    .pop_front()
+                    .or_else(|| self.device_policy_manager.poll_event(source_capabilities))
+                {
+                    let state = self.event_state(event, power_source);
+                    self.set_state(state);
+                    return Ok(());
+                }
+
+                if let Some((header, vdos)) = self.attentions.take_due(TIMER::now_micros()) {
+                    self.device_policy_manager
+                        .vendor_defined_message(&VdmHeader::Structured(header), &vdos)
+                        .await;
+                    return Ok(());
+                }
+                #[cfg(feature = "tx-queue")]
+                if let Some(outbound) = self.config.tx_queue().and_then(TxQueue::take) {
+                    self.transmit_outbound(outbound).await?;
+                    return Ok(());
+                }
+
+                let attention_due_micros = self.attentions.due_in_micros(TIMER::now_micros());
+
+                let (outcome, cancelled_event) = {
+                    let receive_fut = self.protocol_layer.receive_message();
+                    let mut event_fut = pin!(self.device_policy_manager.get_event(source_capabilities));
+                    let timer_overrides = self.config.timer_overrides();
+                    let tracer = &self.tracer;
+                    let pps_periodic_fut = async {
+                        match power_source {
+                            PowerSource::Pps(_) => {
+                                timer_overrides
+                                    .get_traced_timer::<TIMER, TRACER>(TimerType::SinkPPSPeriodic, tracer)
+                                    .await
+                            }
+                            _ => core::future::pending().await,
+                        }
+                    };
+                    let epr_keep_alive_fut = async {
+                        match self.mode {
+                            Mode::Epr => {
+                                timer_overrides
+                                    .get_traced_timer::<TIMER, TRACER>(TimerType::SinkEPRKeepAlive, tracer)
+                                    .await
+                            }
+                            Mode::Spr => core::future::pending().await,
+                        }
+                    };
+                    // Per spec 8.3.3.3.7: SinkRequestTimer runs concurrently when re-entering
+                    // Ready after a Wait response. On timeout, transition to SelectCapability.
+                    // Per spec 6.6.4.1: Ensures minimum tSinkRequest (100ms) delay before re-request.
+                    let sink_request_fut = async {
+                        if *after_wait {
+                            timer_overrides
+                                .get_traced_timer::<TIMER, TRACER>(TimerType::SinkRequest, tracer)
+                                .await
+                        } else {
+                            core::future::pending().await
+                        }
+                    };
+                    // Rate limited Attention messages are delivered, when they are due.
+                    let attention_fut = async {
+                        match attention_due_micros {
+                            Some(micros) => TIMER::after_micros(micros).await,
+                            None => core::future::pending().await,
+                        }
+                    };
+                    let timers_fut =
+                        async { select4(pps_periodic_fut, epr_keep_alive_fut, sink_request_fut, attention_fut).await };
+
+                    let outcome = select3(receive_fut, event_fut.as_mut(), timers_fut).await;
+
+                    // Guard against losing an event, if the device policy manager's future is cancelled when it is
+                    // already complete, e.g. because it took the event from its source when it was created.
+                    let cancelled_event = match outcome {
+                        Either3::Second(_) => None,
+                        _ => poll_once(event_fut).await,
+                    };
+
+                    (outcome, cancelled_event)
                 };
-                let timers_fut = async { select3(pps_periodic_fut, epr_keep_alive_fut, sink_request_fut).await };
 
-                match select3(receive_fut, event_fut, timers_fut).await {
+                if let Some(event) = cancelled_event {
+                    retain_event(&mut self.pending_events, event);
+                }
+
+                match outcome {
                     // A message was received.
                     Either3::First(message) => {
                         let message = message?;
@@ -379,7 +1069,7 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                                 } else {
                                     let Some(Payload::Data(Data::SourceCapabilities(capabilities))) = message.payload
                                     else {
-                                        unreachable!()
+                                        unreachable_or_return!(ProtocolError::UnexpectedMessage)
                                     };
                                     self.get_source_cap_pending = false;
                                     State::EvaluateCapabilities(capabilities)
@@ -400,84 +1090,260 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                                         State::EvaluateCapabilities(caps)
                                     }
                                 } else {
-                                    unreachable!()
+                                    unreachable_or_return!(ProtocolError::UnexpectedMessage)
                                 }
                             }
                             MessageType::Data(DataMessageType::EprMode) => {
                                 // Handle source exit notification.
                                 State::EprExitReceived(*power_source)
                             }
+                            // Without an outstanding request, PS_RDY is unexpected, see spec Table 6.72
+                            MessageType::Control(ControlMessageType::PsRdy) => {
+                                warn!("PS_RDY without an accepted request");
+                                self.device_policy_manager
+                                    .transition_anomaly(TransitionAnomaly::UnexpectedPsRdy)
+                                    .await;
+                                State::SendSoftReset
+                            }
+                            MessageType::Data(DataMessageType::VendorDefined) => {
+                                let handled = match &message.payload {
+                                    Some(Payload::Data(Data::VendorDefined((header, vdos)))) => {
+                                        let attention = match header {
+                                            VdmHeader::Structured(header) => {
+                                                self.vdm_version =
+                                                    crate::vdm::negotiate_version(self.vdm_version, header);
+                                                crate::vdm::is_attention(header).then_some(header)
+                                            }
+                                            VdmHeader::Unstructured(_) => None,
+                                        };
+
+                                        // Attention messages are not answered, neither when queued, nor when dropped.
+                                        match attention.map(|attention| {
+                                            (attention, self.attentions.offer(TIMER::now_micros(), attention, vdos))
+                                        }) {
+                                            Some((_, Offer::Queued)) => true,
+                                            Some((attention, Offer::Dropped)) => {
+                                                warn!("Attention queue full, dropping message");
+                                                self.device_policy_manager.attention_dropped(attention).await;
+                                                true
+                                            }
+                                            _ => self.device_policy_manager.vendor_defined_message(header, vdos).await,
+                                        }
+                                    }
+                                    _ => false,
+                                };
+
+                                if handled {
+                                    State::Ready(*power_source, false)
+                                } else {
+                                    State::SendNotSupported(*power_source)
+                                }
+                            }
+                            // Per spec 8.3.3.3.7: Alert → Source_Alert_Received, then Get_Status.
+                            MessageType::Data(DataMessageType::Alert) => match &message.payload {
+                                Some(Payload::Data(Data::Alert(alert))) => {
+                                    State::GetSourceStatus(*power_source, *alert)
+                                }
+                                _ => State::SendNotSupported(*power_source),
+                            },
+                            #[cfg(feature = "bist")]
+                            MessageType::Data(DataMessageType::Bist) => match &message.payload {
+                                Some(Payload::Data(Data::Bist(bist))) => {
+                                    self.bist_state(bist.mode(), *power_source).await
+                                }
+                                _ => State::Ready(*power_source, false),
+                            },
                             // Per spec 8.3.3.3.7: Get_Sink_Cap → GiveSinkCap (send Sink_Capabilities)
                             MessageType::Control(ControlMessageType::GetSinkCap) => {
                                 State::GiveSinkCap(Mode::Spr, *power_source)
                             }
+                            MessageType::Control(ControlMessageType::GetSourceCap) => {
+                                State::GiveSourceCap(*power_source)
+                            }
+                            MessageType::Control(ControlMessageType::GetStatus) => State::GiveSinkStatus(*power_source),
+                            MessageType::Control(ControlMessageType::PrSwap) => State::PrsEvaluateSwap(*power_source),
+                            MessageType::Extended(ExtendedMessageType::GetManufacturerInfo) => match &message.payload {
+                                Some(Payload::Extended(extended::Extended::GetManufacturerInfo(target))) => {
+                                    State::GiveManufacturerInfo(*power_source, *target)
+                                }
+                                _ => State::SendNotSupported(*power_source),
+                            },
                             // Per spec 8.3.3.3.7: EPR_Get_Sink_Cap → GiveSinkCap (send EPR_Sink_Capabilities)
                             MessageType::Extended(ExtendedMessageType::ExtendedControl) => {
                                 if let Some(Payload::Extended(extended::Extended::ExtendedControl(ctrl))) =
                                     &message.payload
                                 {
-                                    if ctrl.message_type() == ExtendedControlMessageType::EprGetSinkCap {
-                                        State::GiveSinkCap(Mode::Epr, *power_source)
-                                    } else {
-                                        State::SendNotSupported(*power_source)
+                                    match ctrl.message_type() {
+                                        ExtendedControlMessageType::EprGetSinkCap => {
+                                            State::GiveSinkCap(Mode::Epr, *power_source)
+                                        }
+                                        ExtendedControlMessageType::Unknown(message_type) => {
+                                            warn!("Unknown extended control message type {}", message_type);
+                                            self.device_policy_manager.unknown_extended_control(message_type).await;
+                                            State::SendNotSupported(*power_source)
+                                        }
+                                        _ => State::SendNotSupported(*power_source),
                                     }
                                 } else {
                                     State::SendNotSupported(*power_source)
                                 }
                             }
+                            message_type @ (MessageType::Data(_) | MessageType::Extended(_)) => {
+                                match self
+                                    .device_policy_manager
+                                    .unhandled_message(message_type, self.protocol_layer.rx_payload())
+                                    .await
+                                {
+                                    UnhandledMessageResponse::NotSupported => State::SendNotSupported(*power_source),
+                                    UnhandledMessageResponse::Ignore => State::Ready(*power_source, false),
+                                    UnhandledMessageResponse::Data(message_type, data_objects) => {
+                                        self.protocol_layer
+                                            .transmit_data_objects(message_type, &data_objects)
+                                            .await?;
+                                        State::Ready(*power_source, false)
+                                    }
+                                }
+                            }
                             _ => State::SendNotSupported(*power_source),
                         }
                     }
                     // Event from device policy manager.
-                    Either3::Second(event) => match event {
-                        Event::RequestSprSourceCapabilities => State::GetSourceCap(Mode::Spr, *power_source),
-                        Event::RequestEprSourceCapabilities => State::GetSourceCap(Mode::Epr, *power_source),
-                        Event::EnterEprMode(pdp) => State::EprModeEntry(*power_source, pdp),
-                        Event::ExitEprMode => State::EprSendExit,
-                        Event::RequestPower(power_source) => State::SelectCapability(power_source),
-                        Event::None => State::Ready(*power_source, false),
-                    },
+                    Either3::Second(event) => self.event_state(event, power_source),
                     // Timer timeout handling
                     Either3::Third(timeout_source) => match timeout_source {
                         // PPS periodic timeout -> select capability again as keep-alive.
-                        Either3::First(_) => State::SelectCapability(*power_source),
+                        Either4::First(_) => State::SelectCapability(*power_source),
                         // EPR keep-alive timeout
-                        Either3::Second(_) => State::EprKeepAlive(*power_source),
-                        // SinkRequest timeout -> re-request power after Wait response
-                        Either3::Third(_) => State::SelectCapability(*power_source),
+                        Either4::Second(_) => State::EprKeepAlive(*power_source),
+                        // SinkRequest timeout -> re-request power, or re-enter EPR mode after Wait response
+                        Either4::Third(_) => match self.epr_entry_retry.take() {
+                            Some(operational_pdp) => State::EprModeEntry(*power_source, operational_pdp),
+                            None => State::SelectCapability(self.request_retry.take().unwrap_or(*power_source)),
+                        },
+                        // A queued Attention message is due, deliver it on re-entry.
+                        Either4::Fourth(_) => State::Ready(*power_source, *after_wait),
                     },
                 }
             }
+            State::SendVdm(power_source, header, vdos) => {
+                let power_source = *power_source;
+                let header = header.with_version(self.vdm_version);
+                match self.protocol_layer.request_structured_vdm(header, vdos).await {
+                    Ok(response) => {
+                        if let Some(Payload::Data(Data::VendorDefined((header, vdos)))) = &response.payload {
+                            if let VdmHeader::Structured(header) = header {
+                                self.vdm_version = crate::vdm::negotiate_version(self.vdm_version, header);
+                            }
+                            if let Some(product) = crate::vdm::discovered_product(header, vdos) {
+                                self.source_identity.product = Some(product);
+                                #[cfg(feature = "quirks")]
+                                self.apply_quirks();
+                            }
+
+                            self.device_policy_manager.vendor_defined_message(header, vdos).await;
+                        }
+                    }
+                    // Per spec 6.4.4.2.2: a missing response ends the VDM AMS, without further action.
+                    Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => {
+                        warn!("No response to VDM request {:?}", header);
+                    }
+                    Err(other) => return Err(other.into()),
+                }
+
+                State::Ready(power_source, false)
+            }
             State::SendNotSupported(power_source) => {
-                self.protocol_layer
-                    .transmit_control_message(ControlMessageType::NotSupported)
-                    .await?;
+                self.protocol_layer.transmit_not_supported().await?;
 
                 State::Ready(*power_source, false)
             }
-            State::SendSoftReset => {
-                self.protocol_layer.reset();
-
+            State::PrsEvaluateSwap(power_source) => {
+                let power_source = *power_source;
+
+                if !DRIVER::HAS_POWER_ROLE_SWAP {
+                    State::SendNotSupported(power_source)
+                } else if self.device_policy_manager.evaluate_power_role_swap().await {
+                    self.protocol_layer
+                        .transmit_control_message(ControlMessageType::Accept)
+                        .await?;
+                    State::PrsTransitionToOff
+                } else {
+                    self.protocol_layer.transmit_reject().await?;
+                    State::Ready(power_source, false)
+                }
+            }
+            State::PrsSendSwap(power_source) => {
+                let power_source = *power_source;
                 self.protocol_layer
-                    .transmit_control_message(ControlMessageType::SoftReset)
+                    .transmit_control_message(ControlMessageType::PrSwap)
                     .await?;
 
-                self.protocol_layer
+                // Per spec 8.3.3.19: without a response, or when refused, the sink stays ready.
+                match self
+                    .protocol_layer
                     .receive_message_type(
-                        &[MessageType::Control(ControlMessageType::Accept)],
+                        &[
+                            MessageType::Control(ControlMessageType::Accept),
+                            MessageType::Control(ControlMessageType::Reject),
+                            MessageType::Control(ControlMessageType::Wait),
+                        ],
                         TimerType::SenderResponse,
                     )
+                    .await
+                {
+                    Ok(message)
+                        if message.header.message_type() == MessageType::Control(ControlMessageType::Accept) =>
+                    {
+                        State::PrsTransitionToOff
+                    }
+                    Ok(_) | Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => State::Ready(power_source, false),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            State::PrsTransitionToOff => {
+                // The contract ends with the swap, so draw no more than standby power, until VBUS is off.
+                self.contract = Contract::Safe5V;
+                self.accepted_power_source = None;
+                self.device_policy_manager.enter_standby().await;
+
+                let timer_type = match self.mode {
+                    Mode::Spr => TimerType::PSSourceOffSpr,
+                    Mode::Epr => TimerType::PSSourceOffEpr,
+                };
+                match self
+                    .protocol_layer
+                    .receive_message_type(&[MessageType::Control(ControlMessageType::PsRdy)], timer_type)
+                    .await
+                {
+                    Ok(_) => State::PrsSourceOn,
+                    Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => return Err(Error::PowerRoleSwapFailed),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            State::PrsSourceOn => {
+                self.protocol_layer.set_cc_termination(CcTermination::Rp).await;
+                self.device_policy_manager.vsafe5v().await;
+
+                // PS_RDY is the first message, that is sent as the source.
+                self.protocol_layer.set_roles(self.data_role(), PowerRole::Source);
+                self.protocol_layer
+                    .transmit_control_message(ControlMessageType::PsRdy)
                     .await?;
 
+                return Err(Error::PowerRoleSwapped);
+            }
+            State::SendSoftReset => {
+                self.protocol_layer.soft_reset().await?;
+
                 State::WaitForCapabilities
             }
             State::SoftReset => {
+                // The Accept is the first message after the reset, with message ID zero. See spec, [6.7.1]
+                self.protocol_layer.reset();
                 self.protocol_layer
                     .transmit_control_message(ControlMessageType::Accept)
                     .await?;
 
-                self.protocol_layer.reset();
-
                 State::WaitForCapabilities
             }
             State::HardReset => {
@@ -497,15 +1363,47 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                 // With counter max_value = 3, we allow 3 hard reset attempts (counter 1, 2, 3)
                 // before wrap returns Err.
                 if self.hard_reset_counter.increment().is_err() {
-                    return Err(Error::PortPartnerUnresponsive);
-                }
+                    let diagnosis = if self.config.stats_enabled() {
+                        Diagnosis::from_stats(&self.stats_at_capabilities, self.protocol_layer.stats())
+                    } else {
+                        Diagnosis::Unknown
+                    };
+                    error!("Port partner unresponsive: {:?}", diagnosis);
+
+                    // The counter wrapped to zero, so that a retry starts another series of hard resets.
+                    match self.device_policy_manager.hard_resets_exhausted(diagnosis).await {
+                        HardResetsExhausted::GiveUp => return Err(Error::PortPartnerUnresponsive(diagnosis)),
+                        HardResetsExhausted::RetryAfter(cooldown_millis) => {
+                            TIMER::after_millis(cooldown_millis).await;
+                            State::HardReset
+                        }
+                        HardResetsExhausted::FallBackToTypeC => State::TypeCFallback,
+                    }
+                } else {
+                    // Transmit Hard Reset Signaling
+                    self.protocol_layer.hard_reset().await?;
 
-                // Transmit Hard Reset Signaling
-                self.protocol_layer.hard_reset().await?;
+                    State::TransitionToDefault
+                }
+            }
+            State::TypeCFallback => {
+                // The contract was reset to vSafe5V by the last hard reset. Keep listening, in case the source
+                // starts sending capabilities again.
+                let capabilities = loop {
+                    match Self::wait_for_source_capabilities(&mut self.protocol_layer).await {
+                        Ok(capabilities) => break capabilities,
+                        Err(Error::Protocol(ProtocolError::RxError(RxError::ReceiveTimeout))) => continue,
+                        Err(error) => return Err(error),
+                    }
+                };
 
-                State::TransitionToDefault
+                State::EvaluateCapabilities(capabilities)
             }
             State::TransitionToDefault => {
+                // Events of the device policy manager relate to the previous contract.
+                self.pending_events.clear();
+                self.standby = false;
+
                 // Per USB PD Spec R3.2 Section 8.3.3.3.9 (PE_SNK_Transition_to_default):
                 // This state is entered when:
                 // - Hard Reset Signaling is detected (received or transmitted)
@@ -530,10 +1428,24 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
 
                 // Reset contract to default
                 self.contract = Contract::Safe5V;
+                self.accepted_power_source = None;
+                self.pending_request = None;
 
                 // Clear cached source capabilities
                 self.source_capabilities = None;
 
+                // Per spec 6.4.4.3.1: cable discovery results are invalid after hard reset.
+                self.cable_identity = None;
+                self.vdm_version = VdmVersion::LATEST;
+                self.attentions.clear();
+
+                self.source_identity = SourceIdentity::default();
+                #[cfg(feature = "quirks")]
+                self.apply_quirks();
+
+                self.auto_epr_attempted = false;
+                self.epr_reentry = None;
+
                 State::Startup
             }
             State::GiveSinkCap(response_mode, power_source) => {
@@ -552,6 +1464,49 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
 
                 State::Ready(*power_source, false)
             }
+            State::GiveSourceCap(power_source) => {
+                match self.device_policy_manager.source_capabilities() {
+                    Some(capabilities) => {
+                        self.protocol_layer.transmit_source_capabilities(capabilities).await?;
+                    }
+                    None => self.protocol_layer.transmit_not_supported().await?,
+                }
+
+                State::Ready(*power_source, false)
+            }
+            State::GiveManufacturerInfo(power_source, target) => {
+                // Per USB PD Spec R3.2 Section 6.5.8: a battery or reserved target is answered with the VID 0xFFFF,
+                // and the "Not Supported" string. The sink has no batteries.
+                match self.device_policy_manager.identity() {
+                    Some(identity) => {
+                        let manufacturer_info = match target {
+                            ManufacturerInfoTarget::Port => identity.manufacturer_info(),
+                            ManufacturerInfoTarget::Battery(_) | ManufacturerInfoTarget::Reserved(_) => {
+                                ManufacturerInfo::not_supported()
+                            }
+                        };
+
+                        self.protocol_layer
+                            .transmit_extended(
+                                ExtendedMessageType::ManufacturerInfo,
+                                extended::Extended::ManufacturerInfo(manufacturer_info),
+                            )
+                            .await?;
+                    }
+                    None => self.protocol_layer.transmit_not_supported().await?,
+                }
+
+                State::Ready(*power_source, false)
+            }
+            State::GiveSinkStatus(power_source) => {
+                // Per USB PD Spec R3.2 (PE_SNK_Give_Sink_Status): answer Get_Status with Status.
+                let status = self.device_policy_manager.status().status_data_block();
+                self.protocol_layer
+                    .transmit_extended(ExtendedMessageType::Status, extended::Extended::Status(status))
+                    .await?;
+
+                State::Ready(*power_source, false)
+            }
             State::GetSourceCap(requested_mode, power_source) => {
                 // Per USB PD Spec R3.2 Section 8.3.3.3.12 (PE_SNK_Get_Source_Cap):
                 // - Send Get_Source_Cap (SPR) or EPR_Get_Source_Cap (EPR)
@@ -599,45 +1554,84 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                     Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => {
                         // Inform DPM of timeout (no capabilities received)
                         warn!("Get_Source_Cap timeout, returning to Ready");
-                        self.state = State::Ready(*power_source, false);
+                        self.set_state(State::Ready(*power_source, false));
                         return Ok(());
                     }
                     Err(e) => return Err(e.into()),
                 };
 
-                // Per spec 8.3.3.3.12:
-                // - In SPR mode + SPR caps requested + Source_Capabilities received → EvaluateCapabilities
-                // - In EPR mode + EPR caps requested + EPR_Source_Capabilities received → EvaluateCapabilities
-                // - Mode mismatch (e.g., EPR mode but SPR caps requested) → Ready
-                let received_spr = matches!(
-                    message.header.message_type(),
-                    MessageType::Data(DataMessageType::SourceCapabilities)
-                );
-                let received_epr = matches!(
-                    message.header.message_type(),
-                    MessageType::Extended(ExtendedMessageType::EprSourceCapabilities)
-                );
-
-                let mode_matches = (*requested_mode == Mode::Spr && self.mode == Mode::Spr && received_spr)
-                    || (*requested_mode == Mode::Epr && self.mode == Mode::Epr && received_epr);
-
                 // Extract capabilities from the message
-                let capabilities = match message.payload {
-                    Some(Payload::Data(Data::SourceCapabilities(caps))) => caps,
+                let (received_mode, capabilities) = match message.payload {
+                    Some(Payload::Data(Data::SourceCapabilities(caps))) => (Mode::Spr, caps),
                     Some(Payload::Extended(extended::Extended::EprSourceCapabilities(pdos))) => {
-                        SourceCapabilities(pdos)
+                        (Mode::Epr, SourceCapabilities(pdos))
                     }
-                    _ => unreachable!(),
+                    _ => unreachable_or_return!(ProtocolError::UnexpectedMessage),
                 };
 
                 self.device_policy_manager.inform(&capabilities).await;
 
-                if mode_matches {
-                    State::EvaluateCapabilities(capabilities)
-                } else {
-                    State::Ready(*power_source, false)
+                match discarded_capabilities(*requested_mode, self.mode, received_mode) {
+                    None => State::EvaluateCapabilities(capabilities),
+                    Some(reason) => {
+                        warn!("Discarding requested source capabilities: {:?}", reason);
+                        self.device_policy_manager
+                            .capabilities_discarded(&capabilities, reason)
+                            .await;
+                        State::Ready(*power_source, false)
+                    }
                 }
             }
+            State::GetSourceStatus(power_source, alert) => {
+                // Per USB PD Spec R3.2 Section 8.3.3.3.7 (PE_SNK_Get_Source_Status):
+                // - Send Get_Status, and start SenderResponseTimer
+                // - On Status received, or on timeout → Ready
+                self.protocol_layer
+                    .transmit_control_message(ControlMessageType::GetStatus)
+                    .await?;
+
+                let result = self
+                    .protocol_layer
+                    .receive_message_type(
+                        &[
+                            MessageType::Extended(ExtendedMessageType::Status),
+                            MessageType::Control(ControlMessageType::NotSupported),
+                        ],
+                        TimerType::SenderResponse,
+                    )
+                    .await;
+
+                let status = match result {
+                    Ok(message) => match message.payload {
+                        Some(Payload::Extended(extended::Extended::Status(status))) => Some(status),
+                        _ => None,
+                    },
+                    Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => {
+                        warn!("Get_Status timeout, returning to Ready");
+                        None
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+
+                self.device_policy_manager.alert_received(*alert, status.as_ref()).await;
+                State::Ready(*power_source, false)
+            }
+            State::GetPpsStatus(power_source) => {
+                // Per USB PD Spec R3.2 (PE_SNK_Get_PPS_Status):
+                // - Send Get_PPS_Status, and start SenderResponseTimer
+                // - On PPS_Status received, or on timeout → Ready
+                let status = match self.protocol_layer.get_pps_status().await {
+                    Ok(status) => status,
+                    Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => {
+                        warn!("Get_PPS_Status timeout, returning to Ready");
+                        None
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+
+                self.device_policy_manager.pps_status_received(status.as_ref()).await;
+                State::Ready(*power_source, false)
+            }
             State::EprModeEntry(power_source, operational_pdp) => {
                 // Request entry into EPR mode.
                 // Per spec 8.3.3.26.2.1 (PE_SNK_Send_EPR_Mode_Entry), sink sends EPR_Mode (Enter)
@@ -651,74 +1645,126 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                 // SinkEPREnterTimer (500ms) in EprEntryWaitForResponse. This means the total
                 // timeout could be ~530ms instead of 500ms in edge cases. However, this is
                 // within the spec's allowed range (tEnterEPR max = 550ms per Table 6.71).
-                let pdp_watts: u8 = operational_pdp.get::<watt>() as u8;
-                self.protocol_layer.transmit_epr_mode(Action::Enter, pdp_watts).await?;
+                if self.cable_identity.is_none() {
+                    self.cable_identity = self.device_policy_manager.discover_cable_identity().await;
+                }
+
+                if let Some(cable_identity) = &self.cable_identity
+                    && !cable_identity.epr_capable()
+                {
+                    warn!("Entering EPR mode with a cable that is not EPR capable");
+                }
+
+                self.protocol_layer
+                    .transmit_epr_mode(EprMode::Enter {
+                        operational_pdp: operational_pdp.get::<watt>() as u8,
+                    })
+                    .await?;
+                self.epr_operational_pdp = Some(*operational_pdp);
 
                 // Wait for EnterAcknowledged with SenderResponseTimer (spec step 9-14)
-                let message = self
+                // Per spec 8.3.3.26.2.1: any other EPR_Mode message is unexpected → Soft Reset
+                //
+                // A busy source may answer Wait, and a source that does not support EPR mode at this time Reject.
+                // Like for a power request, these end the AMS, and the SPR contract remains in place.
+                let response = self
                     .protocol_layer
-                    .receive_message_type(
-                        &[MessageType::Data(DataMessageType::EprMode)],
+                    .receive_message_matching(
+                        |message| match message.header.message_type() {
+                            MessageType::Control(refusal @ (ControlMessageType::Wait | ControlMessageType::Reject)) => {
+                                Some(Err(refusal))
+                            }
+                            _ => epr_mode_with_action(
+                                message,
+                                &[
+                                    Action::EnterAcknowledged,
+                                    Action::EnterSucceeded,
+                                    Action::Exit,
+                                    Action::EnterFailed,
+                                ],
+                            )
+                            .map(Ok),
+                        },
                         TimerType::SenderResponse,
                     )
                     .await?;
 
-                let Some(Payload::Data(Data::EprMode(epr_mode))) = message.payload else {
-                    unreachable!()
+                let epr_mode = match response {
+                    Ok(epr_mode) => epr_mode,
+                    Err(ControlMessageType::Wait) if self.epr_entry_busy_counter.increment().is_ok() => {
+                        // Per spec 8.3.3.3.7: retry after tSinkRequest, like a power request.
+                        self.request_retry = None;
+                        self.epr_entry_retry = Some(*operational_pdp);
+                        self.set_state(State::Ready(*power_source, true));
+                        return Ok(());
+                    }
+                    Err(refusal) => {
+                        let refusal = match refusal {
+                            ControlMessageType::Wait => Refusal::Wait,
+                            _ => Refusal::Reject,
+                        };
+                        self.epr_entry_busy_counter.reset();
+                        self.device_policy_manager.epr_mode_entry_refused(refusal).await;
+                        self.set_state(State::Ready(*power_source, false));
+                        return Ok(());
+                    }
                 };
+                self.epr_entry_busy_counter.reset();
 
-                match epr_mode.action() {
-                    Action::EnterAcknowledged => {
+                match epr_mode.mode() {
+                    EprMode::EnterAcknowledged => {
                         // Source acknowledged, now wait for EnterSucceeded
                         State::EprEntryWaitForResponse(*power_source)
                     }
-                    Action::EnterSucceeded => {
+                    EprMode::EnterSucceeded => {
                         // Source skipped EnterAcknowledged and went directly to EnterSucceeded
                         self.mode = Mode::Epr;
                         State::EprWaitForCapabilities(*power_source)
                     }
-                    Action::Exit => State::EprExitReceived(*power_source),
-                    Action::EnterFailed => {
+                    EprMode::Exit => State::EprExitReceived(*power_source),
+                    EprMode::EnterFailed(reason) => {
                         // Per spec 8.3.3.26.2.1: EnterFailed → Soft Reset
                         // Notify DPM of the failure reason before soft reset
-                        let reason = epr_mode::DataEnterFailed::from(epr_mode.data());
                         self.device_policy_manager.epr_mode_entry_failed(reason).await;
                         State::SendSoftReset
                     }
-                    // Per spec 8.3.3.26.2.1: any other EPR_Mode message → Soft Reset
-                    _ => State::SendSoftReset,
+                    EprMode::Enter { .. } | EprMode::Reserved { .. } => {
+                        unreachable_or_return!(ProtocolError::UnexpectedMessage)
+                    }
                 }
             }
             State::EprEntryWaitForResponse(power_source) => {
                 // Wait for EnterSucceeded after receiving EnterAcknowledged.
                 // Per spec 8.3.3.26.2.2 (PE_SNK_EPR_Mode_Wait_For_Response), use SinkEPREnterTimer
                 // for the overall timeout while source performs cable discovery.
-                let message = self
+                // Per spec 8.3.3.26.2.2: any other EPR_Mode message is unexpected → Soft Reset
+                let epr_mode = self
                     .protocol_layer
-                    .receive_message_type(&[MessageType::Data(DataMessageType::EprMode)], TimerType::SinkEPREnter)
+                    .receive_message_matching(
+                        |message| {
+                            epr_mode_with_action(message, &[Action::EnterSucceeded, Action::Exit, Action::EnterFailed])
+                        },
+                        TimerType::SinkEPREnter,
+                    )
                     .await?;
 
-                let Some(Payload::Data(Data::EprMode(epr_mode))) = message.payload else {
-                    unreachable!()
-                };
-
-                match epr_mode.action() {
-                    Action::EnterSucceeded => {
+                match epr_mode.mode() {
+                    EprMode::EnterSucceeded => {
                         // EPR mode entry succeeded. Per spec Table 8.39 step 21-29,
                         // source will automatically send EPR_Source_Capabilities after this.
                         self.mode = Mode::Epr;
                         State::EprWaitForCapabilities(*power_source)
                     }
-                    Action::Exit => State::EprExitReceived(*power_source),
-                    Action::EnterFailed => {
+                    EprMode::Exit => State::EprExitReceived(*power_source),
+                    EprMode::EnterFailed(reason) => {
                         // Per spec 8.3.3.26.2.2: EnterFailed → Soft Reset
                         // Notify DPM of the failure reason before soft reset
-                        let reason = epr_mode::DataEnterFailed::from(epr_mode.data());
                         self.device_policy_manager.epr_mode_entry_failed(reason).await;
                         State::SendSoftReset
                     }
-                    // Per spec 8.3.3.26.2.2: any other EPR_Mode message → Soft Reset
-                    _ => State::SendSoftReset,
+                    EprMode::Enter { .. } | EprMode::EnterAcknowledged | EprMode::Reserved { .. } => {
+                        unreachable_or_return!(ProtocolError::UnexpectedMessage)
+                    }
                 }
             }
             State::EprWaitForCapabilities(_power_source) => {
@@ -740,10 +1786,11 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                     }
                 }
             }
-            State::EprSendExit => {
+            State::EprSendExit(reentry) => {
                 // Inform partner we are exiting EPR.
-                self.protocol_layer.transmit_epr_mode(Action::Exit, 0).await?;
+                self.protocol_layer.transmit_epr_mode(EprMode::Exit).await?;
                 self.mode = Mode::Spr;
+                self.epr_reentry = *reentry;
                 State::WaitForCapabilities
             }
             State::EprExitReceived(power_source) => {
@@ -757,8 +1804,7 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
 
                 let is_epr_pdo_contract = match power_source {
                     PowerSource::EprRequest(epr) => {
-                        // Extract object position from RDO (bits 28-31)
-                        epr.object_position() >= 8
+                        ObjectPosition::new(epr.object_position()).is_some_and(ObjectPosition::is_epr)
                     }
                     // Non-EprRequest variants are only used in SPR mode, so always SPR PDOs
                     _ => false,
@@ -805,10 +1851,254 @@ impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Sink<DRIVER, TIMER,
                     Err(_) => State::HardReset,
                 }
             }
+            #[cfg(feature = "bist")]
+            State::BistCarrierMode => {
+                // Per spec 8.3.3.25.2: after tBISTContMode, the sink transitions to default.
+                self.protocol_layer.bist_carrier_mode().await;
+                State::TransitionToDefault
+            }
+            #[cfg(feature = "bist")]
+            State::BistTestData => {
+                // Per spec 6.4.3.2: test frames are acknowledged, but ignored. Only Hard Reset ends the mode.
+                self.protocol_layer.receive_message().await?;
+                State::BistTestData
+            }
         };
 
-        self.state = new_state;
+        self.set_state(new_state);
 
         Ok(())
     }
+
+    /// The state that handles a BIST message, from the `Ready` state.
+    ///
+    /// Test modes are only entered at vSafe5V, otherwise the message is ignored. See spec, [6.4.3]
+    #[cfg(feature = "bist")]
+    async fn bist_state(
+        &mut self,
+        mode: crate::protocol_layer::message::data::bist::Mode,
+        power_source: PowerSource,
+    ) -> State {
+        use crate::protocol_layer::message::data::bist::Mode as BistMode;
+
+        if power_source.object_position() != ObjectPosition::VSAFE_5V.get() {
+            warn!("Ignoring BIST {:?} outside of vSafe5V", mode);
+            return State::Ready(power_source, false);
+        }
+
+        match mode {
+            BistMode::CarrierMode if DRIVER::HAS_BIST_CARRIER_MODE => State::BistCarrierMode,
+            BistMode::TestData => State::BistTestData,
+            BistMode::SharedTestModeEntry | BistMode::SharedTestModeExit => {
+                self.device_policy_manager
+                    .bist_shared_test_mode(mode == BistMode::SharedTestModeEntry)
+                    .await;
+                State::Ready(power_source, false)
+            }
+            _ => {
+                warn!("Ignoring unsupported BIST {:?}", mode);
+                State::Ready(power_source, false)
+            }
+        }
+    }
+
+    /// Transmit a message that an auxiliary task submitted to the queue, from the `Ready` state.
+    #[cfg(feature = "tx-queue")]
+    async fn transmit_outbound(&mut self, outbound: Outbound) -> Result<(), ProtocolError> {
+        match outbound {
+            Outbound::Vdm(VdmHeader::Structured(header), vdos) => {
+                let header = VdmHeader::Structured(header.with_version(self.vdm_version));
+                self.protocol_layer.transmit_vdm(header, &vdos).await
+            }
+            Outbound::Vdm(header, vdos) => self.protocol_layer.transmit_vdm(header, &vdos).await,
+            Outbound::ExtendedControl(message_type) => {
+                self.protocol_layer
+                    .transmit_extended_control_message(message_type)
+                    .await
+            }
+        }
+    }
+
+    /// The state that handles an event of the device policy manager, from the `Ready` state.
+    fn event_state(&self, event: Event, power_source: &PowerSource) -> State {
+        match event {
+            Event::RequestEprSourceCapabilities | Event::EnterEprMode(_) | Event::ChangeEprOperationalPdp(_)
+                if !self.epr_enabled() =>
+            {
+                warn!("EPR mode is disabled, ignoring EPR request");
+                State::Ready(*power_source, false)
+            }
+            Event::RequestSprSourceCapabilities => State::GetSourceCap(Mode::Spr, *power_source),
+            Event::RequestEprSourceCapabilities => State::GetSourceCap(Mode::Epr, *power_source),
+            Event::EnterEprMode(pdp) => State::EprModeEntry(*power_source, pdp),
+            Event::ExitEprMode => State::EprSendExit(None),
+            Event::ChangeEprOperationalPdp(_) if self.mode != Mode::Epr => {
+                warn!("Not in EPR mode, ignoring operational PDP change");
+                State::Ready(*power_source, false)
+            }
+            Event::ChangeEprOperationalPdp(pdp) if self.epr_operational_pdp == Some(pdp) => {
+                State::Ready(*power_source, false)
+            }
+            // Per spec 6.4.10, the operational PDP is only sent at EPR mode entry, so EPR mode is exited and entered again.
+            Event::ChangeEprOperationalPdp(pdp) => State::EprSendExit(Some(pdp)),
+            Event::RequestPower(power_source) => State::SelectCapability(power_source),
+            Event::RequestPpsStatus => State::GetPpsStatus(*power_source),
+            Event::RequestVdm(header, vdos) => State::SendVdm(*power_source, header, vdos),
+            Event::SoftReset => State::SendSoftReset,
+            Event::PowerRoleSwap if !DRIVER::HAS_POWER_ROLE_SWAP => {
+                warn!("Power role swaps are not supported by the driver, ignoring request");
+                State::Ready(*power_source, false)
+            }
+            Event::PowerRoleSwap => State::PrsSendSwap(*power_source),
+            Event::None => State::Ready(*power_source, false),
+        }
+    }
+
+    /// The operational PDP to automatically enter EPR mode with, if this is due.
+    ///
+    /// Entry is only attempted once, from an SPR contract with an EPR capable source.
+    fn auto_epr_pdp(&self) -> Option<units::Power> {
+        let operational_pdp = self.config.auto_epr()?;
+        let epr_capable = self
+            .source_capabilities
+            .as_ref()
+            .is_some_and(SourceCapabilities::epr_mode_capable);
+
+        (self.epr_enabled() && self.mode == Mode::Spr && !self.auto_epr_attempted && epr_capable)
+            .then_some(operational_pdp)
+    }
+
+    /// Whether EPR mode may be used, by configuration and the quirks of the source.
+    fn epr_enabled(&self) -> bool {
+        #[cfg(feature = "quirks")]
+        if self.active_quirks.skip_epr {
+            return false;
+        }
+
+        self.config.epr_enabled()
+    }
+
+    /// Whether PS_RDY is accepted as the answer to a request, by configuration and the quirks of the source.
+    fn tolerate_missing_accept(&self) -> bool {
+        #[cfg(feature = "quirks")]
+        if self.active_quirks.tolerate_missing_accept {
+            return true;
+        }
+
+        self.config.tolerate_missing_accept()
+    }
+
+    /// Look up the quirks of the source by its identity, and apply them.
+    #[cfg(feature = "quirks")]
+    fn apply_quirks(&mut self) {
+        let quirks = self.quirk_registry.lookup(&self.source_identity);
+        if quirks != self.active_quirks {
+            info!("Source quirks: {:?}", quirks);
+        }
+
+        self.active_quirks = quirks;
+        self.protocol_layer
+            .set_timer_overrides(quirks.apply_timing_slack(*self.config.timer_overrides()));
+    }
+
+    /// Invalidate the contract, EPR mode, and all caches of the previous attach, and start over.
+    fn reset_attachment(&mut self) {
+        self.contract = Default::default();
+        self.accepted_power_source = None;
+        self.pending_request = None;
+        self.hard_reset_counter.reset();
+        self.source_capabilities = None;
+        self.capability_history.clear();
+        self.mode = Mode::Spr;
+        self.get_source_cap_pending = false;
+        self.cable_identity = None;
+        self.vdm_version = VdmVersion::LATEST;
+        self.attentions.clear();
+        self.stats_at_capabilities = Stats::default();
+        self.attached_at_micros = None;
+        self.pending_events.clear();
+        self.standby = false;
+        self.auto_epr_attempted = false;
+        self.request_retry = None;
+        self.epr_entry_retry = None;
+        self.epr_entry_busy_counter.reset();
+        self.epr_operational_pdp = None;
+        self.epr_reentry = None;
+        self.source_identity = SourceIdentity::default();
+        #[cfg(feature = "quirks")]
+        self.apply_quirks();
+        self.protocol_layer.reset();
+        self.set_state(State::Startup);
+    }
+
+    /// Enter a new state, recording the change.
+    fn set_state(&mut self, state: State) {
+        if state.name() != self.state.name() {
+            trace!("Enter state {:?}", state);
+            self.protocol_layer.trace(TraceEvent::StateChanged(state.name()));
+        }
+
+        self.state = state;
+    }
+}
+
+/// Request vSafe5V, at the highest current that the source offers for it.
+///
+/// Falls back to the first object position, if the capabilities lack a vSafe5V supply.
+fn vsafe_5v_request(capabilities: &SourceCapabilities) -> PowerSource {
+    PowerSource::new_fixed(
+        request::CurrentRequest::Highest,
+        request::VoltageRequest::Safe5V,
+        capabilities,
+    )
+    .unwrap_or(PowerSource::FixedVariableSupply(request::FixedVariableSupply::new(
+        ObjectPosition::VSAFE_5V,
+        50,
+        50,
+    )))
+}
+
+/// Keep an event for handling on the next entry to the `Ready` state.
+///
+/// If there are too many pending events, the oldest one is dropped.
+fn retain_event(pending_events: &mut Deque<Event, PENDING_EVENT_COUNT>, event: Event) {
+    if let Err(event) = pending_events.push_back(event) {
+        warn!("Too many pending events, dropping the oldest");
+        pending_events.pop_front();
+        _ = pending_events.push_back(event);
+    }
+}
+
+/// Poll a future once, returning its output, if it is complete.
+async fn poll_once<F: Future + Unpin>(mut future: F) -> Option<F::Output> {
+    core::future::poll_fn(|cx| {
+        Poll::Ready(match core::pin::Pin::new(&mut future).poll(cx) {
+            Poll::Ready(output) => Some(output),
+            Poll::Pending => None,
+        })
+    })
+    .await
+}
+
+/// Why requested source capabilities of the `received` mode are not evaluated, if so.
+///
+/// Per spec 8.3.3.3.12:
+/// - In SPR mode + SPR caps requested + Source_Capabilities received → EvaluateCapabilities
+/// - In EPR mode + EPR caps requested + EPR_Source_Capabilities received → EvaluateCapabilities
+/// - Mode mismatch (e.g., EPR mode but SPR caps requested) → Ready
+fn discarded_capabilities(requested: Mode, mode: Mode, received: Mode) -> Option<CapabilitiesDiscarded> {
+    match (requested, mode, received) {
+        (Mode::Spr, Mode::Spr, Mode::Spr) | (Mode::Epr, Mode::Epr, Mode::Epr) => None,
+        (Mode::Spr, Mode::Epr, Mode::Spr) => Some(CapabilitiesDiscarded::SprInEprMode),
+        (Mode::Epr, Mode::Spr, Mode::Epr) => Some(CapabilitiesDiscarded::EprInSprMode),
+        _ => Some(CapabilitiesDiscarded::NotRequested),
+    }
+}
+
+/// The EPR mode data object of a message, if it carries one of the `actions`.
+fn epr_mode_with_action(message: Message, actions: &[Action]) -> Option<epr_mode::EprModeDataObject> {
+    match message.payload {
+        Some(Payload::Data(Data::EprMode(epr_mode))) if actions.contains(&epr_mode.action()) => Some(epr_mode),
+        _ => None,
+    }
 }
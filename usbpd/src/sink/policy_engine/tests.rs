@@ -1,7 +1,7 @@
 //! Tests for the policy engine.
 
 use super::Sink;
-use crate::counters::{Counter, CounterType};
+use crate::counters::MessageId;
 use crate::dummy::{DUMMY_CAPABILITIES, DummyDriver, DummySinkDevice, DummyTimer, MAX_DATA_MESSAGE_SIZE};
 use crate::protocol_layer::message::data::Data;
 use crate::protocol_layer::message::data::epr_mode::Action;
@@ -17,8 +17,12 @@ fn get_policy_engine() -> Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, D
     Sink::new(DummyDriver::new(), DummySinkDevice {})
 }
 
-fn simulate_source_control_message<DPM: crate::sink::device_policy_manager::DevicePolicyManager>(
-    policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+fn simulate_source_control_message<
+    TIMER: crate::timers::Timer,
+    DPM: crate::sink::device_policy_manager::DevicePolicyManager,
+    TRACER: crate::trace::Tracer,
+>(
+    policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, TIMER, DPM, TRACER>,
     control_message_type: ControlMessageType,
     message_id: u8,
 ) {
@@ -27,7 +31,7 @@ fn simulate_source_control_message<DPM: crate::sink::device_policy_manager::Devi
 
     Message::new(Header::new_control(
         header,
-        Counter::new_from_value(CounterType::MessageId, message_id),
+        MessageId::new(message_id),
         control_message_type,
     ))
     .to_bytes(&mut buf);
@@ -56,7 +60,7 @@ fn simulate_source_epr_mode_message<DPM: crate::sink::device_policy_manager::Dev
     let source_header = get_source_header_template();
     let header = Header::new_data(
         source_header,
-        Counter::new_from_value(CounterType::MessageId, message_id),
+        MessageId::new(message_id),
         DataMessageType::EprMode,
         1, // 1 data object (the EprModeDataObject)
     );
@@ -84,12 +88,11 @@ fn simulate_epr_keep_alive_ack<DPM: crate::sink::device_policy_manager::DevicePo
     use crate::protocol_layer::message::extended::extended_control::{ExtendedControl, ExtendedControlMessageType};
 
     let source_header = get_source_header_template();
-    // Create extended message header (num_objects=0 as used in transmit_extended_control_message)
-    let header = Header::new_extended(
+    let header = Header::new_extended_for_payload(
         source_header,
-        Counter::new_from_value(CounterType::MessageId, message_id),
+        MessageId::new(message_id),
         ExtendedMessageType::ExtendedControl,
-        0,
+        2,
     );
 
     // Create the message with proper payload
@@ -108,6 +111,79 @@ fn simulate_epr_keep_alive_ack<DPM: crate::sink::device_policy_manager::DevicePo
     result
 }
 
+/// Negotiate a contract with the dummy capabilities, until the `Ready` state is reached.
+async fn negotiate_to_ready<
+    TIMER: crate::timers::Timer,
+    DPM: crate::sink::device_policy_manager::DevicePolicyManager,
+    TRACER: crate::trace::Tracer,
+>(
+    policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, TIMER, DPM, TRACER>,
+) {
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+
+    // `SelectCapability` -> `TransitionSink` -> `Ready`
+    simulate_source_control_message(policy_engine, ControlMessageType::Accept, 1);
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(policy_engine, ControlMessageType::PsRdy, 2);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    while policy_engine.protocol_layer.driver().has_transmitted_data() {
+        policy_engine.protocol_layer.driver().probe_transmitted_data();
+    }
+}
+
+/// Records the refused requests and EPR mode entries.
+#[derive(Default)]
+struct RefusalDevice {
+    refusals: std::vec::Vec<crate::sink::device_policy_manager::Refusal>,
+}
+
+impl crate::sink::device_policy_manager::DevicePolicyManager for RefusalDevice {
+    async fn request_refused(&mut self, _refused: &PowerSource, refusal: crate::sink::device_policy_manager::Refusal) {
+        self.refusals.push(refusal);
+    }
+
+    async fn epr_mode_entry_refused(&mut self, refusal: crate::sink::device_policy_manager::Refusal) {
+        self.refusals.push(refusal);
+    }
+}
+
+/// Produces a soft reset event, when polled.
+struct PollingDevice {
+    soft_reset: bool,
+}
+
+impl crate::sink::device_policy_manager::DevicePolicyManager for PollingDevice {
+    fn poll_event(
+        &mut self,
+        _source_capabilities: &crate::protocol_layer::message::data::source_capabilities::SourceCapabilities,
+    ) -> Option<crate::sink::device_policy_manager::Event> {
+        core::mem::take(&mut self.soft_reset).then_some(crate::sink::device_policy_manager::Event::SoftReset)
+    }
+}
+
+/// Records the reported transition anomalies.
+#[derive(Default)]
+struct AnomalyDevice {
+    anomalies: heapless::Vec<crate::sink::device_policy_manager::TransitionAnomaly, 2>,
+}
+
+impl crate::sink::device_policy_manager::DevicePolicyManager for AnomalyDevice {
+    async fn transition_anomaly(&mut self, anomaly: crate::sink::device_policy_manager::TransitionAnomaly) {
+        self.anomalies.push(anomaly).unwrap();
+    }
+}
+
 #[tokio::test]
 async fn test_negotiation() {
     // Instantiated in `Discovery` state
@@ -581,3 +657,2346 @@ async fn test_epr_negotiation() {
     eprintln!("=== Phase 5 Complete: {} EPR keep-alive cycles succeeded ===\n", 3);
     eprintln!("=== Full EPR negotiation test PASSED ===");
 }
+
+#[tokio::test]
+async fn test_trace_buffer() {
+    use core::cell::RefCell;
+
+    use crate::trace::{TraceBuffer, TraceEvent};
+
+    let trace = RefCell::new(TraceBuffer::<8>::new());
+    let mut policy_engine: Sink<_, DummyTimer, _, _> =
+        Sink::new_with_tracer(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, &trace);
+
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+
+    let trace = trace.borrow();
+    let events: std::vec::Vec<_> = trace.iter().map(|record| record.event.clone()).collect();
+
+    assert!(matches!(
+        events.as_slice(),
+        [
+            TraceEvent::StateChanged("WaitForCapabilities"),
+            TraceEvent::MessageReceived(MessageType::Data(DataMessageType::SourceCapabilities)),
+            TraceEvent::StateChanged("EvaluateCapabilities"),
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn test_config() {
+    use crate::protocol_layer::message::header::SpecificationRevision;
+    use crate::sink::config::SinkConfig;
+
+    const CONFIG: SinkConfig = SinkConfig::new()
+        .with_max_spec_revision(SpecificationRevision::R2_0)
+        .with_stats(false);
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, CONFIG);
+
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+
+    assert!(matches!(
+        policy_engine.protocol_layer.header().spec_revision(),
+        Ok(SpecificationRevision::R2_0)
+    ));
+    assert_eq!(policy_engine.stats().good_crc_transmitted, 0);
+
+    policy_engine.re_attach(DummyDriver::new());
+    assert!(!policy_engine.config().stats_enabled());
+    assert!(matches!(
+        policy_engine.protocol_layer.header().spec_revision(),
+        Ok(SpecificationRevision::R2_0)
+    ));
+}
+
+#[tokio::test]
+async fn test_auto_epr() {
+    use uom::si::power::watt;
+
+    use crate::dummy::DUMMY_SPR_CAPS_EPR_CAPABLE;
+    use crate::sink::config::SinkConfig;
+    use crate::units::Power;
+
+    let config = SinkConfig::new().with_auto_epr(Some(Power::new::<watt>(140)));
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, config);
+
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_SPR_CAPS_EPR_CAPABLE);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+
+    // `EvaluateCapabilities` -> `SelectCapability` -> `TransitionSink`
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Accept, 1);
+    policy_engine.run_step().await.unwrap();
+
+    // `TransitionSink` -> `Ready`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 2);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    // `Ready` -> `EprModeEntry`, only once.
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::EprModeEntry(..)));
+    assert!(policy_engine.auto_epr_pdp().is_none());
+}
+
+#[tokio::test]
+async fn test_request_refused() {
+    use crate::protocol_layer::message::data::request::{CurrentRequest, VoltageRequest};
+    use crate::sink::device_policy_manager::Refusal;
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), RefusalDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let accepted = policy_engine.accepted_power_source.unwrap().raw();
+    let capabilities = policy_engine.source_capabilities.clone().unwrap();
+    let request = PowerSource::new_fixed(CurrentRequest::Highest, VoltageRequest::Highest, &capabilities).unwrap();
+    assert_ne!(request.raw(), accepted);
+
+    // A refused request keeps the existing contract.
+    policy_engine.contract = super::Contract::Explicit;
+    policy_engine.state = State::SelectCapability(request);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Reject, 3);
+    policy_engine.run_step().await.unwrap();
+
+    let State::Ready(power_source, false) = policy_engine.state else {
+        panic!("expected `Ready`, got {:?}", policy_engine.state);
+    };
+    assert_eq!(power_source.raw(), accepted);
+    assert_eq!(policy_engine.device_policy_manager.refusals, [Refusal::Reject]);
+
+    // A request that the source answered with Wait also keeps the existing contract, and is retried after
+    // tSinkRequest.
+    policy_engine.state = State::SelectCapability(request);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 2);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Wait, 4);
+    policy_engine.run_step().await.unwrap();
+
+    let State::Ready(power_source, true) = policy_engine.state else {
+        panic!("expected `Ready` after Wait, got {:?}", policy_engine.state);
+    };
+    assert_eq!(power_source.raw(), accepted);
+    assert_eq!(
+        policy_engine.request_retry.map(|retry| retry.raw()),
+        Some(request.raw())
+    );
+    assert_eq!(
+        policy_engine.device_policy_manager.refusals,
+        [Refusal::Reject, Refusal::Wait]
+    );
+}
+
+#[tokio::test]
+async fn test_epr_mode_entry_refused() {
+    use uom::si::power::watt;
+
+    use crate::sink::device_policy_manager::Refusal;
+    use crate::units::Power;
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), RefusalDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let power_source = policy_engine.accepted_power_source.unwrap();
+    let operational_pdp = Power::new::<watt>(140);
+    let mut tx_id = 1;
+    let mut rx_id = 3;
+    let mut enter_epr_mode = async |policy_engine: &mut Sink<_, DummyTimer, RefusalDevice>, response| {
+        policy_engine.state = State::EprModeEntry(power_source, operational_pdp);
+        simulate_source_control_message(policy_engine, ControlMessageType::GoodCRC, tx_id);
+        simulate_source_control_message(policy_engine, response, rx_id);
+        tx_id = (tx_id + 1) % 8;
+        rx_id = (rx_id + 1) % 8;
+        policy_engine.run_step().await.unwrap();
+    };
+
+    // Wait is retried after tSinkRequest, without a soft reset, up to nBusyCount times.
+    for _ in 0..5 {
+        enter_epr_mode(&mut policy_engine, ControlMessageType::Wait).await;
+        assert!(matches!(policy_engine.state, State::Ready(_, true)));
+        assert_eq!(policy_engine.epr_entry_retry, Some(operational_pdp));
+        assert!(policy_engine.device_policy_manager.refusals.is_empty());
+    }
+
+    enter_epr_mode(&mut policy_engine, ControlMessageType::Wait).await;
+    assert!(matches!(policy_engine.state, State::Ready(_, false)));
+    assert_eq!(policy_engine.device_policy_manager.refusals, [Refusal::Wait]);
+
+    // Reject is not retried.
+    enter_epr_mode(&mut policy_engine, ControlMessageType::Reject).await;
+    assert!(matches!(policy_engine.state, State::Ready(_, false)));
+    assert_eq!(
+        policy_engine.device_policy_manager.refusals,
+        [Refusal::Wait, Refusal::Reject]
+    );
+
+    // The retry is cancelled, when `Ready` is re-entered for another reason.
+    policy_engine.epr_entry_retry = Some(operational_pdp);
+    policy_engine.state = State::Ready(power_source, false);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Ping, rx_id);
+    policy_engine.run_step().await.unwrap();
+    assert!(policy_engine.epr_entry_retry.is_none());
+}
+
+#[tokio::test]
+async fn test_change_epr_operational_pdp() {
+    use uom::si::power::watt;
+
+    use crate::sink::device_policy_manager::Event;
+    use crate::units::Power;
+
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let power_source = policy_engine.accepted_power_source.unwrap();
+    let change = |pdp| Event::ChangeEprOperationalPdp(Power::new::<watt>(pdp));
+
+    // Outside of EPR mode, there is no operational PDP to change.
+    assert!(policy_engine.epr_operational_pdp().is_none());
+    let state = policy_engine.event_state(change(100), &power_source);
+    assert!(matches!(state, State::Ready(_, false)));
+
+    policy_engine.mode = super::Mode::Epr;
+    policy_engine.epr_operational_pdp = Some(Power::new::<watt>(140));
+    assert_eq!(policy_engine.epr_operational_pdp(), Some(Power::new::<watt>(140)));
+
+    let state = policy_engine.event_state(change(140), &power_source);
+    assert!(matches!(state, State::Ready(_, false)));
+
+    let state = policy_engine.event_state(change(100), &power_source);
+    assert!(matches!(state, State::EprSendExit(Some(pdp)) if pdp == Power::new::<watt>(100)));
+
+    // EPR mode is exited, and entered again with the new operational PDP from the SPR contract.
+    policy_engine.set_state(state);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::WaitForCapabilities));
+    assert!(policy_engine.epr_operational_pdp().is_none());
+
+    policy_engine.set_state(State::Ready(power_source, false));
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::EprModeEntry(_, pdp) if pdp == Power::new::<watt>(100)));
+    assert!(policy_engine.epr_reentry.is_none());
+}
+
+#[tokio::test]
+async fn test_alert() {
+    use crate::protocol_layer::message::data::alert::AlertDataObject;
+    use crate::protocol_layer::message::extended::Extended;
+    use crate::protocol_layer::message::extended::status::{StatusExtended, TemperatureStatus};
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    #[derive(Default)]
+    struct AlertDevice {
+        alerts: std::vec::Vec<(AlertDataObject, Option<StatusExtended>)>,
+    }
+
+    impl DevicePolicyManager for AlertDevice {
+        async fn alert_received(&mut self, alert: AlertDataObject, status: Option<&StatusExtended>) {
+            self.alerts.push((alert, status.copied()));
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), AlertDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let source_header = get_source_header_template();
+    let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+
+    // `Ready` -> `GetSourceStatus`
+    let alert = AlertDataObject::default().with_otp_event(true);
+    let header = Header::new_data(source_header, MessageId::new(3), DataMessageType::Alert, 1);
+    let len = Message::new_with_data(header, Data::Alert(alert)).to_bytes(&mut buf);
+    policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::GetSourceStatus(..)));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+
+    // `GetSourceStatus` -> `Ready`
+    let status = StatusExtended {
+        internal_temperature: Some(90),
+        temperature_status: TemperatureStatus::OverTemperature,
+        ..Default::default()
+    };
+    let header = Header::new_extended_for_payload(
+        source_header,
+        MessageId::new(4),
+        ExtendedMessageType::Status,
+        crate::status::STATUS_DATA_BLOCK_SIZE,
+    );
+    let mut message = Message::new(header);
+    message.payload = Some(Payload::Extended(Extended::Status(status)));
+    let len = message.to_bytes(&mut buf);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    let get_status = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert!(matches!(
+        get_status.header.message_type(),
+        MessageType::Control(ControlMessageType::GetStatus)
+    ));
+    assert_eq!(policy_engine.device_policy_manager.alerts, [(alert, Some(status))]);
+}
+
+#[tokio::test]
+async fn test_pps_status() {
+    use crate::protocol_layer::message::extended::pps_status::PpsStatusExtended;
+    use crate::sink::device_policy_manager::{DevicePolicyManager, Event};
+
+    #[derive(Default)]
+    struct PpsDevice {
+        statuses: std::vec::Vec<Option<PpsStatusExtended>>,
+    }
+
+    impl DevicePolicyManager for PpsDevice {
+        async fn pps_status_received(&mut self, status: Option<&PpsStatusExtended>) {
+            self.statuses.push(status.copied());
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), PpsDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let power_source = policy_engine.accepted_power_source.unwrap();
+    let state = policy_engine.event_state(Event::RequestPpsStatus, &power_source);
+    assert!(matches!(state, State::GetPpsStatus(_)));
+
+    // `GetPpsStatus` -> `Ready`, if the source does not support PPS status.
+    policy_engine.set_state(state);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::NotSupported, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(_, false)));
+    assert_eq!(policy_engine.device_policy_manager.statuses, [None]);
+
+    let get_pps_status = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert!(matches!(
+        get_pps_status.header.message_type(),
+        MessageType::Control(ControlMessageType::GetPpsStatus)
+    ));
+}
+
+#[test]
+fn test_discarded_capabilities() {
+    use super::Mode::{Epr, Spr};
+    use super::discarded_capabilities;
+    use crate::sink::device_policy_manager::CapabilitiesDiscarded;
+
+    // (requested, mode, received)
+    let matrix = [
+        ((Spr, Spr, Spr), None),
+        ((Epr, Epr, Epr), None),
+        ((Spr, Epr, Spr), Some(CapabilitiesDiscarded::SprInEprMode)),
+        ((Epr, Spr, Epr), Some(CapabilitiesDiscarded::EprInSprMode)),
+        ((Spr, Spr, Epr), Some(CapabilitiesDiscarded::NotRequested)),
+        ((Spr, Epr, Epr), Some(CapabilitiesDiscarded::NotRequested)),
+        ((Epr, Spr, Spr), Some(CapabilitiesDiscarded::NotRequested)),
+        ((Epr, Epr, Spr), Some(CapabilitiesDiscarded::NotRequested)),
+    ];
+
+    for ((requested, mode, received), expected) in matrix {
+        assert_eq!(discarded_capabilities(requested, mode, received), expected);
+    }
+}
+
+#[tokio::test]
+async fn test_get_source_cap_discarded() {
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::device_policy_manager::{CapabilitiesDiscarded, DevicePolicyManager};
+
+    #[derive(Default)]
+    struct DiscardingDevice {
+        discarded: std::vec::Vec<CapabilitiesDiscarded>,
+    }
+
+    impl DevicePolicyManager for DiscardingDevice {
+        async fn capabilities_discarded(&mut self, _capabilities: &SourceCapabilities, reason: CapabilitiesDiscarded) {
+            self.discarded.push(reason);
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DiscardingDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+    let power_source = policy_engine.accepted_power_source.unwrap();
+
+    // SPR capabilities in EPR mode are informative, and the contract remains in place.
+    policy_engine.mode = super::Mode::Epr;
+    policy_engine.state = State::GetSourceCap(super::Mode::Spr, power_source);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(_, false)));
+    assert!(!policy_engine.get_source_cap_pending);
+
+    // SPR capabilities, when EPR capabilities were requested.
+    policy_engine.mode = super::Mode::Spr;
+    policy_engine.state = State::GetSourceCap(super::Mode::Epr, power_source);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 2);
+    let mut capabilities = DUMMY_CAPABILITIES;
+    capabilities[1] |= 1 << 1; // Message ID 1
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&capabilities);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(_, false)));
+
+    assert_eq!(
+        policy_engine.device_policy_manager.discarded,
+        [CapabilitiesDiscarded::SprInEprMode, CapabilitiesDiscarded::NotRequested]
+    );
+}
+
+#[test]
+fn test_diagnosis() {
+    use super::Diagnosis;
+    use crate::protocol_layer::stats::Stats;
+
+    let before = Stats {
+        frames_received: 10,
+        frames_discarded: 2,
+        ..Default::default()
+    };
+
+    assert_eq!(Diagnosis::from_stats(&before, &before), Diagnosis::NoTraffic);
+    assert_eq!(
+        Diagnosis::from_stats(
+            &before,
+            &Stats {
+                frames_discarded: 5,
+                ..before
+            }
+        ),
+        Diagnosis::CorruptTraffic
+    );
+    assert_eq!(
+        Diagnosis::from_stats(
+            &before,
+            &Stats {
+                frames_received: 11,
+                frames_discarded: 5,
+                ..before
+            }
+        ),
+        Diagnosis::NoCapabilities
+    );
+}
+
+/// Send Get_Source_Cap to a sink in `Ready`, and return the type of its response.
+async fn get_source_cap_response<DPM: crate::sink::device_policy_manager::DevicePolicyManager>(
+    policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+) -> MessageType {
+    // `Ready` -> `GiveSourceCap`
+    simulate_source_control_message(policy_engine, ControlMessageType::GetSourceCap, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::GiveSourceCap(_)));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+
+    // `GiveSourceCap` -> `Ready`
+    simulate_source_control_message(policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data())
+        .unwrap()
+        .header
+        .message_type()
+}
+
+#[tokio::test]
+async fn test_hard_resets_exhausted() {
+    use super::{Diagnosis, Error};
+    use crate::sink::device_policy_manager::{DevicePolicyManager, HardResetsExhausted};
+
+    struct FallbackDevice {
+        diagnosis: Option<Diagnosis>,
+    }
+
+    impl DevicePolicyManager for FallbackDevice {
+        async fn hard_resets_exhausted(&mut self, diagnosis: Diagnosis) -> HardResetsExhausted {
+            self.diagnosis = Some(diagnosis);
+            HardResetsExhausted::FallBackToTypeC
+        }
+    }
+
+    // By default, the sink gives up.
+    let mut policy_engine = get_policy_engine();
+    policy_engine.state = State::HardReset;
+    policy_engine.hard_reset_counter.set(3);
+    assert_eq!(policy_engine.hard_reset_count(), 3);
+    assert!(matches!(
+        policy_engine.run_step().await,
+        Err(Error::PortPartnerUnresponsive(Diagnosis::NoTraffic))
+    ));
+
+    // `HardReset` -> `TypeCFallback`
+    let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new(
+        DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+        FallbackDevice { diagnosis: None },
+    );
+    policy_engine.state = State::HardReset;
+    policy_engine.hard_reset_counter.set(3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::TypeCFallback));
+    assert_eq!(
+        policy_engine.device_policy_manager.diagnosis,
+        Some(Diagnosis::NoTraffic)
+    );
+    assert_eq!(policy_engine.hard_reset_count(), 0);
+
+    // `TypeCFallback` -> `EvaluateCapabilities`, once the source sends capabilities.
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::EvaluateCapabilities(_)));
+}
+
+#[tokio::test]
+async fn test_get_source_cap_not_supported() {
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    assert_eq!(
+        get_source_cap_response(&mut policy_engine).await,
+        MessageType::Control(ControlMessageType::NotSupported)
+    );
+}
+
+#[tokio::test]
+async fn test_get_source_cap_reject_revision_2() {
+    use crate::protocol_layer::message::header::SpecificationRevision;
+    use crate::sink::config::SinkConfig;
+
+    let config = SinkConfig::new().with_max_spec_revision(SpecificationRevision::R2_0);
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, config);
+    negotiate_to_ready(&mut policy_engine).await;
+
+    assert_eq!(
+        get_source_cap_response(&mut policy_engine).await,
+        MessageType::Control(ControlMessageType::Reject)
+    );
+}
+
+#[tokio::test]
+async fn test_get_source_cap_dual_role() {
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    struct DualRoleDevice;
+
+    impl DevicePolicyManager for DualRoleDevice {
+        fn source_capabilities(&self) -> Option<SourceCapabilities> {
+            let pdos = crate::dummy::get_dummy_source_capabilities();
+            Some(SourceCapabilities::new(heapless::Vec::from_slice(&pdos[..1]).unwrap()))
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DualRoleDevice);
+    negotiate_to_ready(&mut policy_engine).await;
+
+    assert_eq!(
+        get_source_cap_response(&mut policy_engine).await,
+        MessageType::Data(DataMessageType::SourceCapabilities)
+    );
+}
+
+/// Send Get_Manufacturer_Info for the `target` to a sink in `Ready`, and return its response.
+///
+/// The message IDs are those of the Get_Manufacturer_Info, and of the GoodCRC for the response.
+async fn get_manufacturer_info_response<DPM: crate::sink::device_policy_manager::DevicePolicyManager>(
+    policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+    target: crate::protocol_layer::message::extended::manufacturer_info::ManufacturerInfoTarget,
+    message_id: u8,
+    good_crc_message_id: u8,
+) -> Message {
+    use crate::protocol_layer::message::extended::Extended;
+
+    let mut message = Message::new(Header::new_extended_for_payload(
+        get_source_header_template(),
+        MessageId::new(message_id),
+        ExtendedMessageType::GetManufacturerInfo,
+        2,
+    ));
+    message.payload = Some(Payload::Extended(Extended::GetManufacturerInfo(target)));
+
+    let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+    let len = message.to_bytes(&mut buf);
+    policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+
+    // `Ready` -> `GiveManufacturerInfo`
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::GiveManufacturerInfo(..)));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+
+    // `GiveManufacturerInfo` -> `Ready`
+    simulate_source_control_message(policy_engine, ControlMessageType::GoodCRC, good_crc_message_id);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap()
+}
+
+#[tokio::test]
+async fn test_manufacturer_info() {
+    use crate::identity::DeviceIdentity;
+    use crate::protocol_layer::message::extended::Extended;
+    use crate::protocol_layer::message::extended::manufacturer_info::{ManufacturerInfo, ManufacturerInfoTarget};
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    struct IdentifiedDevice;
+
+    impl DevicePolicyManager for IdentifiedDevice {
+        fn identity(&self) -> Option<DeviceIdentity> {
+            Some(DeviceIdentity::new(0x1209, 0x0001).with_manufacturer_string("usbpd"))
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), IdentifiedDevice);
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let response = get_manufacturer_info_response(&mut policy_engine, ManufacturerInfoTarget::Port, 3, 1).await;
+    let Some(Payload::Extended(Extended::ManufacturerInfo(info))) = response.payload else {
+        panic!("expected `Manufacturer_Info`, got {:?}", response);
+    };
+    assert_eq!(info, ManufacturerInfo::new(0x1209, 0x0001, b"usbpd"));
+
+    // The sink has no batteries.
+    let response = get_manufacturer_info_response(&mut policy_engine, ManufacturerInfoTarget::Battery(0), 4, 2).await;
+    let Some(Payload::Extended(Extended::ManufacturerInfo(info))) = response.payload else {
+        panic!("expected `Manufacturer_Info`, got {:?}", response);
+    };
+    assert_eq!(info, ManufacturerInfo::not_supported());
+
+    // Without an identity, Get_Manufacturer_Info is not supported.
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let response = get_manufacturer_info_response(&mut policy_engine, ManufacturerInfoTarget::Port, 3, 1).await;
+    assert_eq!(
+        response.header.message_type(),
+        MessageType::Control(ControlMessageType::NotSupported)
+    );
+}
+
+#[tokio::test]
+async fn test_give_sink_status() {
+    use crate::protocol_layer::message::extended::Extended;
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+    use crate::status::{DeviceStatus, TemperatureStatus};
+
+    struct HotDevice;
+
+    impl DevicePolicyManager for HotDevice {
+        fn status(&self) -> DeviceStatus {
+            DeviceStatus::new()
+                .with_internal_temperature(80)
+                .with_temperature_status(TemperatureStatus::Warning)
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), HotDevice);
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // `Ready` -> `GiveSinkStatus`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GetStatus, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::GiveSinkStatus(_)));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+
+    // `GiveSinkStatus` -> `Ready`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    let response = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    let Some(Payload::Extended(Extended::Status(status))) = response.payload else {
+        panic!("expected `Status`, got {:?}", response);
+    };
+    assert_eq!(status, policy_engine.device_policy_manager.status().status_data_block());
+    assert_eq!(status.internal_temperature, Some(80));
+}
+
+#[tokio::test]
+async fn test_power_role_swap_rejected() {
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // `Ready` -> `PrsEvaluateSwap` -> `Ready`, since the device rejects swaps by default.
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PrSwap, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::PrsEvaluateSwap(_)));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+    assert!(policy_engine.accepted_power_source.is_some());
+
+    let message = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert_eq!(
+        message.header.message_type(),
+        MessageType::Control(ControlMessageType::Reject)
+    );
+    assert!(policy_engine.protocol_layer.driver().cc_termination().is_none());
+}
+
+#[tokio::test]
+async fn test_poll_event() {
+    let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new(
+        DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+        PollingDevice { soft_reset: false },
+    );
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // `Ready` -> `SendSoftReset`, without waiting for a message.
+    policy_engine.device_policy_manager.soft_reset = true;
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendSoftReset));
+}
+
+#[tokio::test]
+async fn test_event_not_lost() {
+    use core::future::Future;
+
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::device_policy_manager::{DevicePolicyManager, Event};
+
+    /// Takes the event from its source when creating the future, and loses it, if the future is dropped.
+    struct EagerDevice {
+        events: std::vec::Vec<Event>,
+    }
+
+    impl DevicePolicyManager for EagerDevice {
+        fn get_event(&mut self, _source_capabilities: &SourceCapabilities) -> impl Future<Output = Event> {
+            let event = self.events.pop();
+            async move {
+                match event {
+                    Some(event) => event,
+                    None => core::future::pending().await,
+                }
+            }
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new(
+        DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+        EagerDevice {
+            events: std::vec::Vec::new(),
+        },
+    );
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // The message wins against the event. `Ready` -> `GiveSinkCap`
+    policy_engine.device_policy_manager.events.push(Event::SoftReset);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GetSinkCap, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::GiveSinkCap(..)));
+    assert!(policy_engine.device_policy_manager.events.is_empty());
+
+    // `GiveSinkCap` -> `Ready`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    // The kept event is handled. `Ready` -> `SendSoftReset`
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendSoftReset));
+}
+
+#[tokio::test]
+async fn test_pending_event_order() {
+    use crate::sink::device_policy_manager::Event;
+
+    let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new(
+        DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+        PollingDevice { soft_reset: true },
+    );
+    negotiate_to_ready(&mut policy_engine).await;
+    let State::Ready(power_source, _) = policy_engine.state else {
+        unreachable!()
+    };
+
+    // Pending events come first, in the order they were produced.
+    super::retain_event(&mut policy_engine.pending_events, Event::RequestSprSourceCapabilities);
+    super::retain_event(&mut policy_engine.pending_events, Event::ExitEprMode);
+
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::GetSourceCap(super::Mode::Spr, _)));
+
+    policy_engine.set_state(State::Ready(power_source, false));
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::EprSendExit(None)));
+
+    // Then, new events are polled.
+    policy_engine.set_state(State::Ready(power_source, false));
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendSoftReset));
+
+    // The oldest events are dropped on overflow.
+    for _ in 0..super::PENDING_EVENT_COUNT {
+        super::retain_event(&mut policy_engine.pending_events, Event::None);
+    }
+    super::retain_event(&mut policy_engine.pending_events, Event::SoftReset);
+    assert_eq!(policy_engine.pending_events.len(), super::PENDING_EVENT_COUNT);
+    assert!(matches!(policy_engine.pending_events.back(), Some(Event::SoftReset)));
+
+    // A hard reset discards pending events.
+    policy_engine.set_state(State::TransitionToDefault);
+    policy_engine.run_step().await.unwrap();
+    assert!(policy_engine.pending_events.is_empty());
+}
+
+#[tokio::test]
+async fn test_source_capabilities_accessor() {
+    let mut policy_engine = get_policy_engine();
+    assert!(policy_engine.source_capabilities().is_none());
+
+    negotiate_to_ready(&mut policy_engine).await;
+    let capabilities = policy_engine.source_capabilities().unwrap();
+    assert_eq!(capabilities.pdos().len(), 7);
+
+    policy_engine.re_attach(DummyDriver::new());
+    assert!(policy_engine.source_capabilities().is_none());
+}
+
+#[tokio::test]
+async fn test_pps_standby() {
+    use crate::protocol_layer::message::data::request::{self, Pps};
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+    use crate::sink::power_transition::CurrentRamp;
+
+    #[derive(Default)]
+    struct StandbyDevice {
+        calls: std::vec::Vec<&'static str>,
+    }
+
+    impl DevicePolicyManager for StandbyDevice {
+        async fn enter_standby(&mut self) {
+            self.calls.push("enter");
+        }
+
+        async fn exit_standby(&mut self) {
+            self.calls.push("exit");
+        }
+
+        async fn transition_power(&mut self, _accepted: &request::PowerSource, ramp: &CurrentRamp) {
+            assert!(ramp.standby());
+            self.calls.push("transition");
+        }
+    }
+
+    let pps = |millivolts: u16| {
+        PowerSource::Pps(
+            Pps(0)
+                .with_object_position(5)
+                .with_raw_output_voltage(millivolts / 20)
+                .with_raw_operating_current(20),
+        )
+    };
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), StandbyDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+    policy_engine.device_policy_manager.calls.clear();
+    policy_engine.contract = super::Contract::Explicit;
+    policy_engine.accepted_power_source = Some(pps(5_000));
+
+    // A small step does not require standby.
+    policy_engine.state = State::SelectCapability(pps(5_500));
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Reject, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(policy_engine.device_policy_manager.calls.is_empty());
+
+    // A refused request ends standby in `Ready`.
+    policy_engine.state = State::SelectCapability(pps(9_000));
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 2);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Reject, 4);
+    policy_engine.run_step().await.unwrap();
+    assert_eq!(policy_engine.device_policy_manager.calls, ["enter"]);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GetSinkCap, 5);
+    policy_engine.run_step().await.unwrap();
+    assert_eq!(policy_engine.device_policy_manager.calls, ["enter", "exit"]);
+
+    // An accepted request ends standby after PS_RDY.
+    policy_engine.device_policy_manager.calls.clear();
+    policy_engine.state = State::SelectCapability(pps(9_000));
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 3);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Accept, 6);
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 7);
+    policy_engine.run_step().await.unwrap();
+    assert_eq!(
+        policy_engine.device_policy_manager.calls,
+        ["enter", "exit", "transition"]
+    );
+}
+
+#[tokio::test]
+async fn test_timer_audit() {
+    use core::cell::RefCell;
+
+    use crate::timers::TimerType;
+    use crate::timers::audit::TimerAudit;
+
+    let audit = RefCell::new(TimerAudit::<16>::new());
+    let mut policy_engine: Sink<_, DummyTimer, _, _> =
+        Sink::new_with_tracer(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, &audit);
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let audit = audit.borrow();
+    audit.assert_within_spec();
+    assert!(audit.armed_in("WaitForCapabilities").eq([TimerType::SinkWaitCap]));
+    assert!(audit.armed_in("TransitionSink").eq([TimerType::PSTransitionSpr]));
+    assert!(
+        audit
+            .armed_in("SelectCapability")
+            .eq([TimerType::CRCReceive, TimerType::SenderResponse])
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_extended_control() {
+    use crate::protocol_layer::message::Payload;
+    use crate::protocol_layer::message::extended::Extended;
+    use crate::protocol_layer::message::extended::extended_control::{ExtendedControl, ExtendedControlMessageType};
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    #[derive(Default)]
+    struct LoggingDevice {
+        unknown: Option<u8>,
+    }
+
+    impl DevicePolicyManager for LoggingDevice {
+        async fn unknown_extended_control(&mut self, message_type: u8) {
+            self.unknown = Some(message_type);
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), LoggingDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+    policy_engine.mode = super::Mode::Epr;
+
+    let mut message = Message::new(Header::new_extended_for_payload(
+        get_source_header_template(),
+        MessageId::new(3),
+        ExtendedMessageType::ExtendedControl,
+        2,
+    ));
+    message.payload = Some(Payload::Extended(Extended::ExtendedControl(
+        ExtendedControl::default().with_message_type(ExtendedControlMessageType::Unknown(0x2a)),
+    )));
+    let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+    let len = message.to_bytes(&mut buf);
+    policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+
+    // `Ready` -> `SendNotSupported` -> `Ready`
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendNotSupported(_)));
+    assert_eq!(policy_engine.device_policy_manager.unknown, Some(0x2a));
+
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+    assert_eq!(policy_engine.mode, super::Mode::Epr);
+
+    // GoodCRC for the extended control message, then Not_Supported.
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    let not_supported = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert_eq!(
+        not_supported.header.message_type(),
+        MessageType::Control(ControlMessageType::NotSupported)
+    );
+}
+
+#[cfg(feature = "bist")]
+#[tokio::test]
+async fn test_bist() {
+    use crate::protocol_layer::message::data::bist::{BistDataObject, Mode};
+    use crate::protocol_layer::message::data::request;
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    #[derive(Default)]
+    struct TestDevice {
+        shared_test_mode: Option<bool>,
+    }
+
+    impl DevicePolicyManager for TestDevice {
+        async fn bist_shared_test_mode(&mut self, active: bool) {
+            self.shared_test_mode = Some(active);
+        }
+    }
+
+    fn simulate_bist(
+        policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, TestDevice>,
+        mode: Mode,
+        message_id: u8,
+    ) {
+        let header = Header::new_data(
+            get_source_header_template(),
+            MessageId::new(message_id),
+            DataMessageType::Bist,
+            1,
+        );
+        let message = Message::new_with_data(header, Data::Bist(BistDataObject::default().with_mode(mode)));
+
+        let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+        let len = message.to_bytes(&mut buf);
+        policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+    }
+
+    let fixed = |object_position| {
+        PowerSource::FixedVariableSupply(request::FixedVariableSupply(0).with_object_position(object_position))
+    };
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), TestDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // Test modes are ignored outside of vSafe5V.
+    policy_engine.state = State::Ready(fixed(2), false);
+    simulate_bist(&mut policy_engine, Mode::TestData, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    policy_engine.state = State::Ready(fixed(1), false);
+    simulate_bist(&mut policy_engine, Mode::SharedTestModeEntry, 4);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+    assert_eq!(policy_engine.device_policy_manager.shared_test_mode, Some(true));
+
+    simulate_bist(&mut policy_engine, Mode::CarrierMode, 5);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::BistCarrierMode));
+
+    // In test data mode, all messages are ignored.
+    policy_engine.state = State::Ready(fixed(1), false);
+    simulate_bist(&mut policy_engine, Mode::TestData, 6);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::BistTestData));
+
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GetSinkCap, 7);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::BistTestData));
+}
+
+#[tokio::test]
+async fn test_detach() {
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // The driver reports the detach at the next reception.
+    policy_engine.protocol_layer.driver().set_detached(true);
+    assert!(matches!(policy_engine.run_step().await, Err(super::Error::Detached)));
+    assert!(matches!(policy_engine.state, State::Startup));
+    assert!(matches!(policy_engine.contract, super::Contract::Safe5V));
+    assert!(policy_engine.accepted_power_source.is_none());
+    assert!(policy_engine.source_capabilities().is_none());
+
+    // After the next attach, the sink negotiates from scratch, with fresh message IDs.
+    policy_engine.protocol_layer.driver().set_detached(false);
+    policy_engine.run_step().await.unwrap();
+    negotiate_to_ready(&mut policy_engine).await;
+}
+
+#[tokio::test]
+async fn test_re_attach() {
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // Leave stale state from the previous attach.
+    policy_engine.mode = super::Mode::Epr;
+    for _ in 0..policy_engine.hard_reset_counter.max_value() {
+        policy_engine.hard_reset_counter.increment().unwrap();
+    }
+
+    policy_engine.re_attach(DummyDriver::new());
+    assert!(matches!(policy_engine.state, State::Startup));
+    assert!(matches!(policy_engine.contract, super::Contract::Safe5V));
+    assert_eq!(policy_engine.mode, super::Mode::Spr);
+    assert!(policy_engine.accepted_power_source.is_none());
+    assert!(policy_engine.source_capabilities().is_none());
+    assert!(policy_engine.hard_reset_counter.increment().is_ok());
+
+    // `Startup` -> `Discovery`, then a full renegotiation.
+    policy_engine.run_step().await.unwrap();
+    negotiate_to_ready(&mut policy_engine).await;
+    assert!(policy_engine.accepted_power_source.is_some());
+}
+
+#[tokio::test]
+async fn test_soft_reset_message_ids() {
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // The Soft_Reset reuses the ID of the last message, but is not a retransmission.
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::SoftReset, 2);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SoftReset));
+
+    let good_crc = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert_eq!(
+        good_crc.header.message_type(),
+        MessageType::Control(ControlMessageType::GoodCRC)
+    );
+    assert_eq!(good_crc.header.message_id(), 2);
+
+    // `SoftReset` -> `WaitForCapabilities`, where the Accept is the first message after the reset.
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::WaitForCapabilities));
+
+    let accept = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert_eq!(
+        accept.header.message_type(),
+        MessageType::Control(ControlMessageType::Accept)
+    );
+    assert_eq!(accept.header.message_id(), 0);
+}
+
+#[tokio::test]
+async fn test_discard_storm() {
+    let mut policy_engine = get_policy_engine();
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+
+    // The request cannot be transmitted, as the line stays busy.
+    policy_engine
+        .protocol_layer
+        .driver()
+        .discard_transmissions(crate::protocol_layer::DISCARD_STORM_THRESHOLD);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+}
+
+#[tokio::test]
+async fn test_good_crc_discard_storm() {
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // The received message cannot be acknowledged, as the line stays busy.
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Ping, 3);
+    policy_engine
+        .protocol_layer
+        .driver()
+        .discard_transmissions(crate::protocol_layer::DISCARD_STORM_THRESHOLD);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+}
+
+#[tokio::test]
+async fn test_rng() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::protocol_layer::backoff::ExponentialBackoff;
+    use crate::rng::Rng;
+    use crate::sink::config::SinkConfig;
+
+    /// Counts the random numbers that were taken, which are all zero, such that backoff delays are zero as well.
+    #[derive(Debug)]
+    struct CountingRng(AtomicU64);
+
+    impl Rng for CountingRng {
+        fn next_u64(&self) -> u64 {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            0
+        }
+    }
+
+    static RNG: CountingRng = CountingRng(AtomicU64::new(0));
+    let config = SinkConfig::new()
+        .with_backoff(Some(&ExponentialBackoff::DEFAULT))
+        .with_rng(Some(&RNG));
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, config);
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+
+    // Each discarded request is retried after a randomized backoff.
+    policy_engine.protocol_layer.driver().discard_transmissions(2);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Accept, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::TransitionSink(_)));
+    assert_eq!(RNG.0.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn test_unhandled_message() {
+    use crate::sink::device_policy_manager::{DevicePolicyManager, UnhandledMessageResponse};
+
+    #[derive(Default)]
+    struct RevisionDevice {
+        payload: heapless::Vec<u8, 8>,
+    }
+
+    impl DevicePolicyManager for RevisionDevice {
+        async fn unhandled_message(&mut self, message_type: MessageType, payload: &[u8]) -> UnhandledMessageResponse {
+            self.payload = heapless::Vec::from_slice(payload).unwrap();
+
+            match message_type {
+                MessageType::Data(DataMessageType::BatteryStatus) => UnhandledMessageResponse::Data(
+                    DataMessageType::Revision,
+                    heapless::Vec::from_slice(&[0x3210_0000]).unwrap(),
+                ),
+                _ => UnhandledMessageResponse::Ignore,
+            }
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), RevisionDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    for (message_type, message_id) in [(DataMessageType::BatteryStatus, 3), (DataMessageType::SourceInfo, 4)] {
+        let header = Header::new_data(
+            get_source_header_template(),
+            MessageId::new(message_id),
+            message_type,
+            1,
+        );
+        let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+        header.to_bytes(&mut buf);
+        buf[2..6].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        policy_engine.protocol_layer.driver().inject_received_data(&buf[..6]);
+    }
+
+    // A custom response, acknowledged by the source.
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+    assert_eq!(policy_engine.device_policy_manager.payload, [0x78, 0x56, 0x34, 0x12]);
+
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    let response = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+    assert_eq!(
+        response.header.message_type(),
+        MessageType::Data(DataMessageType::Revision)
+    );
+    assert_eq!(response.header.num_objects(), 1);
+
+    // Ignored, only acknowledged.
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    assert!(!policy_engine.protocol_layer.driver().has_transmitted_data());
+}
+
+#[tokio::test]
+async fn test_transition_anomaly() {
+    use crate::protocol_layer::message::data::request::FixedVariableSupply;
+    use crate::sink::device_policy_manager::TransitionAnomaly;
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), AnomalyDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // `Ready` -> `SendSoftReset`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendSoftReset));
+    assert_eq!(
+        policy_engine.device_policy_manager.anomalies,
+        [TransitionAnomaly::UnexpectedPsRdy]
+    );
+
+    // The power transition completes a request, that was never transmitted.
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), AnomalyDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+    let State::Ready(power_source, _) = policy_engine.state else {
+        panic!("Not in Ready");
+    };
+    policy_engine.state = State::TransitionSink(power_source);
+
+    // `TransitionSink` -> `HardReset`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+    assert_eq!(
+        policy_engine.device_policy_manager.anomalies,
+        [TransitionAnomaly::UnexpectedPsRdy]
+    );
+
+    // The power transition completes a different request, than the one that was transmitted.
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), AnomalyDevice::default());
+    negotiate_to_ready(&mut policy_engine).await;
+    let State::Ready(power_source, _) = policy_engine.state else {
+        panic!("Not in Ready");
+    };
+    policy_engine.pending_request = Some(PowerSource::FixedVariableSupply(
+        FixedVariableSupply(0).with_object_position(2),
+    ));
+    policy_engine.state = State::TransitionSink(power_source);
+
+    // `TransitionSink` -> `HardReset`
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+    assert_eq!(
+        policy_engine.device_policy_manager.anomalies,
+        [TransitionAnomaly::UnexpectedPsRdy]
+    );
+
+    // The source accepts a request, but then sends the same capabilities again.
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), AnomalyDevice::default());
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Accept, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::TransitionSink(_)));
+
+    let mut capabilities = DUMMY_CAPABILITIES;
+    capabilities[1] = (capabilities[1] & !0x0e) | (2 << 1);
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&capabilities);
+
+    // `TransitionSink` -> `HardReset`
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+    assert_eq!(
+        policy_engine.device_policy_manager.anomalies,
+        [TransitionAnomaly::CapabilitiesDuringTransition { changed: false }]
+    );
+
+    // The source accepts a request, but then sends Soft_Reset.
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), AnomalyDevice::default());
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::Accept, 1);
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::SoftReset, 0);
+
+    // `TransitionSink` -> `HardReset`, without completing the request.
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+    assert!(policy_engine.accepted_power_source.is_none());
+    assert_eq!(
+        policy_engine.device_policy_manager.anomalies,
+        [TransitionAnomaly::SoftResetDuringTransition]
+    );
+}
+
+#[tokio::test]
+async fn test_missing_accept() {
+    use crate::sink::config::SinkConfig;
+    use crate::sink::device_policy_manager::TransitionAnomaly;
+    for tolerate in [false, true] {
+        let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new_with_config(
+            DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+            AnomalyDevice::default(),
+            SinkConfig::new().with_tolerate_missing_accept(tolerate),
+        );
+        policy_engine
+            .protocol_layer
+            .driver()
+            .inject_received_data(&DUMMY_CAPABILITIES);
+
+        // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability`
+        policy_engine.run_step().await.unwrap();
+        policy_engine.run_step().await.unwrap();
+        simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+        policy_engine.run_step().await.unwrap();
+
+        // The source skips Accept.
+        simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 1);
+        policy_engine.run_step().await.unwrap();
+
+        if tolerate {
+            assert!(matches!(policy_engine.state, State::Ready(..)));
+            assert_eq!(
+                policy_engine.device_policy_manager.anomalies,
+                [TransitionAnomaly::MissingAccept]
+            );
+        } else {
+            assert!(matches!(policy_engine.state, State::SendSoftReset));
+            assert!(policy_engine.device_policy_manager.anomalies.is_empty());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_tick() {
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    #[derive(Default)]
+    struct WatchdogDevice {
+        ticks: usize,
+    }
+
+    impl DevicePolicyManager for WatchdogDevice {
+        fn tick(&mut self) {
+            self.ticks += 1;
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), WatchdogDevice::default());
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    assert_eq!(policy_engine.device_policy_manager.ticks, 2);
+}
+
+#[tokio::test]
+async fn test_capability_history() {
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::device_policy_manager::{CapabilityHistory, DevicePolicyManager};
+
+    #[derive(Default)]
+    struct HysteresisDevice {
+        retained: usize,
+        changed: bool,
+    }
+
+    impl DevicePolicyManager for HysteresisDevice {
+        async fn capabilities_received(&mut self, history: CapabilityHistory<'_>) {
+            self.retained = history.len();
+            self.changed = history
+                .previous()
+                .is_some_and(|previous| previous.pdos().len() != history.latest().unwrap().pdos().len());
+        }
+    }
+
+    let all = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+    let reduced = SourceCapabilities::new(heapless::Vec::from_iter(
+        get_dummy_source_capabilities().into_iter().take(1),
+    ));
+
+    let mut policy_engine: Sink<_, DummyTimer, _, (), 2> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), HysteresisDevice::default());
+
+    for (capabilities, retained, changed) in [(&all, 1, false), (&reduced, 2, true), (&reduced, 2, false)] {
+        policy_engine.state = State::EvaluateCapabilities(capabilities.clone());
+        policy_engine.run_step().await.unwrap();
+        assert_eq!(policy_engine.device_policy_manager.retained, retained);
+        assert_eq!(policy_engine.device_policy_manager.changed, changed);
+    }
+    assert_eq!(policy_engine.capability_history().latest().unwrap().pdos().len(), 1);
+
+    // The history is kept across hard resets, but not across attachments.
+    policy_engine.re_attach(DummyDriver::new());
+    assert!(policy_engine.capability_history().is_empty());
+}
+
+#[tokio::test]
+async fn test_preferred_contract() {
+    use uom::si::electric_current::ampere;
+    use uom::si::electric_potential::volt;
+
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::request::{CurrentRequest, VoltageRequest};
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::device_policy_manager::{ContractHint, DevicePolicyManager};
+    use crate::units::{ElectricCurrent, ElectricPotential};
+
+    struct HintedDevice {
+        hint: ContractHint,
+        requests: usize,
+    }
+
+    impl DevicePolicyManager for HintedDevice {
+        fn preferred_contract(&self) -> Option<ContractHint> {
+            Some(self.hint)
+        }
+
+        async fn request(&mut self, source_capabilities: &SourceCapabilities) -> PowerSource {
+            self.requests += 1;
+            PowerSource::new_fixed(CurrentRequest::Highest, VoltageRequest::Safe5V, source_capabilities).unwrap()
+        }
+    }
+
+    let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+
+    // The source offers 9 V at 3 A, so that the hint is requested without asking the device policy manager.
+    // It offers 20 V only at 2.25 A, so that the device policy manager decides.
+    for (voltage, expected_position, expected_requests) in [(9, 2, 0), (20, 1, 1)] {
+        let hint = ContractHint {
+            voltage: ElectricPotential::new::<volt>(voltage),
+            current: ElectricCurrent::new::<ampere>(3),
+        };
+        let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new(
+            DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+            HintedDevice { hint, requests: 0 },
+        );
+
+        policy_engine.state = State::EvaluateCapabilities(capabilities.clone());
+        policy_engine.run_step().await.unwrap();
+
+        let State::SelectCapability(request) = policy_engine.state else {
+            panic!("Expected SelectCapability, got {:?}", policy_engine.state);
+        };
+        assert_eq!(request.object_position(), expected_position);
+        assert_eq!(policy_engine.device_policy_manager.requests, expected_requests);
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_pdo_policy() {
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::ObjectPosition;
+    use crate::protocol_layer::message::data::request::{CurrentRequest, VoltageRequest};
+    use crate::protocol_layer::message::data::source_capabilities::{Augmented, SourceCapabilities};
+    use crate::sink::config::{SinkConfig, UnknownPdoPolicy};
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+
+    #[derive(Default)]
+    struct HighVoltageDevice {
+        unknown_pdos: std::vec::Vec<(ObjectPosition, u32)>,
+    }
+
+    impl DevicePolicyManager for HighVoltageDevice {
+        async fn request(&mut self, source_capabilities: &SourceCapabilities) -> PowerSource {
+            PowerSource::new_fixed(CurrentRequest::Highest, VoltageRequest::Highest, source_capabilities).unwrap()
+        }
+
+        async fn unknown_pdo(&mut self, position: ObjectPosition, raw: u32) {
+            self.unknown_pdos.push((position, raw));
+        }
+    }
+
+    let mut pdos = heapless::Vec::from_iter(get_dummy_source_capabilities());
+    pdos.push(PowerDataObject::Augmented(Augmented::Unknown(0xE000_0000)))
+        .unwrap();
+    let capabilities = SourceCapabilities::new(pdos);
+    let unknown_position = ObjectPosition::new(capabilities.pdos().len() as u8).unwrap();
+
+    // The position that is requested, and the unknown PDOs that the device policy manager sees.
+    for (policy, expected_position, expected_unknown_pdos) in [
+        (UnknownPdoPolicy::Ignore, 4, &[][..]),
+        (UnknownPdoPolicy::Reject, 1, &[][..]),
+        (UnknownPdoPolicy::Forward, 4, &[(unknown_position, 0xE000_0000)][..]),
+    ] {
+        let config = SinkConfig::new().with_unknown_pdo_policy(policy);
+        let mut policy_engine: Sink<_, DummyTimer, _> = Sink::new_with_config(
+            DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(),
+            HighVoltageDevice::default(),
+            config,
+        );
+
+        policy_engine.state = State::EvaluateCapabilities(capabilities.clone());
+        policy_engine.run_step().await.unwrap();
+
+        let State::SelectCapability(request) = policy_engine.state else {
+            panic!("Expected SelectCapability, got {:?}", policy_engine.state);
+        };
+        assert_eq!(request.object_position(), expected_position);
+        assert_eq!(policy_engine.device_policy_manager.unknown_pdos, expected_unknown_pdos);
+    }
+}
+
+#[tokio::test]
+async fn test_time_to_contract() {
+    use core::future::pending;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::sink::config::SinkConfig;
+    use crate::timers::Timer;
+
+    static CLOCK_MICROS: AtomicU64 = AtomicU64::new(0);
+
+    /// A timer, whose clock advances by 100 ms, whenever it is read.
+    struct SteppingTimer {}
+
+    impl Timer for SteppingTimer {
+        async fn after_millis(_milliseconds: u64) {
+            pending().await
+        }
+
+        fn now_micros() -> Option<u64> {
+            Some(CLOCK_MICROS.fetch_add(100_000, Ordering::Relaxed))
+        }
+    }
+
+    let config = SinkConfig::new().with_time_to_contract_budget(Some(50_000));
+    let mut policy_engine: Sink<_, SteppingTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, config);
+    assert_eq!(policy_engine.stats().time_to_contract_micros, None);
+
+    negotiate_to_ready(&mut policy_engine).await;
+    let stats = policy_engine.stats();
+    assert!(stats.time_to_contract_micros.is_some_and(|micros| micros >= 100_000));
+    assert_eq!(stats.time_to_contract_budget_violations, 1);
+}
+
+#[tokio::test]
+async fn test_stored_contract() {
+    use crate::sink::persistence::StoredContract;
+
+    let mut policy_engine = get_policy_engine();
+    assert!(policy_engine.stored_contract().is_none());
+
+    negotiate_to_ready(&mut policy_engine).await;
+    let stored = policy_engine.stored_contract().unwrap();
+    let capabilities = policy_engine.source_capabilities().unwrap();
+    assert!(stored.matches(capabilities));
+    assert!(!stored.epr);
+
+    let State::Ready(accepted, _) = policy_engine.state else {
+        unreachable!()
+    };
+    let restored = StoredContract::from_bytes(&stored.to_bytes()).unwrap();
+    assert_eq!(restored.power_source(capabilities).unwrap().raw(), accepted.raw());
+}
+
+#[cfg(feature = "quirks")]
+#[tokio::test]
+async fn test_quirks() {
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+    use crate::sink::quirks::{QuirkRegistry, Quirks, SourceMatch, fingerprint};
+
+    struct QuirkyDevice;
+
+    impl DevicePolicyManager for QuirkyDevice {
+        fn register_quirks(&mut self, registry: &mut QuirkRegistry) {
+            let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+            registry
+                .register(
+                    SourceMatch::Capabilities(fingerprint(&capabilities)),
+                    Quirks::new().with_skip_epr().with_tolerate_missing_accept(),
+                )
+                .unwrap();
+        }
+    }
+
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), QuirkyDevice);
+    assert_eq!(policy_engine.active_quirks(), Quirks::new());
+
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+    assert!(policy_engine.active_quirks().skip_epr);
+    assert!(!policy_engine.epr_enabled());
+
+    // The source skips Accept, which the quirk tolerates.
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::PsRdy, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+
+    // Quirks are looked up again after a re-attach.
+    policy_engine.re_attach(DummyDriver::new());
+    assert_eq!(policy_engine.active_quirks(), Quirks::new());
+    assert!(policy_engine.source_identity().fingerprint.is_none());
+}
+
+#[tokio::test]
+async fn test_error_mapping() {
+    use crate::protocol_layer::inject::InjectionPoint;
+    use crate::protocol_layer::message::data::ObjectPosition;
+    use crate::protocol_layer::message::data::request::FixedVariableSupply;
+    use crate::protocol_layer::{ProtocolError, RxError, TxError};
+
+    let power_source = PowerSource::FixedVariableSupply(FixedVariableSupply::new(ObjectPosition::VSAFE_5V, 100, 100));
+
+    // The state before the failing step, the injected error, and the state that it leads to.
+    type Case = (State, ProtocolError, fn(&State) -> bool);
+
+    let cases: [Case; 14] = [
+        (State::Ready(power_source, false), RxError::HardReset.into(), |state| {
+            matches!(state, State::TransitionToDefault)
+        }),
+        (State::Ready(power_source, false), TxError::HardReset.into(), |state| {
+            matches!(state, State::TransitionToDefault)
+        }),
+        (State::Ready(power_source, false), RxError::SoftReset.into(), |state| {
+            matches!(state, State::SoftReset)
+        }),
+        (State::SoftReset, ProtocolError::TransmitRetriesExceeded(2), |state| {
+            matches!(state, State::HardReset)
+        }),
+        (
+            State::SendSoftReset,
+            ProtocolError::TransmitRetriesExceeded(2),
+            |state| matches!(state, State::HardReset),
+        ),
+        (
+            State::Ready(power_source, false),
+            TxError::DiscardStorm(100).into(),
+            |state| matches!(state, State::HardReset),
+        ),
+        (State::WaitForCapabilities, RxError::ReceiveTimeout.into(), |state| {
+            matches!(state, State::HardReset)
+        }),
+        (
+            State::SelectCapability(power_source),
+            RxError::ReceiveTimeout.into(),
+            |state| matches!(state, State::HardReset),
+        ),
+        (
+            State::TransitionSink(power_source),
+            ProtocolError::UnexpectedMessage,
+            |state| matches!(state, State::HardReset),
+        ),
+        (
+            State::GetSourceCap(super::Mode::Spr, power_source),
+            ProtocolError::UnexpectedMessage,
+            |state| matches!(state, State::SendSoftReset),
+        ),
+        (
+            State::Ready(power_source, false),
+            RxError::UnsupportedMessage.into(),
+            |state| matches!(state, State::SendNotSupported(_)),
+        ),
+        (
+            State::GiveSinkCap(super::Mode::Spr, power_source),
+            RxError::UnsupportedMessage.into(),
+            |state| matches!(state, State::GiveSinkCap(..)),
+        ),
+        (
+            State::GiveSinkCap(super::Mode::Spr, power_source),
+            ProtocolError::TransmitRetriesExceeded(2),
+            |state| matches!(state, State::SendSoftReset),
+        ),
+        // Unhandled errors leave the state as it is.
+        (
+            State::Ready(power_source, false),
+            RxError::ReceiveTimeout.into(),
+            |state| matches!(state, State::Ready(..)),
+        ),
+    ];
+
+    for (state, error, expected) in cases {
+        let mut policy_engine = get_policy_engine();
+        let name = state.name();
+        policy_engine.state = state;
+        policy_engine
+            .protocol_layer
+            .injector()
+            .inject(InjectionPoint::State(name), error.clone());
+
+        policy_engine.run_step().await.unwrap();
+        assert!(
+            expected(&policy_engine.state),
+            "{:?} in {} led to {:?}",
+            error,
+            name,
+            policy_engine.state
+        );
+        assert!(policy_engine.protocol_layer.injector().is_exhausted());
+    }
+
+    // A detach ends the run, regardless of the state.
+    for error in [RxError::Detached.into(), TxError::Detached.into()] {
+        let mut policy_engine = get_policy_engine();
+        policy_engine.state = State::TransitionSink(power_source);
+        policy_engine
+            .protocol_layer
+            .injector()
+            .inject(InjectionPoint::State("TransitionSink"), error);
+
+        assert!(matches!(policy_engine.run_step().await, Err(super::Error::Detached)));
+        assert!(matches!(policy_engine.state, State::Startup));
+    }
+}
+
+#[tokio::test]
+async fn test_error_injection_on_receive() {
+    use crate::protocol_layer::ProtocolError;
+    use crate::protocol_layer::inject::InjectionPoint;
+
+    let mut policy_engine = get_policy_engine();
+    policy_engine
+        .protocol_layer
+        .injector()
+        .inject(InjectionPoint::Receive(1), ProtocolError::UnexpectedMessage);
+    policy_engine
+        .protocol_layer
+        .driver()
+        .inject_received_data(&DUMMY_CAPABILITIES);
+
+    // `Discovery` -> `WaitForCapabilities` -> `EvaluateCapabilities` -> `SelectCapability`
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 0);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SelectCapability(_)));
+
+    // The second reception, waiting for Accept, fails.
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendSoftReset));
+    assert!(policy_engine.protocol_layer.injector().is_exhausted());
+}
+
+/// The documented outcome of a protocol error in a policy engine step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transition {
+    /// The run ends with [`super::Error::Detached`], and the sink starts over.
+    Detach,
+    /// The run ends with [`super::Error::PowerRoleSwapFailed`], for Type-C error recovery.
+    SwapFailed,
+    /// The sink enters the named state.
+    To(&'static str),
+    /// The error is logged, and the state is kept.
+    Unchanged,
+}
+
+/// The expected transition for a protocol error, per spec Table 6.72 and the sink policy engine in [8.3.3.3].
+///
+/// Matches all states exhaustively, so that new states must be classified here.
+fn expected_transition(state: &State, error: &crate::protocol_layer::ProtocolError) -> Transition {
+    use crate::protocol_layer::{ProtocolError, RxError, TxError};
+
+    // Errors that are handled alike in every state.
+    if error.driver_fault().is_some() {
+        return Transition::Unchanged;
+    }
+    match error {
+        ProtocolError::RxError(RxError::Detached) | ProtocolError::TxError(TxError::Detached) => {
+            return Transition::Detach;
+        }
+        // Once a power role swap was accepted, any other error demands error recovery.
+        _ if matches!(state, State::PrsTransitionToOff | State::PrsSourceOn) => return Transition::SwapFailed,
+        ProtocolError::RxError(RxError::HardReset) | ProtocolError::TxError(TxError::HardReset) => {
+            return Transition::To("TransitionToDefault");
+        }
+        // Also a Soft_Reset demands a hard reset during a power transition.
+        ProtocolError::RxError(RxError::SoftReset) if matches!(state, State::TransitionSink(_)) => {
+            return Transition::To("HardReset");
+        }
+        ProtocolError::RxError(RxError::SoftReset) => return Transition::To("SoftReset"),
+        ProtocolError::TxError(TxError::DiscardStorm(_)) => return Transition::To("HardReset"),
+        _ => (),
+    }
+
+    // Soft reset on unexpected messages, or failed transmissions, unless the state demands otherwise.
+    let soft_reset = match error {
+        ProtocolError::UnexpectedMessage | ProtocolError::TransmitRetriesExceeded(_) => Transition::To("SendSoftReset"),
+        _ => Transition::Unchanged,
+    };
+
+    match state {
+        // Any error during a power transition demands a hard reset.
+        State::TransitionSink(_) => Transition::To("HardReset"),
+        // A failing soft reset escalates to a hard reset.
+        State::SoftReset | State::SendSoftReset => match error {
+            ProtocolError::TransmitRetriesExceeded(_) => Transition::To("HardReset"),
+            _ => soft_reset,
+        },
+        // SinkWaitCapTimer and SenderResponseTimer timeouts demand a hard reset.
+        State::WaitForCapabilities | State::SelectCapability(_) => match error {
+            ProtocolError::RxError(RxError::ReceiveTimeout) => Transition::To("HardReset"),
+            _ => soft_reset,
+        },
+        State::Ready(..) => match error {
+            ProtocolError::RxError(RxError::UnsupportedMessage) => Transition::To("SendNotSupported"),
+            _ => soft_reset,
+        },
+        State::Startup
+        | State::Discovery
+        | State::EvaluateCapabilities(_)
+        | State::SendNotSupported(_)
+        | State::HardReset
+        | State::TransitionToDefault
+        | State::TypeCFallback
+        | State::GiveSinkCap(..)
+        | State::GiveSourceCap(_)
+        | State::GiveManufacturerInfo(..)
+        | State::GiveSinkStatus(_)
+        | State::GetSourceCap(..)
+        | State::GetSourceStatus(..)
+        | State::GetPpsStatus(_)
+        | State::SendVdm(..)
+        | State::EprModeEntry(..)
+        | State::EprEntryWaitForResponse(_)
+        | State::EprWaitForCapabilities(_)
+        | State::EprSendExit(_)
+        | State::EprExitReceived(_)
+        | State::EprKeepAlive(_)
+        | State::PrsEvaluateSwap(_)
+        | State::PrsSendSwap(_) => soft_reset,
+        State::PrsTransitionToOff | State::PrsSourceOn => Transition::SwapFailed,
+        #[cfg(feature = "bist")]
+        State::BistCarrierMode | State::BistTestData => soft_reset,
+    }
+}
+
+#[tokio::test]
+async fn test_error_transition_matrix() {
+    use usbpd_traits::DriverFault;
+
+    use crate::protocol_layer::inject::InjectionPoint;
+    use crate::protocol_layer::message::ParseError;
+    use crate::protocol_layer::message::data::ObjectPosition;
+    use crate::protocol_layer::message::data::request::FixedVariableSupply;
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::protocol_layer::message::data::vendor_defined::VdmHeaderStructured;
+    use crate::protocol_layer::{ProtocolError, RxError, TxError};
+    use crate::units::Power;
+
+    let power_source = PowerSource::FixedVariableSupply(FixedVariableSupply::new(ObjectPosition::VSAFE_5V, 100, 100));
+
+    // Every state, see `expected_transition`.
+    let states = [
+        State::Startup,
+        State::Discovery,
+        State::WaitForCapabilities,
+        State::EvaluateCapabilities(SourceCapabilities(heapless::Vec::new())),
+        State::SelectCapability(power_source),
+        State::TransitionSink(power_source),
+        State::Ready(power_source, false),
+        State::Ready(power_source, true),
+        State::SendNotSupported(power_source),
+        State::SendSoftReset,
+        State::SoftReset,
+        State::HardReset,
+        State::TransitionToDefault,
+        State::TypeCFallback,
+        State::GiveSinkCap(super::Mode::Spr, power_source),
+        State::GiveSinkCap(super::Mode::Epr, power_source),
+        State::GiveSourceCap(power_source),
+        State::GetSourceCap(super::Mode::Spr, power_source),
+        State::GetSourceCap(super::Mode::Epr, power_source),
+        State::GetSourceStatus(power_source, Default::default()),
+        State::GetPpsStatus(power_source),
+        State::SendVdm(power_source, VdmHeaderStructured::default(), heapless::Vec::new()),
+        State::EprModeEntry(power_source, Power::new::<uom::si::power::watt>(140)),
+        State::EprEntryWaitForResponse(power_source),
+        State::EprWaitForCapabilities(power_source),
+        State::EprSendExit(None),
+        State::EprExitReceived(power_source),
+        State::EprKeepAlive(power_source),
+        State::PrsEvaluateSwap(power_source),
+        State::PrsSendSwap(power_source),
+        State::PrsTransitionToOff,
+        State::PrsSourceOn,
+        #[cfg(feature = "bist")]
+        State::BistCarrierMode,
+        #[cfg(feature = "bist")]
+        State::BistTestData,
+    ];
+
+    // Every protocol error variant.
+    let errors: [ProtocolError; 18] = [
+        RxError::SoftReset.into(),
+        RxError::HardReset.into(),
+        RxError::Detached.into(),
+        RxError::ReceiveTimeout.into(),
+        RxError::UnsupportedMessage.into(),
+        RxError::ParseError(ParseError::InvalidMessageType(0)).into(),
+        RxError::AcknowledgeMismatch(3).into(),
+        TxError::HardReset.into(),
+        TxError::Detached.into(),
+        TxError::DiscardStorm(100).into(),
+        TxError::UnchunkedExtendedMessagesNotSupported.into(),
+        TxError::AvsVoltageAlignmentInvalid.into(),
+        TxError::AmsTokenRequired.into(),
+        TxError::TooManyDataObjects.into(),
+        ProtocolError::TransmitRetriesExceeded(2),
+        ProtocolError::UnexpectedMessage,
+        RxError::DriverFault(DriverFault::Overrun).into(),
+        TxError::DriverFault(DriverFault::Dma).into(),
+    ];
+
+    for state in &states {
+        for error in &errors {
+            let mut policy_engine = get_policy_engine();
+            let name = state.name();
+            policy_engine.state = state.clone();
+            policy_engine
+                .protocol_layer
+                .injector()
+                .inject(InjectionPoint::State(name), error.clone());
+
+            let result = policy_engine.run_step().await;
+            let transition = match result {
+                Err(super::Error::Detached) => {
+                    assert!(matches!(policy_engine.state, State::Startup));
+                    Transition::Detach
+                }
+                Err(super::Error::PowerRoleSwapFailed) => Transition::SwapFailed,
+                Err(other) => panic!("{:?} in {} failed with {:?}", error, name, other),
+                Ok(()) if policy_engine.state.name() == name => Transition::Unchanged,
+                Ok(()) => Transition::To(policy_engine.state.name()),
+            };
+
+            // Re-entering the same state cannot be told apart from keeping it.
+            let expected = match expected_transition(state, error) {
+                Transition::To(expected) if expected == name => Transition::Unchanged,
+                expected => expected,
+            };
+
+            assert_eq!(transition, expected, "{:?} in {}", error, name);
+        }
+    }
+}
+
+#[cfg(feature = "tx-queue")]
+#[tokio::test]
+async fn test_tx_queue() {
+    use crate::protocol_layer::message::data::vendor_defined::{
+        VdmCommand, VdmCommandType, VdmHeader, VdmHeaderStructured, VdmHeaderUnstructured, VdmVersion,
+    };
+    use crate::protocol_layer::tx_queue::{Outbound, TxQueue};
+    use crate::sink::config::SinkConfig;
+
+    static TX_QUEUE: TxQueue = TxQueue::new();
+
+    let config = SinkConfig::new().with_tx_queue(Some(&TX_QUEUE));
+    let mut policy_engine: Sink<_, DummyTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), DummySinkDevice {}, config);
+    negotiate_to_ready(&mut policy_engine).await;
+    policy_engine.vdm_version = VdmVersion::V2_0;
+
+    let attention = VdmHeaderStructured::default()
+        .with_standard_or_vid(0xff01)
+        .with_command(VdmCommand::Attention)
+        .with_command_type(VdmCommandType::InitiatorREQ)
+        .with_version(VdmVersion::V2_1);
+    TX_QUEUE
+        .submit(Outbound::Vdm(
+            VdmHeader::Structured(attention),
+            heapless::Vec::from_slice(&[0x1234]).unwrap(),
+        ))
+        .unwrap();
+    TX_QUEUE
+        .submit(Outbound::Vdm(
+            VdmHeader::Unstructured(VdmHeaderUnstructured(0).with_standard_or_vid(0x1234)),
+            heapless::Vec::new(),
+        ))
+        .unwrap();
+
+    // Queued messages are transmitted one per step, in order, with consecutive message IDs.
+    for (message_id, expected_objects) in [(1, 2), (2, 1)] {
+        simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, message_id);
+        policy_engine.run_step().await.unwrap();
+        assert!(matches!(policy_engine.state, State::Ready(..)));
+
+        let frame = policy_engine.protocol_layer.driver().probe_transmitted_data();
+        let message = Message::from_bytes(&frame).unwrap();
+        assert_eq!(
+            message.header.message_type(),
+            MessageType::Data(DataMessageType::VendorDefined)
+        );
+        assert_eq!(message.header.message_id(), message_id);
+        assert_eq!(message.header.num_objects(), expected_objects);
+
+        // Structured VDMs are sent with the negotiated version.
+        if let Some(Payload::Data(Data::VendorDefined((VdmHeader::Structured(header), _)))) = message.payload {
+            assert_eq!(header.version(), Some(VdmVersion::V2_0));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_vdm_version() {
+    use crate::protocol_layer::message::data::vendor_defined::{
+        VdmCommand, VdmCommandType, VdmHeader, VdmHeaderStructured, VdmVersion,
+    };
+
+    fn inject_vdm(
+        policy_engine: &mut Sink<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DummySinkDevice>,
+        header: VdmHeaderStructured,
+        message_id: u8,
+    ) {
+        let message_header = Header::new_data(
+            get_source_header_template(),
+            MessageId::new(message_id),
+            DataMessageType::VendorDefined,
+            1,
+        );
+        let message = Message::new_with_data(
+            message_header,
+            Data::VendorDefined((VdmHeader::Structured(header), heapless::Vec::new())),
+        );
+
+        let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+        let len = message.to_bytes(&mut buf);
+        policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+    }
+
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+    assert_eq!(policy_engine.vdm_version(), VdmVersion::LATEST);
+
+    let attention = VdmHeaderStructured::default()
+        .with_standard_or_vid(0xff01)
+        .with_command(VdmCommand::Attention)
+        .with_command_type(VdmCommandType::InitiatorREQ);
+
+    // An attention from a VDM 2.0 source lowers the version. The dummy device does not handle it.
+    inject_vdm(&mut policy_engine, attention.with_version(VdmVersion::V2_0), 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendNotSupported(_)));
+    assert_eq!(policy_engine.vdm_version(), VdmVersion::V2_0);
+
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 1);
+    policy_engine.run_step().await.unwrap();
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+
+    // Requests of the device policy manager are sent with the negotiated version.
+    let State::Ready(power_source, _) = policy_engine.state else {
+        panic!("Not in Ready state");
+    };
+    let request = VdmHeaderStructured::default()
+        .with_standard_or_vid(0xff00)
+        .with_command(VdmCommand::DiscoverIdentity)
+        .with_command_type(VdmCommandType::InitiatorREQ)
+        .with_version(VdmVersion::V2_1);
+    policy_engine.state = State::SendVdm(power_source, request, heapless::Vec::new());
+
+    simulate_source_control_message(&mut policy_engine, ControlMessageType::GoodCRC, 2);
+    inject_vdm(
+        &mut policy_engine,
+        request
+            .with_command_type(VdmCommandType::ResponderNAK)
+            .with_version(VdmVersion::V2_0),
+        4,
+    );
+    policy_engine.run_step().await.unwrap();
+
+    let mut sent = None;
+    while policy_engine.protocol_layer.driver().has_transmitted_data() {
+        let transmitted = Message::from_bytes(&policy_engine.protocol_layer.driver().probe_transmitted_data()).unwrap();
+        if let Some(Payload::Data(Data::VendorDefined((VdmHeader::Structured(header), _)))) = transmitted.payload {
+            sent = Some(header);
+        }
+    }
+    assert_eq!(sent.and_then(|header| header.version()), Some(VdmVersion::V2_0));
+
+    // The version is reset with the attachment.
+    policy_engine.re_attach(DummyDriver::new());
+    assert_eq!(policy_engine.vdm_version(), VdmVersion::LATEST);
+}
+
+#[tokio::test]
+async fn test_attention_rate_limit() {
+    use core::future::pending;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use crate::protocol_layer::message::data::vendor_defined::{
+        VdmCommand, VdmCommandType, VdmHeader, VdmHeaderStructured,
+    };
+    use crate::sink::config::SinkConfig;
+    use crate::sink::device_policy_manager::DevicePolicyManager;
+    use crate::timers::Timer;
+    use crate::vdm::{ATTENTION_QUEUE_SIZE, AttentionRateLimit};
+
+    static CLOCK_MICROS: AtomicU64 = AtomicU64::new(0);
+
+    /// A timer, whose clock only advances when the test says so.
+    struct ManualTimer {}
+
+    impl Timer for ManualTimer {
+        async fn after_millis(_milliseconds: u64) {
+            pending().await
+        }
+
+        fn now_micros() -> Option<u64> {
+            Some(CLOCK_MICROS.load(Ordering::Relaxed))
+        }
+    }
+
+    #[derive(Default)]
+    struct Device {
+        attentions: usize,
+        dropped: usize,
+    }
+
+    impl DevicePolicyManager for Device {
+        async fn vendor_defined_message(&mut self, _header: &VdmHeader, _vdos: &[u32]) -> bool {
+            self.attentions += 1;
+            true
+        }
+
+        async fn attention_dropped(&mut self, _header: &VdmHeaderStructured) {
+            self.dropped += 1;
+        }
+    }
+
+    let config = SinkConfig::new().with_attention_rate_limit(Some(AttentionRateLimit::new(1, 100_000)));
+    let mut policy_engine: Sink<_, ManualTimer, _> =
+        Sink::new_with_config(DummyDriver::<MAX_DATA_MESSAGE_SIZE>::new(), Device::default(), config);
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let attention = VdmHeaderStructured::default()
+        .with_standard_or_vid(0xff01)
+        .with_command(VdmCommand::Attention)
+        .with_command_type(VdmCommandType::InitiatorREQ);
+    let header = *policy_engine.protocol_layer.header();
+
+    // One is delivered, the queue fills up, and the rest is dropped.
+    let flood = ATTENTION_QUEUE_SIZE + 3;
+    for message_id in 0..flood {
+        let message = Message::new_with_data(
+            Header::new_data(
+                header,
+                MessageId::new(3 + message_id as u8),
+                DataMessageType::VendorDefined,
+                1,
+            ),
+            Data::VendorDefined((VdmHeader::Structured(attention), heapless::Vec::new())),
+        );
+        let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+        let len = message.to_bytes(&mut buf);
+        policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+        policy_engine.run_step().await.unwrap();
+        assert!(matches!(policy_engine.state, State::Ready(..)));
+    }
+    assert_eq!(policy_engine.device_policy_manager.attentions, 1);
+    assert_eq!(policy_engine.device_policy_manager.dropped, 2);
+
+    // The next window delivers one queued message, without receiving another one.
+    CLOCK_MICROS.fetch_add(100_000, Ordering::Relaxed);
+    policy_engine.run_step().await.unwrap();
+    assert_eq!(policy_engine.device_policy_manager.attentions, 2);
+
+    // Queued messages are dropped with the attachment.
+    policy_engine.re_attach(DummyDriver::new());
+    assert!(policy_engine.attentions.due_in_micros(Some(0)).is_none());
+}
+
+#[tokio::test]
+async fn test_good_crc_driver_fault() {
+    use usbpd_traits::{DriverFault, DriverTxError};
+
+    use crate::counters::{Counter, CounterType};
+
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    // Failing to acknowledge messages is a local fault, that recovers the driver, instead of answering the source
+    // with Not_Supported.
+    let tolerated = Counter::new(CounterType::DriverFault).max_value();
+    for message_id in 0..=tolerated {
+        simulate_source_control_message(&mut policy_engine, ControlMessageType::Ping, (message_id + 3) % 8);
+        policy_engine
+            .protocol_layer
+            .driver()
+            .fail_transmission(DriverTxError::Fault(DriverFault::Overrun));
+        policy_engine.run_step().await.unwrap();
+        assert!(matches!(policy_engine.state, State::Ready(..)));
+    }
+
+    assert!(!policy_engine.protocol_layer.driver().has_transmitted_data());
+    assert_eq!(policy_engine.protocol_layer.driver().recoveries(), 1);
+}
+
+#[tokio::test]
+async fn test_driver_recovery() {
+    use usbpd_traits::DriverFault;
+
+    use crate::counters::{Counter, CounterType};
+    use crate::protocol_layer::RxError;
+    use crate::protocol_layer::inject::InjectionPoint;
+
+    let mut policy_engine = get_policy_engine();
+    negotiate_to_ready(&mut policy_engine).await;
+
+    let tolerated = Counter::new(CounterType::DriverFault).max_value();
+    let inject_faults = |policy_engine: &mut Sink<_, _, _>, count: u8| {
+        for _ in 0..count {
+            policy_engine.protocol_layer.injector().inject(
+                InjectionPoint::State("Ready"),
+                RxError::DriverFault(DriverFault::Overrun).into(),
+            );
+        }
+    };
+
+    // Sporadic faults are retried.
+    inject_faults(&mut policy_engine, tolerated);
+    for _ in 0..tolerated {
+        policy_engine.run_step().await.unwrap();
+        assert!(matches!(policy_engine.state, State::Ready(..)));
+    }
+    assert_eq!(policy_engine.protocol_layer.driver().recoveries(), 0);
+
+    // Persistent faults recover the driver, without resetting the port partner.
+    inject_faults(&mut policy_engine, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(..)));
+    assert_eq!(policy_engine.protocol_layer.driver().recoveries(), 1);
+
+    // Faults that persist after recovery escalate to a hard reset.
+    inject_faults(&mut policy_engine, tolerated + 1);
+    for _ in 0..=tolerated {
+        policy_engine.run_step().await.unwrap();
+    }
+    assert!(matches!(policy_engine.state, State::HardReset));
+    assert_eq!(policy_engine.protocol_layer.driver().recoveries(), 1);
+}
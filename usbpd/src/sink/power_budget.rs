@@ -0,0 +1,298 @@
+//! Sharing the power of a contract among several consumers of a device.
+//!
+//! A device, whose subsystems (e.g. a charger, a display, and a motor) draw from one PD contract, registers them as
+//! [`Consumer`]s in a [`PowerBudget`]. Whenever the contract changes, the device policy manager allocates the
+//! contract's power, as found by [`contract_power`].
+//!
+//! ```
+//! use usbpd::protocol_layer::message::data::request::PowerSource;
+//! use usbpd::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+//! use usbpd::sink::device_policy_manager::DevicePolicyManager;
+//! use usbpd::sink::power_budget::{Allocation, PowerBudget, contract_power};
+//! use usbpd::sink::power_transition::CurrentRamp;
+//!
+//! struct Device {
+//!     source_capabilities: Option<SourceCapabilities>,
+//!     budget: PowerBudget<4>,
+//!     allocation: Allocation<4>,
+//! }
+//!
+//! impl DevicePolicyManager for Device {
+//!     async fn inform(&mut self, source_capabilities: &SourceCapabilities) {
+//!         self.source_capabilities = Some(source_capabilities.clone());
+//!     }
+//!
+//!     async fn transition_power(&mut self, accepted: &PowerSource, _ramp: &CurrentRamp) {
+//!         if let Some(power) = self
+//!             .source_capabilities
+//!             .as_ref()
+//!             .and_then(|capabilities| contract_power(accepted, capabilities))
+//!         {
+//!             self.allocation = self.budget.allocate(power);
+//!         }
+//!     }
+//! }
+//! ```
+use super::power_transition::operating_point;
+use crate::_250milliwatts_mod::_250milliwatts;
+use crate::protocol_layer::message::data::request::PowerSource;
+use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+use crate::units::Power;
+
+/// Errors that can occur when managing consumers of a power budget.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// No space left for more consumers.
+    #[error("no space for more consumers")]
+    Full,
+    /// The consumer is not registered.
+    #[error("unknown consumer")]
+    UnknownConsumer,
+    /// The minimum power of the consumer exceeds its maximum power.
+    #[error("minimum power exceeds maximum power")]
+    InvalidRange,
+}
+
+/// A subsystem of the device, that draws power from the contract.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Consumer {
+    priority: u8,
+    minimum: Power,
+    maximum: Power,
+}
+
+impl Consumer {
+    /// Create a consumer that needs at least `minimum` power to operate, and can use up to `maximum`.
+    ///
+    /// Consumers with a higher `priority` are served first.
+    pub const fn new(priority: u8, minimum: Power, maximum: Power) -> Self {
+        Self {
+            priority,
+            minimum,
+            maximum,
+        }
+    }
+
+    /// The priority of the consumer.
+    pub const fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// The minimum power that the consumer needs to operate.
+    pub const fn minimum(&self) -> Power {
+        self.minimum
+    }
+
+    /// The maximum power that the consumer can use.
+    pub const fn maximum(&self) -> Power {
+        self.maximum
+    }
+}
+
+/// A handle to a registered consumer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConsumerId(usize);
+
+/// The power allocated to each consumer of a [`PowerBudget`].
+#[derive(Debug, Clone, Copy)]
+pub struct Allocation<const N: usize> {
+    power: [Option<Power>; N],
+    unallocated: Power,
+}
+
+impl<const N: usize> Default for Allocation<N> {
+    fn default() -> Self {
+        Self {
+            power: [None; N],
+            unallocated: Power::default(),
+        }
+    }
+}
+
+impl<const N: usize> Allocation<N> {
+    /// The power allocated to a consumer.
+    ///
+    /// `None`, if its minimum power could not be allocated, and it shall not operate.
+    pub fn get(&self, id: ConsumerId) -> Option<Power> {
+        self.power.get(id.0).copied().flatten()
+    }
+
+    /// The power that remains after serving all consumers.
+    pub fn unallocated(&self) -> Power {
+        self.unallocated
+    }
+}
+
+/// A set of up to `N` consumers, that share the power of a contract.
+#[derive(Debug, Clone)]
+pub struct PowerBudget<const N: usize> {
+    consumers: [Option<Consumer>; N],
+}
+
+impl<const N: usize> Default for PowerBudget<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PowerBudget<N> {
+    /// Create an empty budget.
+    pub const fn new() -> Self {
+        Self { consumers: [None; N] }
+    }
+
+    /// Register a consumer.
+    pub fn register(&mut self, consumer: Consumer) -> Result<ConsumerId, Error> {
+        if consumer.minimum > consumer.maximum {
+            return Err(Error::InvalidRange);
+        }
+
+        let index = self.consumers.iter().position(Option::is_none).ok_or(Error::Full)?;
+        self.consumers[index] = Some(consumer);
+
+        Ok(ConsumerId(index))
+    }
+
+    /// Unregister a consumer, returning it.
+    pub fn unregister(&mut self, id: ConsumerId) -> Result<Consumer, Error> {
+        self.consumers
+            .get_mut(id.0)
+            .and_then(Option::take)
+            .ok_or(Error::UnknownConsumer)
+    }
+
+    /// Get a registered consumer.
+    pub fn consumer(&self, id: ConsumerId) -> Option<&Consumer> {
+        self.consumers.get(id.0).and_then(Option::as_ref)
+    }
+
+    /// Allocate the `available` power.
+    ///
+    /// In the order of their priority, consumers first get their minimum power, as long as it is available.
+    /// Consumers that do not fit are skipped. The remaining power is then handed out in the same order, up to the
+    /// maximum power of each consumer. Consumers of equal priority are served in the order of registration.
+    pub fn allocate(&self, available: Power) -> Allocation<N> {
+        let mut order: heapless::Vec<usize, N> = (0..N).filter(|&index| self.consumers[index].is_some()).collect();
+        order.sort_unstable_by_key(|&index| (core::cmp::Reverse(self.consumers[index].map(|c| c.priority)), index));
+
+        let mut allocation = Allocation {
+            power: [None; N],
+            unallocated: available,
+        };
+
+        for &index in &order {
+            let Some(consumer) = self.consumers[index] else {
+                continue;
+            };
+
+            if consumer.minimum <= allocation.unallocated {
+                allocation.power[index] = Some(consumer.minimum);
+                allocation.unallocated -= consumer.minimum;
+            }
+        }
+
+        for &index in &order {
+            let Some(consumer) = self.consumers[index] else {
+                continue;
+            };
+
+            if let Some(power) = allocation.power[index].as_mut() {
+                let extra = core::cmp::min(consumer.maximum - *power, allocation.unallocated);
+                *power += extra;
+                allocation.unallocated -= extra;
+            }
+        }
+
+        allocation
+    }
+}
+
+/// The power of a contract, based on the request that the source accepted.
+///
+/// Uses the operating current (or power) of the request, at the voltage of the requested PDO. Variable supplies
+/// count at their minimum voltage. `None`, if the request does not match the capabilities.
+pub fn contract_power(accepted: &PowerSource, capabilities: &SourceCapabilities) -> Option<Power> {
+    match accepted {
+        PowerSource::Battery(rdo) => Some(Power::new::<_250milliwatts>(rdo.raw_operating_power().into())),
+        _ => operating_point(accepted, capabilities).map(|(voltage, current)| voltage * current),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::power::watt;
+
+    use super::*;
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::request;
+
+    fn watts(power: u32) -> Power {
+        Power::new::<watt>(power)
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut budget = PowerBudget::<4>::new();
+        let display = budget.register(Consumer::new(1, watts(2), watts(5))).unwrap();
+        let charger = budget.register(Consumer::new(2, watts(10), watts(30))).unwrap();
+        let motor = budget.register(Consumer::new(0, watts(20), watts(40))).unwrap();
+
+        // The charger gets its maximum, the display its minimum and the rest, and the motor does not fit.
+        let allocation = budget.allocate(watts(27));
+        assert_eq!(allocation.get(charger), Some(watts(25)));
+        assert_eq!(allocation.get(display), Some(watts(2)));
+        assert_eq!(allocation.get(motor), None);
+        assert_eq!(allocation.unallocated(), watts(0));
+
+        let allocation = budget.allocate(watts(100));
+        assert_eq!(allocation.get(charger), Some(watts(30)));
+        assert_eq!(allocation.get(display), Some(watts(5)));
+        assert_eq!(allocation.get(motor), Some(watts(40)));
+        assert_eq!(allocation.unallocated(), watts(25));
+
+        budget.unregister(display).unwrap();
+        let allocation = budget.allocate(watts(8));
+        assert_eq!(allocation.get(charger), None);
+        assert_eq!(allocation.get(motor), None);
+        assert_eq!(allocation.get(display), None);
+
+        // A lower priority consumer is served, if higher priority ones do not fit.
+        let small = budget.register(Consumer::new(0, watts(1), watts(3))).unwrap();
+        assert_eq!(small, display);
+        assert_eq!(budget.allocate(watts(8)).get(small), Some(watts(3)));
+    }
+
+    #[test]
+    fn test_register() {
+        let mut budget = PowerBudget::<1>::new();
+        assert_eq!(
+            budget.register(Consumer::new(0, watts(2), watts(1))),
+            Err(Error::InvalidRange)
+        );
+
+        let id = budget.register(Consumer::new(0, watts(1), watts(2))).unwrap();
+        assert_eq!(budget.register(Consumer::new(0, watts(1), watts(2))), Err(Error::Full));
+
+        assert_eq!(budget.consumer(id).map(Consumer::maximum), Some(watts(2)));
+        assert!(budget.unregister(id).is_ok());
+        assert_eq!(budget.unregister(id), Err(Error::UnknownConsumer));
+    }
+
+    #[test]
+    fn test_contract_power() {
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+
+        // 9 V at 2 A
+        let accepted = PowerSource::FixedVariableSupply(
+            request::FixedVariableSupply(0)
+                .with_object_position(2)
+                .with_raw_operating_current(200),
+        );
+        assert_eq!(contract_power(&accepted, &capabilities), Some(watts(18)));
+
+        let accepted = PowerSource::FixedVariableSupply(request::FixedVariableSupply(0).with_object_position(15));
+        assert_eq!(contract_power(&accepted, &capabilities), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Guidance for changing the load current during a power transition.
+//!
+//! Drawing the new current too early, or too fast, can trip the over-current protection of the source. The
+//! [`CurrentRamp`] of a transition tells the device how much current it may draw after PS_RDY, see spec, [7.2.3]
+//! and [7.3].
+use uom::si::electric_current::milliampere;
+use uom::si::electric_potential::millivolt;
+use uom::si::power::milliwatt;
+
+use crate::protocol_layer::message::data::ObjectPosition;
+use crate::protocol_layer::message::data::request::{self, PowerSource};
+use crate::protocol_layer::message::data::source_capabilities::{Augmented, PowerDataObject, SourceCapabilities};
+use crate::units::{ElectricCurrent, ElectricPotential, Power};
+
+/// The maximum time after PS_RDY, until the sink draws its new operating current (tSnkNewPower).
+pub const SINK_NEW_POWER_MICROS: u64 = 15_000;
+
+/// The maximum time after Accept, until the sink has reduced its load to standby (tSnkStdby).
+pub const SINK_STANDBY_MICROS: u64 = 15_000;
+
+/// The maximum power that a sink in standby draws (pSnkStdby), in milliwatts.
+const SINK_STANDBY_POWER_MILLIWATTS: u32 = 2_500;
+
+/// The maximum rate of load current increase (iLoadStepRate), in milliamperes per microsecond.
+const LOAD_STEP_RATE_MILLIAMPERES_PER_MICRO: u32 = 150;
+
+/// The largest programmable voltage change, for which the sink need not enter standby (vPpsSmallStep), in millivolts.
+const SMALL_STEP_MILLIVOLTS: u32 = 500;
+
+/// The maximum power that a sink in standby draws (pSnkStdby).
+pub fn sink_standby_power() -> Power {
+    Power::new::<milliwatt>(SINK_STANDBY_POWER_MILLIWATTS)
+}
+
+/// The load current profile of a power transition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurrentRamp {
+    standby: bool,
+    initial: ElectricCurrent,
+    target: ElectricCurrent,
+}
+
+impl CurrentRamp {
+    /// Derive the profile from the previous and the accepted request.
+    ///
+    /// Without a previous request, the sink operates from an implicit contract, and starts from standby.
+    pub fn new(previous: Option<&PowerSource>, accepted: &PowerSource, capabilities: &SourceCapabilities) -> Self {
+        let target = operating_point(accepted, capabilities);
+        let previous = previous.and_then(|previous| operating_point(previous, capabilities));
+
+        let Some((voltage, target)) = target else {
+            // Unknown requests are not ramped.
+            return Self::unramped();
+        };
+
+        let standby =
+            previous.is_none_or(|(previous_voltage, _)| requires_standby(accepted, previous_voltage, voltage));
+
+        let initial = match previous {
+            Some((_, previous_current)) if !standby => previous_current,
+            _ => sink_standby_power() / voltage,
+        };
+
+        Self {
+            standby,
+            initial: core::cmp::min(initial, target),
+            target,
+        }
+    }
+
+    /// The profile of a transition that is not ramped, without standby and load current limits.
+    pub fn unramped() -> Self {
+        Self {
+            standby: false,
+            initial: ElectricCurrent::default(),
+            target: ElectricCurrent::default(),
+        }
+    }
+
+    /// Whether the sink shall be in standby during the transition, i.e. draw no more than [`sink_standby_power`].
+    pub fn standby(&self) -> bool {
+        self.standby
+    }
+
+    /// The current that the sink may draw right after PS_RDY.
+    pub fn initial_current(&self) -> ElectricCurrent {
+        self.initial
+    }
+
+    /// The operating current of the new contract.
+    pub fn target_current(&self) -> ElectricCurrent {
+        self.target
+    }
+
+    /// The current that the sink may draw, `elapsed_micros` after PS_RDY.
+    ///
+    /// The current rises at the maximum load step rate. The sink shall reach its new operating current within
+    /// [`SINK_NEW_POWER_MICROS`], but it may take longer.
+    pub fn allowed_current(&self, elapsed_micros: u64) -> ElectricCurrent {
+        let step = u64::from(LOAD_STEP_RATE_MILLIAMPERES_PER_MICRO).saturating_mul(elapsed_micros);
+        let current = u64::from(self.initial.get::<milliampere>()).saturating_add(step);
+
+        core::cmp::min(
+            ElectricCurrent::new::<milliampere>(u32::try_from(current).unwrap_or(u32::MAX)),
+            self.target,
+        )
+    }
+}
+
+/// Whether changing from the `previous` to the `requested` programmable supply requires Sink Standby.
+///
+/// This is the case for voltage changes beyond a small step (vPpsSmallStep). Requests for other supplies never
+/// require it.
+pub fn programmable_standby_required(
+    previous: &PowerSource,
+    requested: &PowerSource,
+    capabilities: &SourceCapabilities,
+) -> bool {
+    if !is_programmable(previous) || !is_programmable(requested) {
+        return false;
+    }
+
+    match (
+        operating_point(previous, capabilities),
+        operating_point(requested, capabilities),
+    ) {
+        (Some((previous, _)), Some((requested_voltage, _))) => requires_standby(requested, previous, requested_voltage),
+        _ => false,
+    }
+}
+
+fn is_programmable(rdo: &PowerSource) -> bool {
+    match rdo {
+        PowerSource::Pps(_) | PowerSource::Avs(_) => true,
+        PowerSource::EprRequest(epr) => matches!(epr.pdo, PowerDataObject::Augmented(_)),
+        _ => false,
+    }
+}
+
+/// Whether a voltage change from `previous` to `new` requires standby.
+///
+/// Programmable supplies need not enter standby for small steps.
+fn requires_standby(accepted: &PowerSource, previous: ElectricPotential, new: ElectricPotential) -> bool {
+    let difference = previous.get::<millivolt>().abs_diff(new.get::<millivolt>());
+
+    if is_programmable(accepted) {
+        difference > SMALL_STEP_MILLIVOLTS
+    } else {
+        difference > 0
+    }
+}
+
+/// The voltage and operating current of a request.
+///
+/// Variable supplies count at their minimum voltage. `None` for battery requests, or if the request does not match
+/// the capabilities.
+pub(crate) fn operating_point(
+    rdo: &PowerSource,
+    capabilities: &SourceCapabilities,
+) -> Option<(ElectricPotential, ElectricCurrent)> {
+    let pdo = capabilities.offered_pdo_at(ObjectPosition::new(rdo.object_position())?)?;
+
+    rdo_operating_point(rdo, pdo)
+}
+
+fn rdo_operating_point(rdo: &PowerSource, pdo: &PowerDataObject) -> Option<(ElectricPotential, ElectricCurrent)> {
+    match (rdo, pdo) {
+        (PowerSource::FixedVariableSupply(rdo), PowerDataObject::FixedSupply(pdo)) => {
+            Some((pdo.voltage(), rdo.operating_current()))
+        }
+        (PowerSource::FixedVariableSupply(rdo), PowerDataObject::VariableSupply(pdo)) => {
+            Some((pdo.min_voltage(), rdo.operating_current()))
+        }
+        (PowerSource::Pps(rdo), PowerDataObject::Augmented(Augmented::Spr(_))) => {
+            Some((rdo.output_voltage(), rdo.operating_current()))
+        }
+        (PowerSource::Avs(rdo), PowerDataObject::Augmented(Augmented::Epr(_))) => {
+            Some((rdo.output_voltage(), rdo.operating_current()))
+        }
+        (PowerSource::EprRequest(epr), _) => {
+            let rdo = match epr.pdo {
+                PowerDataObject::FixedSupply(_) => {
+                    PowerSource::FixedVariableSupply(request::FixedVariableSupply(epr.rdo))
+                }
+                PowerDataObject::Augmented(Augmented::Epr(_)) => PowerSource::Avs(request::Avs(epr.rdo)),
+                _ => return None,
+            };
+
+            rdo_operating_point(&rdo, &epr.pdo)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::get_dummy_source_capabilities;
+
+    fn fixed(object_position: u8, centiamperes: u16) -> PowerSource {
+        PowerSource::FixedVariableSupply(
+            request::FixedVariableSupply(0)
+                .with_object_position(object_position)
+                .with_raw_operating_current(centiamperes),
+        )
+    }
+
+    fn milliamperes(current: u32) -> ElectricCurrent {
+        ElectricCurrent::new::<milliampere>(current)
+    }
+
+    #[test]
+    fn test_fixed() {
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+
+        // The first contract starts from standby: 2.5 W at 9 V.
+        let ramp = CurrentRamp::new(None, &fixed(2, 300), &capabilities);
+        assert!(ramp.standby());
+        assert_eq!(ramp.initial_current(), milliamperes(277));
+        assert_eq!(ramp.target_current(), milliamperes(3000));
+        assert_eq!(ramp.allowed_current(1), milliamperes(427));
+        assert_eq!(ramp.allowed_current(SINK_NEW_POWER_MICROS), milliamperes(3000));
+
+        // A current change at the same voltage does not require standby.
+        let ramp = CurrentRamp::new(Some(&fixed(2, 100)), &fixed(2, 300), &capabilities);
+        assert!(!ramp.standby());
+        assert_eq!(ramp.initial_current(), milliamperes(1000));
+
+        // A lower current applies right away.
+        let ramp = CurrentRamp::new(Some(&fixed(2, 300)), &fixed(2, 100), &capabilities);
+        assert_eq!(ramp.allowed_current(0), milliamperes(1000));
+
+        // A voltage change does.
+        let ramp = CurrentRamp::new(Some(&fixed(1, 300)), &fixed(2, 300), &capabilities);
+        assert!(ramp.standby());
+    }
+
+    #[test]
+    fn test_pps() {
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+        let (position, _) = capabilities
+            .positioned_pdos()
+            .find(|(_, pdo)| matches!(pdo, PowerDataObject::Augmented(Augmented::Spr(_))))
+            .unwrap();
+        let pps = |millivolts: u16| {
+            PowerSource::Pps(
+                request::Pps(0)
+                    .with_object_position(position.get())
+                    .with_raw_output_voltage(millivolts / 20)
+                    .with_raw_operating_current(40),
+            )
+        };
+
+        let ramp = CurrentRamp::new(Some(&pps(5_000)), &pps(5_500), &capabilities);
+        assert!(!ramp.standby());
+
+        let ramp = CurrentRamp::new(Some(&pps(5_000)), &pps(5_520), &capabilities);
+        assert!(ramp.standby());
+        assert_eq!(ramp.target_current(), milliamperes(2000));
+
+        assert!(!programmable_standby_required(&pps(5_000), &pps(5_500), &capabilities));
+        assert!(programmable_standby_required(&pps(5_000), &pps(4_480), &capabilities));
+        assert!(!programmable_standby_required(
+            &fixed(1, 300),
+            &pps(9_000),
+            &capabilities
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Workarounds for sources with known interoperability issues ("quirks").
+//!
+//! The device policy manager registers quirks for matching sources with
+//! [`DevicePolicyManager::register_quirks`](super::device_policy_manager::DevicePolicyManager::register_quirks).
+//! Sources are matched by their [`SourceIdentity`].
+//!
+//! The sink policy engine looks up the quirks, whenever it evaluates source capabilities, and when it learns the
+//! identity of the source from a Discover Identity request of the device policy manager.
+use heapless::Vec;
+
+pub use super::identity::{SourceIdentity, fingerprint};
+use crate::timers::{TimerOverrides, TimerType};
+
+/// The maximum number of quirk entries in a [`QuirkRegistry`].
+pub const MAX_QUIRKS: usize = 8;
+
+/// The timers that are extended by [`Quirks::timing_slack_micros`].
+const SLACK_TIMERS: [TimerType; 3] = [
+    TimerType::SenderResponse,
+    TimerType::PSTransitionSpr,
+    TimerType::PSTransitionEpr,
+];
+
+/// The sources that a quirk entry applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SourceMatch {
+    /// A single product of a vendor.
+    Product {
+        /// The USB vendor ID.
+        vid: u16,
+        /// The USB product ID.
+        pid: u16,
+    },
+    /// All products of a vendor.
+    Vendor(u16),
+    /// Sources that offer capabilities with the given fingerprint, see [`fingerprint`].
+    Capabilities(u32),
+}
+
+impl SourceMatch {
+    /// Whether a source with the given identity matches.
+    pub fn matches(&self, identity: &SourceIdentity) -> bool {
+        match *self {
+            SourceMatch::Product { vid, pid } => identity.product == Some((vid, pid)),
+            SourceMatch::Vendor(vid) => identity.product.is_some_and(|(v, _)| v == vid),
+            SourceMatch::Capabilities(fingerprint) => identity.fingerprint == Some(fingerprint),
+        }
+    }
+}
+
+/// Workarounds for a source.
+///
+/// Quirks only relax the behavior of the sink, as configured with [`SinkConfig`](super::config::SinkConfig).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Quirks {
+    /// Additional time in µs, that the source is granted for responses and power transitions.
+    pub timing_slack_micros: u64,
+    /// Do not enter EPR mode, nor request EPR source capabilities.
+    pub skip_epr: bool,
+    /// Accept PS_RDY as the answer to a request, without Accept.
+    pub tolerate_missing_accept: bool,
+}
+
+impl Quirks {
+    /// No workarounds.
+    pub const fn new() -> Self {
+        Self {
+            timing_slack_micros: 0,
+            skip_epr: false,
+            tolerate_missing_accept: false,
+        }
+    }
+
+    /// Grant additional time in µs for responses and power transitions.
+    pub const fn with_timing_slack_micros(self, timing_slack_micros: u64) -> Self {
+        Self {
+            timing_slack_micros,
+            ..self
+        }
+    }
+
+    /// Do not use EPR mode.
+    pub const fn with_skip_epr(self) -> Self {
+        Self { skip_epr: true, ..self }
+    }
+
+    /// Accept PS_RDY without Accept.
+    pub const fn with_tolerate_missing_accept(self) -> Self {
+        Self {
+            tolerate_missing_accept: true,
+            ..self
+        }
+    }
+
+    /// Combine with other quirks, such that the workarounds of both apply.
+    pub const fn merge(self, other: Quirks) -> Self {
+        Self {
+            timing_slack_micros: if self.timing_slack_micros > other.timing_slack_micros {
+                self.timing_slack_micros
+            } else {
+                other.timing_slack_micros
+            },
+            skip_epr: self.skip_epr || other.skip_epr,
+            tolerate_missing_accept: self.tolerate_missing_accept || other.tolerate_missing_accept,
+        }
+    }
+
+    /// Extend the response and power transition timers of `timer_overrides` by the timing slack.
+    pub fn apply_timing_slack(&self, timer_overrides: TimerOverrides) -> TimerOverrides {
+        SLACK_TIMERS.iter().fold(timer_overrides, |overrides, &timer_type| {
+            overrides.with(
+                timer_type,
+                overrides.duration_micros(timer_type) + self.timing_slack_micros,
+            )
+        })
+    }
+}
+
+/// Errors that can occur when registering quirks.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistrationError {
+    /// No space left for more entries.
+    #[error("no space for more quirks")]
+    Full,
+}
+
+/// A registry of quirks, with space for [`MAX_QUIRKS`] entries.
+#[derive(Debug, Clone, Default)]
+pub struct QuirkRegistry {
+    entries: Vec<(SourceMatch, Quirks), MAX_QUIRKS>,
+}
+
+impl QuirkRegistry {
+    /// Create an empty registry.
+    pub const fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Register quirks for matching sources.
+    ///
+    /// If several entries match a source, their quirks are merged.
+    pub fn register(&mut self, source: SourceMatch, quirks: Quirks) -> Result<(), RegistrationError> {
+        self.entries.push((source, quirks)).map_err(|_| RegistrationError::Full)
+    }
+
+    /// The merged quirks of all entries that match a source.
+    pub fn lookup(&self, identity: &SourceIdentity) -> Quirks {
+        self.entries
+            .iter()
+            .filter(|(source, _)| source.matches(identity))
+            .fold(Quirks::new(), |merged, (_, quirks)| merged.merge(*quirks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QuirkRegistry, Quirks, RegistrationError, SourceIdentity, SourceMatch, fingerprint};
+    use crate::dummy::get_dummy_source_capabilities;
+    use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+    use crate::timers::{TimerOverrides, TimerType};
+
+    #[test]
+    fn test_lookup() {
+        let mut registry = QuirkRegistry::new();
+        registry
+            .register(
+                SourceMatch::Vendor(0x1234),
+                Quirks::new().with_timing_slack_micros(10_000),
+            )
+            .unwrap();
+        registry
+            .register(
+                SourceMatch::Product {
+                    vid: 0x1234,
+                    pid: 0x5678,
+                },
+                Quirks::new().with_skip_epr().with_timing_slack_micros(5_000),
+            )
+            .unwrap();
+
+        let product = SourceIdentity {
+            product: Some((0x1234, 0x5678)),
+            fingerprint: None,
+        };
+        assert_eq!(
+            registry.lookup(&product),
+            Quirks::new().with_skip_epr().with_timing_slack_micros(10_000)
+        );
+
+        let other_product = SourceIdentity {
+            product: Some((0x1234, 0x0001)),
+            fingerprint: None,
+        };
+        assert_eq!(
+            registry.lookup(&other_product),
+            Quirks::new().with_timing_slack_micros(10_000)
+        );
+        assert_eq!(registry.lookup(&SourceIdentity::default()), Quirks::new());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+        let identity = SourceIdentity {
+            product: None,
+            fingerprint: Some(fingerprint(&capabilities)),
+        };
+
+        let mut registry = QuirkRegistry::new();
+        registry
+            .register(
+                SourceMatch::Capabilities(fingerprint(&capabilities)),
+                Quirks::new().with_tolerate_missing_accept(),
+            )
+            .unwrap();
+        assert!(registry.lookup(&identity).tolerate_missing_accept);
+    }
+
+    #[test]
+    fn test_registry_full() {
+        let mut registry = QuirkRegistry::new();
+        for vid in 0..super::MAX_QUIRKS as u16 {
+            registry.register(SourceMatch::Vendor(vid), Quirks::new()).unwrap();
+        }
+        assert_eq!(
+            registry.register(SourceMatch::Vendor(0xffff), Quirks::new()),
+            Err(RegistrationError::Full)
+        );
+    }
+
+    #[test]
+    fn test_timing_slack() {
+        let overrides = TimerOverrides::new().with(TimerType::SenderResponse, 40_000);
+        let overrides = Quirks::new()
+            .with_timing_slack_micros(5_000)
+            .apply_timing_slack(overrides);
+
+        assert_eq!(overrides.duration_micros(TimerType::SenderResponse), 45_000);
+        assert_eq!(overrides.duration_micros(TimerType::PSTransitionSpr), 505_000);
+        assert_eq!(overrides.duration_micros(TimerType::SinkWaitCap), 465_000);
+    }
+}
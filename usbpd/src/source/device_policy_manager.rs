@@ -0,0 +1,164 @@
+//! The device policy manager (DPM) of a source provides the capabilities that the policy engine advertises, decides on
+//! requests of the sink, and controls VBUS.
+use core::future::Future;
+
+use uom::si::power::milliwatt;
+
+use crate::protocol_layer::message::data::ObjectPosition;
+use crate::protocol_layer::message::data::alert::AlertDataObject;
+use crate::protocol_layer::message::data::request::PowerSource;
+use crate::protocol_layer::message::data::source_capabilities::{Augmented, PowerDataObject, SourceCapabilities};
+use crate::sink::power_budget::contract_power;
+use crate::sink::power_transition::operating_point;
+use crate::units::{ElectricPotential, Power};
+
+/// Events that the device policy manager can send to the policy engine, while it is ready.
+#[derive(Debug)]
+pub enum Event {
+    /// Empty event, which lets the policy engine re-enter the ready state.
+    None,
+    /// A fault was detected, e.g. by an ADC or a comparator.
+    Fault(Fault),
+    /// Request a power role swap with PR_Swap, to become the sink of a dual-role port.
+    ///
+    /// Ignored, if the driver does not support power role swaps. If the sink accepts, the source stops with
+    /// [`Error::PowerRoleSwapped`](crate::source::policy_engine::Error::PowerRoleSwapped), see [`crate::drp`].
+    PowerRoleSwap,
+}
+
+/// The handling of a detected fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Fault {
+    /// A recoverable fault, such as an over-current event.
+    ///
+    /// The sink is informed with an Alert. If `renegotiate` is set, capabilities are sent again afterwards, e.g.
+    /// with a budget that was reduced in [`DevicePolicyManager::source_capabilities`].
+    Recoverable {
+        /// The alert data object, e.g. with the OCP event flag set.
+        alert: AlertDataObject,
+        /// Whether to send new capabilities.
+        renegotiate: bool,
+    },
+    /// A severe fault, such as an over-voltage event, which is handled by a hard reset.
+    Severe,
+}
+
+/// The decision of the device policy manager on a request of the sink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Evaluation {
+    /// Accept the request, and transition the power supply.
+    Accept,
+    /// Reject the request, e.g. because it exceeds the power budget.
+    Reject,
+    /// The request cannot be met at this time, and the sink may retry later.
+    Wait,
+}
+
+/// Trait for the device policy manager of a source.
+///
+/// This entity commands the policy engine and enforces device policy.
+pub trait DevicePolicyManager {
+    /// The capabilities to advertise to the sink.
+    ///
+    /// They are re-read, whenever capabilities are sent.
+    fn source_capabilities(&self) -> SourceCapabilities;
+
+    /// The power that the source can currently deliver to the sink, or `None`, if it is only limited by the
+    /// advertised capabilities.
+    fn power_budget(&self) -> Option<Power> {
+        None
+    }
+
+    /// Evaluate a request of the sink against the advertised `capabilities`.
+    ///
+    /// By default, requests are accepted, if they are within the capabilities and the power budget.
+    fn evaluate_request(
+        &mut self,
+        request: &PowerSource,
+        capabilities: &SourceCapabilities,
+    ) -> impl Future<Output = Evaluation> {
+        let within_budget = self
+            .power_budget()
+            .is_none_or(|budget| contract_power(request, capabilities).is_some_and(|power| power <= budget));
+
+        async move {
+            if within_budget && request_within_capabilities(request, capabilities) {
+                Evaluation::Accept
+            } else {
+                Evaluation::Reject
+            }
+        }
+    }
+
+    /// The policy engine awaits device policy events when ready, concurrently with messages.
+    ///
+    /// By default, this is a future that never resolves. The function must be safe to cancel.
+    fn get_event(&mut self) -> impl Future<Output = Event> {
+        async { core::future::pending().await }
+    }
+
+    /// Decide whether to accept a power role swap, that the sink requested with PR_Swap.
+    ///
+    /// Only asked, if the driver supports power role swaps. Defaults to rejecting it.
+    fn evaluate_power_role_swap(&mut self) -> impl Future<Output = bool> {
+        async { false }
+    }
+
+    /// Drive VBUS to vSafe5V, e.g. on attach, or after a hard reset.
+    fn vsafe5v(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Discharge VBUS to vSafe0V, e.g. during a hard reset, or a power role swap.
+    fn vsafe0v(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Transition VBUS to the `voltage` of an accepted request.
+    ///
+    /// The policy engine sends PS_RDY, once this completes. See spec, [7.1]
+    fn enable_output(&mut self, _voltage: ElectricPotential) -> impl Future<Output = ()> {
+        async {}
+    }
+}
+
+/// Whether a request can be met by the advertised capabilities.
+pub fn request_within_capabilities(request: &PowerSource, capabilities: &SourceCapabilities) -> bool {
+    let Some(pdo) = requested_pdo(request, capabilities) else {
+        return false;
+    };
+
+    match (request, pdo) {
+        (PowerSource::FixedVariableSupply(rdo), PowerDataObject::FixedSupply(pdo)) => {
+            rdo.operating_current() <= pdo.max_current()
+        }
+        (PowerSource::FixedVariableSupply(rdo), PowerDataObject::VariableSupply(pdo)) => {
+            rdo.operating_current() <= pdo.max_current()
+        }
+        (PowerSource::Battery(rdo), PowerDataObject::Battery(pdo)) => {
+            rdo.operating_power().get::<milliwatt>() <= pdo.max_power().get::<milliwatt>()
+        }
+        (PowerSource::Pps(rdo), PowerDataObject::Augmented(Augmented::Spr(pdo))) => {
+            (pdo.min_voltage()..=pdo.max_voltage()).contains(&rdo.output_voltage())
+                && rdo.operating_current() <= pdo.max_current()
+        }
+        _ => false,
+    }
+}
+
+/// The output voltage of a request.
+///
+/// Variable and battery supplies count at their minimum voltage. `None`, if the request does not match the
+/// capabilities.
+pub fn requested_voltage(request: &PowerSource, capabilities: &SourceCapabilities) -> Option<ElectricPotential> {
+    match (request, requested_pdo(request, capabilities)?) {
+        (PowerSource::Battery(_), PowerDataObject::Battery(pdo)) => Some(pdo.min_voltage()),
+        _ => operating_point(request, capabilities).map(|(voltage, _)| voltage),
+    }
+}
+
+fn requested_pdo<'c>(request: &PowerSource, capabilities: &'c SourceCapabilities) -> Option<&'c PowerDataObject> {
+    capabilities.offered_pdo_at(ObjectPosition::new(request.object_position())?)
+}
@@ -0,0 +1,4 @@
+//! The source implementation.
+
+pub mod device_policy_manager;
+pub mod policy_engine;
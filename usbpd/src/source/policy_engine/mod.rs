@@ -0,0 +1,600 @@
+//! Policy engine for the implementation of a source.
+use core::marker::PhantomData;
+
+use embassy_futures::select::{Either3, select3};
+use usbpd_traits::{CcTermination, Driver, RolePreference};
+
+use super::device_policy_manager::{DevicePolicyManager, Evaluation, Event, Fault, requested_voltage};
+use crate::counters::{Counter, CounterType};
+use crate::protocol_layer::message::data::Data;
+use crate::protocol_layer::message::data::alert::AlertDataObject;
+use crate::protocol_layer::message::data::request::PowerSource;
+use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+use crate::protocol_layer::message::header::{
+    ControlMessageType, DataMessageType, Header, MessageType, SpecificationRevision,
+};
+use crate::protocol_layer::message::{Message, Payload};
+use crate::protocol_layer::{ErrorOrigin, ProtocolError, ProtocolLayer, RxError, TxError};
+use crate::timers::{Timer, TimerType};
+use crate::trace::{TraceEvent, Tracer};
+use crate::{DataRole, PowerRole};
+
+#[cfg(test)]
+mod tests;
+
+/// Source states.
+#[derive(Debug, Clone)]
+enum State {
+    // States of the policy engine as given by the specification.
+    /// Default state at startup.
+    Startup,
+    /// Wait for the SourceCapabilityTimer, before advertising capabilities again.
+    Discovery,
+    SendCapabilities,
+    NegotiateCapability(PowerSource),
+    TransitionSupply(PowerSource),
+    Ready(PowerSource),
+    /// Inform the sink about a fault, and optionally send new capabilities afterwards.
+    SendAlert(PowerSource, AlertDataObject, bool),
+    /// Wait for new capabilities, after rejecting the request of a sink without a contract.
+    WaitNewCapabilities,
+    SendSoftReset,
+    SoftReset,
+    HardReset,
+    TransitionToDefault,
+
+    // Power role swap states (PE_PRS_SRC_SNK_*)
+    /// Decide on a PR_Swap of the sink.
+    PrsEvaluateSwap(PowerSource),
+    /// Send PR_Swap, and wait for the response of the sink.
+    PrsSendSwap(PowerSource),
+    /// Turn VBUS off, assert Rd, and send PS_RDY.
+    PrsTransitionToOff,
+    /// Wait for the new source to turn VBUS on.
+    PrsWaitSourceOn,
+}
+
+impl State {
+    /// The name of the state, for tracing.
+    fn name(&self) -> &'static str {
+        match self {
+            State::Startup => "Startup",
+            State::Discovery => "Discovery",
+            State::SendCapabilities => "SendCapabilities",
+            State::NegotiateCapability(_) => "NegotiateCapability",
+            State::TransitionSupply(_) => "TransitionSupply",
+            State::Ready(_) => "Ready",
+            State::SendAlert(..) => "SendAlert",
+            State::WaitNewCapabilities => "WaitNewCapabilities",
+            State::SendSoftReset => "SendSoftReset",
+            State::SoftReset => "SoftReset",
+            State::HardReset => "HardReset",
+            State::TransitionToDefault => "TransitionToDefault",
+            State::PrsEvaluateSwap(_) => "PrsEvaluateSwap",
+            State::PrsSendSwap(_) => "PrsSendSwap",
+            State::PrsTransitionToOff => "PrsTransitionToOff",
+            State::PrsWaitSourceOn => "PrsWaitSourceOn",
+        }
+    }
+}
+
+/// Only the name of the state.
+#[cfg(feature = "defmt")]
+impl defmt::Format for State {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.name())
+    }
+}
+
+/// Implementation of the source policy engine.
+///
+/// See spec, [8.3.3.2]
+#[derive(Debug)]
+pub struct Source<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager, TRACER: Tracer = ()> {
+    device_policy_manager: DPM,
+    protocol_layer: ProtocolLayer<DRIVER, TIMER, TRACER>,
+    state: State,
+    /// The number of Source_Capabilities messages that were sent without a response (nCapsCount).
+    caps_counter: Counter,
+    hard_reset_counter: Counter,
+    /// The last advertised capabilities, against which requests are evaluated.
+    source_capabilities: Option<SourceCapabilities>,
+    /// The accepted request of the explicit contract, if any.
+    contract: Option<PowerSource>,
+    /// Whether the source took over the port with a power role swap, and did not yet advertise capabilities.
+    swapped_in: bool,
+
+    _timer: PhantomData<TIMER>,
+}
+
+/// Errors that can occur in the source policy engine state machine.
+#[derive(thiserror::Error, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The port partner is unresponsive, e.g. no sink answered any capabilities.
+    #[error("port partner unresponsive")]
+    PortPartnerUnresponsive,
+    /// The port partner detached, as reported by the driver.
+    ///
+    /// The contract was invalidated, and the source starts over, when it is run again.
+    #[error("detached")]
+    Detached,
+    /// The power role was swapped, and the port is the sink now.
+    ///
+    /// Continue with a sink policy engine on the same driver, see [`Source::into_parts`], and [`crate::drp`].
+    #[error("power role swapped")]
+    PowerRoleSwapped,
+    /// The power role swap failed after it was accepted, e.g. because the new source did not turn VBUS on in time.
+    ///
+    /// Per spec 6.8.1, the port shall perform Type-C error recovery.
+    #[error("power role swap failed")]
+    PowerRoleSwapFailed,
+    /// A protocol error has occured.
+    #[error("protocol error")]
+    Protocol(#[from] ProtocolError),
+}
+
+impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager> Source<DRIVER, TIMER, DPM> {
+    /// Create a new source policy engine with a given `driver`.
+    pub fn new(driver: DRIVER, device_policy_manager: DPM) -> Self {
+        Self::new_with_tracer(driver, device_policy_manager, ())
+    }
+}
+
+impl<DRIVER: Driver, TIMER: Timer, DPM: DevicePolicyManager, TRACER: Tracer> Source<DRIVER, TIMER, DPM, TRACER> {
+    /// Create a new source policy engine with a given `driver`, that records protocol events to `tracer`.
+    ///
+    /// See [`crate::trace`].
+    pub fn new_with_tracer(driver: DRIVER, device_policy_manager: DPM, tracer: TRACER) -> Self {
+        let header = Header::new_template(DataRole::Dfp, PowerRole::Source, SpecificationRevision::R3_X);
+
+        Self {
+            device_policy_manager,
+            protocol_layer: ProtocolLayer::new_with_tracer(driver, header, tracer),
+            state: State::Startup,
+            caps_counter: Counter::new(CounterType::Caps),
+            hard_reset_counter: Counter::new(CounterType::HardReset),
+            source_capabilities: None,
+            contract: None,
+            swapped_in: false,
+            _timer: PhantomData,
+        }
+    }
+
+    /// The accepted request of the explicit contract, if any.
+    pub fn contract(&self) -> Option<&PowerSource> {
+        self.contract.as_ref()
+    }
+
+    /// Allows tests of other modules to access the driver directly.
+    #[cfg(test)]
+    pub(crate) fn driver(&mut self) -> &mut DRIVER {
+        self.protocol_layer.driver()
+    }
+
+    /// The data role of the port, which is kept across power role swaps.
+    pub fn data_role(&self) -> DataRole {
+        self.protocol_layer.header().port_data_role()
+    }
+
+    /// Release the driver and the device policy manager, e.g. after [`Error::PowerRoleSwapped`].
+    pub fn into_parts(self) -> (DRIVER, DPM) {
+        (self.protocol_layer.into_driver(), self.device_policy_manager)
+    }
+
+    /// Take over the port from a sink, after a power role swap, keeping its data role.
+    pub(crate) fn swapped_in(&mut self, data_role: DataRole) {
+        self.protocol_layer.set_roles(data_role, PowerRole::Source);
+        self.swapped_in = true;
+    }
+
+    /// Run a single step in the policy engine state machine.
+    pub(crate) async fn run_step(&mut self) -> Result<(), Error> {
+        let result = self.update_state().await;
+        if result.is_ok() {
+            self.protocol_layer.clear_driver_faults();
+            return Ok(());
+        }
+        if let Err(Error::PowerRoleSwapped | Error::PowerRoleSwapFailed) = result {
+            return result;
+        }
+
+        if let Err(Error::Protocol(protocol_error)) = result {
+            self.protocol_layer
+                .trace(TraceEvent::ProtocolError(protocol_error.clone()));
+
+            if matches!(
+                protocol_error,
+                ProtocolError::RxError(RxError::Detached) | ProtocolError::TxError(TxError::Detached)
+            ) {
+                self.contract = None;
+                self.source_capabilities = None;
+                self.hard_reset_counter.reset();
+                self.set_state(State::Startup);
+                return Err(Error::Detached);
+            }
+
+            // A local fault of the driver is not caused by the port partner, so retry the step without a reset, unless
+            // faults persist after recovering the driver.
+            if protocol_error.origin() == ErrorOrigin::Driver {
+                warn!("Driver fault {:?} in source state transition", protocol_error);
+                if !self.protocol_layer.on_driver_fault().await {
+                    self.set_state(State::HardReset);
+                }
+                return Ok(());
+            }
+
+            // Per spec 6.8.1: once a power role swap was accepted, protocol errors lead to error recovery.
+            if matches!(self.state, State::PrsTransitionToOff | State::PrsWaitSourceOn) {
+                return Err(Error::PowerRoleSwapFailed);
+            }
+
+            let new_state = match (&self.state, protocol_error) {
+                // Handle when hard reset is signaled by the driver itself.
+                (_, ProtocolError::RxError(RxError::HardReset) | ProtocolError::TxError(TxError::HardReset)) => {
+                    Some(State::TransitionToDefault)
+                }
+
+                // Handle when soft reset is signaled by the driver itself.
+                (_, ProtocolError::RxError(RxError::SoftReset)) => Some(State::SoftReset),
+
+                // Per spec 6.3.13: If the Soft_Reset Message fails, a Hard Reset shall be initiated.
+                (State::SoftReset | State::SendSoftReset, ProtocolError::TransmitRetriesExceeded(_)) => {
+                    Some(State::HardReset)
+                }
+
+                // A line that stays busy would fail a Soft_Reset as well, so reset the port partner right away.
+                (_, ProtocolError::TxError(TxError::DiscardStorm(_))) => Some(State::HardReset),
+
+                // Per spec 8.3.3.2.3: SenderResponseTimer timeout, while waiting for a Request, triggers Hard Reset.
+                (State::SendCapabilities, ProtocolError::RxError(RxError::ReceiveTimeout)) => Some(State::HardReset),
+
+                // Per spec Table 6.72: any protocol error during the power transition triggers Hard Reset.
+                (State::TransitionSupply(_), _) => Some(State::HardReset),
+
+                // Unexpected messages indicate a protocol error and demand a soft reset.
+                (_, ProtocolError::UnexpectedMessage) => Some(State::SendSoftReset),
+
+                // Per spec 6.6.9.1: Transmission failure (no GoodCRC after retries) triggers Soft Reset.
+                (_, ProtocolError::TransmitRetriesExceeded(_)) => Some(State::SendSoftReset),
+
+                // Unhandled protocol errors - log and continue.
+                (_, error) => {
+                    error!("Protocol error {:?} in source state transition", error);
+                    None
+                }
+            };
+
+            if let Some(state) = new_state {
+                self.set_state(state);
+            }
+
+            Ok(())
+        } else {
+            error!("Unrecoverable result {:?} in source state transition", result);
+            result
+        }
+    }
+
+    /// Select Try.SNK or Try.SRC behavior of a dual-role port, for the next Type-C connection.
+    ///
+    /// Returns `false`, if the driver does not support it.
+    pub async fn set_role_preference(&mut self, preference: RolePreference) -> bool {
+        self.protocol_layer.set_role_preference(preference).await
+    }
+
+    /// Run the source's state machine continuously.
+    ///
+    /// The loop is only broken for unrecoverable errors, for example if no sink responds, or on detach
+    /// ([`Error::Detached`]).
+    pub async fn run(&mut self) -> Result<(), Error> {
+        loop {
+            self.run_step().await?;
+        }
+    }
+
+    async fn update_state(&mut self) -> Result<(), Error> {
+        let new_state = match &self.state {
+            State::Startup => {
+                self.device_policy_manager.vsafe5v().await;
+                self.contract = None;
+                self.caps_counter.reset();
+                self.protocol_layer.reset();
+
+                // Per spec 8.3.3.2.1: after a power role swap, give the new sink time to settle.
+                if core::mem::take(&mut self.swapped_in) {
+                    self.protocol_layer.get_timer(TimerType::SwapSourceStart).await;
+                }
+
+                State::SendCapabilities
+            }
+            State::Discovery => {
+                // Per spec 8.3.3.2.2: re-advertise after the SourceCapabilityTimer times out.
+                self.protocol_layer.get_timer(TimerType::SourceCapability).await;
+
+                State::SendCapabilities
+            }
+            State::SendCapabilities => {
+                // Per spec 8.3.3.2.3: stop advertising after nCapsCount unanswered capabilities.
+                if self.caps_counter.increment().is_err() {
+                    return Err(Error::PortPartnerUnresponsive);
+                }
+
+                let capabilities = self.device_policy_manager.source_capabilities();
+                self.source_capabilities = Some(capabilities.clone());
+
+                match self.protocol_layer.transmit_source_capabilities(capabilities).await {
+                    Ok(()) => (),
+                    // No sink acknowledged the capabilities, try again later.
+                    Err(ProtocolError::TransmitRetriesExceeded(_)) if self.contract.is_none() => {
+                        self.set_state(State::Discovery);
+                        return Ok(());
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+
+                // A sink is attached, and communicates.
+                self.caps_counter.reset();
+                self.hard_reset_counter.reset();
+
+                let message = self
+                    .protocol_layer
+                    .receive_message_type(
+                        &[MessageType::Data(DataMessageType::Request)],
+                        TimerType::SenderResponse,
+                    )
+                    .await?;
+
+                let Some(Payload::Data(Data::Request(request))) = message.payload else {
+                    unreachable_or_return!(ProtocolError::UnexpectedMessage)
+                };
+
+                State::NegotiateCapability(self.parse_request(request))
+            }
+            State::NegotiateCapability(request) => {
+                let request = *request;
+                let evaluation = match self.source_capabilities.as_ref() {
+                    Some(capabilities) => {
+                        self.device_policy_manager
+                            .evaluate_request(&request, capabilities)
+                            .await
+                    }
+                    None => Evaluation::Reject,
+                };
+
+                match evaluation {
+                    Evaluation::Accept => {
+                        self.protocol_layer
+                            .transmit_control_message(ControlMessageType::Accept)
+                            .await?;
+
+                        State::TransitionSupply(request)
+                    }
+                    Evaluation::Reject | Evaluation::Wait => {
+                        if evaluation == Evaluation::Reject {
+                            self.protocol_layer.transmit_reject().await?;
+                        } else {
+                            self.protocol_layer.transmit_wait().await?;
+                        }
+
+                        // Per spec 8.3.3.2.5: an existing contract remains in place.
+                        match self.contract {
+                            Some(contract) => State::Ready(contract),
+                            None => State::WaitNewCapabilities,
+                        }
+                    }
+                }
+            }
+            State::TransitionSupply(request) => {
+                let request = *request;
+                if let Some(voltage) = self
+                    .source_capabilities
+                    .as_ref()
+                    .and_then(|capabilities| requested_voltage(&request, capabilities))
+                {
+                    self.device_policy_manager.enable_output(voltage).await;
+                }
+
+                self.protocol_layer
+                    .transmit_control_message(ControlMessageType::PsRdy)
+                    .await?;
+                self.contract = Some(request);
+
+                State::Ready(request)
+            }
+            State::Ready(contract) => {
+                let contract = *contract;
+                let pps_comm_timer = self.protocol_layer.get_timer(TimerType::SourcePPSComm);
+                // Per spec 8.3.3.2.7: a sink with a PPS contract requests periodically, otherwise the source resets.
+                let pps_comm_fut = async {
+                    match contract {
+                        PowerSource::Pps(_) => pps_comm_timer.await,
+                        _ => core::future::pending().await,
+                    }
+                };
+                let receive_fut = self.protocol_layer.receive_message();
+                let event_fut = self.device_policy_manager.get_event();
+
+                match select3(receive_fut, event_fut, pps_comm_fut).await {
+                    Either3::First(message) => self.handle_message(message?, State::Ready(contract)).await?,
+                    Either3::Second(Event::None) => State::Ready(contract),
+                    Either3::Second(Event::Fault(Fault::Recoverable { alert, renegotiate })) => {
+                        State::SendAlert(contract, alert, renegotiate)
+                    }
+                    // Severe faults, such as over-voltage, are handled by a hard reset.
+                    Either3::Second(Event::Fault(Fault::Severe)) => State::HardReset,
+                    Either3::Second(Event::PowerRoleSwap) if !DRIVER::HAS_POWER_ROLE_SWAP => {
+                        warn!("Power role swaps are not supported by the driver, ignoring request");
+                        State::Ready(contract)
+                    }
+                    Either3::Second(Event::PowerRoleSwap) => State::PrsSendSwap(contract),
+                    Either3::Third(()) => {
+                        warn!("No request from the sink within the PPS timeout");
+                        State::HardReset
+                    }
+                }
+            }
+            State::SendAlert(contract, alert, renegotiate) => {
+                let (contract, renegotiate) = (*contract, *renegotiate);
+                self.protocol_layer.transmit_alert(*alert).await?;
+
+                if renegotiate {
+                    State::SendCapabilities
+                } else {
+                    State::Ready(contract)
+                }
+            }
+            State::WaitNewCapabilities => {
+                let message = self.protocol_layer.receive_message().await?;
+                self.handle_message(message, State::WaitNewCapabilities).await?
+            }
+            State::SendSoftReset => {
+                self.protocol_layer.soft_reset().await?;
+
+                State::SendCapabilities
+            }
+            State::SoftReset => {
+                self.protocol_layer.reset();
+                self.protocol_layer
+                    .transmit_control_message(ControlMessageType::Accept)
+                    .await?;
+
+                State::SendCapabilities
+            }
+            State::HardReset => {
+                if self.hard_reset_counter.increment().is_err() {
+                    return Err(Error::PortPartnerUnresponsive);
+                }
+
+                self.protocol_layer.hard_reset().await?;
+                // Per spec 8.3.3.2.11: wait for PSHardResetTimer, before transitioning to default.
+                self.protocol_layer.get_timer(TimerType::PSHardReset).await;
+
+                State::TransitionToDefault
+            }
+            State::TransitionToDefault => {
+                // Per spec 8.3.3.2.12: VBUS returns to vSafe5V through vSafe0V, see `Startup`.
+                self.device_policy_manager.vsafe0v().await;
+                self.contract = None;
+                self.protocol_layer.reset();
+
+                State::Startup
+            }
+            State::PrsEvaluateSwap(contract) => {
+                let contract = *contract;
+
+                if !DRIVER::HAS_POWER_ROLE_SWAP {
+                    self.protocol_layer.transmit_not_supported().await?;
+                    State::Ready(contract)
+                } else if self.device_policy_manager.evaluate_power_role_swap().await {
+                    self.protocol_layer
+                        .transmit_control_message(ControlMessageType::Accept)
+                        .await?;
+                    State::PrsTransitionToOff
+                } else {
+                    self.protocol_layer.transmit_reject().await?;
+                    State::Ready(contract)
+                }
+            }
+            State::PrsSendSwap(contract) => {
+                let contract = *contract;
+                self.protocol_layer
+                    .transmit_control_message(ControlMessageType::PrSwap)
+                    .await?;
+
+                // Per spec 8.3.3.19: without a response, or when refused, the source stays ready.
+                match self
+                    .protocol_layer
+                    .receive_message_type(
+                        &[
+                            MessageType::Control(ControlMessageType::Accept),
+                            MessageType::Control(ControlMessageType::Reject),
+                            MessageType::Control(ControlMessageType::Wait),
+                        ],
+                        TimerType::SenderResponse,
+                    )
+                    .await
+                {
+                    Ok(message)
+                        if message.header.message_type() == MessageType::Control(ControlMessageType::Accept) =>
+                    {
+                        State::PrsTransitionToOff
+                    }
+                    Ok(_) | Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => State::Ready(contract),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            State::PrsTransitionToOff => {
+                self.device_policy_manager.vsafe0v().await;
+                self.contract = None;
+                self.protocol_layer.set_cc_termination(CcTermination::Rd).await;
+
+                // PS_RDY is the last message, that is sent as the source.
+                self.protocol_layer
+                    .transmit_control_message(ControlMessageType::PsRdy)
+                    .await?;
+                self.protocol_layer.set_roles(self.data_role(), PowerRole::Sink);
+
+                State::PrsWaitSourceOn
+            }
+            State::PrsWaitSourceOn => {
+                match self
+                    .protocol_layer
+                    .receive_message_type(
+                        &[MessageType::Control(ControlMessageType::PsRdy)],
+                        TimerType::PSSourceOnSpr,
+                    )
+                    .await
+                {
+                    Ok(_) => return Err(Error::PowerRoleSwapped),
+                    Err(ProtocolError::RxError(RxError::ReceiveTimeout)) => return Err(Error::PowerRoleSwapFailed),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        };
+
+        self.set_state(new_state);
+
+        Ok(())
+    }
+
+    /// Handle a message in a state that waits for the sink, i.e. `Ready`, or `WaitNewCapabilities`.
+    async fn handle_message(&mut self, message: Message, current_state: State) -> Result<State, Error> {
+        Ok(match message.header.message_type() {
+            MessageType::Data(DataMessageType::Request) => {
+                let Some(Payload::Data(Data::Request(request))) = message.payload else {
+                    unreachable_or_return!(ProtocolError::UnexpectedMessage)
+                };
+
+                State::NegotiateCapability(self.parse_request(request))
+            }
+            MessageType::Control(ControlMessageType::GetSourceCap) => State::SendCapabilities,
+            MessageType::Control(ControlMessageType::SoftReset) => State::SoftReset,
+            // A power role swap needs an explicit contract, see spec 6.3.9.
+            MessageType::Control(ControlMessageType::PrSwap) if let Some(contract) = self.contract => {
+                State::PrsEvaluateSwap(contract)
+            }
+            _ => {
+                self.protocol_layer.transmit_not_supported().await?;
+                current_state
+            }
+        })
+    }
+
+    /// Interpret a request against the advertised capabilities.
+    fn parse_request(&self, request: PowerSource) -> PowerSource {
+        match (request, self.source_capabilities.as_ref()) {
+            (PowerSource::Unknown(raw), Some(capabilities)) => PowerSource::from_raw(raw, capabilities),
+            (request, _) => request,
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        if state.name() != self.state.name() {
+            trace!("Enter state {:?}", state);
+            self.protocol_layer.trace(TraceEvent::StateChanged(state.name()));
+        }
+
+        self.state = state;
+    }
+}
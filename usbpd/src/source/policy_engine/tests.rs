@@ -0,0 +1,390 @@
+//! Tests for the source policy engine.
+
+use uom::si::electric_potential::volt;
+use uom::si::power::watt;
+
+use super::{Error, Source, State};
+use crate::counters::MessageId;
+use crate::dummy::{DummyDriver, DummySourceDevice, DummyTimer, MAX_DATA_MESSAGE_SIZE};
+use crate::protocol_layer::message::data::Data;
+use crate::protocol_layer::message::data::alert::AlertDataObject;
+use crate::protocol_layer::message::data::request::{FixedVariableSupply, PowerSource};
+use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+use crate::protocol_layer::message::header::{
+    ControlMessageType, DataMessageType, Header, MessageType, SpecificationRevision,
+};
+use crate::protocol_layer::message::{Message, Payload};
+use crate::source::device_policy_manager::{DevicePolicyManager, Evaluation, Event, Fault};
+use crate::timers::Timer;
+use crate::units::{ElectricPotential, Power};
+use crate::{DataRole, PowerRole};
+
+/// A timer that expires immediately.
+struct InstantTimer {}
+
+impl Timer for InstantTimer {
+    async fn after_millis(_milliseconds: u64) {}
+}
+
+fn get_policy_engine<TIMER: Timer>() -> Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, TIMER, DummySourceDevice> {
+    Source::new(DummyDriver::new(), DummySourceDevice {})
+}
+
+/// Get a header template for simulating sink messages (Sink/Ufp roles).
+fn get_sink_header_template() -> Header {
+    Header::new_template(DataRole::Ufp, PowerRole::Sink, SpecificationRevision::R3_X)
+}
+
+fn inject<DPM: DevicePolicyManager>(
+    policy_engine: &mut Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+    message: Message,
+) {
+    let mut buf = [0u8; MAX_DATA_MESSAGE_SIZE];
+    let len = message.to_bytes(&mut buf);
+    policy_engine.protocol_layer.driver().inject_received_data(&buf[..len]);
+}
+
+fn simulate_good_crc<DPM: DevicePolicyManager>(
+    policy_engine: &mut Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+    message_id: u8,
+) {
+    let header = Header::new_control(
+        get_sink_header_template(),
+        MessageId::new(message_id),
+        ControlMessageType::GoodCRC,
+    );
+    inject(policy_engine, Message::new(header));
+}
+
+fn simulate_request<DPM: DevicePolicyManager>(
+    policy_engine: &mut Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+    request: PowerSource,
+    message_id: u8,
+) {
+    let header = Header::new_data(
+        get_sink_header_template(),
+        MessageId::new(message_id),
+        DataMessageType::Request,
+        1,
+    );
+    inject(policy_engine, Message::new_with_data(header, Data::Request(request)));
+}
+
+fn fixed_request(object_position: u8, centiamperes: u16) -> PowerSource {
+    PowerSource::FixedVariableSupply(
+        FixedVariableSupply(0)
+            .with_object_position(object_position)
+            .with_raw_operating_current(centiamperes)
+            .with_raw_max_operating_current(centiamperes),
+    )
+}
+
+/// Negotiate a 9 V contract, and discard all transmitted messages.
+///
+/// The next message ID of the source is 3 afterwards.
+async fn negotiate_to_ready<DPM: DevicePolicyManager>(
+    policy_engine: &mut Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+) {
+    simulate_good_crc(policy_engine, 0);
+    simulate_request(policy_engine, fixed_request(2, 300), 0);
+    simulate_good_crc(policy_engine, 1);
+    simulate_good_crc(policy_engine, 2);
+
+    for _ in 0..4 {
+        policy_engine.run_step().await.unwrap();
+    }
+    assert!(matches!(policy_engine.state, State::Ready(_)));
+
+    while policy_engine.protocol_layer.driver().has_transmitted_data() {
+        policy_engine.protocol_layer.driver().probe_transmitted_data();
+    }
+}
+
+fn transmitted_message_type<DPM: DevicePolicyManager>(
+    policy_engine: &mut Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, DPM>,
+) -> MessageType {
+    let data = policy_engine.protocol_layer.driver().probe_transmitted_data();
+    Message::from_bytes(&data).unwrap().header.message_type()
+}
+
+#[tokio::test]
+async fn test_source_capability_timer() {
+    let mut policy_engine = get_policy_engine::<InstantTimer>();
+
+    // Without a sink, capabilities are re-advertised until nCapsCount is exceeded.
+    assert!(matches!(policy_engine.run().await, Err(Error::PortPartnerUnresponsive)));
+
+    let mut transmissions = 0;
+    while policy_engine.protocol_layer.driver().has_transmitted_data() {
+        let data = policy_engine.protocol_layer.driver().probe_transmitted_data();
+        assert_eq!(
+            Message::from_bytes(&data).unwrap().header.message_type(),
+            MessageType::Data(DataMessageType::SourceCapabilities)
+        );
+        transmissions += 1;
+    }
+
+    // Each of the 50 advertisements is retried twice.
+    assert_eq!(transmissions, 50 * 3);
+}
+
+#[tokio::test]
+async fn test_negotiation() {
+    let mut policy_engine = get_policy_engine::<DummyTimer>();
+
+    simulate_good_crc(&mut policy_engine, 0);
+    simulate_request(&mut policy_engine, fixed_request(2, 300), 0);
+
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendCapabilities));
+
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::NegotiateCapability(_)));
+
+    simulate_good_crc(&mut policy_engine, 1);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::TransitionSupply(_)));
+
+    simulate_good_crc(&mut policy_engine, 2);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::Ready(_)));
+    assert_eq!(
+        policy_engine.contract().map(PowerSource::raw),
+        Some(fixed_request(2, 300).raw())
+    );
+
+    for message_type in [
+        MessageType::Data(DataMessageType::SourceCapabilities),
+        MessageType::Control(ControlMessageType::GoodCRC),
+        MessageType::Control(ControlMessageType::Accept),
+        MessageType::Control(ControlMessageType::PsRdy),
+    ] {
+        assert_eq!(transmitted_message_type(&mut policy_engine), message_type);
+    }
+}
+
+#[tokio::test]
+async fn test_reject_invalid_request() {
+    let mut policy_engine = get_policy_engine::<DummyTimer>();
+
+    // The 9 V supply offers no more than 3 A.
+    simulate_good_crc(&mut policy_engine, 0);
+    simulate_request(&mut policy_engine, fixed_request(2, 500), 0);
+    simulate_good_crc(&mut policy_engine, 1);
+
+    for _ in 0..3 {
+        policy_engine.run_step().await.unwrap();
+    }
+
+    assert!(matches!(policy_engine.state, State::WaitNewCapabilities));
+    assert!(policy_engine.contract().is_none());
+
+    for message_type in [
+        MessageType::Data(DataMessageType::SourceCapabilities),
+        MessageType::Control(ControlMessageType::GoodCRC),
+        MessageType::Control(ControlMessageType::Reject),
+    ] {
+        assert_eq!(transmitted_message_type(&mut policy_engine), message_type);
+    }
+}
+
+/// A source device that records its VBUS control.
+struct RecordingSourceDevice {
+    power_budget: Option<Power>,
+    calls: std::vec::Vec<&'static str>,
+    voltage: Option<ElectricPotential>,
+}
+
+impl RecordingSourceDevice {
+    fn new() -> Self {
+        Self {
+            power_budget: None,
+            calls: std::vec::Vec::new(),
+            voltage: None,
+        }
+    }
+}
+
+impl DevicePolicyManager for RecordingSourceDevice {
+    fn source_capabilities(&self) -> SourceCapabilities {
+        DummySourceDevice {}.source_capabilities()
+    }
+
+    fn power_budget(&self) -> Option<Power> {
+        self.power_budget
+    }
+
+    async fn vsafe5v(&mut self) {
+        self.calls.push("vsafe5v");
+    }
+
+    async fn vsafe0v(&mut self) {
+        self.calls.push("vsafe0v");
+    }
+
+    async fn enable_output(&mut self, voltage: ElectricPotential) {
+        self.calls.push("enable_output");
+        self.voltage = Some(voltage);
+    }
+}
+
+/// A source device that cannot meet any request at this time.
+struct WaitingSourceDevice {}
+
+impl DevicePolicyManager for WaitingSourceDevice {
+    fn source_capabilities(&self) -> SourceCapabilities {
+        DummySourceDevice {}.source_capabilities()
+    }
+
+    async fn evaluate_request(&mut self, _request: &PowerSource, _capabilities: &SourceCapabilities) -> Evaluation {
+        Evaluation::Wait
+    }
+}
+
+fn get_recording_policy_engine(
+    device: RecordingSourceDevice,
+) -> Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, RecordingSourceDevice> {
+    Source::new(DummyDriver::new(), device)
+}
+
+#[tokio::test]
+async fn test_vbus_control() {
+    let mut policy_engine = get_recording_policy_engine(RecordingSourceDevice::new());
+
+    simulate_good_crc(&mut policy_engine, 0);
+    simulate_request(&mut policy_engine, fixed_request(2, 300), 0);
+    simulate_good_crc(&mut policy_engine, 1);
+    simulate_good_crc(&mut policy_engine, 2);
+
+    for _ in 0..4 {
+        policy_engine.run_step().await.unwrap();
+    }
+
+    assert!(matches!(policy_engine.state, State::Ready(_)));
+    assert_eq!(policy_engine.device_policy_manager.calls, ["vsafe5v", "enable_output"]);
+    assert_eq!(
+        policy_engine.device_policy_manager.voltage,
+        Some(ElectricPotential::new::<volt>(9))
+    );
+
+    // A hard reset returns VBUS to vSafe5V, through vSafe0V.
+    policy_engine.set_state(State::TransitionToDefault);
+    policy_engine.run_step().await.unwrap();
+    policy_engine.run_step().await.unwrap();
+    assert_eq!(
+        policy_engine.device_policy_manager.calls,
+        ["vsafe5v", "enable_output", "vsafe0v", "vsafe5v"]
+    );
+    assert!(policy_engine.contract().is_none());
+}
+
+#[tokio::test]
+async fn test_power_budget() {
+    let mut device = RecordingSourceDevice::new();
+    device.power_budget = Some(Power::new::<watt>(20));
+    let mut policy_engine = get_recording_policy_engine(device);
+
+    // 9 V at 3 A exceeds the budget of 20 W.
+    simulate_good_crc(&mut policy_engine, 0);
+    simulate_request(&mut policy_engine, fixed_request(2, 300), 0);
+    simulate_good_crc(&mut policy_engine, 1);
+
+    for _ in 0..3 {
+        policy_engine.run_step().await.unwrap();
+    }
+
+    assert!(matches!(policy_engine.state, State::WaitNewCapabilities));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    assert_eq!(
+        transmitted_message_type(&mut policy_engine),
+        MessageType::Control(ControlMessageType::Reject)
+    );
+}
+
+#[tokio::test]
+async fn test_wait() {
+    let mut policy_engine: Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, _> =
+        Source::new(DummyDriver::new(), WaitingSourceDevice {});
+
+    simulate_good_crc(&mut policy_engine, 0);
+    simulate_request(&mut policy_engine, fixed_request(1, 100), 0);
+    simulate_good_crc(&mut policy_engine, 1);
+
+    for _ in 0..3 {
+        policy_engine.run_step().await.unwrap();
+    }
+
+    assert!(matches!(policy_engine.state, State::WaitNewCapabilities));
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    policy_engine.protocol_layer.driver().probe_transmitted_data();
+    assert_eq!(
+        transmitted_message_type(&mut policy_engine),
+        MessageType::Control(ControlMessageType::Wait)
+    );
+}
+
+/// A source device that detects a single fault.
+struct FaultySourceDevice {
+    fault: Option<Fault>,
+}
+
+impl DevicePolicyManager for FaultySourceDevice {
+    fn source_capabilities(&self) -> SourceCapabilities {
+        DummySourceDevice {}.source_capabilities()
+    }
+
+    async fn get_event(&mut self) -> Event {
+        match self.fault.take() {
+            Some(fault) => Event::Fault(fault),
+            None => core::future::pending().await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_recoverable_fault() {
+    let alert = AlertDataObject::default().with_ocp_event(true);
+    let mut policy_engine: Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, _> =
+        Source::new(DummyDriver::new(), FaultySourceDevice { fault: None });
+    negotiate_to_ready(&mut policy_engine).await;
+    policy_engine.device_policy_manager.fault = Some(Fault::Recoverable {
+        alert,
+        renegotiate: true,
+    });
+
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendAlert(..)));
+
+    simulate_good_crc(&mut policy_engine, 3);
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::SendCapabilities));
+
+    let data = policy_engine.protocol_layer.driver().probe_transmitted_data();
+    let message = Message::from_bytes(&data).unwrap();
+    assert_eq!(message.header.message_type(), MessageType::Data(DataMessageType::Alert));
+    assert!(matches!(message.payload, Some(Payload::Data(Data::Alert(ado))) if ado == alert));
+}
+
+#[tokio::test]
+async fn test_severe_fault() {
+    let mut policy_engine: Source<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer, _> =
+        Source::new(DummyDriver::new(), FaultySourceDevice { fault: None });
+    negotiate_to_ready(&mut policy_engine).await;
+    policy_engine.device_policy_manager.fault = Some(Fault::Severe);
+
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+}
+
+#[tokio::test]
+async fn test_pps_comm_timer() {
+    use crate::protocol_layer::message::data::request::Pps;
+
+    let mut policy_engine = get_policy_engine::<InstantTimer>();
+
+    // Without periodic requests, the PPS contract ends with a hard reset.
+    policy_engine.state = State::Ready(PowerSource::Pps(Pps(0).with_object_position(5)));
+    policy_engine.run_step().await.unwrap();
+    assert!(matches!(policy_engine.state, State::HardReset));
+}
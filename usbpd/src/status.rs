@@ -0,0 +1,104 @@
+//! Present status of the local device.
+//!
+//! A single [`DeviceStatus`] holds the telemetry that the device reports to its port partner, such as its
+//! temperature and power consumption. It is the source for all outbound status reporting, such as the Status data
+//! block (see [6.5.2]) in answers to Get_Status.
+use crate::protocol_layer::message::extended::status::{PresentInput, StatusExtended};
+pub use crate::protocol_layer::message::extended::status::{STATUS_DATA_BLOCK_SIZE, TemperatureStatus};
+use crate::units::Power;
+
+/// Present telemetry of the local device.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeviceStatus {
+    /// The internal temperature in °C, if measured.
+    pub internal_temperature: Option<u8>,
+    /// The temperature state.
+    pub temperature_status: TemperatureStatus,
+    /// Whether the device is supplied by external power, besides VBUS.
+    pub external_power: bool,
+    /// The present power consumption, if measured.
+    pub present_power: Option<Power>,
+}
+
+impl DeviceStatus {
+    /// Create a status, without any telemetry.
+    pub const fn new() -> Self {
+        Self {
+            internal_temperature: None,
+            temperature_status: TemperatureStatus::NotSupported,
+            external_power: false,
+            present_power: None,
+        }
+    }
+
+    /// Set the internal temperature in °C.
+    pub const fn with_internal_temperature(self, internal_temperature: u8) -> Self {
+        Self {
+            internal_temperature: Some(internal_temperature),
+            ..self
+        }
+    }
+
+    /// Set the temperature state.
+    pub const fn with_temperature_status(self, temperature_status: TemperatureStatus) -> Self {
+        Self {
+            temperature_status,
+            ..self
+        }
+    }
+
+    /// Set whether the device is supplied by external power.
+    pub const fn with_external_power(self, external_power: bool) -> Self {
+        Self { external_power, ..self }
+    }
+
+    /// Set the present power consumption.
+    pub const fn with_present_power(self, present_power: Power) -> Self {
+        Self {
+            present_power: Some(present_power),
+            ..self
+        }
+    }
+
+    /// The Status data block.
+    ///
+    /// Fields that the device does not report are zero. The power consumption is not part of the block.
+    pub fn status_data_block(&self) -> StatusExtended {
+        StatusExtended {
+            internal_temperature: self.internal_temperature,
+            present_input: PresentInput::default().with_external_power(self.external_power),
+            temperature_status: self.temperature_status,
+            ..StatusExtended::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceStatus, STATUS_DATA_BLOCK_SIZE, TemperatureStatus};
+
+    #[test]
+    fn test_status_data_block() {
+        let mut buf = [0xffu8; STATUS_DATA_BLOCK_SIZE];
+
+        assert_eq!(
+            DeviceStatus::new().status_data_block().to_bytes(&mut buf),
+            STATUS_DATA_BLOCK_SIZE
+        );
+        assert_eq!(buf, [0; STATUS_DATA_BLOCK_SIZE]);
+
+        let status = DeviceStatus::new()
+            .with_internal_temperature(45)
+            .with_temperature_status(TemperatureStatus::Warning)
+            .with_external_power(true);
+        status.status_data_block().to_bytes(&mut buf);
+        assert_eq!(buf, [45, 0b10, 0, 0, 0b100, 0, 0]);
+
+        // A temperature of zero means "not supported", and one means "less than 2 °C".
+        DeviceStatus::new()
+            .with_internal_temperature(0)
+            .status_data_block()
+            .to_bytes(&mut buf);
+        assert_eq!(buf[0], 1);
+    }
+}
@@ -1,16 +1,37 @@
 //! Timers that are used by the protocol layer and policy engine.
+use core::ops::RangeInclusive;
+
+use crate::trace::Tracer;
+
+#[cfg(any(test, feature = "timer-audit"))]
+pub mod audit;
 
 /// The timer trait to implement by the user application.
 pub trait Timer {
     /// Expire after the specified number of milliseconds.
     fn after_millis(milliseconds: u64) -> impl Future<Output = ()>;
+
+    /// Expire after the specified number of microseconds.
+    ///
+    /// Used for tight protocol timings, such as tReceive. By default, this rounds up to the next full millisecond.
+    /// Implement it, if the underlying timer supports a finer resolution.
+    fn after_micros(microseconds: u64) -> impl Future<Output = ()> {
+        Self::after_millis(microseconds.div_ceil(1000))
+    }
+
+    /// The current time of a monotonic clock in microseconds.
+    ///
+    /// Used for collecting timing statistics, see [`crate::protocol_layer::stats`]. Returns `None` by default.
+    fn now_micros() -> Option<u64> {
+        None
+    }
 }
 
 use core::future::Future;
 
 /// Types of timers that are used for timeouts.
 #[allow(missing_docs)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TimerType {
     BISTContMode,
@@ -42,49 +63,247 @@ pub enum TimerType {
     SwapSourceStart,
     VCONNDischarge,
     VCONNOn,
+    VDMBusy,
     VDMModeEntry,
     VDMModeExit,
     VDMResponse,
 }
 
 impl TimerType {
+    /// The timeout duration in microseconds, as given by the USB PD specification.
+    pub const fn duration_micros(self) -> u64 {
+        match self {
+            TimerType::BISTContMode => 45_000,
+            TimerType::ChunkingNotSupported => 45_000,
+            TimerType::ChunkSenderRequest => 27_000,
+            TimerType::ChunkSenderResponse => 27_000,
+            // tReceive is 0.9..1.1 ms, use the upper bound with microsecond resolution.
+            TimerType::CRCReceive => 1_100,
+            TimerType::DataResetFail => 350_000,
+            TimerType::DataResetFailUFP => 500_000,
+            TimerType::DiscoverIdentity => 45_000,
+            TimerType::HardResetComplete => 5_000,
+            TimerType::NoResponse => 5_000_000,
+            TimerType::PSHardReset => 30_000,
+            TimerType::PSSourceOffSpr => 835_000,
+            TimerType::PSSourceOffEpr => 1_260_000,
+            TimerType::PSSourceOnSpr => 435_000,
+            TimerType::PSTransitionSpr => 500_000,
+            TimerType::PSTransitionEpr => 925_000,
+            TimerType::SenderResponse => 30_000,
+            TimerType::SinkEPREnter => 500_000,
+            TimerType::SinkEPRKeepAlive => 375_000,
+            TimerType::SinkPPSPeriodic => 5_000_000, // Max. 10 s
+            TimerType::SinkRequest => 100_000,
+            TimerType::SinkWaitCap => 465_000,
+            TimerType::SourceCapability => 150_000,
+            TimerType::SourceEPRKeepAlive => 875_000,
+            TimerType::SourcePPSComm => 13_500_000,
+            TimerType::SinkTx => 18_000,
+            TimerType::SwapSourceStart => 20_000,
+            TimerType::VCONNDischarge => 200_000,
+            TimerType::VCONNOn => 50_000,
+            TimerType::VDMBusy => 50_000,
+            TimerType::VDMModeEntry => 45_000,
+            TimerType::VDMModeExit => 45_000,
+            TimerType::VDMResponse => 27_000,
+        }
+    }
+
+    /// The range of valid durations in microseconds, as given by the USB PD specification.
+    ///
+    /// Timers without an upper bound end at `u64::MAX`.
+    pub const fn spec_range_micros(self) -> RangeInclusive<u64> {
+        match self {
+            TimerType::BISTContMode => 30_000..=60_000,
+            TimerType::ChunkingNotSupported => 40_000..=50_000,
+            TimerType::ChunkSenderRequest => 24_000..=30_000,
+            TimerType::ChunkSenderResponse => 24_000..=30_000,
+            TimerType::CRCReceive => 900..=1_100,
+            TimerType::DataResetFail => 300_000..=400_000,
+            TimerType::DataResetFailUFP => 450_000..=550_000,
+            TimerType::DiscoverIdentity => 40_000..=50_000,
+            TimerType::HardResetComplete => 4_000..=5_000,
+            TimerType::NoResponse => 4_500_000..=5_500_000,
+            TimerType::PSHardReset => 25_000..=35_000,
+            TimerType::PSSourceOffSpr => 750_000..=920_000,
+            TimerType::PSSourceOffEpr => 1_150_000..=1_370_000,
+            TimerType::PSSourceOnSpr => 390_000..=480_000,
+            TimerType::PSTransitionSpr => 450_000..=550_000,
+            TimerType::PSTransitionEpr => 830_000..=1_020_000,
+            TimerType::SenderResponse => 27_000..=33_000,
+            TimerType::SinkEPREnter => 450_000..=550_000,
+            TimerType::SinkEPRKeepAlive => 250_000..=500_000,
+            TimerType::SinkPPSPeriodic => 0..=10_000_000,
+            TimerType::SinkRequest => 100_000..=u64::MAX,
+            TimerType::SinkWaitCap => 310_000..=620_000,
+            TimerType::SourceCapability => 100_000..=200_000,
+            TimerType::SourceEPRKeepAlive => 750_000..=1_000_000,
+            TimerType::SourcePPSComm => 12_000_000..=15_000_000,
+            TimerType::SinkTx => 16_000..=20_000,
+            TimerType::SwapSourceStart => 20_000..=u64::MAX,
+            TimerType::VCONNDischarge => 160_000..=240_000,
+            TimerType::VCONNOn => 0..=50_000,
+            TimerType::VDMBusy => 50_000..=u64::MAX,
+            TimerType::VDMModeEntry => 40_000..=50_000,
+            TimerType::VDMModeExit => 40_000..=50_000,
+            TimerType::VDMResponse => 24_000..=30_000,
+        }
+    }
+
     /// Create a new timer for a given type.
     ///
     /// Times out after a duration that is given by the USB PD specification.
     pub fn get_timer<TIMER: Timer>(timer_type: TimerType) -> impl Future<Output = ()> {
-        match timer_type {
-            TimerType::BISTContMode => TIMER::after_millis(45),
-            TimerType::ChunkingNotSupported => TIMER::after_millis(45),
-            TimerType::ChunkSenderRequest => TIMER::after_millis(27),
-            TimerType::ChunkSenderResponse => TIMER::after_millis(27),
-            TimerType::CRCReceive => TIMER::after_millis(1),
-            TimerType::DataResetFail => TIMER::after_millis(350),
-            TimerType::DataResetFailUFP => TIMER::after_millis(500),
-            TimerType::DiscoverIdentity => TIMER::after_millis(45),
-            TimerType::HardResetComplete => TIMER::after_millis(5),
-            TimerType::NoResponse => TIMER::after_millis(5000),
-            TimerType::PSHardReset => TIMER::after_millis(30),
-            TimerType::PSSourceOffSpr => TIMER::after_millis(835),
-            TimerType::PSSourceOffEpr => TIMER::after_millis(1260),
-            TimerType::PSSourceOnSpr => TIMER::after_millis(435),
-            TimerType::PSTransitionSpr => TIMER::after_millis(500),
-            TimerType::PSTransitionEpr => TIMER::after_millis(925),
-            TimerType::SenderResponse => TIMER::after_millis(30),
-            TimerType::SinkEPREnter => TIMER::after_millis(500),
-            TimerType::SinkEPRKeepAlive => TIMER::after_millis(375),
-            TimerType::SinkPPSPeriodic => TIMER::after_millis(5000), // Max. 10 s
-            TimerType::SinkRequest => TIMER::after_millis(100),
-            TimerType::SinkWaitCap => TIMER::after_millis(465),
-            TimerType::SourceCapability => TIMER::after_millis(150),
-            TimerType::SourceEPRKeepAlive => TIMER::after_millis(875),
-            TimerType::SourcePPSComm => TIMER::after_millis(13500),
-            TimerType::SinkTx => TIMER::after_millis(18),
-            TimerType::SwapSourceStart => TIMER::after_millis(20),
-            TimerType::VCONNDischarge => TIMER::after_millis(200),
-            TimerType::VCONNOn => TIMER::after_millis(50),
-            TimerType::VDMModeEntry => TIMER::after_millis(45),
-            TimerType::VDMModeExit => TIMER::after_millis(45),
-            TimerType::VDMResponse => TIMER::after_millis(27),
+        TIMER::after_micros(timer_type.duration_micros())
+    }
+}
+
+/// The number of timer types.
+const TIMER_TYPE_COUNT: usize = TimerType::VDMResponse as usize + 1;
+
+/// Durations that replace the ones given by the USB PD specification, for selected timer types.
+///
+/// Useful for port partners that are known to violate the specified timings.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimerOverrides {
+    micros: [Option<u64>; TIMER_TYPE_COUNT],
+}
+
+impl Default for TimerOverrides {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimerOverrides {
+    /// Create a set without overrides.
+    pub const fn new() -> Self {
+        Self {
+            micros: [None; TIMER_TYPE_COUNT],
         }
     }
+
+    /// Override the duration of a timer type in microseconds.
+    pub const fn with(mut self, timer_type: TimerType, microseconds: u64) -> Self {
+        self.micros[timer_type as usize] = Some(microseconds);
+        self
+    }
+
+    /// The effective duration of a timer type in microseconds.
+    pub const fn duration_micros(&self, timer_type: TimerType) -> u64 {
+        match self.micros[timer_type as usize] {
+            Some(microseconds) => microseconds,
+            None => timer_type.duration_micros(),
+        }
+    }
+
+    /// Create a new timer for a given type, that uses the overridden duration, if any.
+    pub fn get_timer<TIMER: Timer>(&self, timer_type: TimerType) -> impl Future<Output = ()> + use<TIMER> {
+        TIMER::after_micros(self.duration_micros(timer_type))
+    }
+
+    /// Create a new timer like [`Self::get_timer`], that reports its start and expiry to the `tracer`.
+    pub(crate) fn get_traced_timer<TIMER: Timer, TRACER: Tracer>(
+        &self,
+        timer_type: TimerType,
+        tracer: &TRACER,
+    ) -> impl Future<Output = ()> + use<TIMER, TRACER> {
+        let duration_micros = self.duration_micros(timer_type);
+        let tracer = tracer.clone();
+
+        async move {
+            tracer.timer_started(TIMER::now_micros(), timer_type, duration_micros);
+            TIMER::after_micros(duration_micros).await;
+            tracer.timer_expired(TIMER::now_micros(), timer_type);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::{Timer, TimerOverrides, TimerType};
+
+    static LAST_MILLISECONDS: AtomicU64 = AtomicU64::new(0);
+
+    /// A timer that only supports millisecond resolution, and records the last requested duration.
+    struct MillisecondTimer {}
+
+    impl Timer for MillisecondTimer {
+        async fn after_millis(milliseconds: u64) {
+            LAST_MILLISECONDS.store(milliseconds, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_after_micros_rounds_up() {
+        MillisecondTimer::after_micros(1_100).await;
+        assert_eq!(LAST_MILLISECONDS.load(Ordering::Relaxed), 2);
+
+        MillisecondTimer::after_micros(3_000).await;
+        assert_eq!(LAST_MILLISECONDS.load(Ordering::Relaxed), 3);
+
+        TimerType::get_timer::<MillisecondTimer>(TimerType::SenderResponse).await;
+        assert_eq!(LAST_MILLISECONDS.load(Ordering::Relaxed), 30);
+    }
+
+    #[test]
+    fn test_spec_ranges() {
+        use TimerType::*;
+
+        let timer_types = [
+            BISTContMode,
+            ChunkingNotSupported,
+            ChunkSenderRequest,
+            ChunkSenderResponse,
+            CRCReceive,
+            DataResetFail,
+            DataResetFailUFP,
+            DiscoverIdentity,
+            HardResetComplete,
+            NoResponse,
+            PSHardReset,
+            PSSourceOffSpr,
+            PSSourceOffEpr,
+            PSSourceOnSpr,
+            PSTransitionSpr,
+            PSTransitionEpr,
+            SenderResponse,
+            SinkEPREnter,
+            SinkEPRKeepAlive,
+            SinkPPSPeriodic,
+            SinkRequest,
+            SinkWaitCap,
+            SourceCapability,
+            SourceEPRKeepAlive,
+            SourcePPSComm,
+            SinkTx,
+            SwapSourceStart,
+            VCONNDischarge,
+            VCONNOn,
+            VDMBusy,
+            VDMModeEntry,
+            VDMModeExit,
+            VDMResponse,
+        ];
+        assert_eq!(timer_types.len(), super::TIMER_TYPE_COUNT);
+
+        for timer_type in timer_types {
+            assert!(
+                timer_type.spec_range_micros().contains(&timer_type.duration_micros()),
+                "{:?}",
+                timer_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_overrides() {
+        const OVERRIDES: TimerOverrides = TimerOverrides::new().with(TimerType::SinkWaitCap, 620_000);
+        assert_eq!(OVERRIDES.duration_micros(TimerType::SinkWaitCap), 620_000);
+        assert_eq!(OVERRIDES.duration_micros(TimerType::SenderResponse), 30_000);
+    }
 }
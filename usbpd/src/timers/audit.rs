@@ -0,0 +1,177 @@
+//! Auditing of timer durations (`timer-audit` feature).
+//!
+//! A [`TimerAudit`] is a [`Tracer`] that records every timer that the policy engine and protocol layer start,
+//! together with the state that armed it. Tests can then check that each timer is of the expected type, and that its
+//! duration is within the range of the specification:
+//!
+//! ```ignore
+//! let audit = RefCell::new(TimerAudit::<32>::new());
+//! let mut sink = Sink::new_with_tracer(driver, device, &audit);
+//!
+//! // Run the sink...
+//!
+//! audit.borrow().assert_within_spec();
+//! ```
+use core::cell::RefCell;
+
+use heapless::Deque;
+
+use super::TimerType;
+use crate::trace::{TraceEvent, Tracer};
+
+/// A started timer.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimerRecord {
+    /// The type of the timer.
+    pub timer_type: TimerType,
+    /// The name of the policy engine state that armed the timer.
+    pub state: &'static str,
+    /// The duration of the timer in µs.
+    pub duration_micros: u64,
+    /// The time of the start in µs, if the [`Timer`](super::Timer) provides timestamps.
+    pub started_micros: Option<u64>,
+    /// Whether the timer expired, rather than being cancelled.
+    pub expired: bool,
+}
+
+impl TimerRecord {
+    /// Whether the duration is within the range of the specification.
+    pub fn within_spec(&self) -> bool {
+        self.timer_type.spec_range_micros().contains(&self.duration_micros)
+    }
+}
+
+/// A fixed-size buffer of the `N` most recently started timers.
+#[derive(Debug)]
+pub struct TimerAudit<const N: usize> {
+    records: Deque<TimerRecord, N>,
+    state: &'static str,
+}
+
+impl<const N: usize> Default for TimerAudit<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TimerAudit<N> {
+    /// Create a new, empty audit.
+    pub const fn new() -> Self {
+        Self {
+            records: Deque::new(),
+            state: "",
+        }
+    }
+
+    /// The records, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &TimerRecord> {
+        self.records.iter()
+    }
+
+    /// The records of timers, whose duration is outside of the range of the specification.
+    pub fn violations(&self) -> impl Iterator<Item = &TimerRecord> {
+        self.iter().filter(|record| !record.within_spec())
+    }
+
+    /// The timer types that were armed in a given state, from oldest to newest.
+    pub fn armed_in<'a>(&'a self, state: &'a str) -> impl Iterator<Item = TimerType> + 'a {
+        self.iter()
+            .filter(move |record| record.state == state)
+            .map(|record| record.timer_type)
+    }
+
+    /// Panic, if any timer duration is outside of the range of the specification.
+    pub fn assert_within_spec(&self) {
+        if let Some(record) = self.violations().next() {
+            panic!(
+                "{:?} armed in {} with {} us, outside of {:?}",
+                record.timer_type,
+                record.state,
+                record.duration_micros,
+                record.timer_type.spec_range_micros()
+            );
+        }
+    }
+
+    /// Remove all records.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    fn push(&mut self, record: TimerRecord) {
+        if self.records.is_full() {
+            self.records.pop_front();
+        }
+
+        // Cannot fail, there is space now.
+        let _ = self.records.push_back(record);
+    }
+}
+
+/// Records into a shared audit.
+///
+/// Timers are not recorded, while the audit is borrowed elsewhere.
+impl<const N: usize> Tracer for &RefCell<TimerAudit<N>> {
+    fn record(&self, _timestamp_micros: Option<u64>, event: TraceEvent) {
+        if let TraceEvent::StateChanged(state) = event
+            && let Ok(mut audit) = self.try_borrow_mut()
+        {
+            audit.state = state;
+        }
+    }
+
+    fn timer_started(&self, timestamp_micros: Option<u64>, timer_type: TimerType, duration_micros: u64) {
+        if let Ok(mut audit) = self.try_borrow_mut() {
+            let state = audit.state;
+            audit.push(TimerRecord {
+                timer_type,
+                state,
+                duration_micros,
+                started_micros: timestamp_micros,
+                expired: false,
+            });
+        }
+    }
+
+    fn timer_expired(&self, _timestamp_micros: Option<u64>, timer_type: TimerType) {
+        if let Ok(mut audit) = self.try_borrow_mut()
+            && let Some(record) = audit
+                .records
+                .iter_mut()
+                .rev()
+                .find(|record| record.timer_type == timer_type)
+        {
+            record.expired = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::TimerAudit;
+    use crate::timers::TimerType;
+    use crate::trace::{TraceEvent, Tracer};
+
+    #[test]
+    fn test_audit() {
+        let audit = RefCell::new(TimerAudit::<2>::new());
+        let tracer = &audit;
+
+        tracer.record(None, TraceEvent::StateChanged("WaitForCapabilities"));
+        tracer.timer_started(None, TimerType::SinkWaitCap, 465_000);
+        tracer.record(None, TraceEvent::StateChanged("SelectCapability"));
+        tracer.timer_started(None, TimerType::SenderResponse, 10_000);
+        tracer.timer_expired(None, TimerType::SenderResponse);
+
+        let audit = audit.borrow();
+        assert!(audit.armed_in("WaitForCapabilities").eq([TimerType::SinkWaitCap]));
+
+        let violations: std::vec::Vec<_> = audit.violations().collect();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].state, "SelectCapability");
+        assert!(violations[0].expired);
+    }
+}
@@ -0,0 +1,279 @@
+//! Traces of protocol activity, for debugging and archiving negotiations.
+//!
+//! On embedded targets, a [`TraceBuffer`] keeps the most recent protocol events (state changes, message types,
+//! errors) in a fixed-size ring buffer. An application can dump it after a failure, e.g. over RTT, to debug field
+//! units without a PD analyzer:
+//!
+//! ```ignore
+//! let trace = RefCell::new(TraceBuffer::<32>::new());
+//! let mut sink = Sink::new_with_tracer(driver, device, &trace);
+//!
+//! if sink.run().await.is_err() {
+//!     trace.borrow().dump();
+//! }
+//! ```
+//!
+//! A [`Timeline`] keeps a compact event timeline, including timers, which renders to a sequence diagram.
+//!
+//! On hosts (`std` feature), a [`TraceRecorder`] captures complete messages instead.
+//!
+//! With the `capability-summary` feature, sinks also log and trace a [`CapabilitiesSummary`] whenever they evaluate
+//! source capabilities, so that field logs show what the source offered without trace level.
+use core::cell::RefCell;
+
+use heapless::Deque;
+#[cfg(feature = "capability-summary")]
+use uom::si::power::watt;
+
+use crate::protocol_layer::ProtocolError;
+#[cfg(feature = "capability-summary")]
+use crate::protocol_layer::message::data::source_capabilities::{Augmented, PowerDataObject, SourceCapabilities};
+use crate::protocol_layer::message::header::MessageType;
+use crate::timers::TimerType;
+
+#[cfg(feature = "std")]
+mod recorder;
+mod timeline;
+
+#[cfg(feature = "std")]
+pub use recorder::{Direction, RecordingDriver, TraceEntry, TraceFrame, TraceRecorder};
+pub use timeline::{Timeline, TimelineEntry, TimelineEvent};
+
+/// A protocol event.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TraceEvent {
+    /// The policy engine entered a new state.
+    StateChanged(&'static str),
+    /// A message was received (GoodCRC excluded).
+    MessageReceived(MessageType),
+    /// A message was transmitted, and acknowledged by the port partner.
+    MessageTransmitted(MessageType),
+    /// The port partner signaled Hard Reset.
+    HardResetReceived,
+    /// Hard Reset was signaled to the port partner.
+    HardResetTransmitted,
+    /// A protocol error occurred in the policy engine.
+    ProtocolError(ProtocolError),
+    /// The policy engine evaluates source capabilities.
+    #[cfg(feature = "capability-summary")]
+    CapabilitiesEvaluated(CapabilitiesSummary),
+}
+
+/// A one-line summary of source capabilities.
+#[cfg(feature = "capability-summary")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CapabilitiesSummary {
+    /// The number of PDOs, without zero-padding.
+    pub count: u8,
+    /// The maximum power in W that any PDO offers.
+    pub max_power_watts: u32,
+    /// Whether the source is EPR mode capable.
+    pub epr_mode_capable: bool,
+    /// Whether the capabilities differ from the previously evaluated ones.
+    pub changed: bool,
+}
+
+#[cfg(feature = "capability-summary")]
+impl CapabilitiesSummary {
+    /// Summarize `capabilities`, compared to the `previous` capabilities, if any.
+    pub fn new(capabilities: &SourceCapabilities, previous: Option<&SourceCapabilities>) -> Self {
+        let pdos = || capabilities.offered_pdos().map(|(_, pdo)| pdo);
+
+        Self {
+            count: pdos().count() as u8,
+            max_power_watts: pdos().map(max_power_watts).max().unwrap_or(0),
+            epr_mode_capable: capabilities.epr_mode_capable(),
+            changed: previous.is_none_or(|previous| previous.pdos() != capabilities.pdos()),
+        }
+    }
+}
+
+/// The maximum power of a PDO in W, rounded down.
+#[cfg(feature = "capability-summary")]
+fn max_power_watts(pdo: &PowerDataObject) -> u32 {
+    let power = match pdo {
+        PowerDataObject::FixedSupply(supply) => supply.voltage() * supply.max_current(),
+        PowerDataObject::Battery(battery) => battery.max_power(),
+        PowerDataObject::VariableSupply(supply) => supply.max_voltage() * supply.max_current(),
+        PowerDataObject::Augmented(Augmented::Spr(pps)) => pps.max_voltage() * pps.max_current(),
+        PowerDataObject::Augmented(Augmented::Epr(avs)) => avs.pd_power(),
+        PowerDataObject::Augmented(Augmented::Unknown(_)) | PowerDataObject::Unknown(_) => return 0,
+    };
+
+    power.get::<watt>()
+}
+
+/// A protocol event, with the time of its occurrence.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TraceRecord {
+    /// The time in µs, if the [`Timer`](crate::timers::Timer) provides timestamps.
+    pub timestamp_micros: Option<u64>,
+    /// The event.
+    pub event: TraceEvent,
+}
+
+/// A receiver of protocol events.
+///
+/// Tracers are cloned into the policy engine and protocol layer, so they are usually references to shared storage.
+pub trait Tracer: Clone {
+    /// Record an event.
+    fn record(&self, timestamp_micros: Option<u64>, event: TraceEvent);
+
+    /// A timer was started, with the given duration.
+    ///
+    /// Ignored by default. Used for auditing timer durations, see [`crate::timers::audit`].
+    fn timer_started(&self, _timestamp_micros: Option<u64>, _timer_type: TimerType, _duration_micros: u64) {}
+
+    /// A timer expired.
+    ///
+    /// Ignored by default. Timers that are cancelled before, do not report their expiry.
+    fn timer_expired(&self, _timestamp_micros: Option<u64>, _timer_type: TimerType) {}
+}
+
+/// Tracing is disabled.
+impl Tracer for () {
+    fn record(&self, _timestamp_micros: Option<u64>, _event: TraceEvent) {}
+}
+
+/// Records into a shared ring buffer.
+///
+/// Events are dropped, while the buffer is borrowed elsewhere, e.g. during a dump.
+impl<const N: usize> Tracer for &RefCell<TraceBuffer<N>> {
+    fn record(&self, timestamp_micros: Option<u64>, event: TraceEvent) {
+        if let Ok(mut buffer) = self.try_borrow_mut() {
+            buffer.push(TraceRecord {
+                timestamp_micros,
+                event,
+            });
+        }
+    }
+}
+
+/// A fixed-size ring buffer of the `N` most recent protocol events.
+#[derive(Debug, Default)]
+pub struct TraceBuffer<const N: usize> {
+    records: Deque<TraceRecord, N>,
+    overwritten: u32,
+}
+
+impl<const N: usize> TraceBuffer<N> {
+    /// Create a new, empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            records: Deque::new(),
+            overwritten: 0,
+        }
+    }
+
+    /// Add a record, overwriting the oldest one if the buffer is full.
+    pub fn push(&mut self, record: TraceRecord) {
+        if self.records.is_full() {
+            self.records.pop_front();
+            self.overwritten = self.overwritten.wrapping_add(1);
+        }
+
+        // Cannot fail, there is space now.
+        let _ = self.records.push_back(record);
+    }
+
+    /// The records, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.records.iter()
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whether there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The number of records that were overwritten, since the buffer was created or cleared.
+    pub fn overwritten(&self) -> u32 {
+        self.overwritten
+    }
+
+    /// Remove all records.
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.overwritten = 0;
+    }
+
+    /// Log all records, from oldest to newest.
+    pub fn dump(&self) {
+        info!("Trace: {} records, {} overwritten", self.len(), self.overwritten);
+
+        for record in self.iter() {
+            match record.timestamp_micros {
+                Some(timestamp) => info!("[{} us] {:?}", timestamp, record.event),
+                None => info!("{:?}", record.event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::{TraceBuffer, TraceEvent, Tracer};
+    use crate::protocol_layer::message::header::{ControlMessageType, MessageType};
+
+    #[test]
+    fn test_ring_buffer() {
+        let buffer = RefCell::new(TraceBuffer::<2>::new());
+        let tracer = &buffer;
+
+        tracer.record(Some(1), TraceEvent::StateChanged("Discovery"));
+        tracer.record(Some(2), TraceEvent::HardResetReceived);
+        tracer.record(
+            None,
+            TraceEvent::MessageReceived(MessageType::Control(ControlMessageType::Accept)),
+        );
+
+        let buffer = buffer.borrow();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.overwritten(), 1);
+
+        let mut records = buffer.iter();
+        assert_eq!(records.next().unwrap().timestamp_micros, Some(2));
+        assert!(matches!(
+            records.next().unwrap().event,
+            TraceEvent::MessageReceived(MessageType::Control(ControlMessageType::Accept))
+        ));
+    }
+
+    #[cfg(feature = "capability-summary")]
+    #[test]
+    fn test_capabilities_summary() {
+        use super::CapabilitiesSummary;
+        use crate::dummy::get_dummy_source_capabilities;
+        use crate::protocol_layer::message::data::source_capabilities::SourceCapabilities;
+
+        let capabilities = SourceCapabilities::new(heapless::Vec::from_iter(get_dummy_source_capabilities()));
+        let summary = CapabilitiesSummary::new(&capabilities, None);
+        assert_eq!(summary.count, capabilities.num_objects());
+        // The PPS with 11 V at 5 A.
+        assert_eq!(summary.max_power_watts, 55);
+        assert!(!summary.epr_mode_capable);
+        assert!(summary.changed);
+
+        let summary = CapabilitiesSummary::new(&capabilities, Some(&capabilities));
+        assert!(!summary.changed);
+    }
+
+    #[test]
+    fn test_record_while_borrowed() {
+        let buffer = RefCell::new(TraceBuffer::<2>::new());
+
+        let borrowed = buffer.borrow();
+        (&buffer).record(None, TraceEvent::HardResetTransmitted);
+        assert!(borrowed.is_empty());
+    }
+}
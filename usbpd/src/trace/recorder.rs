@@ -0,0 +1,349 @@
+//! Recording of complete negotiation traces on hosts.
+//!
+//! A [`RecordingDriver`] wraps any [`Driver`] and records all frames that pass through it into a shared
+//! [`TraceRecorder`], which can be exported as JSON. With the `serde` feature, the recorder also implements
+//! `serde::Serialize`, for use with other formats.
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::string::String;
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+use usbpd_traits::{CcTermination, Driver, DriverRxError, DriverTxError, Frame, RolePreference};
+
+use crate::protocol_layer::message::Message;
+use crate::timers::Timer;
+
+/// The direction of a recorded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// Received from the port partner.
+    Rx,
+    /// Transmitted to the port partner.
+    Tx,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        }
+    }
+}
+
+/// A recorded frame.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraceFrame {
+    /// A message, with its raw bytes.
+    ///
+    /// The message is `None`, if it could not be parsed on its own, e.g. for chunks of extended messages.
+    Message {
+        /// The raw bytes, starting with the message header.
+        raw: Vec<u8>,
+        /// The parsed message.
+        message: Option<Message>,
+    },
+    /// Hard Reset signaling.
+    HardReset,
+}
+
+/// A recorded frame, with its timestamp and direction.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    /// The time of recording in µs, as reported by [`Timer::now_micros`], or zero if not available.
+    pub timestamp_micros: u64,
+    /// The direction of the frame.
+    pub direction: Direction,
+    /// The frame itself.
+    pub frame: TraceFrame,
+}
+
+/// A recorder of protocol traces.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceRecorder {
+    entries: Vec<TraceEntry>,
+}
+
+impl TraceRecorder {
+    /// Create a new, empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new, empty recorder that can be shared with a [`RecordingDriver`].
+    pub fn new_shared() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Record a frame.
+    pub fn record(&mut self, timestamp_micros: u64, direction: Direction, frame: TraceFrame) {
+        self.entries.push(TraceEntry {
+            timestamp_micros,
+            direction,
+            frame,
+        });
+    }
+
+    /// Record a message from its raw bytes.
+    pub fn record_bytes(&mut self, timestamp_micros: u64, direction: Direction, raw: &[u8]) {
+        let message = (raw.len() >= 2).then(|| Message::from_bytes(raw).ok()).flatten();
+
+        self.record(
+            timestamp_micros,
+            direction,
+            TraceFrame::Message {
+                raw: raw.to_vec(),
+                message,
+            },
+        );
+    }
+
+    /// The recorded entries, in order of recording.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Remove all recorded entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Export the trace as a JSON array.
+    ///
+    /// Each entry holds the timestamp, direction, and raw bytes (as a hex string) of a frame. Parsed messages are
+    /// added in their debug representation, for human readers. Hard resets are marked by `"hard_reset": true`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+
+            write!(
+                json,
+                "{{\"timestamp_micros\":{},\"direction\":\"{}\"",
+                entry.timestamp_micros,
+                entry.direction.as_str()
+            )
+            .unwrap();
+
+            match &entry.frame {
+                TraceFrame::Message { raw, message } => {
+                    json.push_str(",\"raw\":\"");
+                    for byte in raw {
+                        write!(json, "{byte:02X}").unwrap();
+                    }
+                    json.push('"');
+
+                    if let Some(message) = message {
+                        json.push_str(",\"message\":");
+                        push_json_string(&mut json, &std::format!("{message:?}"));
+                    }
+                }
+                TraceFrame::HardReset => json.push_str(",\"hard_reset\":true"),
+            }
+
+            json.push('}');
+        }
+
+        json.push(']');
+        json
+    }
+}
+
+/// Append a string to JSON output, with quotes and escapes.
+fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+}
+
+/// A driver that records all frames into a [`TraceRecorder`].
+///
+/// Timestamps are taken from the timer `TIMER`.
+pub struct RecordingDriver<DRIVER: Driver, TIMER: Timer> {
+    driver: DRIVER,
+    recorder: Arc<Mutex<TraceRecorder>>,
+    _timer: PhantomData<TIMER>,
+}
+
+impl<DRIVER: Driver, TIMER: Timer> RecordingDriver<DRIVER, TIMER> {
+    /// Wrap a driver, recording into `recorder`.
+    pub fn new(driver: DRIVER, recorder: Arc<Mutex<TraceRecorder>>) -> Self {
+        Self {
+            driver,
+            recorder,
+            _timer: PhantomData,
+        }
+    }
+
+    /// Get the wrapped driver back.
+    pub fn into_inner(self) -> DRIVER {
+        self.driver
+    }
+
+    fn now() -> u64 {
+        TIMER::now_micros().unwrap_or(0)
+    }
+
+    fn record_bytes(&self, direction: Direction, raw: &[u8]) {
+        self.recorder.lock().unwrap().record_bytes(Self::now(), direction, raw);
+    }
+
+    fn record_hard_reset(&self, direction: Direction) {
+        self.recorder
+            .lock()
+            .unwrap()
+            .record(Self::now(), direction, TraceFrame::HardReset);
+    }
+}
+
+impl<DRIVER: Driver, TIMER: Timer> Driver for RecordingDriver<DRIVER, TIMER> {
+    const HAS_AUTO_GOOD_CRC: bool = DRIVER::HAS_AUTO_GOOD_CRC;
+    const HAS_AUTO_RETRY: bool = DRIVER::HAS_AUTO_RETRY;
+    const HAS_VBUS_DETECTION: bool = DRIVER::HAS_VBUS_DETECTION;
+    const HAS_BIST_CARRIER_MODE: bool = DRIVER::HAS_BIST_CARRIER_MODE;
+    const HAS_ROLE_PREFERENCE: bool = DRIVER::HAS_ROLE_PREFERENCE;
+    const HAS_POWER_ROLE_SWAP: bool = DRIVER::HAS_POWER_ROLE_SWAP;
+
+    async fn wait_for_vbus(&mut self) {
+        self.driver.wait_for_vbus().await
+    }
+
+    async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, DriverRxError> {
+        let result = self.driver.receive(buffer).await;
+
+        match result {
+            Ok(len) => self.record_bytes(Direction::Rx, &buffer[..len]),
+            Err(DriverRxError::HardReset) => self.record_hard_reset(Direction::Rx),
+            Err(DriverRxError::Discarded | DriverRxError::Detached | DriverRxError::Fault(_)) => (),
+        }
+
+        result
+    }
+
+    async fn receive_with<R>(&mut self, handler: impl FnOnce(Frame<'_>) -> R) -> Result<R, DriverRxError> {
+        let recorder = &self.recorder;
+        let result = self
+            .driver
+            .receive_with(|frame| {
+                recorder
+                    .lock()
+                    .unwrap()
+                    .record_bytes(Self::now(), Direction::Rx, &frame);
+                handler(frame)
+            })
+            .await;
+
+        if let Err(DriverRxError::HardReset) = result {
+            self.record_hard_reset(Direction::Rx);
+        }
+
+        result
+    }
+
+    async fn transmit(&mut self, data: &[u8]) -> Result<(), DriverTxError> {
+        let result = self.driver.transmit(data).await;
+
+        match result {
+            Ok(()) => self.record_bytes(Direction::Tx, data),
+            // The port partner signaled Hard Reset during transmission.
+            Err(DriverTxError::HardReset) => self.record_hard_reset(Direction::Rx),
+            Err(DriverTxError::Discarded | DriverTxError::Detached | DriverTxError::Fault(_)) => (),
+        }
+
+        result
+    }
+
+    async fn transmit_hard_reset(&mut self) -> Result<(), DriverTxError> {
+        let result = self.driver.transmit_hard_reset().await;
+
+        if result.is_ok() {
+            self.record_hard_reset(Direction::Tx);
+        }
+
+        result
+    }
+
+    async fn set_bist_carrier_mode(&mut self, enabled: bool) {
+        self.driver.set_bist_carrier_mode(enabled).await
+    }
+
+    async fn set_role_preference(&mut self, preference: RolePreference) {
+        self.driver.set_role_preference(preference).await
+    }
+
+    async fn set_cc_termination(&mut self, termination: CcTermination) {
+        self.driver.set_cc_termination(termination).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use usbpd_traits::Driver;
+
+    use super::{Direction, RecordingDriver, TraceFrame, TraceRecorder};
+    use crate::dummy::{DUMMY_CAPABILITIES, DummyDriver, DummyTimer, MAX_DATA_MESSAGE_SIZE};
+
+    #[tokio::test]
+    async fn test_recording_driver() {
+        let recorder = TraceRecorder::new_shared();
+
+        let mut inner = DummyDriver::new();
+        inner.inject_received_data(&DUMMY_CAPABILITIES);
+        let mut driver: RecordingDriver<DummyDriver<MAX_DATA_MESSAGE_SIZE>, DummyTimer> =
+            RecordingDriver::new(inner, recorder.clone());
+
+        let mut buffer = [0u8; MAX_DATA_MESSAGE_SIZE];
+        driver.receive(&mut buffer).await.unwrap();
+        driver.transmit(&[0x41, 0x00]).await.unwrap();
+        driver.transmit_hard_reset().await.unwrap();
+
+        let recorder = recorder.lock().unwrap();
+        let entries = recorder.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].direction, Direction::Rx);
+        assert!(matches!(
+            &entries[0].frame,
+            TraceFrame::Message { message: Some(_), .. }
+        ));
+        assert_eq!(entries[1].direction, Direction::Tx);
+        assert!(matches!(entries[2].frame, TraceFrame::HardReset));
+
+        let json = recorder.to_json();
+        assert!(json.starts_with("[{\"timestamp_micros\":0,\"direction\":\"rx\",\"raw\":\"A1712C9101082CD1"));
+        assert!(json.contains("{\"timestamp_micros\":0,\"direction\":\"tx\",\"raw\":\"4100\",\"message\":\"Message {"));
+        assert!(json.ends_with("{\"timestamp_micros\":0,\"direction\":\"tx\",\"hard_reset\":true}]"));
+    }
+
+    #[test]
+    fn test_unparsable_frame() {
+        let mut recorder = TraceRecorder::new();
+        recorder.record_bytes(42, Direction::Rx, &[0x01]);
+
+        assert!(matches!(
+            &recorder.entries()[0].frame,
+            TraceFrame::Message { message: None, .. }
+        ));
+        assert_eq!(
+            recorder.to_json(),
+            "[{\"timestamp_micros\":42,\"direction\":\"rx\",\"raw\":\"01\"}]"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Compact negotiation timelines, for visualization.
+//!
+//! A [`Timeline`] is a [`Tracer`] that keeps state changes, messages, and timer events with millisecond timestamps.
+//! It is small enough to keep on embedded targets, and to attach to bug reports. On hosts (`std` feature),
+//! [`Timeline::to_mermaid`] renders it as a Mermaid sequence diagram:
+//!
+//! ```ignore
+//! let timeline = RefCell::new(Timeline::<64>::new());
+//! let mut sink = Sink::new_with_tracer(driver, device, &timeline);
+//!
+//! // Run the sink...
+//!
+//! println!("{}", timeline.borrow().to_mermaid());
+//! ```
+use core::cell::RefCell;
+
+use heapless::Deque;
+
+use super::{TraceEvent, Tracer};
+use crate::protocol_layer::ProtocolError;
+use crate::protocol_layer::message::header::MessageType;
+use crate::timers::TimerType;
+
+/// An event on a timeline.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimelineEvent {
+    /// The policy engine entered a new state.
+    State(&'static str),
+    /// A message was received from the port partner.
+    Received(MessageType),
+    /// A message was transmitted to the port partner.
+    Transmitted(MessageType),
+    /// The port partner signaled Hard Reset.
+    HardResetReceived,
+    /// Hard Reset was signaled to the port partner.
+    HardResetTransmitted,
+    /// A timer was started, with its duration in ms.
+    TimerStarted(TimerType, u32),
+    /// A timer expired.
+    TimerExpired(TimerType),
+    /// A protocol error occurred in the policy engine.
+    Error(ProtocolError),
+}
+
+/// An event, with the time of its occurrence.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimelineEntry {
+    /// The time in ms, if the [`Timer`](crate::timers::Timer) provides timestamps.
+    pub timestamp_millis: Option<u32>,
+    /// The event.
+    pub event: TimelineEvent,
+}
+
+/// A fixed-size timeline of the `N` most recent events.
+#[derive(Debug, Default)]
+pub struct Timeline<const N: usize> {
+    entries: Deque<TimelineEntry, N>,
+}
+
+impl<const N: usize> Timeline<N> {
+    /// Create a new, empty timeline.
+    pub const fn new() -> Self {
+        Self { entries: Deque::new() }
+    }
+
+    /// Add an event, overwriting the oldest one if the timeline is full.
+    pub fn push(&mut self, timestamp_micros: Option<u64>, event: TimelineEvent) {
+        if self.entries.is_full() {
+            self.entries.pop_front();
+        }
+
+        // Cannot fail, there is space now.
+        let _ = self.entries.push_back(TimelineEntry {
+            timestamp_millis: timestamp_micros.map(millis),
+            event,
+        });
+    }
+
+    /// The entries, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &TimelineEntry> {
+        self.entries.iter()
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Render the timeline as a Mermaid sequence diagram.
+    ///
+    /// Messages are arrows between the local port and its partner, all other events are notes on the port.
+    #[cfg(feature = "std")]
+    pub fn to_mermaid(&self) -> std::string::String {
+        use std::fmt::Write;
+
+        let mut diagram = std::string::String::from("sequenceDiagram\n    participant Port\n    participant Partner\n");
+
+        for entry in self.iter() {
+            let time = match entry.timestamp_millis {
+                Some(timestamp) => std::format!("[{timestamp} ms] "),
+                None => std::string::String::new(),
+            };
+
+            let line = match &entry.event {
+                TimelineEvent::State(state) => std::format!("Note over Port: {time}{state}"),
+                TimelineEvent::Received(message_type) => {
+                    std::format!("Partner->>Port: {time}{}", message_name(message_type))
+                }
+                TimelineEvent::Transmitted(message_type) => {
+                    std::format!("Port->>Partner: {time}{}", message_name(message_type))
+                }
+                TimelineEvent::HardResetReceived => std::format!("Partner-)Port: {time}Hard Reset"),
+                TimelineEvent::HardResetTransmitted => std::format!("Port-)Partner: {time}Hard Reset"),
+                TimelineEvent::TimerStarted(timer_type, duration) => {
+                    std::format!("Note over Port: {time}{timer_type:?} started ({duration} ms)")
+                }
+                TimelineEvent::TimerExpired(timer_type) => {
+                    std::format!("Note over Port: {time}{timer_type:?} expired")
+                }
+                TimelineEvent::Error(error) => std::format!("Note over Port,Partner: {time}{error:?}"),
+            };
+
+            // Semicolons and `#` start comments and entities in Mermaid.
+            writeln!(diagram, "    {}", line.replace([';', '#'], " ")).unwrap();
+        }
+
+        diagram
+    }
+}
+
+/// The name of a message type, without its class.
+#[cfg(feature = "std")]
+fn message_name(message_type: &MessageType) -> std::string::String {
+    match message_type {
+        MessageType::Control(message_type) => std::format!("{message_type:?}"),
+        MessageType::Data(message_type) => std::format!("{message_type:?}"),
+        MessageType::Extended(message_type) => std::format!("{message_type:?}"),
+    }
+}
+
+fn millis(micros: u64) -> u32 {
+    (micros / 1000).try_into().unwrap_or(u32::MAX)
+}
+
+/// Records into a shared timeline.
+///
+/// Events are dropped, while the timeline is borrowed elsewhere, e.g. during rendering.
+impl<const N: usize> Tracer for &RefCell<Timeline<N>> {
+    fn record(&self, timestamp_micros: Option<u64>, event: TraceEvent) {
+        let event = match event {
+            TraceEvent::StateChanged(state) => TimelineEvent::State(state),
+            TraceEvent::MessageReceived(message_type) => TimelineEvent::Received(message_type),
+            TraceEvent::MessageTransmitted(message_type) => TimelineEvent::Transmitted(message_type),
+            TraceEvent::HardResetReceived => TimelineEvent::HardResetReceived,
+            TraceEvent::HardResetTransmitted => TimelineEvent::HardResetTransmitted,
+            TraceEvent::ProtocolError(error) => TimelineEvent::Error(error),
+            #[cfg(feature = "capability-summary")]
+            TraceEvent::CapabilitiesEvaluated(_) => return,
+        };
+
+        if let Ok(mut timeline) = self.try_borrow_mut() {
+            timeline.push(timestamp_micros, event);
+        }
+    }
+
+    fn timer_started(&self, timestamp_micros: Option<u64>, timer_type: TimerType, duration_micros: u64) {
+        if let Ok(mut timeline) = self.try_borrow_mut() {
+            timeline.push(
+                timestamp_micros,
+                TimelineEvent::TimerStarted(timer_type, millis(duration_micros)),
+            );
+        }
+    }
+
+    fn timer_expired(&self, timestamp_micros: Option<u64>, timer_type: TimerType) {
+        if let Ok(mut timeline) = self.try_borrow_mut() {
+            timeline.push(timestamp_micros, TimelineEvent::TimerExpired(timer_type));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::{Timeline, TimelineEvent};
+    use crate::protocol_layer::message::header::{ControlMessageType, DataMessageType, MessageType};
+    use crate::timers::TimerType;
+    use crate::trace::{TraceEvent, Tracer};
+
+    #[test]
+    fn test_timeline() {
+        let timeline = RefCell::new(Timeline::<8>::new());
+        let tracer = &timeline;
+
+        tracer.record(Some(1_500), TraceEvent::StateChanged("WaitForCapabilities"));
+        tracer.timer_started(Some(1_600), TimerType::SinkWaitCap, 465_000);
+        tracer.record(
+            Some(12_000),
+            TraceEvent::MessageReceived(MessageType::Data(DataMessageType::SourceCapabilities)),
+        );
+        tracer.record(
+            Some(13_000),
+            TraceEvent::MessageTransmitted(MessageType::Data(DataMessageType::Request)),
+        );
+        tracer.timer_expired(None, TimerType::SenderResponse);
+        tracer.record(None, TraceEvent::HardResetTransmitted);
+
+        let timeline = timeline.borrow();
+        assert_eq!(timeline.len(), 6);
+
+        let entry = timeline.iter().nth(1).unwrap();
+        assert_eq!(entry.timestamp_millis, Some(1));
+        assert!(matches!(
+            entry.event,
+            TimelineEvent::TimerStarted(TimerType::SinkWaitCap, 465)
+        ));
+
+        #[cfg(feature = "std")]
+        assert_eq!(
+            timeline.to_mermaid(),
+            "sequenceDiagram\n    participant Port\n    participant Partner\n    \
+            Note over Port: [1 ms] WaitForCapabilities\n    \
+            Note over Port: [1 ms] SinkWaitCap started (465 ms)\n    \
+            Partner->>Port: [12 ms] SourceCapabilities\n    \
+            Port->>Partner: [13 ms] Request\n    \
+            Note over Port: SenderResponse expired\n    \
+            Port-)Partner: Hard Reset\n"
+        );
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let timeline = RefCell::new(Timeline::<1>::new());
+
+        (&timeline).record(None, TraceEvent::StateChanged("Discovery"));
+        (&timeline).record(
+            None,
+            TraceEvent::MessageReceived(MessageType::Control(ControlMessageType::Accept)),
+        );
+
+        let timeline = timeline.borrow();
+        assert_eq!(timeline.len(), 1);
+        assert!(matches!(
+            timeline.iter().next().unwrap().event,
+            TimelineEvent::Received(MessageType::Control(ControlMessageType::Accept))
+        ));
+    }
+}
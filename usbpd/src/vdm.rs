@@ -0,0 +1,687 @@
+//! Handling of structured vendor defined messages (VDM) for alternate modes.
+//!
+//! Applications register one [`SvidHandler`] per standard or vendor ID (SVID) with [`SvidHandlers`], and forward
+//! received VDMs to [`SvidHandlers::dispatch`], e.g. from
+//! [`DevicePolicyManager::vendor_defined_message`](crate::sink::device_policy_manager::DevicePolicyManager::vendor_defined_message).
+//!
+//! Requests are sent with [`Event::RequestVdm`](crate::sink::device_policy_manager::Event::RequestVdm). The initiator
+//! retries requests that are answered with BUSY, and reports the final response.
+//!
+//! Attention messages are rate limited with an [`AttentionRateLimit`], such that a partner that floods them cannot
+//! starve the policy engine. Excess messages are queued, and dropped when the queue is full.
+//!
+//! See [6.4.4.2].
+use heapless::{Deque, Vec};
+
+use crate::counters::{Counter, CounterType, Error as CounterError};
+use crate::protocol_layer::message::data::vendor_defined::{
+    CertStatVDO, ProductVDO, VdmCommand, VdmCommandType, VdmHeader, VdmHeaderStructured, VdmIdentityHeader, VdmVersion,
+};
+use crate::timers::TimerType;
+
+/// The outcome of a mode request, as reported by the responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModeOutcome {
+    /// The request was acknowledged (ACK).
+    Ack,
+    /// The request was rejected (NAK).
+    Nak,
+    /// The responder is busy (BUSY).
+    Busy,
+}
+
+/// A handler for the alternate mode(s) of a single SVID.
+///
+/// All methods default to ignoring the message.
+pub trait SvidHandler {
+    /// The SVID that this handler is responsible for.
+    fn svid(&self) -> u16;
+
+    /// The result of a Discover Modes request, containing one VDO per mode.
+    fn discover_modes(&mut self, _outcome: ModeOutcome, _modes: &[u32]) {}
+
+    /// The result of an Enter Mode request for the mode at the given object position.
+    fn enter_mode(&mut self, _object_position: u8, _outcome: ModeOutcome) {}
+
+    /// The result of an Exit Mode request for the mode at the given object position.
+    fn exit_mode(&mut self, _object_position: u8, _outcome: ModeOutcome) {}
+
+    /// An Attention message for the mode at the given object position, with optional VDOs.
+    fn attention(&mut self, _object_position: u8, _vdos: &[u32]) {}
+
+    /// The negotiated structured VDM version changed, e.g. after the first response of a port partner that supports
+    /// an older version.
+    fn vdm_version(&mut self, _version: VdmVersion) {}
+}
+
+/// Errors that can occur when registering SVID handlers.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistrationError {
+    /// No space left for more handlers.
+    #[error("no space for more SVID handlers")]
+    Full,
+    /// A handler for this SVID is registered already.
+    #[error("SVID `{0:#06x}` is registered already")]
+    Duplicate(u16),
+}
+
+/// The result of dispatching a VDM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Dispatch {
+    /// A handler consumed the message.
+    Handled,
+    /// No handler is registered for the message's SVID.
+    UnknownSvid(u16),
+    /// The message is not a mode result or attention, e.g. an unstructured VDM, or a request.
+    Unhandled,
+}
+
+/// A registry of SVID handlers, with space for `N` handlers.
+pub struct SvidHandlers<'a, const N: usize> {
+    handlers: Vec<&'a mut dyn SvidHandler, N>,
+    vdm_version: VdmVersion,
+}
+
+impl<const N: usize> Default for SvidHandlers<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, const N: usize> SvidHandlers<'a, N> {
+    /// Create an empty registry.
+    pub const fn new() -> Self {
+        Self {
+            handlers: Vec::new(),
+            vdm_version: VdmVersion::LATEST,
+        }
+    }
+
+    /// The structured VDM version that is common to both port partners, as negotiated from dispatched VDMs.
+    pub fn vdm_version(&self) -> VdmVersion {
+        self.vdm_version
+    }
+
+    /// Register a handler for its SVID.
+    pub fn register(&mut self, handler: &'a mut dyn SvidHandler) -> Result<(), RegistrationError> {
+        let svid = handler.svid();
+        if self.handlers.iter().any(|h| h.svid() == svid) {
+            return Err(RegistrationError::Duplicate(svid));
+        }
+
+        self.handlers.push(handler).map_err(|_| RegistrationError::Full)
+    }
+
+    /// Remove the handler for an SVID, returning it.
+    pub fn unregister(&mut self, svid: u16) -> Option<&'a mut dyn SvidHandler> {
+        let index = self.handlers.iter().position(|h| h.svid() == svid)?;
+        Some(self.handlers.swap_remove(index))
+    }
+
+    /// Get the handler for an SVID.
+    pub fn get(&mut self, svid: u16) -> Option<&mut (dyn SvidHandler + 'a)> {
+        self.handlers.iter_mut().find(|h| h.svid() == svid).map(|h| &mut **h)
+    }
+
+    /// Dispatch a received VDM to the handler for its SVID.
+    pub fn dispatch(&mut self, header: &VdmHeader, vdos: &[u32]) -> Dispatch {
+        let VdmHeader::Structured(header) = header else {
+            return Dispatch::Unhandled;
+        };
+
+        let version = negotiate_version(self.vdm_version, header);
+        if version != self.vdm_version {
+            self.vdm_version = version;
+            for handler in self.handlers.iter_mut() {
+                handler.vdm_version(version);
+            }
+        }
+
+        let svid = header.standard_or_vid();
+        let Some(handler) = self.get(svid) else {
+            return Dispatch::UnknownSvid(svid);
+        };
+
+        let object_position = header.object_position();
+        let outcome = match header.command_type() {
+            VdmCommandType::InitiatorREQ => None,
+            VdmCommandType::ResponderACK => Some(ModeOutcome::Ack),
+            VdmCommandType::ResponderNAK => Some(ModeOutcome::Nak),
+            VdmCommandType::ResponderBSY => Some(ModeOutcome::Busy),
+        };
+
+        match (raw_command(header), outcome) {
+            (Some(VdmCommand::DiscoverModes), Some(outcome)) => handler.discover_modes(outcome, vdos),
+            (Some(VdmCommand::EnterMode), Some(outcome)) => handler.enter_mode(object_position, outcome),
+            (Some(VdmCommand::ExitMode), Some(outcome)) => handler.exit_mode(object_position, outcome),
+            (Some(VdmCommand::Attention), None) => handler.attention(object_position, vdos),
+            _ => return Dispatch::Unhandled,
+        }
+
+        Dispatch::Handled
+    }
+}
+
+/// The number of rate limited Attention messages that are queued for later delivery.
+pub const ATTENTION_QUEUE_SIZE: usize = 4;
+
+/// The maximum rate of Attention messages that are delivered to the device policy manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AttentionRateLimit {
+    /// The number of Attention messages that are delivered per window.
+    pub max_attentions: u8,
+    /// The length of a window in µs.
+    pub window_micros: u64,
+}
+
+impl AttentionRateLimit {
+    /// Eight Attention messages per 100 ms.
+    pub const DEFAULT: Self = Self::new(8, 100_000);
+
+    /// Deliver at most `max_attentions` per window of `window_micros`.
+    pub const fn new(max_attentions: u8, window_micros: u64) -> Self {
+        Self {
+            max_attentions,
+            window_micros,
+        }
+    }
+}
+
+impl Default for AttentionRateLimit {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What happened to an offered Attention message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum Offer {
+    /// Deliver the message now.
+    Deliver,
+    /// The message was queued, see [`AttentionQueue::take_due`].
+    Queued,
+    /// The queue is full, and the message was dropped.
+    Dropped,
+}
+
+/// Rate limiting and queueing of received Attention messages.
+///
+/// Without a clock (see [`Timer::now_micros`](crate::timers::Timer::now_micros)), all messages are delivered.
+#[derive(Debug)]
+pub(crate) struct AttentionQueue {
+    limit: Option<AttentionRateLimit>,
+    window_start_micros: u64,
+    delivered: u8,
+    queue: Deque<(VdmHeaderStructured, Vec<u32, 7>), ATTENTION_QUEUE_SIZE>,
+}
+
+impl AttentionQueue {
+    /// Create an empty queue, that delivers at most at the given rate, if any.
+    pub fn new(limit: Option<AttentionRateLimit>) -> Self {
+        Self {
+            limit,
+            window_start_micros: 0,
+            delivered: 0,
+            queue: Deque::new(),
+        }
+    }
+
+    /// Offer a received Attention message.
+    ///
+    /// Messages are delivered in order, so a message is only delivered right away, if none are queued.
+    pub fn offer(&mut self, now_micros: Option<u64>, header: &VdmHeaderStructured, vdos: &[u32]) -> Offer {
+        if self.queue.is_empty() && self.consume(now_micros) {
+            return Offer::Deliver;
+        }
+
+        let vdos = vdos.iter().copied().collect();
+        match self.queue.push_back((*header, vdos)) {
+            Ok(()) => Offer::Queued,
+            Err(_) => Offer::Dropped,
+        }
+    }
+
+    /// Take the oldest queued message, if the rate limit permits delivering it.
+    pub fn take_due(&mut self, now_micros: Option<u64>) -> Option<(VdmHeaderStructured, Vec<u32, 7>)> {
+        if self.queue.is_empty() || !self.consume(now_micros) {
+            return None;
+        }
+        self.queue.pop_front()
+    }
+
+    /// The time in µs until the next queued message is due, if any is queued.
+    pub fn due_in_micros(&self, now_micros: Option<u64>) -> Option<u64> {
+        if self.queue.is_empty() {
+            return None;
+        }
+
+        match (self.limit, now_micros) {
+            (Some(limit), Some(now_micros)) => {
+                Some((self.window_start_micros + limit.window_micros).saturating_sub(now_micros))
+            }
+            _ => Some(0),
+        }
+    }
+
+    /// Drop all queued messages, and start a new window.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.delivered = 0;
+        self.window_start_micros = 0;
+    }
+
+    /// Count a delivery, if the rate limit permits it.
+    fn consume(&mut self, now_micros: Option<u64>) -> bool {
+        let (Some(limit), Some(now_micros)) = (self.limit, now_micros) else {
+            return true;
+        };
+
+        if self.delivered == 0 || now_micros >= self.window_start_micros + limit.window_micros {
+            self.window_start_micros = now_micros;
+            self.delivered = 0;
+        }
+
+        if self.delivered < limit.max_attentions {
+            self.delivered += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The identity of a cable, as discovered with Discover Identity on SOP'.
+///
+/// Per spec 6.4.4.3.1, cable discovery results stay valid across soft resets, and only become invalid on detach, or
+/// hard reset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CableIdentity {
+    /// The ID header VDO.
+    pub id_header: VdmIdentityHeader,
+    /// The Cert Stat VDO.
+    pub cert_stat: CertStatVDO,
+    /// The Product VDO.
+    pub product: ProductVDO,
+    /// The product type VDOs, e.g. the passive or active cable VDO.
+    pub cable_vdos: Vec<u32, 3>,
+}
+
+impl CableIdentity {
+    /// Parse the VDOs (without VDM header) of a Discover Identity ACK.
+    ///
+    /// Returns `None`, if the mandatory ID header, Cert Stat, and Product VDOs are missing.
+    pub fn from_discover_identity(vdos: &[u32]) -> Option<Self> {
+        let [id_header, cert_stat, product, cable_vdos @ ..] = vdos else {
+            return None;
+        };
+
+        Some(Self {
+            id_header: VdmIdentityHeader(*id_header),
+            cert_stat: CertStatVDO(*cert_stat),
+            product: ProductVDO(*product),
+            cable_vdos: cable_vdos.iter().copied().take(3).collect(),
+        })
+    }
+
+    /// The vendor ID of the cable.
+    pub fn vid(&self) -> u16 {
+        self.id_header.vid()
+    }
+
+    /// Whether the cable is EPR capable, as indicated in the (first) cable VDO.
+    pub fn epr_capable(&self) -> bool {
+        self.cable_vdos.first().is_some_and(|vdo| (vdo >> 17) & 1 == 1)
+    }
+}
+
+/// The vendor and product ID from a Discover Identity ACK, if the message is one.
+pub(crate) fn discovered_product(header: &VdmHeader, vdos: &[u32]) -> Option<(u16, u16)> {
+    let VdmHeader::Structured(header) = header else {
+        return None;
+    };
+    if !matches!(raw_command(header), Some(VdmCommand::DiscoverIdentity))
+        || !matches!(header.command_type(), VdmCommandType::ResponderACK)
+    {
+        return None;
+    }
+
+    let [id_header, _cert_stat, product, ..] = vdos else {
+        return None;
+    };
+
+    Some((VdmIdentityHeader(*id_header).vid(), ProductVDO(*product).pid()))
+}
+
+/// The next step of a VDM initiator, after receiving a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InitiatorStep {
+    /// The request is complete (ACK, NAK, or BUSY after exhausting all retries).
+    Complete(ModeOutcome),
+    /// The responder is busy, retry the request after tVDMBusy.
+    RetryAfterBusy,
+    /// The response does not belong to the request, keep waiting.
+    Ignore,
+}
+
+/// Initiator side of a structured VDM request.
+///
+/// Handles responder BUSY with the busy retry counter and NAK, see [6.4.4.2.2].
+#[derive(Debug)]
+pub(crate) struct Initiator {
+    request: VdmHeaderStructured,
+    busy: Counter,
+}
+
+impl Initiator {
+    /// Create a new initiator for a request.
+    pub fn new(request: VdmHeaderStructured) -> Self {
+        Self {
+            request,
+            busy: Counter::new(CounterType::Busy),
+        }
+    }
+
+    /// The timer that is used for waiting for a response.
+    pub fn response_timer(&self) -> TimerType {
+        match raw_command(&self.request) {
+            Some(VdmCommand::EnterMode) => TimerType::VDMModeEntry,
+            Some(VdmCommand::ExitMode) => TimerType::VDMModeExit,
+            _ => TimerType::VDMResponse,
+        }
+    }
+
+    /// Evaluate a response to the request.
+    pub fn on_response(&mut self, response: &VdmHeaderStructured) -> InitiatorStep {
+        if response.standard_or_vid() != self.request.standard_or_vid()
+            || command_bits(response) != command_bits(&self.request)
+        {
+            return InitiatorStep::Ignore;
+        }
+
+        match response.command_type() {
+            VdmCommandType::InitiatorREQ => InitiatorStep::Ignore,
+            VdmCommandType::ResponderACK => InitiatorStep::Complete(ModeOutcome::Ack),
+            VdmCommandType::ResponderNAK => InitiatorStep::Complete(ModeOutcome::Nak),
+            VdmCommandType::ResponderBSY => match self.busy.increment() {
+                Ok(_) => InitiatorStep::RetryAfterBusy,
+                Err(CounterError::Exceeded) => {
+                    warn!("VDM responder busy, retries exhausted");
+                    InitiatorStep::Complete(ModeOutcome::Busy)
+                }
+            },
+        }
+    }
+}
+
+/// The structured VDM version that is common to both port partners, after receiving a header from the partner.
+///
+/// Headers with a reserved version leave the version unchanged. See [6.4.4.2.3].
+pub(crate) fn negotiate_version(current: VdmVersion, received: &VdmHeaderStructured) -> VdmVersion {
+    received
+        .version()
+        .map_or(current, |version| version.min(VdmVersion::LATEST))
+}
+
+/// Whether a structured VDM header belongs to an Attention message.
+pub(crate) fn is_attention(header: &VdmHeaderStructured) -> bool {
+    matches!(raw_command(header), Some(VdmCommand::Attention))
+        && matches!(header.command_type(), VdmCommandType::InitiatorREQ)
+}
+
+/// The raw command bits of a structured VDM header.
+fn command_bits(header: &VdmHeaderStructured) -> u8 {
+    (header.0 & 0x1f) as u8
+}
+
+/// Decode the command of a structured VDM header, without panicking on SVID specific commands.
+fn raw_command(header: &VdmHeaderStructured) -> Option<VdmCommand> {
+    match command_bits(header) {
+        0x01 => Some(VdmCommand::DiscoverIdentity),
+        0x02 => Some(VdmCommand::DiscoverSVIDS),
+        0x03 => Some(VdmCommand::DiscoverModes),
+        0x04 => Some(VdmCommand::EnterMode),
+        0x05 => Some(VdmCommand::ExitMode),
+        0x06 => Some(VdmCommand::Attention),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ATTENTION_QUEUE_SIZE, AttentionQueue, AttentionRateLimit, CableIdentity, Dispatch, Initiator, InitiatorStep,
+        ModeOutcome, Offer, RegistrationError, SvidHandler, SvidHandlers,
+    };
+    use crate::protocol_layer::message::data::vendor_defined::{
+        VdmCommand, VdmCommandType, VdmHeader, VdmHeaderStructured, VdmHeaderUnstructured, VdmVersion,
+    };
+
+    const DISPLAYPORT_SVID: u16 = 0xff01;
+
+    #[derive(Default)]
+    struct DisplayPort {
+        modes: usize,
+        entered: Option<(u8, ModeOutcome)>,
+        attentions: usize,
+        vdm_version: Option<VdmVersion>,
+    }
+
+    impl SvidHandler for DisplayPort {
+        fn svid(&self) -> u16 {
+            DISPLAYPORT_SVID
+        }
+
+        fn discover_modes(&mut self, _outcome: ModeOutcome, modes: &[u32]) {
+            self.modes = modes.len();
+        }
+
+        fn enter_mode(&mut self, object_position: u8, outcome: ModeOutcome) {
+            self.entered = Some((object_position, outcome));
+        }
+
+        fn attention(&mut self, _object_position: u8, _vdos: &[u32]) {
+            self.attentions += 1;
+        }
+
+        fn vdm_version(&mut self, version: VdmVersion) {
+            self.vdm_version = Some(version);
+        }
+    }
+
+    fn header(svid: u16, command: VdmCommand, command_type: VdmCommandType) -> VdmHeader {
+        VdmHeader::Structured(
+            VdmHeaderStructured::default()
+                .with_standard_or_vid(svid)
+                .with_object_position(1)
+                .with_command(command)
+                .with_command_type(command_type),
+        )
+    }
+
+    #[test]
+    fn test_registration() {
+        let mut first = DisplayPort::default();
+        let mut second = DisplayPort::default();
+        let mut handlers: SvidHandlers<'_, 1> = SvidHandlers::new();
+
+        handlers.register(&mut first).unwrap();
+        assert_eq!(
+            handlers.register(&mut second),
+            Err(RegistrationError::Duplicate(DISPLAYPORT_SVID))
+        );
+        assert!(handlers.unregister(DISPLAYPORT_SVID).is_some());
+        assert!(handlers.get(DISPLAYPORT_SVID).is_none());
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let mut display_port = DisplayPort::default();
+        let mut handlers: SvidHandlers<'_, 2> = SvidHandlers::new();
+        handlers.register(&mut display_port).unwrap();
+
+        let ack = VdmCommandType::ResponderACK;
+        assert_eq!(
+            handlers.dispatch(&header(DISPLAYPORT_SVID, VdmCommand::DiscoverModes, ack), &[0x1, 0x2]),
+            Dispatch::Handled
+        );
+        assert_eq!(
+            handlers.dispatch(
+                &header(DISPLAYPORT_SVID, VdmCommand::EnterMode, VdmCommandType::ResponderNAK),
+                &[]
+            ),
+            Dispatch::Handled
+        );
+        assert_eq!(
+            handlers.dispatch(
+                &header(DISPLAYPORT_SVID, VdmCommand::Attention, VdmCommandType::InitiatorREQ),
+                &[]
+            ),
+            Dispatch::Handled
+        );
+        assert_eq!(
+            handlers.dispatch(&header(0x8087, VdmCommand::EnterMode, ack), &[]),
+            Dispatch::UnknownSvid(0x8087)
+        );
+        assert_eq!(
+            handlers.dispatch(&header(DISPLAYPORT_SVID, VdmCommand::DiscoverIdentity, ack), &[]),
+            Dispatch::Unhandled
+        );
+        assert_eq!(
+            handlers.dispatch(&VdmHeader::Unstructured(VdmHeaderUnstructured(0)), &[]),
+            Dispatch::Unhandled
+        );
+
+        drop(handlers);
+        assert_eq!(display_port.modes, 2);
+        assert_eq!(display_port.entered, Some((1, ModeOutcome::Nak)));
+        assert_eq!(display_port.attentions, 1);
+    }
+
+    #[test]
+    fn test_vdm_version() {
+        let mut display_port = DisplayPort::default();
+        let mut handlers: SvidHandlers<'_, 1> = SvidHandlers::new();
+        handlers.register(&mut display_port).unwrap();
+        assert_eq!(handlers.vdm_version(), VdmVersion::LATEST);
+
+        // A partner with the latest version does not change the negotiated version.
+        let VdmHeader::Structured(ack) = header(
+            DISPLAYPORT_SVID,
+            VdmCommand::DiscoverModes,
+            VdmCommandType::ResponderACK,
+        ) else {
+            unreachable!()
+        };
+        handlers.dispatch(&VdmHeader::Structured(ack.with_version(VdmVersion::V2_1)), &[]);
+        assert_eq!(handlers.vdm_version(), VdmVersion::V2_1);
+
+        // An older partner lowers it, and handlers are notified.
+        handlers.dispatch(&VdmHeader::Structured(ack.with_version(VdmVersion::V2_0)), &[]);
+        assert_eq!(handlers.vdm_version(), VdmVersion::V2_0);
+
+        // Reserved major versions are ignored.
+        handlers.dispatch(&VdmHeader::Structured(ack.with_vdm_version_major(0b11)), &[]);
+        assert_eq!(handlers.vdm_version(), VdmVersion::V2_0);
+
+        drop(handlers);
+        assert_eq!(display_port.vdm_version, Some(VdmVersion::V2_0));
+    }
+
+    #[test]
+    fn test_version_round_trip() {
+        for version in [VdmVersion::V1_0, VdmVersion::V2_0, VdmVersion::V2_1] {
+            assert_eq!(
+                VdmHeaderStructured::default().with_version(version).version(),
+                Some(version)
+            );
+        }
+
+        // Minor versions beyond 2.1 are treated as 2.1.
+        let newer = VdmHeaderStructured::default()
+            .with_vdm_version_major(0b01)
+            .with_vdm_version_minor(0b10);
+        assert_eq!(newer.version(), Some(VdmVersion::V2_1));
+    }
+
+    #[test]
+    fn test_attention_queue() {
+        let VdmHeader::Structured(attention) =
+            header(DISPLAYPORT_SVID, VdmCommand::Attention, VdmCommandType::InitiatorREQ)
+        else {
+            unreachable!()
+        };
+        let mut queue = AttentionQueue::new(Some(AttentionRateLimit::new(2, 1_000)));
+
+        // Two are delivered per window, then the queue fills up.
+        assert_eq!(queue.offer(Some(0), &attention, &[]), Offer::Deliver);
+        assert_eq!(queue.offer(Some(10), &attention, &[]), Offer::Deliver);
+        for object_position in 0..ATTENTION_QUEUE_SIZE as u8 {
+            let attention = attention.with_object_position(object_position);
+            assert_eq!(queue.offer(Some(20), &attention, &[0x1]), Offer::Queued);
+        }
+        assert_eq!(queue.offer(Some(30), &attention, &[]), Offer::Dropped);
+
+        // Queued messages are delivered in order, in the next window.
+        assert!(queue.take_due(Some(500)).is_none());
+        assert_eq!(queue.due_in_micros(Some(500)), Some(500));
+        let (first, vdos) = queue.take_due(Some(1_000)).unwrap();
+        assert_eq!(first.object_position(), 0);
+        assert_eq!(vdos.as_slice(), &[0x1]);
+        assert_eq!(queue.take_due(Some(1_100)).unwrap().0.object_position(), 1);
+        assert!(queue.take_due(Some(1_200)).is_none());
+        assert_eq!(queue.due_in_micros(Some(1_200)), Some(800));
+
+        // Without a clock, nothing is limited.
+        queue.clear();
+        assert_eq!(queue.due_in_micros(None), None);
+        for _ in 0..10 {
+            assert_eq!(queue.offer(None, &attention, &[]), Offer::Deliver);
+        }
+    }
+
+    #[test]
+    fn test_initiator_busy() {
+        let VdmHeader::Structured(request) =
+            header(DISPLAYPORT_SVID, VdmCommand::EnterMode, VdmCommandType::InitiatorREQ)
+        else {
+            unreachable!()
+        };
+        let VdmHeader::Structured(busy) = header(DISPLAYPORT_SVID, VdmCommand::EnterMode, VdmCommandType::ResponderBSY)
+        else {
+            unreachable!()
+        };
+
+        let mut initiator = Initiator::new(request);
+        for _ in 0..5 {
+            assert_eq!(initiator.on_response(&busy), InitiatorStep::RetryAfterBusy);
+        }
+        assert_eq!(initiator.on_response(&busy), InitiatorStep::Complete(ModeOutcome::Busy));
+
+        // Responses to other commands are not considered.
+        let VdmHeader::Structured(other) = header(DISPLAYPORT_SVID, VdmCommand::ExitMode, VdmCommandType::ResponderNAK)
+        else {
+            unreachable!()
+        };
+        assert_eq!(initiator.on_response(&other), InitiatorStep::Ignore);
+        assert_eq!(
+            initiator.on_response(&request.with_command_type(VdmCommandType::ResponderNAK)),
+            InitiatorStep::Complete(ModeOutcome::Nak)
+        );
+    }
+
+    #[test]
+    fn test_cable_identity() {
+        assert!(CableIdentity::from_discover_identity(&[0x1, 0x2]).is_none());
+
+        let identity = CableIdentity::from_discover_identity(&[0x1800_05ac, 0x0, 0x1234_0001, 1 << 17]).unwrap();
+        assert_eq!(identity.vid(), 0x05ac);
+        assert_eq!(identity.product.pid(), 0x1234);
+        assert!(identity.epr_capable());
+    }
+}